@@ -0,0 +1,22 @@
+int f() {
+    return 4;
+}
+
+int add(int a, int b, int c, int d, int e, int g, int h, int i) {
+    return a + b * 2 - c / 3 + d % 5 + e + g + h - i;
+}
+
+int main() {
+    int x;
+    int y = 1;
+    x = f(), y++;
+    ;
+    x + 1;
+    int z = (x == 4) && (y != 1) || 0;
+    {
+        int x = 10;
+        y = y + x;
+    }
+    --y;
+    return add(x, y, z, 7, 1, 1, 1, 1) + !0 + -(~2);
+}
@@ -0,0 +1,13 @@
+int main() {
+    goto skip;
+    return 1;
+skip:
+    switch (2) {
+        case 2:
+            goto done;
+        default:
+            return 3;
+    }
+done:
+    return 7;
+}
@@ -0,0 +1,5 @@
+#pragma once
+
+#define ANSWER 42
+
+int answer(void);
@@ -0,0 +1,19 @@
+#include "include/answer.h"
+
+#define SQUARE(x) ((x) * (x))
+#define CAT(a, b) a##b
+#define DEBUG 0
+
+int answer(void) {
+    return ANSWER;
+}
+
+int main() {
+    int CAT(total, _) = SQUARE(2 + 1);
+#if DEBUG || !defined(ANSWER)
+    total_ = 0;
+#elif ANSWER > 40
+    total_ = total_ + answer() - __LINE__;
+#endif
+    return total_;
+}
@@ -0,0 +1,30 @@
+#include <stdio.h>
+
+int calls = 0;
+static int base = 10;
+static char flag;
+
+static int counter(void) {
+    static int count = 5;
+    count++;
+    return count;
+}
+
+static int helper(int x);
+
+int helper(int x) {
+    static int count;
+    calls++;
+    count = count + x;
+    return count;
+}
+
+int main() {
+    counter();
+    counter();
+    helper(3);
+    helper(4);
+    flag = 1;
+    printf("%d %d %d %d\n", counter(), helper(0), calls, base + flag);
+    return counter() + helper(1) + calls;
+}
@@ -0,0 +1,21 @@
+int main() {
+    switch (3) {
+        case 1:
+            return 10;
+        case 2:
+        case 3:
+            switch (7) {
+                case 7:
+                    break;
+                default:
+                    return 1;
+            }
+        case 4:
+            return 40;
+        case 6:
+            break;
+        default:
+            return 0;
+    }
+    return 99;
+}
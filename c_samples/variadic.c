@@ -0,0 +1,24 @@
+#include <stdarg.h>
+#include <stdio.h>
+
+int sum(int count, ...) {
+    va_list ap;
+    va_start(ap, count);
+    int total = 0;
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    total = total + va_arg(ap, int);
+    va_end(ap);
+    return total;
+}
+
+int main() {
+    char c = 3;
+    printf("sum %d, char %d\n", sum(8, 1, 2, 3, 4, 5, 6, 7, 8), c);
+    return sum(8, 1, 2, 3, 4, 5, 6, 7, 8);
+}
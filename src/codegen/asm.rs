@@ -0,0 +1,563 @@
+// x86-64 instructions as the backend builds them, printed in AT&T syntax
+// only at the very end.
+use std::fmt;
+
+use crate::syntax::DataType;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Register {
+    Ax,
+    Bx,
+    Cx,
+    Dx,
+    Si,
+    Di,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Bp,
+    Sp,
+}
+impl Register {
+    pub fn name(&self, width: Width) -> &'static str {
+        let names = match self {
+            Self::Ax => ["%al", "%eax", "%rax"],
+            Self::Bx => ["%bl", "%ebx", "%rbx"],
+            Self::Cx => ["%cl", "%ecx", "%rcx"],
+            Self::Dx => ["%dl", "%edx", "%rdx"],
+            Self::Si => ["%sil", "%esi", "%rsi"],
+            Self::Di => ["%dil", "%edi", "%rdi"],
+            Self::R8 => ["%r8b", "%r8d", "%r8"],
+            Self::R9 => ["%r9b", "%r9d", "%r9"],
+            Self::R10 => ["%r10b", "%r10d", "%r10"],
+            Self::R11 => ["%r11b", "%r11d", "%r11"],
+            Self::R12 => ["%r12b", "%r12d", "%r12"],
+            Self::R13 => ["%r13b", "%r13d", "%r13"],
+            Self::R14 => ["%r14b", "%r14d", "%r14"],
+            Self::R15 => ["%r15b", "%r15d", "%r15"],
+            Self::Bp => ["%bpl", "%ebp", "%rbp"],
+            Self::Sp => ["%spl", "%esp", "%rsp"],
+        };
+        match width {
+            Width::Byte => names[0],
+            Width::Long => names[1],
+            Width::Quad => names[2],
+        }
+    }
+}
+
+pub const ARGUMENT_REGISTERS: [Register; 6] = [
+    Register::Di,
+    Register::Si,
+    Register::Dx,
+    Register::Cx,
+    Register::R8,
+    Register::R9,
+];
+// What a call may change, besides the vector registers.
+pub const CALLER_SAVED_REGISTERS: [Register; 9] = [
+    Register::Ax,
+    Register::Cx,
+    Register::Dx,
+    Register::Si,
+    Register::Di,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+];
+pub const CALLEE_SAVED_REGISTERS: [Register; 5] = [
+    Register::Bx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+pub const VECTOR_ARGUMENT_REGISTERS: u8 = 8;
+// Six general purpose registers followed by eight 16 byte vector registers.
+pub const REGISTER_SAVE_AREA_SIZE: i32 = 176;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Width {
+    Byte,
+    Long,
+    Quad,
+}
+impl Width {
+    pub fn of(data_type: &DataType) -> Width {
+        match data_type {
+            DataType::Char => Self::Byte,
+            DataType::Int => Self::Long,
+            DataType::Pointer(_) | DataType::VaList => Self::Quad,
+            DataType::Const(val) => Self::of(val),
+        }
+    }
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Byte => "b",
+            Self::Long => "l",
+            Self::Quad => "q",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand {
+    Immediate(i64),
+    Register(Register),
+    // An IR variable that hasn't been given a home yet, and a field at some
+    // offset inside one.
+    Pseudo(String),
+    PseudoMemory(String, i32),
+    Memory(Register, i32),
+    // A symbol, addressed relative to %rip.
+    Data(String, i32),
+    Indexed {
+        base: Register,
+        index: Register,
+        scale: u8,
+    },
+}
+impl Operand {
+    pub fn is_memory(&self) -> bool {
+        matches!(
+            self,
+            Self::Memory(..) | Self::Data(..) | Self::Indexed { .. }
+        )
+    }
+    fn format(&self, width: Width) -> String {
+        match self {
+            Self::Immediate(val) => format!("${}", val),
+            Self::Register(register) => String::from(register.name(width)),
+            Self::Pseudo(name) => format!("<{}>", name),
+            Self::PseudoMemory(name, offset) => format!("<{}+{}>", name, offset),
+            Self::Memory(base, offset) => format!("{}({})", offset, base.name(Width::Quad)),
+            Self::Data(symbol, 0) => format!("{}(%rip)", symbol),
+            Self::Data(symbol, offset) => format!("{}+{}(%rip)", symbol, offset),
+            Self::Indexed { base, index, scale } => format!(
+                "({},{},{})",
+                base.name(Width::Quad),
+                index.name(Width::Quad),
+                scale
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Condition {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Above,
+    AboveEqual,
+}
+impl Condition {
+    fn suffix(&self) -> &'static str {
+        match self {
+            Self::Equal => "e",
+            Self::NotEqual => "ne",
+            Self::Less => "l",
+            Self::LessEqual => "le",
+            Self::Greater => "g",
+            Self::GreaterEqual => "ge",
+            Self::Above => "a",
+            Self::AboveEqual => "ae",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum UnaryOperator {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Sar,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instruction {
+    Mov(Width, Operand, Operand),
+    // movsbl and movslq.
+    MovsxByte(Operand, Operand),
+    MovsxLong(Operand, Operand),
+    Lea(Operand, Operand),
+    Unary(UnaryOperator, Width, Operand),
+    Binary(BinaryOperator, Width, Operand, Operand),
+    Cmp(Width, Operand, Operand),
+    Test(Width, Operand, Operand),
+    Idiv(Width, Operand),
+    Cdq,
+    Jmp(String),
+    JmpIndirect(Operand),
+    JmpCC(Condition, String),
+    SetCC(Condition, Operand),
+    Label(String),
+    Push(Operand),
+    Pop(Register),
+    // With the number of argument registers the callee reads.
+    Call(String, usize),
+    Ret,
+    // Saves a vector register, for va_arg.
+    SaveVector(u8, Operand),
+    // Targets of a computed jump, as offsets from the table, in .rodata.
+    JumpTable(String, Vec<String>),
+}
+impl Instruction {
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Mov(_, source, destination)
+            | Self::MovsxByte(source, destination)
+            | Self::MovsxLong(source, destination)
+            | Self::Lea(source, destination)
+            | Self::Binary(_, _, source, destination)
+            | Self::Cmp(_, source, destination)
+            | Self::Test(_, source, destination) => vec![source, destination],
+            Self::Unary(_, _, operand)
+            | Self::Idiv(_, operand)
+            | Self::JmpIndirect(operand)
+            | Self::SetCC(_, operand)
+            | Self::Push(operand)
+            | Self::SaveVector(_, operand) => vec![operand],
+            Self::Cdq
+            | Self::Jmp(_)
+            | Self::JmpCC(..)
+            | Self::Label(_)
+            | Self::Pop(_)
+            | Self::Call(..)
+            | Self::Ret
+            | Self::JumpTable(..) => vec![],
+        }
+    }
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mov(width, source, destination) => write!(
+                f,
+                "mov{} {}, {}",
+                width.suffix(),
+                source.format(*width),
+                destination.format(*width)
+            ),
+            Self::MovsxByte(source, destination) => write!(
+                f,
+                "movsbl {}, {}",
+                source.format(Width::Byte),
+                destination.format(Width::Long)
+            ),
+            Self::MovsxLong(source, destination) => write!(
+                f,
+                "movslq {}, {}",
+                source.format(Width::Long),
+                destination.format(Width::Quad)
+            ),
+            Self::Lea(source, destination) => write!(
+                f,
+                "leaq {}, {}",
+                source.format(Width::Quad),
+                destination.format(Width::Quad)
+            ),
+            Self::Unary(operator, width, operand) => {
+                let name = match operator {
+                    UnaryOperator::Neg => "neg",
+                    UnaryOperator::Not => "not",
+                };
+                write!(f, "{}{} {}", name, width.suffix(), operand.format(*width))
+            }
+            Self::Binary(operator, width, source, destination) => {
+                let name = match operator {
+                    BinaryOperator::Add => "add",
+                    BinaryOperator::Sub => "sub",
+                    BinaryOperator::Imul => "imul",
+                    BinaryOperator::And => "and",
+                    BinaryOperator::Or => "or",
+                    BinaryOperator::Xor => "xor",
+                    BinaryOperator::Shl => "shl",
+                    BinaryOperator::Sar => "sar",
+                };
+                // Shift counts that aren't immediates live in %cl.
+                let source_width = match operator {
+                    BinaryOperator::Shl | BinaryOperator::Sar => Width::Byte,
+                    _ => *width,
+                };
+                write!(
+                    f,
+                    "{}{} {}, {}",
+                    name,
+                    width.suffix(),
+                    source.format(source_width),
+                    destination.format(*width)
+                )
+            }
+            Self::Cmp(width, left, right) => write!(
+                f,
+                "cmp{} {}, {}",
+                width.suffix(),
+                left.format(*width),
+                right.format(*width)
+            ),
+            Self::Test(width, left, right) => write!(
+                f,
+                "test{} {}, {}",
+                width.suffix(),
+                left.format(*width),
+                right.format(*width)
+            ),
+            Self::Idiv(width, operand) => {
+                write!(f, "idiv{} {}", width.suffix(), operand.format(*width))
+            }
+            Self::Cdq => write!(f, "cltd"),
+            Self::Jmp(label) => write!(f, "jmp {}", label),
+            Self::JmpIndirect(operand) => write!(f, "jmp *{}", operand.format(Width::Quad)),
+            Self::JmpCC(condition, label) => write!(f, "j{} {}", condition.suffix(), label),
+            Self::SetCC(condition, operand) => write!(
+                f,
+                "set{} {}",
+                condition.suffix(),
+                operand.format(Width::Byte)
+            ),
+            Self::Label(label) => write!(f, "{}:", label),
+            Self::Push(operand) => write!(f, "pushq {}", operand.format(Width::Quad)),
+            Self::Pop(register) => write!(f, "popq {}", register.name(Width::Quad)),
+            Self::Call(name, _) => write!(f, "call {}", name),
+            Self::Ret => write!(f, "ret"),
+            Self::SaveVector(register, operand) => write!(
+                f,
+                "movaps %xmm{}, {}",
+                register,
+                operand.format(Width::Quad)
+            ),
+            Self::JumpTable(label, targets) => {
+                write!(f, ".section .rodata\n.align 4\n{}:", label)?;
+                for target in targets.iter() {
+                    write!(f, "\n.long {}-{}", target, label)?;
+                }
+                write!(f, "\n.text")
+            }
+        }
+    }
+}
+
+pub struct Function {
+    pub name: String,
+    pub global: bool,
+    pub weak: bool,
+    pub instructions: Vec<Instruction>,
+}
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            writeln!(f, ".weak {}", self.name)?;
+        } else if self.global {
+            writeln!(f, ".globl {}", self.name)?;
+        }
+        writeln!(f, "{}:", self.name)?;
+        for instruction in self.instructions.iter() {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+// What a static variable holds: a number, or the address of a label.
+#[derive(PartialEq)]
+pub enum Initializer {
+    Integer(i32),
+    Label(String),
+}
+impl fmt::Display for Initializer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
+
+// Statically allocated storage. Zeroed storage goes to .bss.
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub width: Width,
+    pub initial: Initializer,
+}
+impl fmt::Display for StaticVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = match self.width {
+            Width::Byte => 1,
+            Width::Long => 4,
+            Width::Quad => 8,
+        };
+        if self.global {
+            writeln!(f, ".globl {}", self.name)?;
+        }
+        if self.initial == Initializer::Integer(0) {
+            writeln!(f, ".bss\n.align {}\n{}:\n.zero {}", size, self.name, size)?;
+        } else {
+            let directive = match self.width {
+                Width::Byte => ".byte",
+                Width::Long => ".long",
+                Width::Quad => ".quad",
+            };
+            writeln!(
+                f,
+                ".data\n.align {}\n{}:\n{} {}",
+                size, self.name, directive, self.initial
+            )?;
+        }
+        writeln!(f, ".text")
+    }
+}
+
+pub struct StringConstant {
+    pub label: String,
+    pub value: String,
+}
+impl fmt::Display for StringConstant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            ".section .rodata\n{}:\n.string \"{}\"\n.text",
+            self.label, self.value
+        )
+    }
+}
+
+pub struct Program {
+    pub statics: Vec<StaticVariable>,
+    pub strings: Vec<StringConstant>,
+    pub functions: Vec<Function>,
+}
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for variable in self.statics.iter() {
+            write!(f, "{}", variable)?;
+        }
+        for string in self.strings.iter() {
+            write!(f, "{}", string)?;
+        }
+        for function in self.functions.iter() {
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_instructions() {
+        let instructions = [
+            Instruction::Mov(
+                Width::Byte,
+                Operand::Register(Register::Ax),
+                Operand::Memory(Register::Bp, -1),
+            ),
+            Instruction::MovsxByte(
+                Operand::Register(Register::Di),
+                Operand::Register(Register::Cx),
+            ),
+            Instruction::Binary(
+                BinaryOperator::Shl,
+                Width::Long,
+                Operand::Register(Register::Cx),
+                Operand::Register(Register::Ax),
+            ),
+            Instruction::Lea(
+                Operand::Data(String::from("count"), 4),
+                Operand::Register(Register::R8),
+            ),
+            Instruction::Push(Operand::Immediate(7)),
+            Instruction::JmpIndirect(Operand::Register(Register::Ax)),
+            Instruction::SetCC(Condition::AboveEqual, Operand::Register(Register::R9)),
+            Instruction::SaveVector(3, Operand::Memory(Register::Bp, -128)),
+        ];
+        let expected = [
+            "movb %al, -1(%rbp)",
+            "movsbl %dil, %ecx",
+            "shll %cl, %eax",
+            "leaq count+4(%rip), %r8",
+            "pushq $7",
+            "jmp *%rax",
+            "setae %r9b",
+            "movaps %xmm3, -128(%rbp)",
+        ];
+        let formatted: Vec<String> = instructions
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(expected.to_vec(), formatted);
+    }
+
+    #[test]
+    fn test_static_storage_and_internal_linkage() {
+        let program = Program {
+            statics: vec![
+                StaticVariable {
+                    name: String::from("flag"),
+                    global: true,
+                    width: Width::Byte,
+                    initial: Initializer::Integer(0),
+                },
+                StaticVariable {
+                    name: String::from("next.count.0"),
+                    global: false,
+                    width: Width::Long,
+                    initial: Initializer::Integer(5),
+                },
+            ],
+            strings: vec![StringConstant {
+                label: String::from(".Lstring.0"),
+                value: String::from("hi\\n"),
+            }],
+            functions: vec![Function {
+                name: String::from("next"),
+                global: false,
+                weak: false,
+                instructions: vec![Instruction::Ret],
+            }],
+        };
+        let expected = indoc! {r#"
+            .globl flag
+            .bss
+            .align 1
+            flag:
+            .zero 1
+            .text
+            .data
+            .align 4
+            next.count.0:
+            .long 5
+            .text
+            .section .rodata
+            .Lstring.0:
+            .string "hi\n"
+            .text
+            next:
+            ret
+        "#};
+        assert_eq!(expected, program.to_string());
+    }
+}
@@ -0,0 +1,933 @@
+// Generates x86-64 assembly from the IR. Instructions are first selected with
+// every variable as a pseudo operand, the pseudos are then given registers
+// where the allocator finds them and stack slots otherwise, and finally the
+// instructions x86 can't encode, like a move between two memory operands, are
+// rewritten through the scratch registers %r10 and %r11. A peephole pass
+// tidies up the finished function.
+use std::collections::HashMap;
+
+use crate::ir;
+use crate::syntax::DataType;
+use asm::{Condition, Instruction, Operand, Register, Width};
+
+pub mod asm;
+mod peephole;
+mod regalloc;
+
+// Switches with at least this many cases are considered for a jump table.
+const JUMP_TABLE_MIN_CASES: usize = 4;
+// A jump table may have at most this many slots per case, holes included.
+const JUMP_TABLE_MAX_SLOTS_PER_CASE: usize = 3;
+
+pub fn generate(program: &ir::Program) -> asm::Program {
+    let mut generator = Generator {
+        program,
+        label_count: 0,
+    };
+    asm::Program {
+        statics: program
+            .statics
+            .iter()
+            .map(|variable| asm::StaticVariable {
+                name: variable.name.clone(),
+                global: variable.global,
+                width: Width::of(&variable.data_type),
+                initial: match &variable.initial {
+                    ir::Initializer::Integer(value) => asm::Initializer::Integer(*value),
+                    ir::Initializer::Address(name) => asm::Initializer::Label(label(name)),
+                },
+            })
+            .collect(),
+        strings: program
+            .strings
+            .iter()
+            .map(|string| asm::StringConstant {
+                label: label(&string.name),
+                value: string.value.clone(),
+            })
+            .collect(),
+        functions: program
+            .functions
+            .iter()
+            .map(|function| generator.function(function))
+            .collect(),
+    }
+}
+
+// IR labels and strings become local assembler labels.
+fn label(name: &str) -> String {
+    format!(".L{}", name)
+}
+
+struct Generator<'a> {
+    program: &'a ir::Program,
+    label_count: usize,
+}
+impl Generator<'_> {
+    fn function(&mut self, function: &ir::Function) -> asm::Function {
+        let mut selector = Selector {
+            generator: self,
+            function,
+            instructions: vec![],
+        };
+        // Register arguments are moved to their variables on entry.
+        for (parameter, register) in function.parameters.iter().zip(asm::ARGUMENT_REGISTERS) {
+            let width = Width::of(&selector.data_type(parameter));
+            let destination = selector.operand(parameter);
+            selector.emit(Instruction::Mov(
+                width,
+                Operand::Register(register),
+                destination,
+            ));
+        }
+        for instruction in function.body.iter() {
+            selector.instruction(instruction);
+        }
+        let body = selector.instructions;
+        let (body, saved) = regalloc::allocate_registers(body, function);
+        let (body, mut frame_size) = allocate_stack(body, function, self.program);
+        // Callee saved registers are pushed below the frame, which is padded
+        // to keep %rsp 16 byte aligned.
+        if saved.len() % 2 == 1 {
+            frame_size += 8;
+        }
+        let mut instructions = vec![
+            Instruction::Push(Operand::Register(Register::Bp)),
+            Instruction::Mov(
+                Width::Quad,
+                Operand::Register(Register::Sp),
+                Operand::Register(Register::Bp),
+            ),
+        ];
+        if frame_size > 0 {
+            instructions.push(Instruction::Binary(
+                asm::BinaryOperator::Sub,
+                Width::Quad,
+                Operand::Immediate(frame_size as i64),
+                Operand::Register(Register::Sp),
+            ));
+        }
+        for register in saved.iter() {
+            instructions.push(Instruction::Push(Operand::Register(*register)));
+        }
+        if function.variadic {
+            instructions.extend(self.register_save_area());
+        }
+        for instruction in legalize(body) {
+            if instruction != Instruction::Ret {
+                instructions.push(instruction);
+                continue;
+            }
+            for register in saved.iter().rev() {
+                instructions.push(Instruction::Pop(*register));
+            }
+            instructions.extend([
+                Instruction::Mov(
+                    Width::Quad,
+                    Operand::Register(Register::Bp),
+                    Operand::Register(Register::Sp),
+                ),
+                Instruction::Pop(Register::Bp),
+                Instruction::Ret,
+            ]);
+        }
+        asm::Function {
+            name: function.name.clone(),
+            global: function.global,
+            weak: function.weak,
+            instructions: peephole::optimize(instructions),
+        }
+    }
+
+    fn unique(&mut self, name: &str) -> String {
+        let label = format!(".L{}.{}", name, self.label_count);
+        self.label_count += 1;
+        label
+    }
+
+    // Variadic functions spill every argument register to the register save
+    // area at the bottom of the frame, so va_arg can walk the unnamed ones.
+    // Vector registers are only saved when the caller says in %al that it
+    // used any.
+    fn register_save_area(&mut self) -> Vec<Instruction> {
+        let save_area = -asm::REGISTER_SAVE_AREA_SIZE;
+        let mut instructions: Vec<Instruction> = asm::ARGUMENT_REGISTERS
+            .iter()
+            .enumerate()
+            .map(|(idx, register)| {
+                Instruction::Mov(
+                    Width::Quad,
+                    Operand::Register(*register),
+                    Operand::Memory(Register::Bp, save_area + idx as i32 * 8),
+                )
+            })
+            .collect();
+        let skip = self.unique("va_skip_vector");
+        instructions.push(Instruction::Test(
+            Width::Byte,
+            Operand::Register(Register::Ax),
+            Operand::Register(Register::Ax),
+        ));
+        instructions.push(Instruction::JmpCC(Condition::Equal, skip.clone()));
+        for idx in 0..asm::VECTOR_ARGUMENT_REGISTERS {
+            instructions.push(Instruction::SaveVector(
+                idx,
+                Operand::Memory(Register::Bp, save_area + 48 + idx as i32 * 16),
+            ));
+        }
+        instructions.push(Instruction::Label(skip));
+        instructions
+    }
+}
+
+struct Selector<'a, 'b> {
+    generator: &'a mut Generator<'b>,
+    function: &'a ir::Function,
+    instructions: Vec<Instruction>,
+}
+impl Selector<'_, '_> {
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
+    fn data_type(&self, name: &str) -> DataType {
+        data_type(name, self.function, self.generator.program)
+    }
+
+    fn operand(&self, name: &str) -> Operand {
+        self.field(name, 0)
+    }
+
+    fn field(&self, name: &str, offset: i32) -> Operand {
+        if self.function.variable(name).is_some() {
+            match offset {
+                0 => Operand::Pseudo(String::from(name)),
+                _ => Operand::PseudoMemory(String::from(name), offset),
+            }
+        } else if self
+            .generator
+            .program
+            .strings
+            .iter()
+            .any(|string| string.name == name)
+        {
+            Operand::Data(label(name), offset)
+        } else {
+            Operand::Data(String::from(name), offset)
+        }
+    }
+
+    fn value(&self, value: &ir::Value) -> Operand {
+        match value {
+            ir::Value::Constant(val) => Operand::Immediate(*val as i64),
+            ir::Value::Variable(name) => self.operand(name),
+        }
+    }
+
+    fn value_type(&self, value: &ir::Value) -> DataType {
+        match value {
+            ir::Value::Constant(_) => DataType::Int,
+            ir::Value::Variable(name) => self.data_type(name),
+        }
+    }
+
+    // The value as an int operand, sign extending a char into `scratch`.
+    fn int_value(&mut self, value: &ir::Value, scratch: Register) -> Operand {
+        let operand = self.value(value);
+        if self.value_type(value) != DataType::Char {
+            return operand;
+        }
+        self.emit(Instruction::MovsxByte(operand, Operand::Register(scratch)));
+        Operand::Register(scratch)
+    }
+
+    // Moves the value into `register`, extended to the whole register if it
+    // is narrower than a pointer.
+    fn load(&mut self, value: &ir::Value, register: Register) {
+        let operand = self.value(value);
+        let destination = Operand::Register(register);
+        self.emit(match Width::of(&self.value_type(value)) {
+            Width::Byte => Instruction::MovsxByte(operand, destination),
+            width => Instruction::Mov(width, operand, destination),
+        });
+    }
+
+    // Writing a value to a variable converts it to the variable's type.
+    fn store(&mut self, source: Operand, source_type: &DataType, destination: &str) {
+        let destination_type = self.data_type(destination);
+        let destination = self.operand(destination);
+        self.emit(match (&destination_type, source) {
+            (DataType::Char, Operand::Immediate(val)) => Instruction::Mov(
+                Width::Byte,
+                Operand::Immediate(val as i8 as i64),
+                destination,
+            ),
+            (DataType::Char, source) => Instruction::Mov(Width::Byte, source, destination),
+            (DataType::Int, source) if *source_type == DataType::Char => {
+                Instruction::MovsxByte(source, destination)
+            }
+            (DataType::Int, source) => Instruction::Mov(Width::Long, source, destination),
+            (_, source) => Instruction::Mov(Width::Quad, source, destination),
+        });
+    }
+
+    fn instruction(&mut self, instruction: &ir::Instruction) {
+        match instruction {
+            ir::Instruction::Copy {
+                source,
+                destination,
+            } => {
+                let source_type = self.value_type(source);
+                self.store(self.value(source), &source_type, destination);
+            }
+            ir::Instruction::Unary {
+                operator,
+                source,
+                destination,
+            } => self.unary(*operator, source, destination),
+            ir::Instruction::Binary {
+                operator,
+                left,
+                right,
+                destination,
+            } => self.binary(*operator, left, right, destination),
+            ir::Instruction::GetAddress { name, destination } => {
+                let source = self.operand(name);
+                let destination = self.operand(destination);
+                self.emit(Instruction::Lea(source, destination));
+            }
+            ir::Instruction::Call {
+                name,
+                arguments,
+                destination,
+                variadic,
+            } => self.call(name, arguments, destination, *variadic),
+            ir::Instruction::VaStart { list } => self.va_start(list),
+            ir::Instruction::VaArg {
+                list,
+                data_type,
+                destination,
+            } => self.va_arg(list, data_type, destination),
+            ir::Instruction::Label(name) => self.emit(Instruction::Label(label(name))),
+            ir::Instruction::Jump(target) => self.emit(Instruction::Jmp(label(target))),
+            ir::Instruction::JumpIfZero { condition, target } => {
+                self.jump_if(condition, Condition::Equal, target)
+            }
+            ir::Instruction::JumpIfNotZero { condition, target } => {
+                self.jump_if(condition, Condition::NotEqual, target)
+            }
+            ir::Instruction::Switch {
+                value,
+                cases,
+                default,
+            } => self.switch(value, cases, default),
+            // The epilogue goes in once the registers to restore are known.
+            ir::Instruction::Return(value) => {
+                self.load(value, Register::Ax);
+                self.emit(Instruction::Ret);
+            }
+            ir::Instruction::Phi { destination, .. } => {
+                panic!("phi for {} reached code generation", destination)
+            }
+        }
+    }
+
+    fn unary(&mut self, operator: ir::UnaryOperator, source: &ir::Value, destination: &str) {
+        let source = self.int_value(source, Register::R10);
+        let destination = self.operand(destination);
+        let operator = match operator {
+            ir::UnaryOperator::Negate => asm::UnaryOperator::Neg,
+            ir::UnaryOperator::Complement => asm::UnaryOperator::Not,
+            ir::UnaryOperator::Not => {
+                self.emit(Instruction::Cmp(Width::Long, Operand::Immediate(0), source));
+                self.set(Condition::Equal, destination);
+                return;
+            }
+        };
+        self.emit(Instruction::Mov(Width::Long, source, destination.clone()));
+        self.emit(Instruction::Unary(operator, Width::Long, destination));
+    }
+
+    // Materialises the flags as 0 or 1, without disturbing them first.
+    fn set(&mut self, condition: Condition, destination: Operand) {
+        self.emit(Instruction::Mov(
+            Width::Long,
+            Operand::Immediate(0),
+            destination.clone(),
+        ));
+        self.emit(Instruction::SetCC(condition, destination));
+    }
+
+    fn binary(
+        &mut self,
+        operator: ir::BinaryOperator,
+        left: &ir::Value,
+        right: &ir::Value,
+        destination: &str,
+    ) {
+        use ir::BinaryOperator as Op;
+        let condition = match operator {
+            Op::Add | Op::Subtract | Op::Multiply => {
+                let operator = match operator {
+                    Op::Add => asm::BinaryOperator::Add,
+                    Op::Subtract => asm::BinaryOperator::Sub,
+                    _ => asm::BinaryOperator::Imul,
+                };
+                // The left operand is moved to the result first, so a right
+                // operand that is the result itself is read before that.
+                let overlaps = *right == ir::Value::Variable(String::from(destination));
+                let result = if overlaps {
+                    Operand::Register(Register::R11)
+                } else {
+                    self.operand(destination)
+                };
+                let left = self.int_value(left, Register::R11);
+                self.emit(Instruction::Mov(Width::Long, left, result.clone()));
+                let right = self.int_value(right, Register::R10);
+                self.emit(Instruction::Binary(
+                    operator,
+                    Width::Long,
+                    right,
+                    result.clone(),
+                ));
+                if overlaps {
+                    let destination = self.operand(destination);
+                    self.emit(Instruction::Mov(Width::Long, result, destination));
+                }
+                return;
+            }
+            Op::Divide | Op::Remainder => {
+                self.load(left, Register::Ax);
+                self.emit(Instruction::Cdq);
+                let right = self.int_value(right, Register::R10);
+                self.emit(Instruction::Idiv(Width::Long, right));
+                let result = match operator {
+                    Op::Divide => Register::Ax,
+                    _ => Register::Dx,
+                };
+                let destination = self.operand(destination);
+                self.emit(Instruction::Mov(
+                    Width::Long,
+                    Operand::Register(result),
+                    destination,
+                ));
+                return;
+            }
+            Op::Equal => Condition::Equal,
+            Op::NotEqual => Condition::NotEqual,
+            Op::LessThan => Condition::Less,
+            Op::LessEqual => Condition::LessEqual,
+            Op::GreaterThan => Condition::Greater,
+            Op::GreaterEqual => Condition::GreaterEqual,
+        };
+        let left = self.int_value(left, Register::R11);
+        let right = self.int_value(right, Register::R10);
+        self.emit(Instruction::Cmp(Width::Long, right, left));
+        let destination = self.operand(destination);
+        self.set(condition, destination);
+    }
+
+    fn jump_if(&mut self, condition: &ir::Value, jump: Condition, target: &str) {
+        let condition = self.int_value(condition, Register::R10);
+        self.emit(Instruction::Cmp(
+            Width::Long,
+            Operand::Immediate(0),
+            condition,
+        ));
+        self.emit(Instruction::JmpCC(jump, label(target)));
+    }
+
+    // The first six arguments go in registers and the rest on the stack,
+    // pushed right to left with %rsp kept 16 byte aligned at the call.
+    fn call(&mut self, name: &str, arguments: &[ir::Value], destination: &str, variadic: bool) {
+        let register_count = arguments.len().min(asm::ARGUMENT_REGISTERS.len());
+        let stack_arguments = &arguments[register_count..];
+        let padding = if stack_arguments.len() % 2 == 1 { 8 } else { 0 };
+        if padding != 0 {
+            self.emit(Instruction::Binary(
+                asm::BinaryOperator::Sub,
+                Width::Quad,
+                Operand::Immediate(padding),
+                Operand::Register(Register::Sp),
+            ));
+        }
+        for argument in stack_arguments.iter().rev() {
+            match self.value_type(argument) {
+                DataType::Pointer(_) | DataType::VaList => {
+                    let operand = self.value(argument);
+                    self.emit(Instruction::Push(operand));
+                }
+                _ if matches!(argument, ir::Value::Constant(_)) => {
+                    let operand = self.value(argument);
+                    self.emit(Instruction::Push(operand));
+                }
+                _ => {
+                    self.load(argument, Register::Ax);
+                    self.emit(Instruction::Push(Operand::Register(Register::Ax)));
+                }
+            }
+        }
+        for (argument, register) in arguments.iter().zip(asm::ARGUMENT_REGISTERS.iter()) {
+            self.load(argument, *register);
+        }
+        // Variadic callees read %al to find how many vector registers hold
+        // arguments. No argument type is passed in a vector register yet.
+        if variadic {
+            self.emit(Instruction::Mov(
+                Width::Long,
+                Operand::Immediate(0),
+                Operand::Register(Register::Ax),
+            ));
+        }
+        self.emit(Instruction::Call(String::from(name), register_count));
+        let cleanup = stack_arguments.len() as i64 * 8 + padding;
+        if cleanup != 0 {
+            self.emit(Instruction::Binary(
+                asm::BinaryOperator::Add,
+                Width::Quad,
+                Operand::Immediate(cleanup),
+                Operand::Register(Register::Sp),
+            ));
+        }
+        self.store(Operand::Register(Register::Ax), &DataType::Int, destination);
+    }
+
+    // Fills in gp_offset, fp_offset, overflow_arg_area and reg_save_area.
+    fn va_start(&mut self, list: &str) {
+        let parameters = self.function.parameters.len();
+        let named_registers = parameters.min(asm::ARGUMENT_REGISTERS.len());
+        let named_stack = parameters - named_registers;
+        self.emit(Instruction::Mov(
+            Width::Long,
+            Operand::Immediate(named_registers as i64 * 8),
+            self.field(list, 0),
+        ));
+        self.emit(Instruction::Mov(
+            Width::Long,
+            Operand::Immediate(asm::ARGUMENT_REGISTERS.len() as i64 * 8),
+            self.field(list, 4),
+        ));
+        self.emit(Instruction::Lea(
+            Operand::Memory(Register::Bp, 16 + named_stack as i32 * 8),
+            Operand::Register(Register::Ax),
+        ));
+        self.emit(Instruction::Mov(
+            Width::Quad,
+            Operand::Register(Register::Ax),
+            self.field(list, 8),
+        ));
+        self.emit(Instruction::Lea(
+            Operand::Memory(Register::Bp, -asm::REGISTER_SAVE_AREA_SIZE),
+            Operand::Register(Register::Ax),
+        ));
+        self.emit(Instruction::Mov(
+            Width::Quad,
+            Operand::Register(Register::Ax),
+            self.field(list, 16),
+        ));
+    }
+
+    // Takes the next argument from the register save area until the six
+    // general purpose registers are used up, then from the overflow area.
+    fn va_arg(&mut self, list: &str, data_type: &DataType, destination: &str) {
+        let stack = self.generator.unique("va_stack");
+        let fetch = self.generator.unique("va_fetch");
+        let cx = Operand::Register(Register::Cx);
+        let ax = Operand::Register(Register::Ax);
+        let instructions = vec![
+            Instruction::Mov(Width::Long, self.field(list, 0), cx.clone()),
+            Instruction::Cmp(
+                Width::Long,
+                Operand::Immediate(asm::ARGUMENT_REGISTERS.len() as i64 * 8),
+                cx.clone(),
+            ),
+            Instruction::JmpCC(Condition::AboveEqual, stack.clone()),
+            Instruction::Mov(Width::Quad, self.field(list, 16), ax.clone()),
+            Instruction::Binary(
+                asm::BinaryOperator::Add,
+                Width::Quad,
+                cx.clone(),
+                ax.clone(),
+            ),
+            Instruction::Binary(
+                asm::BinaryOperator::Add,
+                Width::Long,
+                Operand::Immediate(8),
+                self.field(list, 0),
+            ),
+            Instruction::Jmp(fetch.clone()),
+            Instruction::Label(stack),
+            Instruction::Mov(Width::Quad, self.field(list, 8), ax.clone()),
+            Instruction::Lea(Operand::Memory(Register::Ax, 8), cx.clone()),
+            Instruction::Mov(Width::Quad, cx, self.field(list, 8)),
+            Instruction::Label(fetch),
+        ];
+        self.instructions.extend(instructions);
+        let width = Width::of(data_type);
+        self.emit(Instruction::Mov(
+            width,
+            Operand::Memory(Register::Ax, 0),
+            Operand::Register(Register::Ax),
+        ));
+        self.store(Operand::Register(Register::Ax), data_type, destination);
+    }
+
+    fn switch(&mut self, value: &ir::Value, cases: &[(i32, String)], default: &str) {
+        let values: Vec<i64> = cases.iter().map(|(case, _)| *case as i64).collect();
+        let (min, max) = match (values.iter().min(), values.iter().max()) {
+            (Some(min), Some(max)) => (*min, *max),
+            _ => (0, 0),
+        };
+        let slots = (max - min + 1) as usize;
+        if cases.len() >= JUMP_TABLE_MIN_CASES
+            && slots <= cases.len() * JUMP_TABLE_MAX_SLOTS_PER_CASE
+        {
+            self.jump_table(value, cases, default, min, max);
+            return;
+        }
+        let operand = self.int_value(value, Register::R10);
+        for (case, target) in cases.iter() {
+            self.emit(Instruction::Cmp(
+                Width::Long,
+                Operand::Immediate(*case as i64),
+                operand.clone(),
+            ));
+            self.emit(Instruction::JmpCC(Condition::Equal, label(target)));
+        }
+        self.emit(Instruction::Jmp(label(default)));
+    }
+
+    fn jump_table(
+        &mut self,
+        value: &ir::Value,
+        cases: &[(i32, String)],
+        default: &str,
+        min: i64,
+        max: i64,
+    ) {
+        let table = self.generator.unique("switch_table");
+        let targets = (min..=max)
+            .map(|slot| {
+                cases
+                    .iter()
+                    .find(|(case, _)| *case as i64 == slot)
+                    .map(|(_, target)| label(target))
+                    .unwrap_or_else(|| label(default))
+            })
+            .collect();
+        let ax = Operand::Register(Register::Ax);
+        let cx = Operand::Register(Register::Cx);
+        self.load(value, Register::Ax);
+        self.instructions.extend([
+            Instruction::Binary(
+                asm::BinaryOperator::Sub,
+                Width::Long,
+                Operand::Immediate(min),
+                ax.clone(),
+            ),
+            Instruction::Cmp(Width::Long, Operand::Immediate(max - min), ax.clone()),
+            Instruction::JmpCC(Condition::Above, label(default)),
+            Instruction::Lea(Operand::Data(table.clone(), 0), cx.clone()),
+            Instruction::MovsxLong(
+                Operand::Indexed {
+                    base: Register::Cx,
+                    index: Register::Ax,
+                    scale: 4,
+                },
+                ax.clone(),
+            ),
+            Instruction::Binary(asm::BinaryOperator::Add, Width::Quad, cx, ax.clone()),
+            Instruction::JmpIndirect(ax),
+            Instruction::JumpTable(table, targets),
+        ]);
+    }
+}
+
+fn data_type(name: &str, function: &ir::Function, program: &ir::Program) -> DataType {
+    function
+        .variable(name)
+        .map(|variable| variable.data_type.clone())
+        .or_else(|| {
+            program
+                .static_variable(name)
+                .map(|variable| variable.data_type.clone())
+        })
+        .unwrap_or_else(|| panic!("Use of undeclared identifier '{}'", name))
+}
+
+// Gives every pseudo left a slot below %rbp, under the register save area of a
+// variadic function, and returns the frame size, kept 16 byte aligned.
+// Parameters the caller passed on the stack stay where they are.
+fn allocate_stack(
+    mut instructions: Vec<Instruction>,
+    function: &ir::Function,
+    program: &ir::Program,
+) -> (Vec<Instruction>, i32) {
+    let mut homes: HashMap<String, i32> = HashMap::new();
+    for (idx, parameter) in function.parameters.iter().enumerate() {
+        if idx >= asm::ARGUMENT_REGISTERS.len() {
+            let stack_idx = (idx - asm::ARGUMENT_REGISTERS.len()) as i32;
+            homes.insert(parameter.clone(), 16 + stack_idx * 8);
+        }
+    }
+    let mut size = if function.variadic {
+        asm::REGISTER_SAVE_AREA_SIZE
+    } else {
+        0
+    };
+    let mut home = |name: &str| -> i32 {
+        *homes.entry(String::from(name)).or_insert_with(|| {
+            let data_type = data_type(name, function, program);
+            let alignment = data_type.alignment();
+            size = (size + data_type.size() + alignment - 1) / alignment * alignment;
+            -size
+        })
+    };
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            match operand {
+                Operand::Pseudo(name) => *operand = Operand::Memory(Register::Bp, home(name)),
+                Operand::PseudoMemory(name, offset) => {
+                    *operand = Operand::Memory(Register::Bp, home(name) + *offset)
+                }
+                _ => {}
+            }
+        }
+    }
+    (instructions, (size + 15) / 16 * 16)
+}
+
+// Rewrites what x86 can't encode: two memory operands in one instruction, a
+// memory destination for imul, movsx or lea, an immediate divisor, or an
+// immediate on the right of a compare.
+fn legalize(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let r10 = Operand::Register(Register::R10);
+    let r11 = Operand::Register(Register::R11);
+    let mut legal = vec![];
+    for instruction in instructions {
+        match instruction {
+            Instruction::Mov(width, source, destination)
+                if source.is_memory() && destination.is_memory() =>
+            {
+                legal.push(Instruction::Mov(width, source, r10.clone()));
+                legal.push(Instruction::Mov(width, r10.clone(), destination));
+            }
+            Instruction::MovsxByte(source, destination) if !is_register(&destination) => {
+                legal.push(Instruction::MovsxByte(source, r11.clone()));
+                legal.push(Instruction::Mov(Width::Long, r11.clone(), destination));
+            }
+            Instruction::Lea(source, destination) if !is_register(&destination) => {
+                legal.push(Instruction::Lea(source, r11.clone()));
+                legal.push(Instruction::Mov(Width::Quad, r11.clone(), destination));
+            }
+            Instruction::Binary(asm::BinaryOperator::Imul, width, source, destination)
+                if !is_register(&destination) =>
+            {
+                legal.push(Instruction::Mov(width, destination.clone(), r11.clone()));
+                legal.push(Instruction::Binary(
+                    asm::BinaryOperator::Imul,
+                    width,
+                    source,
+                    r11.clone(),
+                ));
+                legal.push(Instruction::Mov(width, r11.clone(), destination));
+            }
+            Instruction::Binary(operator, width, source, destination)
+                if source.is_memory() && destination.is_memory() =>
+            {
+                legal.push(Instruction::Mov(width, source, r10.clone()));
+                legal.push(Instruction::Binary(
+                    operator,
+                    width,
+                    r10.clone(),
+                    destination,
+                ));
+            }
+            Instruction::Idiv(width, Operand::Immediate(val)) => {
+                legal.push(Instruction::Mov(
+                    width,
+                    Operand::Immediate(val),
+                    r10.clone(),
+                ));
+                legal.push(Instruction::Idiv(width, r10.clone()));
+            }
+            Instruction::Cmp(width, left, Operand::Immediate(val)) => {
+                legal.push(Instruction::Mov(
+                    width,
+                    Operand::Immediate(val),
+                    r11.clone(),
+                ));
+                legal.push(Instruction::Cmp(width, left, r11.clone()));
+            }
+            Instruction::Cmp(width, left, right) if left.is_memory() && right.is_memory() => {
+                legal.push(Instruction::Mov(width, left, r10.clone()));
+                legal.push(Instruction::Cmp(width, r10.clone(), right));
+            }
+            instruction => legal.push(instruction),
+        }
+    }
+    legal
+}
+
+fn is_register(operand: &Operand) -> bool {
+    matches!(operand, Operand::Register(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use crate::ir::lower::lower_source;
+    use indoc::indoc;
+
+    fn assembly(source: &str) -> String {
+        generate(&lower_source(source)).to_string()
+    }
+
+    fn switch(cases: &[i32], with_default: bool) -> String {
+        let mut body: String = cases
+            .iter()
+            .map(|case| format!("case {}: return {};\n", case, case * 10))
+            .collect();
+        if with_default {
+            body.push_str("default: break;\n");
+        }
+        format!("int f(int n) {{ switch (n) {{\n{}}} return 0; }}", body)
+    }
+
+    #[test]
+    fn test_switch_with_compare_chain() {
+        let expected = indoc! {"
+            cmpl $1, %edi
+            je .Lcase.1
+            cmpl $2, %edi
+            je .Lcase.2
+            jmp .Ldefault.3
+            .Lcase.1:
+            movl $10, %eax
+        "};
+        let output = assembly(&switch(&[1, 2], true));
+        assert!(output.contains(expected), "{}", output);
+        assert!(
+            output.contains(".Ldefault.3:\n.Lswitch_end.0:\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_switch_without_default_falls_through_to_end() {
+        let expected = indoc! {"
+            cmpl $5, %edi
+            je .Lcase.1
+            jmp .Lswitch_end.0
+        "};
+        let output = assembly(&switch(&[5], false));
+        assert!(output.contains(expected), "{}", output);
+    }
+
+    #[test]
+    fn test_switch_with_jump_table() {
+        let expected = indoc! {"
+            movl %edi, %eax
+            subl $1, %eax
+            cmpl $4, %eax
+            ja .Lswitch_end.0
+            leaq .Lswitch_table.0(%rip), %rcx
+            movslq (%rcx,%rax,4), %rax
+            addq %rcx, %rax
+            jmp *%rax
+            .section .rodata
+            .align 4
+            .Lswitch_table.0:
+            .long .Lcase.2-.Lswitch_table.0
+            .long .Lcase.3-.Lswitch_table.0
+            .long .Lcase.1-.Lswitch_table.0
+            .long .Lswitch_end.0-.Lswitch_table.0
+            .long .Lcase.4-.Lswitch_table.0
+            .text
+        "};
+        let output = assembly(&switch(&[3, 1, 2, 5], false));
+        assert!(output.contains(expected), "{}", output);
+    }
+
+    #[test]
+    fn test_switch_density_picks_dispatch() {
+        let uses_jump_table = |cases: &[i32]| assembly(&switch(cases, true)).contains("jmp *");
+        assert!(uses_jump_table(&[1, 2, 3, 4]));
+        assert!(!uses_jump_table(&[1, 10, 100, 1000]));
+        assert!(!uses_jump_table(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn test_goto_and_label() {
+        let output = assembly(indoc! {"
+            int f(int n) {
+            again:
+                switch (n) {
+                case 0:
+                    return 7;
+                }
+                n = n - 1;
+                goto again;
+            }
+        "});
+        assert!(
+            output.contains("movq %rsp, %rbp\n.Lf.again:\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("subl $1, %edi\njmp .Lf.again\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_call_aligns_stack_and_passes_stack_arguments() {
+        let expected = indoc! {"
+            subq $8, %rsp
+            pushq $7
+            movl $1, %edi
+            movl $2, %esi
+            movl $3, %edx
+            movl $4, %ecx
+            movl $5, %r8d
+            movl $6, %r9d
+            call g
+            addq $16, %rsp
+        "};
+        let output = assembly(
+            "int g(int a, int b, int c, int d, int e, int f, int h);
+            int f(void) { return g(1, 2, 3, 4, 5, 6, 7); }",
+        );
+        assert!(output.contains(expected), "{}", output);
+    }
+
+    #[test]
+    fn test_static_storage_and_internal_linkage() {
+        let expected = indoc! {"
+            .globl flag
+            .bss
+            .align 1
+            flag:
+            .zero 1
+            .text
+            .data
+            .align 4
+            next.count.0:
+            .long 5
+            .text
+            next:
+            pushq %rbp
+            movq %rsp, %rbp
+            movl next.count.0(%rip), %eax
+        "};
+        let output = assembly(
+            "char flag;
+            static int next(void) { static int count = 5; return count; }
+            int main(void) { return next(); }",
+        );
+        assert!(output.starts_with(expected), "{}", output);
+    }
+}
@@ -0,0 +1,190 @@
+// Peephole optimization of a function's finished instructions, looking at
+// each one next to what's been kept before it: a move straight back to where
+// a value came from, a register pushed and popped again, a jump to the
+// instruction that follows anyway, and a multiplication by a power of two,
+// which a shift does more cheaply.
+use super::asm::{BinaryOperator, Instruction, Operand, Register};
+
+pub fn optimize(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut kept: Vec<Instruction> = vec![];
+    for instruction in instructions {
+        match (kept.last(), instruction) {
+            (
+                Some(Instruction::Mov(previous_width, previous_source, previous_destination)),
+                Instruction::Mov(width, source, destination),
+            ) if *previous_width == width
+                && *previous_source == destination
+                && *previous_destination == source
+                && !addresses_through(&destination, &source) => {}
+            (Some(Instruction::Push(Operand::Register(pushed))), Instruction::Pop(popped))
+                if *pushed == popped =>
+            {
+                kept.pop();
+            }
+            (_, Instruction::Label(label)) => {
+                // Other labels may come between the jump and its target.
+                let jump = kept
+                    .iter()
+                    .rposition(|instruction| !matches!(instruction, Instruction::Label(_)));
+                if let Some(idx) = jump {
+                    if let Instruction::Jmp(target) | Instruction::JmpCC(_, target) = &kept[idx] {
+                        if *target == label {
+                            kept.remove(idx);
+                        }
+                    }
+                }
+                kept.push(Instruction::Label(label));
+            }
+            (
+                _,
+                Instruction::Binary(
+                    BinaryOperator::Imul,
+                    width,
+                    Operand::Immediate(factor),
+                    destination,
+                ),
+            ) if factor > 1 && factor & (factor - 1) == 0 => {
+                kept.push(Instruction::Binary(
+                    BinaryOperator::Shl,
+                    width,
+                    Operand::Immediate(factor.trailing_zeros() as i64),
+                    destination,
+                ));
+            }
+            (_, instruction) => kept.push(instruction),
+        }
+    }
+    kept
+}
+
+// Whether writing the register changes the address of the memory operand, so
+// moving back to it writes somewhere else.
+fn addresses_through(memory: &Operand, register: &Operand) -> bool {
+    let Operand::Register(register) = register else {
+        return false;
+    };
+    let uses = |used: &Register| used == register;
+    match memory {
+        Operand::Memory(base, _) => uses(base),
+        Operand::Indexed { base, index, .. } => uses(base) || uses(index),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::codegen::asm::{BinaryOperator, Condition, Instruction, Operand, Register, Width};
+    use crate::codegen::generate;
+    use crate::ir::lower::lower_source;
+
+    fn listing(instructions: &[Instruction]) -> String {
+        instructions
+            .iter()
+            .map(|instruction| format!("{}\n", instruction))
+            .collect()
+    }
+
+    fn register(register: Register) -> Operand {
+        Operand::Register(register)
+    }
+
+    #[test]
+    fn test_optimize() {
+        let before = vec![
+            Instruction::Mov(Width::Long, register(Register::Di), register(Register::Ax)),
+            Instruction::Mov(Width::Long, register(Register::Ax), register(Register::Di)),
+            Instruction::Push(register(Register::Bx)),
+            Instruction::Pop(Register::Bx),
+            Instruction::Binary(
+                BinaryOperator::Imul,
+                Width::Long,
+                Operand::Immediate(8),
+                register(Register::Ax),
+            ),
+            Instruction::Binary(
+                BinaryOperator::Imul,
+                Width::Long,
+                Operand::Immediate(6),
+                register(Register::Ax),
+            ),
+            Instruction::Jmp(String::from(".Lnext")),
+            Instruction::Label(String::from(".Lother")),
+            Instruction::Label(String::from(".Lnext")),
+            Instruction::Cmp(Width::Long, Operand::Immediate(0), register(Register::Ax)),
+            Instruction::JmpCC(Condition::Equal, String::from(".Lend")),
+            Instruction::Label(String::from(".Lend")),
+            Instruction::Ret,
+        ];
+        assert_eq!(
+            "\
+movl %edi, %eax
+movl %eax, %edi
+pushq %rbx
+popq %rbx
+imull $8, %eax
+imull $6, %eax
+jmp .Lnext
+.Lother:
+.Lnext:
+cmpl $0, %eax
+je .Lend
+.Lend:
+ret
+",
+            listing(&before)
+        );
+        assert_eq!(
+            "\
+movl %edi, %eax
+shll $3, %eax
+imull $6, %eax
+.Lother:
+.Lnext:
+cmpl $0, %eax
+.Lend:
+ret
+",
+            listing(&optimize(before))
+        );
+    }
+
+    #[test]
+    fn test_optimize_keeps_what_matters() {
+        let before = vec![
+            // The second move goes through the %rax the first one changed.
+            Instruction::Mov(
+                Width::Quad,
+                Operand::Memory(Register::Ax, 8),
+                register(Register::Ax),
+            ),
+            Instruction::Mov(
+                Width::Quad,
+                register(Register::Ax),
+                Operand::Memory(Register::Ax, 8),
+            ),
+            Instruction::Mov(Width::Byte, register(Register::Di), register(Register::Ax)),
+            Instruction::Mov(Width::Long, register(Register::Ax), register(Register::Di)),
+            Instruction::Push(register(Register::Bx)),
+            Instruction::Pop(Register::R12),
+            Instruction::Jmp(String::from(".Lend")),
+            Instruction::Label(String::from(".Lnext")),
+            Instruction::Binary(
+                BinaryOperator::Imul,
+                Width::Long,
+                Operand::Immediate(1),
+                register(Register::Ax),
+            ),
+            Instruction::Label(String::from(".Lend")),
+        ];
+        let expected = listing(&before);
+        assert_eq!(expected, listing(&optimize(before)));
+    }
+
+    #[test]
+    fn test_multiplications_become_shifts() {
+        let output = generate(&lower_source("int f(int a) { return a * 16; }")).to_string();
+        assert!(output.contains("shll $4, %edi"), "{}", output);
+        assert!(!output.contains("imul"), "{}", output);
+    }
+}
@@ -0,0 +1,544 @@
+// Register allocation by graph colouring. Two values interfere when one is
+// written while the other is still live, which liveness over the selected
+// instructions finds; the hard registers selection already uses, for
+// arguments, division, calls and so on, are nodes of the graph too.
+//
+// Moves between values that don't interfere are coalesced first, as long as
+// the merged node can't make the graph harder to colour, and what's left is
+// coloured by simplifying nodes with fewer neighbours than there are
+// registers. A node that can't be coloured keeps its pseudo and gets a stack
+// slot like before.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::asm::{self, Instruction, Operand, Register, Width};
+use crate::ir;
+use crate::syntax::DataType;
+
+// Caller saved registers come first, so a value that isn't live across a
+// call doesn't take a callee saved one, which would have to be saved and
+// restored. %r10 and %r11 are left for legalizing spilled operands.
+const ALLOCATABLE: [Register; 12] = [
+    Register::Ax,
+    Register::Cx,
+    Register::Dx,
+    Register::Si,
+    Register::Di,
+    Register::R8,
+    Register::R9,
+    Register::Bx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+enum Node {
+    Register(Register),
+    Pseudo(String),
+}
+
+type Graph = BTreeMap<Node, BTreeSet<Node>>;
+
+// Gives the pseudos of `instructions` registers where it can, and returns
+// the callee saved registers the function now has to preserve.
+pub fn allocate_registers(
+    mut instructions: Vec<Instruction>,
+    function: &ir::Function,
+) -> (Vec<Instruction>, Vec<Register>) {
+    // Values that live in memory, and parameters the caller left on the
+    // stack, keep their slots.
+    let address_taken = function.address_taken();
+    let stack_parameters: Vec<&String> = function
+        .parameters
+        .iter()
+        .skip(asm::ARGUMENT_REGISTERS.len())
+        .collect();
+    let types: HashMap<String, DataType> = function
+        .variables
+        .iter()
+        .filter(|variable| {
+            matches!(
+                variable.data_type,
+                DataType::Int | DataType::Char | DataType::Pointer(_)
+            ) && !address_taken.contains(variable.name.as_str())
+                && !stack_parameters.contains(&&variable.name)
+        })
+        .map(|variable| (variable.name.clone(), variable.data_type.clone()))
+        .collect();
+
+    while coalesce(&mut instructions, &types) {}
+    let graph = interference(&instructions, &types);
+    let colours = colour(&graph, &instructions);
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Operand::Pseudo(name) = operand {
+                if let Some(register) = colours.get(name) {
+                    *operand = Operand::Register(*register);
+                }
+            }
+        }
+    }
+    instructions.retain(|instruction| {
+        !matches!(instruction, Instruction::Mov(_, source, destination) if source == destination)
+    });
+
+    let mut used = HashSet::new();
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Operand::Register(register) = operand {
+                used.insert(*register);
+            }
+        }
+    }
+    let saved = asm::CALLEE_SAVED_REGISTERS
+        .iter()
+        .copied()
+        .filter(|register| used.contains(register))
+        .collect();
+    (instructions, saved)
+}
+
+// The node an operand read as a value is, if it's one that's allocated.
+fn node(operand: &Operand, types: &HashMap<String, DataType>) -> Option<Node> {
+    match operand {
+        Operand::Register(Register::Bp | Register::Sp) => None,
+        Operand::Register(register) => Some(Node::Register(*register)),
+        Operand::Pseudo(name) if types.contains_key(name) => Some(Node::Pseudo(name.clone())),
+        _ => None,
+    }
+}
+
+// The registers an operand's address is made of.
+fn address(operand: &Operand) -> Vec<Node> {
+    let registers = match operand {
+        Operand::Memory(base, _) => vec![*base],
+        Operand::Indexed { base, index, .. } => vec![*base, *index],
+        _ => vec![],
+    };
+    registers
+        .into_iter()
+        .filter(|register| !matches!(register, Register::Bp | Register::Sp))
+        .map(Node::Register)
+        .collect()
+}
+
+fn read(operand: &Operand, types: &HashMap<String, DataType>) -> Vec<Node> {
+    let mut nodes = address(operand);
+    nodes.extend(node(operand, types));
+    nodes
+}
+
+// The nodes an instruction reads and those it writes.
+fn uses_and_defs(
+    instruction: &Instruction,
+    types: &HashMap<String, DataType>,
+) -> (Vec<Node>, Vec<Node>) {
+    let register = |register: Register| Node::Register(register);
+    match instruction {
+        Instruction::Mov(_, source, destination)
+        | Instruction::MovsxByte(source, destination)
+        | Instruction::MovsxLong(source, destination) => {
+            let mut uses = read(source, types);
+            uses.extend(address(destination));
+            (uses, node(destination, types).into_iter().collect())
+        }
+        Instruction::Lea(source, destination) => {
+            let mut uses = address(source);
+            uses.extend(address(destination));
+            (uses, node(destination, types).into_iter().collect())
+        }
+        Instruction::Unary(_, _, operand) | Instruction::SetCC(_, operand) => (
+            read(operand, types),
+            node(operand, types).into_iter().collect(),
+        ),
+        Instruction::Binary(_, _, source, destination) => {
+            let mut uses = read(source, types);
+            uses.extend(read(destination, types));
+            (uses, node(destination, types).into_iter().collect())
+        }
+        Instruction::Cmp(_, left, right) | Instruction::Test(_, left, right) => {
+            let mut uses = read(left, types);
+            uses.extend(read(right, types));
+            (uses, vec![])
+        }
+        Instruction::Idiv(_, operand) => {
+            let mut uses = read(operand, types);
+            uses.extend([register(Register::Ax), register(Register::Dx)]);
+            (uses, vec![register(Register::Ax), register(Register::Dx)])
+        }
+        Instruction::Cdq => (vec![register(Register::Ax)], vec![register(Register::Dx)]),
+        Instruction::Push(operand)
+        | Instruction::JmpIndirect(operand)
+        | Instruction::SaveVector(_, operand) => (read(operand, types), vec![]),
+        Instruction::Pop(popped) => (vec![], vec![register(*popped)]),
+        Instruction::Call(_, arguments) => (
+            asm::ARGUMENT_REGISTERS[..*arguments]
+                .iter()
+                .copied()
+                .map(register)
+                .collect(),
+            asm::CALLER_SAVED_REGISTERS
+                .iter()
+                .copied()
+                .map(register)
+                .collect(),
+        ),
+        Instruction::Ret => (vec![register(Register::Ax)], vec![]),
+        Instruction::Jmp(_)
+        | Instruction::JmpCC(..)
+        | Instruction::Label(_)
+        | Instruction::JumpTable(..) => (vec![], vec![]),
+    }
+}
+
+// The live nodes after every instruction, from liveness over the basic
+// blocks the labels and jumps divide the instructions into.
+fn live_after(
+    instructions: &[Instruction],
+    types: &HashMap<String, DataType>,
+) -> Vec<BTreeSet<Node>> {
+    let mut starts = vec![0];
+    for (idx, instruction) in instructions.iter().enumerate() {
+        let ends_block = matches!(
+            instruction,
+            Instruction::Jmp(_)
+                | Instruction::JmpCC(..)
+                | Instruction::JmpIndirect(_)
+                | Instruction::Ret
+        );
+        if matches!(instruction, Instruction::Label(_)) && *starts.last().unwrap() != idx {
+            starts.push(idx);
+        }
+        if ends_block && idx + 1 < instructions.len() {
+            starts.push(idx + 1);
+        }
+    }
+    let ends: Vec<usize> = starts
+        .iter()
+        .skip(1)
+        .copied()
+        .chain([instructions.len()])
+        .collect();
+    let blocks: HashMap<&str, usize> = starts
+        .iter()
+        .enumerate()
+        .filter_map(|(block, start)| match instructions.get(*start) {
+            Some(Instruction::Label(label)) => Some((label.as_str(), block)),
+            _ => None,
+        })
+        .collect();
+    let successors: Vec<Vec<usize>> = ends
+        .iter()
+        .enumerate()
+        .map(|(block, end)| {
+            let next = || (block + 1 < starts.len()).then_some(block + 1);
+            match instructions.get(end.wrapping_sub(1)) {
+                Some(Instruction::Jmp(target)) => vec![blocks[target.as_str()]],
+                Some(Instruction::JmpCC(_, target)) => {
+                    let mut successors = vec![blocks[target.as_str()]];
+                    successors.extend(next());
+                    successors
+                }
+                Some(Instruction::JmpIndirect(_)) => match instructions.get(*end) {
+                    Some(Instruction::JumpTable(_, targets)) => targets
+                        .iter()
+                        .map(|target| blocks[target.as_str()])
+                        .collect(),
+                    _ => vec![],
+                },
+                Some(Instruction::Ret) => vec![],
+                _ => next().into_iter().collect(),
+            }
+        })
+        .collect();
+
+    let facts: Vec<(Vec<Node>, Vec<Node>)> = instructions
+        .iter()
+        .map(|instruction| uses_and_defs(instruction, types))
+        .collect();
+    let transfer = |live: &mut BTreeSet<Node>, idx: usize| {
+        let (uses, defs) = &facts[idx];
+        for def in defs {
+            live.remove(def);
+        }
+        live.extend(uses.iter().cloned());
+    };
+    let mut live_in: Vec<BTreeSet<Node>> = vec![BTreeSet::new(); starts.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in (0..starts.len()).rev() {
+            let mut live: BTreeSet<Node> = successors[block]
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().cloned())
+                .collect();
+            for idx in (starts[block]..ends[block]).rev() {
+                transfer(&mut live, idx);
+            }
+            if live != live_in[block] {
+                live_in[block] = live;
+                changed = true;
+            }
+        }
+    }
+    let mut after = vec![BTreeSet::new(); instructions.len()];
+    for block in 0..starts.len() {
+        let mut live: BTreeSet<Node> = successors[block]
+            .iter()
+            .flat_map(|successor| live_in[*successor].iter().cloned())
+            .collect();
+        for idx in (starts[block]..ends[block]).rev() {
+            after[idx] = live.clone();
+            transfer(&mut live, idx);
+        }
+    }
+    after
+}
+
+// A value written interferes with everything live after it, except the
+// source of a move, which holds the same value.
+fn interference(instructions: &[Instruction], types: &HashMap<String, DataType>) -> Graph {
+    let mut graph = Graph::new();
+    for instruction in instructions.iter() {
+        let (uses, defs) = uses_and_defs(instruction, types);
+        for node in uses.into_iter().chain(defs) {
+            graph.entry(node).or_default();
+        }
+    }
+    for (instruction, live) in instructions.iter().zip(live_after(instructions, types)) {
+        let source = match instruction {
+            Instruction::Mov(_, source, _) => node(source, types),
+            _ => None,
+        };
+        for def in uses_and_defs(instruction, types).1 {
+            for other in live.iter() {
+                if *other != def && Some(other) != source.as_ref() {
+                    graph.get_mut(&def).unwrap().insert(other.clone());
+                    graph.get_mut(other).unwrap().insert(def.clone());
+                }
+            }
+        }
+    }
+    graph
+}
+
+// Nodes with at least this many neighbours might not get a register.
+fn significant(graph: &Graph, node: &Node) -> bool {
+    match node {
+        Node::Register(_) => true,
+        Node::Pseudo(_) => graph[node].len() >= ALLOCATABLE.len(),
+    }
+}
+
+// Merges the two sides of moves whose merging is safe: Briggs' test for two
+// pseudos, that the merged node has fewer significant neighbours than there
+// are registers, and George's for a pseudo and a register, that every
+// significant neighbour of the pseudo already interferes with the register.
+// Returns whether anything was merged, after rewriting the instructions.
+fn coalesce(instructions: &mut Vec<Instruction>, types: &HashMap<String, DataType>) -> bool {
+    let mut graph = interference(instructions, types);
+    let mut merged: HashMap<String, Node> = HashMap::new();
+    let find = |merged: &HashMap<String, Node>, mut node: Node| {
+        while let Node::Pseudo(name) = &node {
+            match merged.get(name) {
+                Some(other) => node = other.clone(),
+                None => break,
+            }
+        }
+        node
+    };
+    for instruction in instructions.iter() {
+        let Instruction::Mov(width, source, destination) = instruction else {
+            continue;
+        };
+        let (Some(source), Some(destination)) = (node(source, types), node(destination, types))
+        else {
+            continue;
+        };
+        let (source, destination) = (find(&merged, source), find(&merged, destination));
+        if source == destination || graph[&source].contains(&destination) {
+            continue;
+        }
+        // Only moves at the values' own width, which neither truncate nor
+        // extend them.
+        let width_of = |node: &Node| match node {
+            Node::Register(_) => None,
+            Node::Pseudo(name) => Some(Width::of(&types[name])),
+        };
+        let (from, into) = match (&source, &destination) {
+            (Node::Register(_), Node::Register(_)) => continue,
+            (Node::Pseudo(_), Node::Register(_)) => (source, destination),
+            _ => (destination, source),
+        };
+        let safe = match &into {
+            Node::Register(register) => {
+                ALLOCATABLE.contains(register)
+                    && width_of(&from) == Some(*width)
+                    && graph[&from].iter().all(|neighbour| {
+                        matches!(neighbour, Node::Register(_))
+                            || !significant(&graph, neighbour)
+                            || graph[neighbour].contains(&into)
+                    })
+            }
+            Node::Pseudo(name) => {
+                let Node::Pseudo(other) = &from else {
+                    unreachable!()
+                };
+                types[name] == types[other]
+                    && width_of(&into) == Some(*width)
+                    && graph[&from]
+                        .union(&graph[&into])
+                        .filter(|neighbour| significant(&graph, neighbour))
+                        .count()
+                        < ALLOCATABLE.len()
+            }
+        };
+        if !safe {
+            continue;
+        }
+        let neighbours = graph.remove(&from).unwrap();
+        for neighbour in neighbours {
+            let edges = graph.get_mut(&neighbour).unwrap();
+            edges.remove(&from);
+            edges.insert(into.clone());
+            graph.get_mut(&into).unwrap().insert(neighbour);
+        }
+        let Node::Pseudo(name) = from else {
+            unreachable!()
+        };
+        merged.insert(name, into);
+    }
+    if merged.is_empty() {
+        return false;
+    }
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Operand::Pseudo(name) = operand {
+                match find(&merged, Node::Pseudo(name.clone())) {
+                    Node::Pseudo(name) => *operand = Operand::Pseudo(name),
+                    Node::Register(register) => *operand = Operand::Register(register),
+                }
+            }
+        }
+    }
+    instructions.retain(|instruction| {
+        !matches!(instruction, Instruction::Mov(_, source, destination) if source == destination)
+    });
+    true
+}
+
+// Picks a register for each pseudo that can have one. Nodes with few
+// neighbours are set aside first, since they're sure to find a register once
+// their neighbours have. When none is left, the one used least for its
+// number of neighbours is set aside too, in the hope that some neighbours
+// end up sharing a register; if not, it goes on the stack.
+fn colour(graph: &Graph, instructions: &[Instruction]) -> HashMap<String, Register> {
+    let mut uses: HashMap<String, usize> = HashMap::new();
+    for instruction in instructions.iter() {
+        for operand in instruction.clone().operands_mut() {
+            if let Operand::Pseudo(name) = operand {
+                *uses.entry(name.clone()).or_default() += 1;
+            }
+        }
+    }
+    let mut remaining: BTreeSet<&Node> = graph
+        .keys()
+        .filter(|node| matches!(node, Node::Pseudo(_)))
+        .collect();
+    let degree = |node: &Node, remaining: &BTreeSet<&Node>| {
+        graph[node]
+            .iter()
+            .filter(|neighbour| match neighbour {
+                Node::Register(register) => ALLOCATABLE.contains(register),
+                Node::Pseudo(_) => remaining.contains(neighbour),
+            })
+            .count()
+    };
+    let mut stack = vec![];
+    while !remaining.is_empty() {
+        let node = remaining
+            .iter()
+            .copied()
+            .find(|node| degree(node, &remaining) < ALLOCATABLE.len())
+            .unwrap_or_else(|| {
+                let cost = |node: &Node| {
+                    let Node::Pseudo(name) = node else {
+                        unreachable!()
+                    };
+                    uses[name] as f64 / degree(node, &remaining) as f64
+                };
+                remaining
+                    .iter()
+                    .copied()
+                    .min_by(|left, right| cost(left).total_cmp(&cost(right)))
+                    .unwrap()
+            });
+        remaining.remove(node);
+        stack.push(node);
+    }
+    let mut colours: HashMap<String, Register> = HashMap::new();
+    while let Some(node) = stack.pop() {
+        let taken: HashSet<Register> = graph[node]
+            .iter()
+            .filter_map(|neighbour| match neighbour {
+                Node::Register(register) => Some(*register),
+                Node::Pseudo(name) => colours.get(name).copied(),
+            })
+            .collect();
+        let Node::Pseudo(name) = node else {
+            unreachable!()
+        };
+        if let Some(register) = ALLOCATABLE
+            .iter()
+            .find(|register| !taken.contains(register))
+        {
+            colours.insert(name.clone(), *register);
+        }
+    }
+    colours
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::generate;
+    use crate::ir::lower::lower_source;
+
+    fn assembly(source: &str) -> String {
+        generate(&lower_source(source)).to_string()
+    }
+
+    #[test]
+    fn test_values_stay_in_registers() {
+        let expected = "\
+.globl f
+f:
+pushq %rbp
+movq %rsp, %rbp
+movl %edi, %eax
+imull %esi, %eax
+addl %edi, %eax
+movq %rbp, %rsp
+popq %rbp
+ret
+";
+        assert_eq!(
+            expected,
+            assembly("int f(int a, int b) { return a * b + a; }")
+        );
+    }
+
+    #[test]
+    fn test_values_live_across_calls_are_callee_saved() {
+        let output = assembly(
+            "int h(void);
+            int f(int a) {
+                int b = h();
+                return a + b;
+            }",
+        );
+        assert!(output.contains("pushq %rbx"), "{}", output);
+        assert!(output.contains("popq %rbx"), "{}", output);
+        assert!(!output.contains("(%rbp)"), "{}", output);
+    }
+}
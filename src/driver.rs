@@ -0,0 +1,914 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::syntax::{self, dump, unparse, Program, Statement};
+use crate::tokenizer::{Position, Span, Token};
+use crate::{codegen, ir, opt, parser, preprocessor, sema, tokenizer};
+
+pub const USAGE: &str = "\
+Usage: rust_cc [options] file...
+
+Options:
+  -E                 Preprocess only, writing the result to stdout or -o
+  -S                 Compile only, writing assembly (<file>.s)
+  -c                 Compile and assemble, writing objects (<file>.o)
+  -o <file>          Write the output to <file>, or stdout for -E/-S with -
+  -I <dir>           Add <dir> to the #include search path
+  -l <library>       Link with <library>
+  -L <dir>           Add <dir> to the library search path
+  -O<level>          Optimize at level 0 (the default), 1 or 2; -O is -O1
+  -fno-<pass>        Leave out one optimization pass: always-inline, inline,
+                     constant-folding, copy-propagation, local-cse,
+                     global-cse, loop-invariant-code-motion or
+                     dead-code-elimination
+  -Wunreachable-code Warn about code that can never run
+  --as=<program>     Assemble with <program> instead of $RUST_CC_AS or as
+  --linker=<program> Link with <program> instead of $RUST_CC_LINKER or cc
+  --trace-macros     Log every macro expansion to stderr
+  --dump-tokens      Print the tokens with their line and column instead of
+                     compiling, to stdout or -o
+  --dump-ast=<format>
+                     Print the syntax tree as json, sexpr or tree instead
+                     of compiling, to stdout or -o
+  --unparse          Print the program back as canonically formatted C
+                     instead of compiling, to stdout or -o
+  --emit=<form>      Print the three-address IR as compiled (ir) or in SSA
+                     form (ssa) instead of compiling, to stdout or -o
+  --verify-ir        Check the IR after every pass
+  --help             Print this message
+  --version          Print the compiler version
+
+Inputs ending in .s are assembled, .o and .a are passed to the linker, and
+anything else is compiled as C. Use - to read C from stdin.
+";
+
+pub const ASSEMBLER_VARIABLE: &str = "RUST_CC_AS";
+pub const LINKER_VARIABLE: &str = "RUST_CC_LINKER";
+
+// Marks the stack as non-executable, as gcc does, so the linker doesn't warn.
+const NOTE_GNU_STACK: &str = ".section .note.GNU-stack,\"\",@progbits\n";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Stage {
+    Preprocess,
+    DumpTokens,
+    DumpAst(dump::Format),
+    Unparse,
+    EmitIr,
+    EmitSsa,
+    Compile,
+    Assemble,
+    Link,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub stage: Stage,
+    pub include_paths: Vec<PathBuf>,
+    pub optimization: u8,
+    pub disabled_passes: Vec<String>,
+    pub warn_unreachable_code: bool,
+    pub trace_macros: bool,
+    pub verify_ir: bool,
+    pub libraries: Vec<String>,
+    pub library_paths: Vec<PathBuf>,
+    pub assembler: Option<String>,
+    pub linker: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Invocation {
+    Help,
+    Version,
+    Build(Options),
+}
+
+pub fn parse_args(args: &[String]) -> Result<Invocation, String> {
+    let mut options = Options {
+        inputs: vec![],
+        output: None,
+        stage: Stage::Link,
+        include_paths: vec![],
+        optimization: 0,
+        disabled_passes: vec![],
+        warn_unreachable_code: false,
+        trace_macros: false,
+        verify_ir: false,
+        libraries: vec![],
+        library_paths: vec![],
+        assembler: None,
+        linker: None,
+    };
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        let mut value = |flag: &str| {
+            args_iter
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("missing argument to '{}'", flag))
+        };
+        match arg.as_str() {
+            "--help" => return Ok(Invocation::Help),
+            "--version" => return Ok(Invocation::Version),
+            "-E" => options.stage = Stage::Preprocess,
+            "-S" => options.stage = Stage::Compile,
+            "-c" => options.stage = Stage::Assemble,
+            "--trace-macros" => options.trace_macros = true,
+            "--dump-tokens" => options.stage = Stage::DumpTokens,
+            "--unparse" => options.stage = Stage::Unparse,
+            "--verify-ir" => options.verify_ir = true,
+            "-O" => options.optimization = 1,
+            "-Wunreachable-code" => options.warn_unreachable_code = true,
+            "-Wno-unreachable-code" => options.warn_unreachable_code = false,
+            "-o" => options.output = Some(value("-o")?),
+            "-I" => options.include_paths.push(PathBuf::from(value("-I")?)),
+            "-l" => options.libraries.push(value("-l")?),
+            "-L" => options.library_paths.push(PathBuf::from(value("-L")?)),
+            "-" => options.inputs.push(arg.clone()),
+            _ if arg.starts_with("-o") => options.output = Some(String::from(&arg[2..])),
+            _ if arg.starts_with("-I") => options.include_paths.push(PathBuf::from(&arg[2..])),
+            _ if arg.starts_with("-l") => options.libraries.push(String::from(&arg[2..])),
+            _ if arg.starts_with("-L") => options.library_paths.push(PathBuf::from(&arg[2..])),
+            _ if arg.starts_with("-O") => {
+                options.optimization = match arg[2..].parse() {
+                    Ok(level) if level <= 2 => level,
+                    _ => {
+                        return Err(format!(
+                            "unknown optimization level '{}'; expected 0, 1 or 2",
+                            &arg[2..]
+                        ))
+                    }
+                }
+            }
+            _ if arg.starts_with("-fno-")
+                && opt::PASSES.iter().any(|pass| pass.name == &arg[5..]) =>
+            {
+                options.disabled_passes.push(String::from(&arg[5..]))
+            }
+            _ if arg.starts_with("--as=") => options.assembler = Some(String::from(&arg[5..])),
+            _ if arg.starts_with("--linker=") => options.linker = Some(String::from(&arg[9..])),
+            _ if arg.starts_with("--emit=") => match &arg[7..] {
+                "ir" => options.stage = Stage::EmitIr,
+                "ssa" => options.stage = Stage::EmitSsa,
+                kind => {
+                    return Err(format!(
+                        "unknown output kind '{}'; expected ir or ssa",
+                        kind
+                    ))
+                }
+            },
+            _ if arg.starts_with("--dump-ast=") => {
+                let format = dump::Format::from_name(&arg[11..]).ok_or_else(|| {
+                    format!(
+                        "unknown AST format '{}'; expected json, sexpr or tree",
+                        &arg[11..]
+                    )
+                })?;
+                options.stage = Stage::DumpAst(format);
+            }
+            _ if arg.starts_with('-') => {
+                return Err(format!("unrecognized command-line option '{}'", arg))
+            }
+            _ => options.inputs.push(arg.clone()),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err(String::from("no input files"));
+    }
+    if options.output.is_some() && options.inputs.len() > 1 && options.stage != Stage::Link {
+        return Err(String::from(
+            "cannot specify '-o' with '-c', '-S' or '-E' with multiple files",
+        ));
+    }
+    Ok(Invocation::Build(options))
+}
+
+enum Input {
+    C,
+    Assembly,
+    Object,
+}
+
+fn input_kind(input: &str) -> Input {
+    match Path::new(input)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("s") => Input::Assembly,
+        Some("o") | Some("a") => Input::Object,
+        _ => Input::C,
+    }
+}
+
+fn read_source(input: &str) -> Result<String, String> {
+    if input == "-" {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|err| format!("<stdin>: {}", err))?;
+        return Ok(source);
+    }
+    fs::read_to_string(input).map_err(|err| format!("{}: {}", input, err))
+}
+
+fn source_name(input: &str) -> &str {
+    if input == "-" {
+        "<stdin>"
+    } else {
+        input
+    }
+}
+
+fn preprocess(input: &str, options: &Options) -> Result<preprocessor::Preprocessed, String> {
+    let source = read_source(input)?;
+    let mut preprocessor = preprocessor::Preprocessor::new(options.include_paths.clone());
+    preprocessor.set_trace_macros(options.trace_macros);
+    let preprocessed = preprocessor.preprocess_source(source_name(input), &source);
+    for expansion in preprocessor.trace() {
+        eprintln!("trace: {}", expansion);
+    }
+    for warning in preprocessor.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    preprocessed
+}
+
+// Moves a position in the preprocessed text back to where it was written.
+// Only lines are tracked exactly: after a macro expansion, columns count the
+// expanded text.
+fn original_position(preprocessed: &preprocessor::Preprocessed, position: Position) -> Position {
+    match preprocessed.location(position.line) {
+        Some(location) => Position {
+            line: location.line,
+            column: location.column + position.column - 1,
+        },
+        None => position,
+    }
+}
+
+// Tokens of the preprocessed text, with spans in the original source. Files
+// are numbered as in `preprocessed.files`.
+fn tokenize<'a>(
+    preprocessed: &preprocessor::Preprocessed,
+    code: &'a str,
+) -> Vec<(Token<'a>, Span)> {
+    let files = &preprocessed.files;
+    tokenizer::tokenize_with_spans(code)
+        .into_iter()
+        .map(|(token, span)| {
+            let span = Span {
+                file: preprocessed
+                    .location(span.start.line)
+                    .and_then(|location| files.iter().position(|file| **file == *location.file)),
+                start: original_position(preprocessed, span.start),
+                end: original_position(preprocessed, span.end),
+            };
+            (token, span)
+        })
+        .collect()
+}
+
+// The declarations in `input`, and the files their spans refer to.
+fn parse(input: &str, options: &Options) -> Result<(Vec<Statement>, Vec<String>), String> {
+    let preprocessed = preprocess(input, options)?;
+    let code = preprocessed.text();
+    match parser::parse_translation_unit(tokenize(&preprocessed, &code)) {
+        Ok(declarations) => Ok((declarations, preprocessed.files)),
+        Err(error) => Err(located(input, &preprocessed.files, error)),
+    }
+}
+
+fn analyze(input: &str, options: &Options) -> Result<Program, String> {
+    let (declarations, files) = parse(input, options)?;
+    let mut program = sema::analyze(declarations).map_err(|error| located(input, &files, error))?;
+    program.files = files;
+    Ok(program)
+}
+
+// An error where it was found in the source, or against the whole input when
+// no one place is to blame.
+fn located(input: &str, files: &[String], error: syntax::Error) -> String {
+    match error.span.file {
+        Some(_) => format!("{}: {}", error.span.location(files), error.message),
+        None => format!("{}: {}", source_name(input), error.message),
+    }
+}
+
+// With --verify-ir, checks the IR a pass left behind.
+fn verify_ir(
+    program: &ir::Program,
+    pass: &str,
+    ssa: bool,
+    options: &Options,
+) -> Result<(), String> {
+    if !options.verify_ir {
+        return Ok(());
+    }
+    ir::verify::verify(program, ssa).map_err(|msg| format!("invalid IR after {}: {}", pass, msg))
+}
+
+fn unused_result_warnings(program: &Program) -> Vec<String> {
+    program
+        .unused_results()
+        .into_iter()
+        .map(|span| {
+            format!(
+                "{}: warning: expression result unused",
+                span.location(&program.files)
+            )
+        })
+        .collect()
+}
+
+// -Wunreachable-code, reported where the code was written, which may be a
+// header rather than the file being compiled.
+fn unreachable_code_warnings(program: &Program) -> Vec<String> {
+    program
+        .unreachable_code()
+        .into_iter()
+        .map(|(function, span)| {
+            format!(
+                "{}: warning: code in function {} will never be executed [-Wunreachable-code]",
+                span.location(&program.files),
+                function
+            )
+        })
+        .collect()
+}
+
+// The IR of `input`, in SSA form after the passes -O asks for.
+fn lower(input: &str, options: &Options) -> Result<ir::Program, String> {
+    let program = analyze(input, options)?;
+    for warning in unused_result_warnings(&program) {
+        eprintln!("{}", warning);
+    }
+    if options.warn_unreachable_code {
+        for warning in unreachable_code_warnings(&program) {
+            eprintln!("{}", warning);
+        }
+    }
+    let mut program = ir::lower::lower(&program);
+    verify_ir(&program, "lowering", false, options)?;
+    for function in program.functions.iter_mut() {
+        ir::ssa::construct(function);
+    }
+    verify_ir(&program, "SSA construction", true, options)?;
+    for pass in opt::PASSES.iter() {
+        if pass.level <= options.optimization
+            && !options.disabled_passes.iter().any(|name| name == pass.name)
+        {
+            (pass.run)(&mut program);
+            verify_ir(&program, pass.name, true, options)?;
+        }
+    }
+    Ok(program)
+}
+
+// The IR of `input` as the backend takes it, out of SSA form.
+fn lower_for_codegen(input: &str, options: &Options) -> Result<ir::Program, String> {
+    let mut program = lower(input, options)?;
+    for function in program.functions.iter_mut() {
+        ir::ssa::destruct(function);
+    }
+    verify_ir(&program, "SSA destruction", false, options)?;
+    Ok(program)
+}
+
+pub fn compile(input: &str, options: &Options) -> Result<String, String> {
+    let program = lower_for_codegen(input, options)?;
+    Ok(format!("{}{}", codegen::generate(&program), NOTE_GNU_STACK))
+}
+
+// Where a single stage writes its result: -o if given, otherwise the input's
+// file name with the stage's extension, in the current directory.
+fn output_path(input: &str, options: &Options, extension: &str) -> String {
+    if let Some(output) = &options.output {
+        return output.clone();
+    }
+    let stem = if input == "-" {
+        "-"
+    } else {
+        Path::new(input)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(input)
+    };
+    format!("{}.{}", stem, extension)
+}
+
+fn write_output(path: &str, contents: &str) -> Result<(), String> {
+    if path == "-" {
+        return io::stdout()
+            .write_all(contents.as_bytes())
+            .map_err(|err| format!("<stdout>: {}", err));
+    }
+    fs::write(path, contents).map_err(|err| format!("{}: {}", path, err))
+}
+
+// Intermediate files, removed when the build is over, however it ends. They
+// go in a new directory only we can enter, so that nobody else can read them
+// or put a link where one will be written.
+struct Temporaries {
+    directory: Option<PathBuf>,
+    count: usize,
+}
+impl Temporaries {
+    fn new() -> Temporaries {
+        Temporaries {
+            directory: None,
+            count: 0,
+        }
+    }
+    fn create(&mut self, extension: &str) -> Result<PathBuf, String> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => self.directory.insert(private_directory()?),
+        };
+        self.count += 1;
+        Ok(directory.join(format!("{}.{}", self.count, extension)))
+    }
+}
+impl Drop for Temporaries {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.directory {
+            let _ = fs::remove_dir_all(directory);
+        }
+    }
+}
+
+// Creating the directory fails if anything, a link included, already has its
+// name, so a name someone else took is skipped rather than used.
+fn private_directory() -> Result<PathBuf, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    for _ in 0..100 {
+        let directory = env::temp_dir().join(format!(
+            "rust_cc-{}-{}-{}",
+            process::id(),
+            nanos,
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        match fs::DirBuilder::new().mode(0o700).create(&directory) {
+            Ok(()) => return Ok(directory),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(format!("{}: {}", directory.display(), err)),
+        }
+    }
+    Err(String::from("cannot create a temporary directory"))
+}
+
+// The external programs that assemble and link. --as and --linker win over
+// the environment, which wins over the system defaults.
+#[derive(Debug, PartialEq)]
+pub struct Toolchain {
+    pub assembler: String,
+    pub linker: String,
+}
+impl Toolchain {
+    pub fn new(options: &Options) -> Toolchain {
+        let pick = |flag: &Option<String>, variable: &str, default: &str| {
+            flag.clone()
+                .or_else(|| env::var(variable).ok().filter(|value| !value.is_empty()))
+                .unwrap_or_else(|| String::from(default))
+        };
+        Toolchain {
+            assembler: pick(&options.assembler, ASSEMBLER_VARIABLE, "as"),
+            linker: pick(&options.linker, LINKER_VARIABLE, "cc"),
+        }
+    }
+    fn assemble(&self, assembly: &Path, object: &str) -> Result<(), String> {
+        let args = vec![
+            String::from("-o"),
+            String::from(object),
+            assembly.to_string_lossy().to_string(),
+        ];
+        run_tool(
+            &self.assembler,
+            "assembler",
+            "--as",
+            ASSEMBLER_VARIABLE,
+            &args,
+        )
+    }
+    fn link(&self, objects: &[PathBuf], output: &str, options: &Options) -> Result<(), String> {
+        let mut args = vec![String::from("-o"), String::from(output)];
+        args.extend(
+            objects
+                .iter()
+                .map(|object| object.to_string_lossy().to_string()),
+        );
+        args.extend(
+            options
+                .library_paths
+                .iter()
+                .map(|path| format!("-L{}", path.display())),
+        );
+        args.extend(
+            options
+                .libraries
+                .iter()
+                .map(|library| format!("-l{}", library)),
+        );
+        run_tool(&self.linker, "linker", "--linker", LINKER_VARIABLE, &args)
+    }
+}
+
+fn run_tool(
+    tool: &str,
+    role: &str,
+    flag: &str,
+    variable: &str,
+    args: &[String],
+) -> Result<(), String> {
+    let status = Command::new(tool).args(args).status().map_err(|err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            format!(
+                "{} '{}' not found; install it or choose another with {}=<program> or {}",
+                role, tool, flag, variable
+            )
+        } else {
+            format!("failed to run {} '{}': {}", role, tool, err)
+        }
+    })?;
+    if !status.success() {
+        return Err(format!("{} '{}' failed with {}", role, tool, status));
+    }
+    Ok(())
+}
+
+// Turns one input into an object file for the linker.
+fn object_for_link(
+    input: &str,
+    options: &Options,
+    toolchain: &Toolchain,
+    temporaries: &mut Temporaries,
+) -> Result<PathBuf, String> {
+    let assembly = match input_kind(input) {
+        Input::Object => return Ok(PathBuf::from(input)),
+        Input::Assembly => PathBuf::from(input),
+        Input::C => {
+            let assembly = temporaries.create("s")?;
+            write_output(&assembly.to_string_lossy(), &compile(input, options)?)?;
+            assembly
+        }
+    };
+    let object = temporaries.create("o")?;
+    toolchain.assemble(&assembly, &object.to_string_lossy())?;
+    Ok(object)
+}
+
+fn link(options: &Options, toolchain: &Toolchain) -> Result<(), String> {
+    let mut temporaries = Temporaries::new();
+    let mut objects = vec![];
+    for input in options.inputs.iter() {
+        objects.push(object_for_link(
+            input,
+            options,
+            toolchain,
+            &mut temporaries,
+        )?);
+    }
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| String::from("a.out"));
+    toolchain.link(&objects, &output, options)
+}
+
+pub fn build(options: &Options) -> Result<(), String> {
+    let toolchain = Toolchain::new(options);
+    if options.stage == Stage::Link {
+        return link(options, &toolchain);
+    }
+    for input in options.inputs.iter() {
+        match (options.stage, input_kind(input)) {
+            (Stage::Preprocess, Input::C) => {
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                write_output(&output, &preprocess(input, options)?.line_marked_text())?;
+            }
+            (Stage::DumpTokens, Input::C) => {
+                let preprocessed = preprocess(input, options)?;
+                let code = preprocessed.text();
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                write_output(&output, &tokenizer::dump(&tokenize(&preprocessed, &code)))?;
+            }
+            (Stage::DumpAst(format), Input::C) => {
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                let (declarations, files) = parse(input, options)?;
+                let mut program = Program::from_declarations(declarations);
+                program.files = files;
+                write_output(&output, &dump::dump(&program, format))?;
+            }
+            (Stage::Unparse, Input::C) => {
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                let printed = unparse::unparse_declarations(&parse(input, options)?.0);
+                write_output(&output, &printed)?;
+            }
+            (Stage::EmitIr, Input::C) => {
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                write_output(&output, &lower_for_codegen(input, options)?.to_string())?;
+            }
+            (Stage::EmitSsa, Input::C) => {
+                let output = options.output.clone().unwrap_or_else(|| String::from("-"));
+                write_output(&output, &lower(input, options)?.to_string())?;
+            }
+            (Stage::Compile, Input::C) => {
+                let output = output_path(input, options, "s");
+                // gcc writes to stdout when compiling stdin with -S.
+                let output = if input == "-" && options.output.is_none() {
+                    String::from("-")
+                } else {
+                    output
+                };
+                write_output(&output, &compile(input, options)?)?;
+            }
+            (Stage::Assemble, Input::C) => {
+                let mut temporaries = Temporaries::new();
+                let assembly = temporaries.create("s")?;
+                write_output(&assembly.to_string_lossy(), &compile(input, options)?)?;
+                toolchain.assemble(&assembly, &output_path(input, options, "o"))?;
+            }
+            (Stage::Assemble, Input::Assembly) => {
+                toolchain.assemble(Path::new(input), &output_path(input, options, "o"))?;
+            }
+            // Like gcc, inputs that are already past the stage are ignored.
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// How every error is reported, whether from the command line or the build.
+fn error(msg: &str) -> String {
+    format!("rust_cc: error: {}", msg)
+}
+
+// Runs the compiler for the command line `args`, program name excluded, and
+// returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Invocation::Help) => {
+            print!("{}", USAGE);
+            return 0;
+        }
+        Ok(Invocation::Version) => {
+            println!("rust_cc {}", env!("CARGO_PKG_VERSION"));
+            return 0;
+        }
+        Ok(Invocation::Build(options)) => options,
+        Err(msg) => {
+            eprintln!("{}", error(&msg));
+            eprintln!("Try 'rust_cc --help' for more information.");
+            return 1;
+        }
+    };
+    match build(&options) {
+        Ok(()) => 0,
+        Err(msg) => {
+            eprintln!("{}", error(&msg));
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let expected = Options {
+            inputs: vec![String::from("a.c"), String::from("-"), String::from("b.o")],
+            output: Some(String::from("prog")),
+            stage: Stage::Link,
+            include_paths: vec![PathBuf::from("inc"), PathBuf::from("other")],
+            optimization: 0,
+            disabled_passes: vec![],
+            warn_unreachable_code: false,
+            trace_macros: false,
+            verify_ir: false,
+            libraries: vec![String::from("m"), String::from("c")],
+            library_paths: vec![PathBuf::from("lib")],
+            assembler: Some(String::from("my-as")),
+            linker: None,
+        };
+        assert_eq!(
+            Ok(Invocation::Build(expected)),
+            parse_args(&args(
+                "a.c -I inc - -lm -oprog -Iother -L lib -l c --as=my-as b.o"
+            ))
+        );
+        assert_eq!(Ok(Invocation::Help), parse_args(&args("a.c --help")));
+        match parse_args(&args("-S -c a.c")) {
+            Ok(Invocation::Build(options)) => assert_eq!(Stage::Assemble, options.stage),
+            _ => panic!("-c was not accepted"),
+        }
+        match parse_args(&args("--dump-tokens a.c")) {
+            Ok(Invocation::Build(options)) => assert_eq!(Stage::DumpTokens, options.stage),
+            _ => panic!("--dump-tokens was not accepted"),
+        }
+        match parse_args(&args("--dump-ast=sexpr a.c")) {
+            Ok(Invocation::Build(options)) => {
+                assert_eq!(Stage::DumpAst(dump::Format::Sexpr), options.stage)
+            }
+            _ => panic!("--dump-ast was not accepted"),
+        }
+        match parse_args(&args("--unparse a.c")) {
+            Ok(Invocation::Build(options)) => assert_eq!(Stage::Unparse, options.stage),
+            _ => panic!("--unparse was not accepted"),
+        }
+        match parse_args(&args("--emit=ir a.c")) {
+            Ok(Invocation::Build(options)) => assert_eq!(Stage::EmitIr, options.stage),
+            _ => panic!("--emit=ir was not accepted"),
+        }
+        match parse_args(&args("-O a.c -O0 -O1 -Wunreachable-code")) {
+            Ok(Invocation::Build(options)) => {
+                assert_eq!(1, options.optimization);
+                assert!(options.warn_unreachable_code);
+            }
+            _ => panic!("-O was not accepted"),
+        }
+        match parse_args(&args("-O2 -fno-global-cse a.c")) {
+            Ok(Invocation::Build(options)) => {
+                assert_eq!(2, options.optimization);
+                assert_eq!(vec![String::from("global-cse")], options.disabled_passes);
+            }
+            _ => panic!("-fno-global-cse was not accepted"),
+        }
+        match parse_args(&args("--emit=ssa --verify-ir a.c")) {
+            Ok(Invocation::Build(options)) => {
+                assert_eq!(Stage::EmitSsa, options.stage);
+                assert!(options.verify_ir);
+            }
+            _ => panic!("--emit=ssa was not accepted"),
+        }
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        let errors = [
+            ("", "no input files"),
+            ("a.c -o", "missing argument to '-o'"),
+            (
+                "a.c --frobnicate",
+                "unrecognized command-line option '--frobnicate'",
+            ),
+            (
+                "-S a.c b.c -o out.s",
+                "cannot specify '-o' with '-c', '-S' or '-E' with multiple files",
+            ),
+            (
+                "--dump-ast=xml a.c",
+                "unknown AST format 'xml'; expected json, sexpr or tree",
+            ),
+            (
+                "-Ofast a.c",
+                "unknown optimization level 'fast'; expected 0, 1 or 2",
+            ),
+            (
+                "-O3 a.c",
+                "unknown optimization level '3'; expected 0, 1 or 2",
+            ),
+            (
+                "a.c -fno-licm",
+                "unrecognized command-line option '-fno-licm'",
+            ),
+            (
+                "--emit=llvm a.c",
+                "unknown output kind 'llvm'; expected ir or ssa",
+            ),
+        ];
+        for (line, expected) in errors.iter() {
+            assert_eq!(Err(String::from(*expected)), parse_args(&args(line)));
+        }
+    }
+
+    #[test]
+    fn test_missing_tool_is_reported() {
+        let options = match parse_args(&args("--as=rust_cc-no-such-assembler -c a.s")) {
+            Ok(Invocation::Build(options)) => options,
+            _ => panic!("Invalid arguments"),
+        };
+        let toolchain = Toolchain::new(&options);
+        assert_eq!("rust_cc-no-such-assembler", toolchain.assembler);
+        assert_eq!(
+            Err(String::from(
+                "assembler 'rust_cc-no-such-assembler' not found; install it or choose \
+                 another with --as=<program> or RUST_CC_AS"
+            )),
+            toolchain.assemble(Path::new("a.s"), "a.o")
+        );
+    }
+
+    #[test]
+    fn test_temporaries_are_removed() {
+        let (directory, path) = {
+            let mut temporaries = Temporaries::new();
+            let path = temporaries.create("s").unwrap();
+            fs::write(&path, "").unwrap();
+            assert_ne!(path, temporaries.create("o").unwrap());
+            let directory = path.parent().unwrap().to_path_buf();
+            let mode = fs::metadata(&directory).unwrap().permissions().mode();
+            assert_eq!(0o700, mode & 0o777);
+            (directory, path)
+        };
+        assert!(!path.exists());
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    fn test_spans_name_the_file_they_came_from() {
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+        let main_file = samples.join("main.c").to_string_lossy().to_string();
+        let header = samples
+            .join("include/answer.h")
+            .to_string_lossy()
+            .to_string();
+        let preprocessed = preprocessor::Preprocessor::new(vec![])
+            .preprocess_source(&main_file, "#include \"include/answer.h\"\n\nint x;\n")
+            .unwrap();
+        let code = preprocessed.text();
+        let locations: Vec<String> = tokenize(&preprocessed, &code)
+            .into_iter()
+            .filter(|(token, _)| *token == Token::Keyword("int"))
+            .map(|(_, span)| span.location(&preprocessed.files))
+            .collect();
+        assert_eq!(
+            vec![format!("{}:5:1", header), format!("{}:3:1", main_file)],
+            locations
+        );
+        assert_eq!(vec![main_file, header], preprocessed.files);
+    }
+
+    fn analyze_source(name: &str, source: &str) -> Program {
+        let preprocessed = preprocessor::Preprocessor::new(vec![])
+            .preprocess_source(name, source)
+            .unwrap();
+        let code = preprocessed.text();
+        let declarations = parser::parse_translation_unit(tokenize(&preprocessed, &code)).unwrap();
+        let mut program = sema::analyze(declarations).unwrap();
+        program.files = preprocessed.files;
+        program
+    }
+
+    #[test]
+    fn test_unreachable_code_in_a_header() {
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+        let main_file = samples.join("main.c").to_string_lossy().to_string();
+        let header = samples
+            .join("include/unreachable.h")
+            .to_string_lossy()
+            .to_string();
+        let source = "#include \"include/unreachable.h\"\nint main() {\n    return twice(2);\n}\n";
+        let program = analyze_source(&main_file, source);
+        assert_eq!(
+            vec![format!(
+                "{}:3:5: warning: code in function twice will never be executed [-Wunreachable-code]",
+                header
+            )],
+            unreachable_code_warnings(&program)
+        );
+    }
+
+    #[test]
+    fn test_unused_result_location() {
+        let main_file = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("c_samples/main.c")
+            .to_string_lossy()
+            .to_string();
+        let source = "int main() {\n    int x = 1;\n    x + 1;\n    return x;\n}\n";
+        let program = analyze_source(&main_file, source);
+        assert_eq!(
+            vec![format!(
+                "{}:3:5: warning: expression result unused",
+                main_file
+            )],
+            unused_result_warnings(&program)
+        );
+    }
+
+    #[test]
+    fn test_output_path() {
+        let options = match parse_args(&args("-c dir/prog.c")) {
+            Ok(Invocation::Build(options)) => options,
+            _ => panic!("Invalid arguments"),
+        };
+        assert_eq!("prog.o", output_path("dir/prog.c", &options, "o"));
+        assert_eq!("-.s", output_path("-", &options, "s"));
+    }
+}
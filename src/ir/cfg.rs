@@ -0,0 +1,403 @@
+// The control-flow graph of a function: its body cut into basic blocks, runs
+// of instructions that are only entered at the top and only left at the
+// bottom, with the dominator tree over them.
+use std::collections::{HashMap, HashSet};
+
+use super::{Function, Instruction};
+
+pub struct Block {
+    pub label: String,
+    // Without the label, which the block keeps apart.
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+impl Block {
+    // Phis come first in a block, so this is where the other instructions
+    // start.
+    pub fn phi_count(&self) -> usize {
+        self.instructions
+            .iter()
+            .take_while(|instruction| matches!(instruction, Instruction::Phi { .. }))
+            .count()
+    }
+}
+
+// Blocks stay in program order, so one that doesn't end in a jump falls
+// through to the next. The first block is the entry, which nothing jumps
+// to.
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+impl Cfg {
+    pub fn new(function: &Function) -> Cfg {
+        let targets: HashSet<&String> = function
+            .body
+            .iter()
+            .flat_map(|instruction| instruction.targets())
+            .collect();
+        let mut runs: Vec<(Option<String>, Vec<Instruction>)> = vec![(None, vec![])];
+        for instruction in function.body.iter() {
+            let entry = runs.len() == 1;
+            let (label, instructions) = runs.last_mut().unwrap();
+            match instruction {
+                // An empty block without a label can take this one, unless
+                // it's the entry and something jumps here.
+                Instruction::Label(name) => {
+                    if label.is_none()
+                        && instructions.is_empty()
+                        && !(entry && targets.contains(name))
+                    {
+                        *label = Some(name.clone());
+                    } else {
+                        runs.push((Some(name.clone()), vec![]));
+                    }
+                }
+                Instruction::JumpIfZero { .. } | Instruction::JumpIfNotZero { .. } => {
+                    instructions.push(instruction.clone());
+                    runs.push((None, vec![]));
+                }
+                _ => {
+                    instructions.push(instruction.clone());
+                    if instruction.is_terminator() {
+                        runs.push((None, vec![]));
+                    }
+                }
+            }
+        }
+        if runs.len() > 1
+            && matches!(runs.last(), Some((None, instructions)) if instructions.is_empty())
+        {
+            runs.pop();
+        }
+        // Blocks that weren't labelled get a name nothing else uses, for phis
+        // to refer to.
+        let mut labels: HashSet<String> =
+            runs.iter().filter_map(|(label, _)| label.clone()).collect();
+        let mut count = 0;
+        let mut blocks = vec![];
+        for (label, instructions) in runs {
+            let label = label.unwrap_or_else(|| loop {
+                let label = format!("{}.block.{}", function.name, count);
+                count += 1;
+                if labels.insert(label.clone()) {
+                    break label;
+                }
+            });
+            blocks.push(Block {
+                label,
+                instructions,
+                successors: vec![],
+                predecessors: vec![],
+            });
+        }
+        let mut cfg = Cfg { blocks };
+        cfg.connect();
+        cfg
+    }
+
+    fn connect(&mut self) {
+        let indices: HashMap<String, usize> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.label.clone(), idx))
+            .collect();
+        let count = self.blocks.len();
+        for (idx, block) in self.blocks.iter_mut().enumerate() {
+            let mut successors = vec![];
+            let falls_through = match block.instructions.last() {
+                Some(last) => {
+                    for target in last.targets() {
+                        successors.push(indices[target]);
+                    }
+                    !last.is_terminator()
+                }
+                None => true,
+            };
+            if falls_through && idx + 1 < count {
+                successors.push(idx + 1);
+            }
+            let mut seen = HashSet::new();
+            successors.retain(|successor| seen.insert(*successor));
+            block.successors = successors;
+            block.predecessors = vec![];
+        }
+        for idx in 0..count {
+            for successor in self.blocks[idx].successors.clone() {
+                self.blocks[successor].predecessors.push(idx);
+            }
+        }
+    }
+
+    // The blocks reachable from the entry, each before its successors
+    // except along back edges.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut order = vec![];
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            match self.blocks[block].successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    // Brings the edges up to date with the jumps, drops the blocks control
+    // can't reach, and drops phi sources from blocks that no longer lead to
+    // the phi.
+    pub fn remove_unreachable(&mut self) {
+        self.connect();
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block] = true;
+        }
+        if reachable.contains(&false) {
+            let mut reachable = reachable.into_iter();
+            self.blocks.retain(|_| reachable.next().unwrap());
+            self.connect();
+        }
+        let labels: Vec<String> = self
+            .blocks
+            .iter()
+            .map(|block| block.label.clone())
+            .collect();
+        for block in self.blocks.iter_mut() {
+            let predecessors: HashSet<&String> = block
+                .predecessors
+                .iter()
+                .map(|predecessor| &labels[*predecessor])
+                .collect();
+            for instruction in block.instructions.iter_mut() {
+                if let Instruction::Phi { sources, .. } = instruction {
+                    sources.retain(|(label, _)| predecessors.contains(label));
+                }
+            }
+        }
+    }
+
+    // The body back as a list, keeping only the labels something refers to
+    // or that start a block with phis, which must stay apart.
+    pub fn instructions(self) -> Vec<Instruction> {
+        let mut referenced = HashSet::new();
+        for block in self.blocks.iter() {
+            for instruction in block.instructions.iter() {
+                referenced.extend(instruction.targets().into_iter().cloned());
+                if let Instruction::Phi { sources, .. } = instruction {
+                    referenced.extend(sources.iter().map(|(label, _)| label.clone()));
+                }
+            }
+        }
+        let mut instructions = vec![];
+        for block in self.blocks {
+            if referenced.contains(&block.label) || block.phi_count() > 0 {
+                instructions.push(Instruction::Label(block.label));
+            }
+            instructions.extend(block.instructions);
+        }
+        instructions
+    }
+}
+
+pub struct Dominators {
+    // The closest block every path from the entry to the block goes through.
+    // None for the entry and for blocks that can't be reached.
+    pub immediate: Vec<Option<usize>>,
+}
+impl Dominators {
+    // Cooper, Harvey and Kennedy's iterative algorithm.
+    pub fn new(cfg: &Cfg) -> Dominators {
+        let order = cfg.reverse_postorder();
+        let mut position = vec![usize::MAX; cfg.blocks.len()];
+        for (idx, block) in order.iter().enumerate() {
+            position[*block] = idx;
+        }
+        let mut immediate: Vec<Option<usize>> = vec![None; cfg.blocks.len()];
+        immediate[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter().skip(1) {
+                let mut new: Option<usize> = None;
+                for predecessor in cfg.blocks[*block].predecessors.iter() {
+                    if immediate[*predecessor].is_none() {
+                        continue;
+                    }
+                    new = Some(match new {
+                        None => *predecessor,
+                        Some(mut other) => {
+                            let mut finger = *predecessor;
+                            while finger != other {
+                                while position[finger] > position[other] {
+                                    finger = immediate[finger].unwrap();
+                                }
+                                while position[other] > position[finger] {
+                                    other = immediate[other].unwrap();
+                                }
+                            }
+                            finger
+                        }
+                    });
+                }
+                if immediate[*block] != new {
+                    immediate[*block] = new;
+                    changed = true;
+                }
+            }
+        }
+        immediate[0] = None;
+        Dominators { immediate }
+    }
+
+    pub fn dominates(&self, dominator: usize, block: usize) -> bool {
+        let mut current = Some(block);
+        while let Some(idx) = current {
+            if idx == dominator {
+                return true;
+            }
+            current = self.immediate[idx];
+        }
+        false
+    }
+
+    // The blocks each block immediately dominates.
+    pub fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![vec![]; self.immediate.len()];
+        for (block, dominator) in self.immediate.iter().enumerate() {
+            if let Some(dominator) = dominator {
+                children[*dominator].push(block);
+            }
+        }
+        children
+    }
+
+    // For each block, where its dominance ends: the blocks it doesn't
+    // strictly dominate but does dominate a predecessor of.
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut frontiers: Vec<Vec<usize>> = vec![vec![]; cfg.blocks.len()];
+        for (block, dominator) in self.immediate.iter().enumerate() {
+            let Some(dominator) = dominator else {
+                continue;
+            };
+            let predecessors = &cfg.blocks[block].predecessors;
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors.iter() {
+                if *predecessor != 0 && self.immediate[*predecessor].is_none() {
+                    continue;
+                }
+                let mut runner = *predecessor;
+                while runner != *dominator {
+                    if !frontiers[runner].contains(&block) {
+                        frontiers[runner].push(block);
+                    }
+                    runner = self.immediate[runner].unwrap();
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+
+    #[test]
+    fn test_blocks_and_dominators() {
+        let program = lower_source(
+            "int f(int n) {
+                top:
+                switch (n) {
+                case 0:
+                    return 1;
+                case 1:
+                    n = 5;
+                    break;
+                default:
+                    n = n - 2;
+                }
+                goto top;
+            }",
+        );
+        // The return lowering adds after the goto is unreachable.
+        let cfg = Cfg::new(&program.functions[0]);
+        let labels: Vec<&str> = cfg
+            .blocks
+            .iter()
+            .map(|block| block.label.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "f.block.0",
+                "f.top",
+                "case.1",
+                "case.2",
+                "default.3",
+                "switch_end.0",
+                "f.block.1"
+            ],
+            labels
+        );
+        let successors: Vec<Vec<usize>> = cfg
+            .blocks
+            .iter()
+            .map(|block| block.successors.clone())
+            .collect();
+        assert_eq!(
+            vec![
+                vec![1],
+                vec![2, 3, 4],
+                vec![],
+                vec![5],
+                vec![5],
+                vec![1],
+                vec![]
+            ],
+            successors
+        );
+        assert_eq!(vec![0, 1, 4, 3, 5, 2], cfg.reverse_postorder());
+        let dominators = Dominators::new(&cfg);
+        assert_eq!(
+            vec![None, Some(0), Some(1), Some(1), Some(1), Some(1), None],
+            dominators.immediate
+        );
+        assert!(dominators.dominates(1, 5));
+        assert!(!dominators.dominates(3, 5));
+        assert_eq!(
+            vec![vec![], vec![1], vec![], vec![5], vec![5], vec![1], vec![]],
+            dominators.frontiers(&cfg)
+        );
+    }
+
+    #[test]
+    fn test_unreachable_blocks_are_removed() {
+        let program = lower_source(
+            "int f(void) {
+                return 1;
+                dead:
+                return 2;
+            }",
+        );
+        let mut cfg = Cfg::new(&program.functions[0]);
+        assert_eq!(2, cfg.blocks.len());
+        cfg.remove_unreachable();
+        assert_eq!(1, cfg.blocks.len());
+        assert_eq!(
+            vec![Instruction::Return(crate::ir::Value::Constant(1))],
+            cfg.instructions()
+        );
+    }
+}
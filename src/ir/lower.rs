@@ -0,0 +1,589 @@
+// Lowers an analysed program to the IR. Every local gets a name unique in its
+// function, `<name>.<n>`, so block scoping is resolved here; temporaries are
+// `tmp.<n>` from the same counter. Labels, static locals and strings are
+// numbered across the whole program, since they end up as assembler symbols.
+use std::collections::HashMap;
+
+use crate::ir::*;
+use crate::syntax::expressions::{self, PostfixOperator, VaOperation};
+use crate::syntax::{self, statements, Expression, ExpressionTrait, Statement, Type};
+
+pub fn lower(program: &syntax::Program) -> Program {
+    let mut lowering = Lowering {
+        program,
+        statics: vec![],
+        strings: vec![],
+        label_count: 0,
+    };
+    for global in program.globals.iter() {
+        let initial = lowering.initial_value(global);
+        lowering.statics.push(StaticVariable {
+            name: global.name.clone(),
+            global: !global.is_static,
+            data_type: global.variable_type.unqualified().clone(),
+            initial,
+        });
+    }
+    let functions = program
+        .functions
+        .iter()
+        .map(|function| lowering.function(function))
+        .collect();
+    Program {
+        statics: lowering.statics,
+        strings: lowering.strings,
+        functions,
+    }
+}
+
+struct Lowering<'a> {
+    program: &'a syntax::Program,
+    statics: Vec<StaticVariable>,
+    strings: Vec<StringConstant>,
+    label_count: usize,
+}
+impl Lowering<'_> {
+    fn unique(&mut self, name: &str) -> String {
+        let label = format!("{}.{}", name, self.label_count);
+        self.label_count += 1;
+        label
+    }
+
+    fn string(&mut self, value: &str) -> String {
+        let name = self.unique("string");
+        self.strings.push(StringConstant {
+            name: name.clone(),
+            value: String::from(value),
+        });
+        name
+    }
+
+    // Static storage is initialised by the loader, and analysis has checked
+    // that the initializer is a constant or a string literal.
+    fn initial_value(&mut self, declaration: &statements::Declaration) -> Initializer {
+        match &declaration.initializer {
+            Some(Expression::StringLiteral(val)) => Initializer::Address(self.string(&val.value)),
+            Some(initializer) => match initializer.evaluate() {
+                Type::Integer(val) => Initializer::Integer(val),
+            },
+            None => Initializer::Integer(0),
+        }
+    }
+
+    fn function(&mut self, function: &statements::Function) -> Function {
+        let mut lowering = FunctionLowering {
+            lowering: self,
+            name: &function.name,
+            scopes: vec![HashMap::new()],
+            variables: vec![],
+            body: vec![],
+            breaks: vec![],
+            switches: vec![],
+        };
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| lowering.declare(&parameter.name, &parameter.data_type))
+            .collect();
+        for statement in function.body.iter() {
+            lowering.statement(statement);
+        }
+        // Falling off the end of a function returns 0, like main does in C99.
+        if !matches!(lowering.body.last(), Some(Instruction::Return(_))) {
+            lowering.body.push(Instruction::Return(Value::Constant(0)));
+        }
+        Function {
+            name: function.name.clone(),
+            global: !function.is_static,
+            weak: !function.is_static && function.is_inline,
+            parameters,
+            variadic: function.variadic,
+            inlining: match function.inlining {
+                None if function.is_inline => Some(statements::Inlining::Hint),
+                inlining => inlining,
+            },
+            variables: lowering.variables,
+            body: lowering.body,
+        }
+    }
+}
+
+struct FunctionLowering<'a, 'b> {
+    lowering: &'a mut Lowering<'b>,
+    name: &'a str,
+    // Source names in scope, mapped to their unique names.
+    scopes: Vec<HashMap<String, String>>,
+    variables: Vec<Variable>,
+    body: Vec<Instruction>,
+    breaks: Vec<String>,
+    switches: Vec<HashMap<Option<i32>, String>>,
+}
+impl FunctionLowering<'_, '_> {
+    // Qualifiers only matter to analysis, so the IR never sees them.
+    fn variable(&mut self, name: &str, data_type: &DataType) -> String {
+        let unique = format!("{}.{}", name, self.variables.len());
+        self.variables.push(Variable {
+            name: unique.clone(),
+            data_type: data_type.unqualified().clone(),
+        });
+        unique
+    }
+
+    fn temporary(&mut self, data_type: &DataType) -> String {
+        self.variable("tmp", data_type)
+    }
+
+    fn declare(&mut self, name: &str, data_type: &DataType) -> String {
+        let unique = self.variable(name, data_type);
+        self.bind(name, &unique);
+        unique
+    }
+
+    fn bind(&mut self, name: &str, unique: &str) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(String::from(name), String::from(unique));
+    }
+
+    // Anything not declared in the function is a file scope variable, which
+    // keeps its name.
+    fn resolve(&self, name: &str) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or_else(|| String::from(name))
+    }
+
+    fn data_type(&self, name: &str) -> DataType {
+        self.variables
+            .iter()
+            .find(|variable| variable.name == name)
+            .map(|variable| variable.data_type.clone())
+            .or_else(|| {
+                self.lowering
+                    .statics
+                    .iter()
+                    .find(|variable| variable.name == name)
+                    .map(|variable| variable.data_type.clone())
+            })
+            .unwrap_or_else(|| panic!("Use of undeclared identifier '{}'", name))
+    }
+
+    // Labels written in C become `<function>.<label>`, which can't collide
+    // with a generated `<kind>.<number>`.
+    fn user_label(&self, label: &str) -> String {
+        format!("{}.{}", self.name, label)
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.body.push(instruction);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Return(val) => {
+                let value = self.expression(&val.expression);
+                self.emit(Instruction::Return(value));
+            }
+            Statement::Expression(val) => {
+                self.expression(&val.expression);
+            }
+            Statement::Declaration(val) if val.is_static => {
+                let symbol = self.lowering.unique(&format!("{}.{}", self.name, val.name));
+                let initial = self.lowering.initial_value(val);
+                self.lowering.statics.push(StaticVariable {
+                    name: symbol.clone(),
+                    global: false,
+                    data_type: val.variable_type.unqualified().clone(),
+                    initial,
+                });
+                self.bind(&val.name, &symbol);
+            }
+            // The name is in scope in its own initializer.
+            Statement::Declaration(val) => {
+                let unique = self.declare(&val.name, &val.variable_type);
+                if let Some(initializer) = &val.initializer {
+                    let source = self.expression(initializer);
+                    self.emit(Instruction::Copy {
+                        source,
+                        destination: unique,
+                    });
+                }
+            }
+            Statement::Block(val) => {
+                self.scopes.push(HashMap::new());
+                val.body
+                    .iter()
+                    .for_each(|statement| self.statement(statement));
+                self.scopes.pop();
+            }
+            Statement::Switch(val) => self.switch(val),
+            Statement::Case(val) => {
+                let Type::Integer(value) = val.value;
+                let label = self.switches.last().unwrap()[&Some(value)].clone();
+                self.emit(Instruction::Label(label));
+                self.statement(&val.statement);
+            }
+            Statement::Default(val) => {
+                let label = self.switches.last().unwrap()[&None].clone();
+                self.emit(Instruction::Label(label));
+                self.statement(&val.statement);
+            }
+            Statement::Break(_) => {
+                let target = self.breaks.last().unwrap().clone();
+                self.emit(Instruction::Jump(target));
+            }
+            Statement::Label(val) => {
+                let label = self.user_label(&val.name);
+                self.emit(Instruction::Label(label));
+                self.statement(&val.statement);
+            }
+            Statement::Goto(val) => {
+                let target = self.user_label(&val.label);
+                self.emit(Instruction::Jump(target));
+            }
+            Statement::Empty(_) | Statement::Function(_) | Statement::Prototype(_) => {}
+        }
+    }
+
+    fn switch(&mut self, switch: &statements::Switch) {
+        let value = self.expression(&switch.expression);
+        let end = self.lowering.unique("switch_end");
+        let mut labels = HashMap::new();
+        let mut cases = vec![];
+        for label in switch.labels() {
+            let target = match label {
+                Some(Type::Integer(case)) => {
+                    let target = self.lowering.unique("case");
+                    cases.push((case, target.clone()));
+                    target
+                }
+                None => self.lowering.unique("default"),
+            };
+            labels.insert(label.map(|Type::Integer(case)| case), target);
+        }
+        let default = labels.get(&None).cloned().unwrap_or_else(|| end.clone());
+        self.emit(Instruction::Switch {
+            value,
+            cases,
+            default,
+        });
+        self.switches.push(labels);
+        self.breaks.push(end.clone());
+        self.statement(&switch.body);
+        self.breaks.pop();
+        self.switches.pop();
+        self.emit(Instruction::Label(end));
+    }
+
+    // A variable read now, whose value is needed after `later` is evaluated,
+    // is copied first in case `later` changes it.
+    fn keep(&mut self, value: Value, later: &[&Expression]) -> Value {
+        match &value {
+            Value::Variable(name)
+                if later.iter().any(|expression| expression.has_side_effects()) =>
+            {
+                let copy = self.temporary(&self.data_type(name));
+                self.emit(Instruction::Copy {
+                    source: value,
+                    destination: copy.clone(),
+                });
+                Value::Variable(copy)
+            }
+            _ => value,
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Value {
+        match expression {
+            Expression::Constant(val) => {
+                let Type::Integer(value) = val.value;
+                Value::Constant(value)
+            }
+            // A va_list is an array, so using it yields its address.
+            Expression::Variable(val) => {
+                let name = self.resolve(&val.name);
+                let data_type = self.data_type(&name);
+                if data_type != DataType::VaList {
+                    return Value::Variable(name);
+                }
+                let destination = self.temporary(&DataType::Pointer(Box::new(data_type)));
+                self.emit(Instruction::GetAddress {
+                    name,
+                    destination: destination.clone(),
+                });
+                Value::Variable(destination)
+            }
+            Expression::Unary(val) => self.unary(val),
+            Expression::Postfix(val) => {
+                let name = self.resolve(variable_name(&val.operand));
+                let old = self.temporary(&self.data_type(&name));
+                self.emit(Instruction::Copy {
+                    source: Value::Variable(name.clone()),
+                    destination: old.clone(),
+                });
+                let operator = match val.operator {
+                    PostfixOperator::Increment => BinaryOperator::Add,
+                    PostfixOperator::Decrement => BinaryOperator::Subtract,
+                };
+                self.step(&name, operator);
+                Value::Variable(old)
+            }
+            Expression::Binary(val) => self.binary(val),
+            Expression::Assignment(val) => {
+                let source = self.expression(&val.value);
+                let name = self.resolve(&val.name);
+                self.emit(Instruction::Copy {
+                    source,
+                    destination: name.clone(),
+                });
+                Value::Variable(name)
+            }
+            Expression::Comma(val) => {
+                self.expression(&val.left);
+                self.expression(&val.right)
+            }
+            Expression::Call(val) => self.call(val),
+            Expression::StringLiteral(val) => {
+                let name = self.lowering.string(&val.value);
+                let destination = self.temporary(&DataType::Pointer(Box::new(DataType::Char)));
+                self.emit(Instruction::GetAddress {
+                    name,
+                    destination: destination.clone(),
+                });
+                Value::Variable(destination)
+            }
+            Expression::VaBuiltin(val) => {
+                let list = self.resolve(&val.list);
+                match &val.operation {
+                    VaOperation::Start(_) => self.emit(Instruction::VaStart { list }),
+                    VaOperation::Arg(data_type) => {
+                        let destination = self.temporary(data_type);
+                        self.emit(Instruction::VaArg {
+                            list,
+                            data_type: data_type.unqualified().clone(),
+                            destination: destination.clone(),
+                        });
+                        return Value::Variable(destination);
+                    }
+                    VaOperation::End => {}
+                }
+                // Analysis rejects any use of the value of the others.
+                Value::Constant(0)
+            }
+            // Reading a value already promotes it to int, so only other
+            // targets need a variable of their own.
+            Expression::Conversion(val) => {
+                let source = self.expression(&val.operand);
+                if val.target == DataType::Int {
+                    return source;
+                }
+                let destination = self.temporary(&val.target);
+                self.emit(Instruction::Copy {
+                    source,
+                    destination: destination.clone(),
+                });
+                Value::Variable(destination)
+            }
+        }
+    }
+
+    // Adds or subtracts one, converting back to the variable's type.
+    fn step(&mut self, name: &str, operator: BinaryOperator) {
+        let result = self.temporary(&DataType::Int);
+        self.emit(Instruction::Binary {
+            operator,
+            left: Value::Variable(String::from(name)),
+            right: Value::Constant(1),
+            destination: result.clone(),
+        });
+        self.emit(Instruction::Copy {
+            source: Value::Variable(result),
+            destination: String::from(name),
+        });
+    }
+
+    fn unary(&mut self, unary: &expressions::Unary) -> Value {
+        let operator = match unary.operator {
+            expressions::UnaryOperator::PreIncrement | expressions::UnaryOperator::PreDecrement => {
+                let name = self.resolve(variable_name(&unary.operand));
+                let operator = match unary.operator {
+                    expressions::UnaryOperator::PreIncrement => BinaryOperator::Add,
+                    _ => BinaryOperator::Subtract,
+                };
+                self.step(&name, operator);
+                return Value::Variable(name);
+            }
+            expressions::UnaryOperator::Negation => UnaryOperator::Negate,
+            expressions::UnaryOperator::BitwiseComplement => UnaryOperator::Complement,
+            expressions::UnaryOperator::LogicalNegation => UnaryOperator::Not,
+        };
+        let source = self.expression(&unary.operand);
+        let destination = self.temporary(&DataType::Int);
+        self.emit(Instruction::Unary {
+            operator,
+            source,
+            destination: destination.clone(),
+        });
+        Value::Variable(destination)
+    }
+
+    fn binary(&mut self, binary: &expressions::Binary) -> Value {
+        use expressions::BinaryOperator as Op;
+        let operator = match binary.operator {
+            Op::LogicalAnd | Op::LogicalOr => return self.short_circuit(binary),
+            Op::Addition => BinaryOperator::Add,
+            Op::Subtraction => BinaryOperator::Subtract,
+            Op::Multiplication => BinaryOperator::Multiply,
+            Op::Division => BinaryOperator::Divide,
+            Op::Modulo => BinaryOperator::Remainder,
+            Op::Equal => BinaryOperator::Equal,
+            Op::NotEqual => BinaryOperator::NotEqual,
+            Op::LessThan => BinaryOperator::LessThan,
+            Op::LessEqual => BinaryOperator::LessEqual,
+            Op::GreaterThan => BinaryOperator::GreaterThan,
+            Op::GreaterEqual => BinaryOperator::GreaterEqual,
+        };
+        let left = self.expression(&binary.left);
+        let left = self.keep(left, &[&binary.right]);
+        let right = self.expression(&binary.right);
+        let destination = self.temporary(&DataType::Int);
+        self.emit(Instruction::Binary {
+            operator,
+            left,
+            right,
+            destination: destination.clone(),
+        });
+        Value::Variable(destination)
+    }
+
+    // The right operand only runs when the left one doesn't decide the
+    // result on its own.
+    fn short_circuit(&mut self, binary: &expressions::Binary) -> Value {
+        let is_and = binary.operator == expressions::BinaryOperator::LogicalAnd;
+        let (short, end) = if is_and {
+            (
+                self.lowering.unique("and_false"),
+                self.lowering.unique("and_end"),
+            )
+        } else {
+            (
+                self.lowering.unique("or_true"),
+                self.lowering.unique("or_end"),
+            )
+        };
+        let destination = self.temporary(&DataType::Int);
+        for operand in [&binary.left, &binary.right] {
+            let condition = self.expression(operand);
+            let target = short.clone();
+            self.emit(if is_and {
+                Instruction::JumpIfZero { condition, target }
+            } else {
+                Instruction::JumpIfNotZero { condition, target }
+            });
+        }
+        self.emit(Instruction::Copy {
+            source: Value::Constant(is_and as i32),
+            destination: destination.clone(),
+        });
+        self.emit(Instruction::Jump(end.clone()));
+        self.emit(Instruction::Label(short));
+        self.emit(Instruction::Copy {
+            source: Value::Constant(!is_and as i32),
+            destination: destination.clone(),
+        });
+        self.emit(Instruction::Label(end));
+        Value::Variable(destination)
+    }
+
+    // Arguments are evaluated right to left, as the stack machine did.
+    fn call(&mut self, call: &expressions::Call) -> Value {
+        let mut arguments = vec![];
+        for idx in (0..call.arguments.len()).rev() {
+            let value = self.expression(&call.arguments[idx]);
+            let later: Vec<&Expression> = call.arguments[..idx].iter().collect();
+            arguments.push(self.keep(value, &later));
+        }
+        arguments.reverse();
+        let variadic = self
+            .lowering
+            .program
+            .signature(&call.name)
+            .is_none_or(|signature| signature.variadic);
+        let destination = self.temporary(&DataType::Int);
+        self.emit(Instruction::Call {
+            name: call.name.clone(),
+            arguments,
+            destination: destination.clone(),
+            variadic,
+        });
+        Value::Variable(destination)
+    }
+}
+
+// Analysis only lets ++ and -- apply to a variable.
+fn variable_name(operand: &Expression) -> &str {
+    match operand {
+        Expression::Variable(val) => &val.name,
+        _ => panic!("Operand is not assignable"),
+    }
+}
+
+// The IR of a whole program given as source, for tests.
+#[cfg(test)]
+pub fn lower_source(code: &str) -> Program {
+    use crate::{parser, sema, tokenizer};
+    let tokens = tokenizer::tokenize_with_spans(code);
+    let declarations = parser::parse_translation_unit(tokens).unwrap();
+    lower(&sema::analyze(declarations).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower_function() {
+        let code = "int count(char c) {
+            static int calls;
+            calls++;
+            return c && calls < 3;
+        }";
+        let program = lower_source(code);
+        let expected = "\
+static int count.calls.0 = 0
+
+global function count(char c.0):
+    int tmp.1
+    int tmp.2
+    int tmp.3
+    int tmp.4
+    tmp.1 = count.calls.0
+    tmp.2 = count.calls.0 + 1
+    count.calls.0 = tmp.2
+    jump and_false.1 if c.0 == 0
+    tmp.4 = count.calls.0 < 3
+    jump and_false.1 if tmp.4 == 0
+    tmp.3 = 1
+    jump and_end.2
+  and_false.1:
+    tmp.3 = 0
+  and_end.2:
+    return tmp.3
+";
+        assert_eq!(expected, program.to_string());
+    }
+
+    #[test]
+    fn test_lower_pointer_initializers() {
+        let program = lower_source("const char *msg = \"hi\"; char *const p = 0;");
+        let expected = "\
+global const char *msg = &string.0
+global char *p = 0
+string string.0 = \"hi\"
+";
+        assert_eq!(expected, program.to_string());
+    }
+}
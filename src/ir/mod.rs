@@ -0,0 +1,479 @@
+// A linear three-address form of the program, lowered from the analysed
+// syntax tree. Each instruction reads constants or variables and writes at
+// most one variable, and control flow is explicit jumps between labels, so
+// passes and backends never have to look at the syntax tree.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::syntax::statements::Inlining;
+use crate::syntax::unparse::declarator;
+use crate::syntax::DataType;
+
+pub mod cfg;
+pub mod lower;
+pub mod ssa;
+pub mod verify;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Value {
+    Constant(i32),
+    Variable(String),
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Constant(val) => write!(f, "{}", val),
+            Self::Variable(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum UnaryOperator {
+    Negate,
+    Complement,
+    Not,
+}
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Negate => "-",
+            Self::Complement => "~",
+            Self::Not => "!",
+        }
+    }
+}
+
+// && and || don't appear here; lowering turns them into jumps.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+}
+impl BinaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Remainder => "%",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::LessThan => "<",
+            Self::LessEqual => "<=",
+            Self::GreaterThan => ">",
+            Self::GreaterEqual => ">=",
+        }
+    }
+}
+
+// Operations work on values promoted to int. A char variable is read sign
+// extended and written truncated, so a Copy between types is a conversion.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instruction {
+    Copy {
+        source: Value,
+        destination: String,
+    },
+    Unary {
+        operator: UnaryOperator,
+        source: Value,
+        destination: String,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Value,
+        right: Value,
+        destination: String,
+    },
+    // The address of a variable, static or string constant.
+    GetAddress {
+        name: String,
+        destination: String,
+    },
+    Call {
+        name: String,
+        arguments: Vec<Value>,
+        destination: String,
+        // Whether the callee may take arguments through `...`, which it
+        // can when nothing declares it.
+        variadic: bool,
+    },
+    VaStart {
+        list: String,
+    },
+    VaArg {
+        list: String,
+        data_type: DataType,
+        destination: String,
+    },
+    Label(String),
+    Jump(String),
+    JumpIfZero {
+        condition: Value,
+        target: String,
+    },
+    JumpIfNotZero {
+        condition: Value,
+        target: String,
+    },
+    Switch {
+        value: Value,
+        cases: Vec<(i32, String)>,
+        default: String,
+    },
+    Return(Value),
+    // Only in SSA form, at the start of a block: the value that arrives from
+    // each predecessor, named by its label.
+    Phi {
+        sources: Vec<(String, Value)>,
+        destination: String,
+    },
+}
+impl Instruction {
+    pub fn destination(&self) -> Option<&String> {
+        match self {
+            Self::Copy { destination, .. }
+            | Self::Unary { destination, .. }
+            | Self::Binary { destination, .. }
+            | Self::GetAddress { destination, .. }
+            | Self::Call { destination, .. }
+            | Self::VaArg { destination, .. }
+            | Self::Phi { destination, .. } => Some(destination),
+            _ => None,
+        }
+    }
+    pub fn destination_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Self::Copy { destination, .. }
+            | Self::Unary { destination, .. }
+            | Self::Binary { destination, .. }
+            | Self::GetAddress { destination, .. }
+            | Self::Call { destination, .. }
+            | Self::VaArg { destination, .. }
+            | Self::Phi { destination, .. } => Some(destination),
+            _ => None,
+        }
+    }
+    // The values the instruction reads. The va_list operands of va_start and
+    // va_arg are updated in memory and aren't values.
+    pub fn values(&self) -> Vec<&Value> {
+        match self {
+            Self::Copy { source, .. } | Self::Unary { source, .. } => vec![source],
+            Self::Binary { left, right, .. } => vec![left, right],
+            Self::Call { arguments, .. } => arguments.iter().collect(),
+            Self::JumpIfZero { condition, .. } | Self::JumpIfNotZero { condition, .. } => {
+                vec![condition]
+            }
+            Self::Switch { value, .. } | Self::Return(value) => vec![value],
+            Self::Phi { sources, .. } => sources.iter().map(|(_, value)| value).collect(),
+            _ => vec![],
+        }
+    }
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Self::Copy { source, .. } | Self::Unary { source, .. } => vec![source],
+            Self::Binary { left, right, .. } => vec![left, right],
+            Self::Call { arguments, .. } => arguments.iter_mut().collect(),
+            Self::JumpIfZero { condition, .. } | Self::JumpIfNotZero { condition, .. } => {
+                vec![condition]
+            }
+            Self::Switch { value, .. } | Self::Return(value) => vec![value],
+            Self::Phi { sources, .. } => sources.iter_mut().map(|(_, value)| value).collect(),
+            _ => vec![],
+        }
+    }
+    // The labels control can move to other than the next instruction.
+    pub fn targets(&self) -> Vec<&String> {
+        match self {
+            Self::Jump(target)
+            | Self::JumpIfZero { target, .. }
+            | Self::JumpIfNotZero { target, .. } => vec![target],
+            Self::Switch { cases, default, .. } => cases
+                .iter()
+                .map(|(_, target)| target)
+                .chain([default])
+                .collect(),
+            _ => vec![],
+        }
+    }
+    pub fn targets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::Jump(target)
+            | Self::JumpIfZero { target, .. }
+            | Self::JumpIfNotZero { target, .. } => vec![target],
+            Self::Switch { cases, default, .. } => cases
+                .iter_mut()
+                .map(|(_, target)| target)
+                .chain([default])
+                .collect(),
+            _ => vec![],
+        }
+    }
+    // Whether control never reaches the next instruction.
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Self::Jump(_) | Self::Switch { .. } | Self::Return(_))
+    }
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Copy {
+                source,
+                destination,
+            } => write!(f, "{} = {}", destination, source),
+            Self::Unary {
+                operator,
+                source,
+                destination,
+            } => write!(f, "{} = {}{}", destination, operator.symbol(), source),
+            Self::Binary {
+                operator,
+                left,
+                right,
+                destination,
+            } => write!(
+                f,
+                "{} = {} {} {}",
+                destination,
+                left,
+                operator.symbol(),
+                right
+            ),
+            Self::GetAddress { name, destination } => write!(f, "{} = &{}", destination, name),
+            Self::Call {
+                name,
+                arguments,
+                destination,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| format!("{}", argument))
+                    .collect();
+                write!(
+                    f,
+                    "{} = call {}({})",
+                    destination,
+                    name,
+                    arguments.join(", ")
+                )
+            }
+            Self::VaStart { list } => write!(f, "va_start {}", list),
+            Self::VaArg {
+                list,
+                data_type,
+                destination,
+            } => write!(
+                f,
+                "{} = va_arg {}, {}",
+                destination,
+                list,
+                declarator(data_type, "")
+            ),
+            Self::Label(label) => write!(f, "{}:", label),
+            Self::Jump(target) => write!(f, "jump {}", target),
+            Self::JumpIfZero { condition, target } => {
+                write!(f, "jump {} if {} == 0", target, condition)
+            }
+            Self::JumpIfNotZero { condition, target } => {
+                write!(f, "jump {} if {} != 0", target, condition)
+            }
+            Self::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                write!(f, "switch {}", value)?;
+                for (case, target) in cases.iter() {
+                    write!(f, ", {}: {}", case, target)?;
+                }
+                write!(f, ", default: {}", default)
+            }
+            Self::Return(value) => write!(f, "return {}", value),
+            Self::Phi {
+                sources,
+                destination,
+            } => {
+                let sources: Vec<String> = sources
+                    .iter()
+                    .map(|(label, value)| format!("[{}: {}]", label, value))
+                    .collect();
+                write!(f, "{} = phi {}", destination, sources.join(", "))
+            }
+        }
+    }
+}
+
+// A parameter, local or temporary of one function.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+pub struct Function {
+    pub name: String,
+    pub global: bool,
+    // An inline definition, which every file that includes it may repeat.
+    pub weak: bool,
+    pub parameters: Vec<String>,
+    pub variadic: bool,
+    pub inlining: Option<Inlining>,
+    pub variables: Vec<Variable>,
+    pub body: Vec<Instruction>,
+}
+impl Function {
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().find(|variable| variable.name == name)
+    }
+    // Adds a variable named after `base` that the function doesn't have yet.
+    pub fn fresh_variable(&mut self, base: &str, data_type: DataType) -> String {
+        let mut count = 1;
+        let name = loop {
+            let name = format!("{}.{}", base, count);
+            if self.variable(&name).is_none() {
+                break name;
+            }
+            count += 1;
+        };
+        self.variables.push(Variable {
+            name: name.clone(),
+            data_type,
+        });
+        name
+    }
+    // Variables that live in memory because something takes their address
+    // or updates them in place, like a va_list, rather than only through
+    // instruction destinations.
+    pub fn address_taken(&self) -> HashSet<&str> {
+        let mut names = HashSet::new();
+        for instruction in self.body.iter() {
+            match instruction {
+                Instruction::GetAddress { name, .. } => names.insert(name.as_str()),
+                Instruction::VaStart { list } | Instruction::VaArg { list, .. } => {
+                    names.insert(list.as_str())
+                }
+                _ => false,
+            };
+        }
+        names
+    }
+}
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|name| match self.variable(name) {
+                Some(variable) => declarator(&variable.data_type, name),
+                None => name.clone(),
+            })
+            .collect();
+        let variadic = match (self.variadic, parameters.is_empty()) {
+            (true, true) => "...",
+            (true, false) => ", ...",
+            (false, _) => "",
+        };
+        writeln!(
+            f,
+            "{} function {}({}{}):",
+            match (self.global, self.weak) {
+                (true, true) => "weak",
+                (true, false) => "global",
+                (false, _) => "static",
+            },
+            self.name,
+            parameters.join(", "),
+            variadic
+        )?;
+        for variable in self.variables.iter() {
+            if !self.parameters.contains(&variable.name) {
+                writeln!(f, "    {}", declarator(&variable.data_type, &variable.name))?;
+            }
+        }
+        for instruction in self.body.iter() {
+            match instruction {
+                Instruction::Label(_) => writeln!(f, "  {}", instruction)?,
+                _ => writeln!(f, "    {}", instruction)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// File scope variables and static locals, which live in the data section.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub data_type: DataType,
+    pub initial: Initializer,
+}
+
+// What static storage holds when the program starts.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Initializer {
+    Integer(i32),
+    // The address of a string constant.
+    Address(String),
+}
+impl fmt::Display for Initializer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Address(name) => write!(f, "&{}", name),
+        }
+    }
+}
+
+pub struct StringConstant {
+    pub name: String,
+    // As written between the quotes, escapes included.
+    pub value: String,
+}
+
+pub struct Program {
+    pub statics: Vec<StaticVariable>,
+    pub strings: Vec<StringConstant>,
+    pub functions: Vec<Function>,
+}
+impl Program {
+    pub fn static_variable(&self, name: &str) -> Option<&StaticVariable> {
+        self.statics.iter().find(|variable| variable.name == name)
+    }
+}
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for variable in self.statics.iter() {
+            writeln!(
+                f,
+                "{} {} = {}",
+                if variable.global { "global" } else { "static" },
+                declarator(&variable.data_type, &variable.name),
+                variable.initial
+            )?;
+        }
+        for string in self.strings.iter() {
+            writeln!(f, "string {} = \"{}\"", string.name, string.value)?;
+        }
+        let has_data = !self.statics.is_empty() || !self.strings.is_empty();
+        for (idx, function) in self.functions.iter().enumerate() {
+            if idx > 0 || has_data {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
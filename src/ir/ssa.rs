@@ -0,0 +1,355 @@
+// Static single assignment form: every variable that doesn't live in memory
+// is written by exactly one instruction, and where control paths with
+// different writes meet, a phi picks the value by the path taken. A
+// variable's original name stands for its value on entry, which is the
+// argument for a parameter and undefined for anything else.
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{Cfg, Dominators};
+use super::{Function, Instruction, Value};
+use crate::opt::dataflow::{self, Liveness};
+
+// Unreachable blocks are dropped on the way in, since they have no place in
+// the dominator tree.
+pub fn construct(function: &mut Function) {
+    let mut cfg = Cfg::new(function);
+    cfg.remove_unreachable();
+    let address_taken = function.address_taken();
+    let variables: Vec<String> = function
+        .variables
+        .iter()
+        .map(|variable| variable.name.clone())
+        .filter(|name| !address_taken.contains(name.as_str()))
+        .collect();
+    let dominators = Dominators::new(&cfg);
+    let frontiers = dominators.frontiers(&cfg);
+    let liveness = Liveness {
+        variables: &variables.iter().cloned().collect(),
+    };
+    let live_in = dataflow::solve(&liveness, &cfg).before;
+
+    // A phi goes where the value of a variable written in one block may
+    // meet another value, as long as the variable is still read there.
+    let mut phis: Vec<Vec<String>> = vec![vec![]; cfg.blocks.len()];
+    for variable in variables.iter() {
+        let mut pending: Vec<usize> = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| {
+                block
+                    .instructions
+                    .iter()
+                    .any(|instruction| instruction.destination() == Some(variable))
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        let mut defined: HashSet<usize> = pending.iter().copied().collect();
+        while let Some(block) = pending.pop() {
+            for frontier in frontiers[block].iter() {
+                if phis[*frontier].contains(variable) || !live_in[*frontier].contains(variable) {
+                    continue;
+                }
+                phis[*frontier].push(variable.clone());
+                if defined.insert(*frontier) {
+                    pending.push(*frontier);
+                }
+            }
+        }
+    }
+    // Keep the phis in the order the variables were declared.
+    for (idx, block) in cfg.blocks.iter_mut().enumerate() {
+        phis[idx].sort_by_key(|name| variables.iter().position(|variable| variable == name));
+        let new: Vec<Instruction> = phis[idx]
+            .iter()
+            .map(|variable| Instruction::Phi {
+                sources: vec![],
+                destination: variable.clone(),
+            })
+            .collect();
+        block.instructions.splice(0..0, new);
+    }
+
+    let mut renamer = Renamer {
+        function: &mut *function,
+        versions: variables
+            .iter()
+            .map(|name| (name.clone(), vec![name.clone()]))
+            .collect(),
+        phis,
+    };
+    let children = dominators.children();
+    renamer.rename(&mut cfg, &children, 0);
+    // List phi sources in program order rather than the order of the walk.
+    let labels: Vec<String> = cfg.blocks.iter().map(|block| block.label.clone()).collect();
+    for block in cfg.blocks.iter_mut() {
+        for instruction in block.instructions.iter_mut() {
+            if let Instruction::Phi { sources, .. } = instruction {
+                sources.sort_by_key(|(label, _)| labels.iter().position(|other| other == label));
+            }
+        }
+    }
+    function.body = cfg.instructions();
+    // Renaming leaves most original names unused, all but those of
+    // parameters and of variables read before they're written.
+    let mut used: HashSet<String> = function.parameters.iter().cloned().collect();
+    for instruction in function.body.iter() {
+        for value in instruction.values() {
+            if let Value::Variable(name) = value {
+                used.insert(name.clone());
+            }
+        }
+    }
+    function
+        .variables
+        .retain(|variable| !variables.contains(&variable.name) || used.contains(&variable.name));
+}
+
+struct Renamer<'a> {
+    function: &'a mut Function,
+    // The names a variable had along the dominator tree down to the block
+    // being renamed, innermost last.
+    versions: HashMap<String, Vec<String>>,
+    // The variable each phi at the start of a block is for.
+    phis: Vec<Vec<String>>,
+}
+impl Renamer<'_> {
+    fn current(&self, value: &mut Value) {
+        if let Value::Variable(name) = value {
+            if let Some(versions) = self.versions.get(name) {
+                *name = versions.last().unwrap().clone();
+            }
+        }
+    }
+
+    fn rename(&mut self, cfg: &mut Cfg, children: &[Vec<usize>], block: usize) {
+        let mut written = vec![];
+        for instruction in cfg.blocks[block].instructions.iter_mut() {
+            if !matches!(instruction, Instruction::Phi { .. }) {
+                for value in instruction.values_mut() {
+                    self.current(value);
+                }
+            }
+            let Some(destination) = instruction.destination_mut() else {
+                continue;
+            };
+            if !self.versions.contains_key(destination) {
+                continue;
+            }
+            let data_type = self
+                .function
+                .variable(destination)
+                .unwrap()
+                .data_type
+                .clone();
+            let version = self.function.fresh_variable(destination, data_type);
+            self.versions
+                .get_mut(destination)
+                .unwrap()
+                .push(version.clone());
+            written.push(destination.clone());
+            *destination = version;
+        }
+        let label = cfg.blocks[block].label.clone();
+        for successor in cfg.blocks[block].successors.clone() {
+            for (idx, variable) in self.phis[successor].clone().iter().enumerate() {
+                let mut value = Value::Variable(variable.clone());
+                self.current(&mut value);
+                if let Instruction::Phi { sources, .. } =
+                    &mut cfg.blocks[successor].instructions[idx]
+                {
+                    sources.push((label.clone(), value));
+                }
+            }
+        }
+        for child in children[block].iter() {
+            self.rename(cfg, children, *child);
+        }
+        for variable in written {
+            self.versions.get_mut(&variable).unwrap().pop();
+        }
+    }
+}
+
+// Each phi becomes a copy at the end of every predecessor into a variable
+// of its own, and a copy from that at the start of its block. Going through
+// a separate variable keeps phis that read each other's destinations, as
+// when two variables swap around a loop, from seeing the new values.
+pub fn destruct(function: &mut Function) {
+    let mut cfg = Cfg::new(function);
+    for idx in 0..cfg.blocks.len() {
+        let count = cfg.blocks[idx].phi_count();
+        let phis: Vec<Instruction> = cfg.blocks[idx].instructions.drain(..count).collect();
+        let mut copies = vec![];
+        for phi in phis {
+            let Instruction::Phi {
+                sources,
+                destination,
+            } = phi
+            else {
+                continue;
+            };
+            let data_type = function.variable(&destination).unwrap().data_type.clone();
+            let incoming = function.fresh_variable("phi", data_type);
+            for (label, value) in sources {
+                let predecessor = cfg
+                    .blocks
+                    .iter_mut()
+                    .find(|block| block.label == label)
+                    .unwrap();
+                let end = match predecessor.instructions.last() {
+                    Some(last) if !last.targets().is_empty() || last.is_terminator() => {
+                        predecessor.instructions.len() - 1
+                    }
+                    _ => predecessor.instructions.len(),
+                };
+                predecessor.instructions.insert(
+                    end,
+                    Instruction::Copy {
+                        source: value,
+                        destination: incoming.clone(),
+                    },
+                );
+            }
+            copies.push(Instruction::Copy {
+                source: Value::Variable(incoming),
+                destination,
+            });
+        }
+        cfg.blocks[idx].instructions.splice(0..0, copies);
+    }
+    function.body = cfg.instructions();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::{verify, Variable};
+    use crate::syntax::DataType;
+
+    #[test]
+    fn test_construct_places_phis_at_joins() {
+        let mut program = lower_source(
+            "int f(int n) {
+                int x = 1;
+                int y = 2;
+                int t;
+                again:
+                t = x;
+                x = y;
+                y = t;
+                n = n - 1;
+                switch (n) {
+                case 0:
+                    return x - y;
+                }
+                goto again;
+            }",
+        );
+        construct(&mut program.functions[0]);
+        let expected = "\
+global function f(int n.0):
+    int x.1.1
+    int y.2.1
+    int n.0.1
+    int x.1.2
+    int y.2.2
+    int t.3.1
+    int x.1.3
+    int y.2.3
+    int tmp.4.1
+    int n.0.2
+    int tmp.5.1
+  f.block.0:
+    x.1.1 = 1
+    y.2.1 = 2
+  f.again:
+    n.0.1 = phi [f.block.0: n.0], [switch_end.0: n.0.2]
+    x.1.2 = phi [f.block.0: x.1.1], [switch_end.0: x.1.3]
+    y.2.2 = phi [f.block.0: y.2.1], [switch_end.0: y.2.3]
+    t.3.1 = x.1.2
+    x.1.3 = y.2.2
+    y.2.3 = t.3.1
+    tmp.4.1 = n.0.1 - 1
+    n.0.2 = tmp.4.1
+    switch n.0.2, 0: case.1, default: switch_end.0
+  case.1:
+    tmp.5.1 = x.1.3 - y.2.3
+    return tmp.5.1
+  switch_end.0:
+    jump f.again
+";
+        assert_eq!(expected, program.to_string());
+        assert_eq!(Ok(()), verify::verify(&program, true));
+    }
+
+    #[test]
+    fn test_destruct_keeps_swapping_phis_apart() {
+        let variable = |name: &str| Value::Variable(String::from(name));
+        let mut function = Function {
+            name: String::from("f"),
+            global: true,
+            weak: false,
+            parameters: vec![String::from("a"), String::from("b")],
+            variadic: false,
+            inlining: None,
+            variables: ["a", "b", "x", "y", "c"]
+                .iter()
+                .map(|name| Variable {
+                    name: String::from(*name),
+                    data_type: DataType::Int,
+                })
+                .collect(),
+            body: vec![
+                Instruction::Label(String::from("loop")),
+                Instruction::Phi {
+                    sources: vec![
+                        (String::from("f.block.0"), variable("a")),
+                        (String::from("loop"), variable("y")),
+                    ],
+                    destination: String::from("x"),
+                },
+                Instruction::Phi {
+                    sources: vec![
+                        (String::from("f.block.0"), variable("b")),
+                        (String::from("loop"), variable("x")),
+                    ],
+                    destination: String::from("y"),
+                },
+                Instruction::Call {
+                    name: String::from("g"),
+                    arguments: vec![variable("x"), variable("y")],
+                    destination: String::from("c"),
+                    variadic: false,
+                },
+                Instruction::JumpIfNotZero {
+                    condition: variable("c"),
+                    target: String::from("loop"),
+                },
+                Instruction::Return(variable("x")),
+            ],
+        };
+        destruct(&mut function);
+        let body: Vec<String> = function
+            .body
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "phi.1 = a",
+                "phi.2 = b",
+                "loop:",
+                "x = phi.1",
+                "y = phi.2",
+                "c = call g(x, y)",
+                "phi.1 = y",
+                "phi.2 = x",
+                "jump loop if c != 0",
+                "return x",
+            ],
+            body
+        );
+    }
+}
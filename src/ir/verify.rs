@@ -0,0 +1,349 @@
+// Checks that a program is well formed IR, and with `ssa` that it keeps the
+// invariants of SSA form, so a pass that breaks them is caught right after
+// it runs instead of as wrong code.
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{Cfg, Dominators};
+use super::{Function, Instruction, Program, Value};
+
+pub fn verify(program: &Program, ssa: bool) -> Result<(), String> {
+    let mut labels = HashSet::new();
+    for function in program.functions.iter() {
+        verify_function(program, function, ssa, &mut labels)
+            .map_err(|msg| format!("in {}: {}", function.name, msg))?;
+    }
+    Ok(())
+}
+
+// `labels` collects the labels of every function, which share one
+// namespace in the assembly.
+fn verify_function(
+    program: &Program,
+    function: &Function,
+    ssa: bool,
+    labels: &mut HashSet<String>,
+) -> Result<(), String> {
+    let mut variables = HashSet::new();
+    for variable in function.variables.iter() {
+        if !variables.insert(variable.name.as_str()) {
+            return Err(format!("{} is declared twice", variable.name));
+        }
+    }
+    for parameter in function.parameters.iter() {
+        if !variables.contains(parameter.as_str()) {
+            return Err(format!("parameter {} isn't declared", parameter));
+        }
+    }
+    let is_variable =
+        |name: &str| variables.contains(name) || program.static_variable(name).is_some();
+    let mut own_labels = HashSet::new();
+    for instruction in function.body.iter() {
+        if let Instruction::Label(label) = instruction {
+            if !labels.insert(label.clone()) {
+                return Err(format!("label {} is defined twice", label));
+            }
+            own_labels.insert(label.as_str());
+        }
+    }
+    for instruction in function.body.iter() {
+        for target in instruction.targets() {
+            if !own_labels.contains(target.as_str()) {
+                return Err(format!(
+                    "'{}' jumps to undefined label {}",
+                    instruction, target
+                ));
+            }
+        }
+        let mut names: Vec<&String> = instruction
+            .values()
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Variable(name) => Some(name),
+                Value::Constant(_) => None,
+            })
+            .collect();
+        names.extend(instruction.destination());
+        match instruction {
+            Instruction::VaStart { list } | Instruction::VaArg { list, .. } => names.push(list),
+            Instruction::GetAddress { name, .. }
+                if program.strings.iter().all(|string| &string.name != name) =>
+            {
+                names.push(name)
+            }
+            Instruction::Phi { .. } if !ssa => {
+                return Err(format!("'{}' is outside SSA form", instruction))
+            }
+            _ => {}
+        }
+        for name in names {
+            if !is_variable(name) {
+                return Err(format!("'{}' uses undeclared {}", instruction, name));
+            }
+        }
+    }
+    if !function
+        .body
+        .last()
+        .is_some_and(|last| last.is_terminator())
+    {
+        return Err(String::from("control reaches the end without a return"));
+    }
+    if ssa {
+        verify_ssa(function)?;
+    }
+    Ok(())
+}
+
+fn verify_ssa(function: &Function) -> Result<(), String> {
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+    let address_taken = function.address_taken();
+    // Where each variable is written, as a block and an index in it. The
+    // original names of parameters hold the arguments from the start.
+    let mut definitions: HashMap<&str, (usize, Option<usize>)> = function
+        .parameters
+        .iter()
+        .map(|parameter| (parameter.as_str(), (0, None)))
+        .collect();
+    for (idx, block) in cfg.blocks.iter().enumerate() {
+        if idx > 0 && dominators.immediate[idx].is_none() {
+            return Err(format!("block {} is unreachable", block.label));
+        }
+        let phi_count = block.phi_count();
+        for (position, instruction) in block.instructions.iter().enumerate() {
+            if let Instruction::Phi { sources, .. } = instruction {
+                if position >= phi_count {
+                    return Err(format!("'{}' isn't at the start of a block", instruction));
+                }
+                let mut labels: Vec<&str> =
+                    sources.iter().map(|(label, _)| label.as_str()).collect();
+                let mut predecessors: Vec<&str> = block
+                    .predecessors
+                    .iter()
+                    .map(|predecessor| cfg.blocks[*predecessor].label.as_str())
+                    .collect();
+                labels.sort();
+                predecessors.sort();
+                if labels != predecessors {
+                    return Err(format!(
+                        "'{}' doesn't have one source for each predecessor of {}",
+                        instruction, block.label
+                    ));
+                }
+            }
+            let Some(destination) = instruction.destination() else {
+                continue;
+            };
+            if address_taken.contains(destination.as_str()) {
+                continue;
+            }
+            if function.variable(destination).is_some()
+                && definitions
+                    .insert(destination.as_str(), (idx, Some(position)))
+                    .is_some()
+            {
+                return Err(format!("{} is assigned more than once", destination));
+            }
+        }
+    }
+    // Every read must come after the write on all paths to it. A phi reads
+    // at the end of the predecessor the value comes from.
+    let defined_before =
+        |name: &str, block: usize, position: Option<usize>| match definitions.get(name) {
+            None => true,
+            Some((definition, None)) => dominators.dominates(*definition, block),
+            Some((definition, Some(index))) if *definition == block => {
+                position.is_none_or(|position| *index < position)
+            }
+            Some((definition, Some(_))) => dominators.dominates(*definition, block),
+        };
+    for (idx, block) in cfg.blocks.iter().enumerate() {
+        for (position, instruction) in block.instructions.iter().enumerate() {
+            let reads: Vec<(&String, usize, Option<usize>)> = match instruction {
+                Instruction::Phi { sources, .. } => sources
+                    .iter()
+                    .filter_map(|(label, value)| match value {
+                        Value::Variable(name) => {
+                            let predecessor = cfg
+                                .blocks
+                                .iter()
+                                .position(|block| &block.label == label)
+                                .unwrap();
+                            Some((name, predecessor, None))
+                        }
+                        Value::Constant(_) => None,
+                    })
+                    .collect(),
+                _ => instruction
+                    .values()
+                    .into_iter()
+                    .filter_map(|value| match value {
+                        Value::Variable(name) => Some((name, idx, Some(position))),
+                        Value::Constant(_) => None,
+                    })
+                    .collect(),
+            };
+            for (name, block, position) in reads {
+                if !defined_before(name, block, position) {
+                    return Err(format!(
+                        "{} is read in '{}' where its assignment doesn't dominate",
+                        name, instruction
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BinaryOperator, Initializer, StaticVariable, Variable};
+    use crate::syntax::DataType;
+
+    fn function(body: Vec<Instruction>) -> Program {
+        let variables = ["a", "b", "c"]
+            .iter()
+            .map(|name| Variable {
+                name: String::from(*name),
+                data_type: DataType::Int,
+            })
+            .collect();
+        Program {
+            statics: vec![StaticVariable {
+                name: String::from("g"),
+                global: true,
+                data_type: DataType::Int,
+                initial: Initializer::Integer(0),
+            }],
+            strings: vec![],
+            functions: vec![Function {
+                name: String::from("f"),
+                global: true,
+                weak: false,
+                parameters: vec![String::from("a")],
+                variadic: false,
+                inlining: None,
+                variables,
+                body,
+            }],
+        }
+    }
+
+    fn copy(source: Value, destination: &str) -> Instruction {
+        Instruction::Copy {
+            source,
+            destination: String::from(destination),
+        }
+    }
+
+    fn variable(name: &str) -> Value {
+        Value::Variable(String::from(name))
+    }
+
+    #[test]
+    fn test_verify_accepts_ssa() {
+        let program = function(vec![
+            Instruction::JumpIfZero {
+                condition: variable("a"),
+                target: String::from("else"),
+            },
+            copy(Value::Constant(1), "b"),
+            Instruction::Jump(String::from("end")),
+            Instruction::Label(String::from("else")),
+            copy(variable("a"), "g"),
+            copy(variable("a"), "g"),
+            Instruction::Label(String::from("end")),
+            Instruction::Phi {
+                sources: vec![
+                    (String::from("f.block.1"), variable("b")),
+                    (String::from("else"), Value::Constant(2)),
+                ],
+                destination: String::from("c"),
+            },
+            Instruction::Return(variable("c")),
+        ]);
+        assert_eq!(Ok(()), verify(&program, true));
+        assert_eq!(
+            Err(String::from(
+                "in f: 'c = phi [f.block.1: b], [else: 2]' is outside SSA form"
+            )),
+            verify(&program, false)
+        );
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let cases = [
+            (
+                vec![copy(Value::Constant(1), "b")],
+                "control reaches the end without a return",
+            ),
+            (
+                vec![Instruction::Jump(String::from("nowhere"))],
+                "'jump nowhere' jumps to undefined label nowhere",
+            ),
+            (
+                vec![
+                    copy(variable("x"), "b"),
+                    Instruction::Return(Value::Constant(0)),
+                ],
+                "'b = x' uses undeclared x",
+            ),
+            (
+                vec![
+                    copy(Value::Constant(1), "b"),
+                    copy(Value::Constant(2), "b"),
+                    Instruction::Return(variable("b")),
+                ],
+                "b is assigned more than once",
+            ),
+            (
+                vec![
+                    copy(variable("b"), "c"),
+                    copy(Value::Constant(1), "b"),
+                    Instruction::Return(variable("c")),
+                ],
+                "b is read in 'c = b' where its assignment doesn't dominate",
+            ),
+            (
+                vec![
+                    Instruction::Binary {
+                        operator: BinaryOperator::Add,
+                        left: variable("a"),
+                        right: Value::Constant(1),
+                        destination: String::from("a"),
+                    },
+                    Instruction::Return(variable("a")),
+                ],
+                "a is assigned more than once",
+            ),
+            (
+                vec![
+                    Instruction::Return(Value::Constant(0)),
+                    Instruction::Label(String::from("dead")),
+                    Instruction::Return(Value::Constant(1)),
+                ],
+                "block dead is unreachable",
+            ),
+            (
+                vec![
+                    copy(Value::Constant(1), "b"),
+                    Instruction::Phi {
+                        sources: vec![(String::from("f.block.0"), Value::Constant(0))],
+                        destination: String::from("c"),
+                    },
+                    Instruction::Return(variable("c")),
+                ],
+                "'c = phi [f.block.0: 0]' isn't at the start of a block",
+            ),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(
+                Err(format!("in f: {}", expected)),
+                verify(&function(body), true)
+            );
+        }
+    }
+}
@@ -1,3 +1,9 @@
+pub mod codegen;
+pub mod driver;
+pub mod ir;
+pub mod opt;
 pub mod parser;
+pub mod preprocessor;
+pub mod sema;
 pub mod syntax;
 pub mod tokenizer;
@@ -0,0 +1,163 @@
+// Copy propagation: after `x = y`, reads of x read y instead for as long as
+// neither changes, which leaves the copy for dead code elimination. Which
+// copies hold where is a forward dataflow analysis, so a copy of a static
+// stops holding at the next write to it or call.
+use std::collections::{HashMap, HashSet};
+
+use super::dataflow::{self, Analysis, Direction};
+use crate::ir::cfg::Cfg;
+use crate::ir::{Function, Instruction, Program, Value};
+use crate::syntax::DataType;
+
+pub fn propagate_copies(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        let mut types: HashMap<String, DataType> = program
+            .statics
+            .iter()
+            .map(|variable| (variable.name.clone(), variable.data_type.clone()))
+            .collect();
+        types.extend(
+            function
+                .variables
+                .iter()
+                .map(|variable| (variable.name.clone(), variable.data_type.clone())),
+        );
+        let registers = super::registers(function);
+        propagate_function(function, &AvailableCopies { registers, types });
+    }
+}
+
+// The copies whose destination still holds what the source does, from
+// destination to source. A copy of a copy is from the original source.
+struct AvailableCopies {
+    registers: HashSet<String>,
+    types: HashMap<String, DataType>,
+}
+impl Analysis for AvailableCopies {
+    type Fact = HashMap<String, String>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+    fn meet(
+        &self,
+        left: &HashMap<String, String>,
+        right: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        left.iter()
+            .filter(|(destination, source)| right.get(*destination) == Some(source))
+            .map(|(destination, source)| (destination.clone(), source.clone()))
+            .collect()
+    }
+    fn transfer(
+        &self,
+        instruction: &Instruction,
+        copies: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        let mut copies = copies.clone();
+        if super::writes_memory(instruction) {
+            copies.retain(|_, source| self.registers.contains(source));
+        }
+        let Some(destination) = instruction.destination() else {
+            return copies;
+        };
+        copies.retain(|other, source| other != destination && source != destination);
+        if let Instruction::Copy {
+            source: Value::Variable(source),
+            ..
+        } = instruction
+        {
+            let source = copies.get(source).unwrap_or(source).clone();
+            // A copy between types converts, so the destination doesn't hold
+            // the same value.
+            if self.registers.contains(destination)
+                && source != *destination
+                && self.types.get(&source) == self.types.get(destination)
+            {
+                copies.insert(destination.clone(), source);
+            }
+        }
+        copies
+    }
+}
+
+fn propagate_function(function: &mut Function, analysis: &AvailableCopies) {
+    let mut cfg = Cfg::new(function);
+    let solution = dataflow::solve(analysis, &cfg);
+    let blocks: HashMap<String, usize> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(idx, block)| (block.label.clone(), idx))
+        .collect();
+    for idx in 0..cfg.blocks.len() {
+        let facts = analysis.instruction_facts(&cfg.blocks[idx], &solution.before[idx]);
+        for (instruction, copies) in cfg.blocks[idx].instructions.iter_mut().zip(facts) {
+            // A phi reads each source at the end of the block it comes from.
+            if let Instruction::Phi { sources, .. } = instruction {
+                for (label, value) in sources.iter_mut() {
+                    replace(value, &solution.after[blocks[label]]);
+                }
+                continue;
+            }
+            for value in instruction.values_mut() {
+                replace(value, &copies);
+            }
+        }
+    }
+    function.body = cfg.instructions();
+}
+
+fn replace(value: &mut Value, copies: &HashMap<String, String>) {
+    if let Value::Variable(name) = value {
+        if let Some(source) = copies.get(name) {
+            *value = Value::Variable(source.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+
+    #[test]
+    fn test_copies_are_propagated_until_overwritten() {
+        let mut program = lower_source(
+            "int g;
+            int h(void);
+            int f(int n) {
+                int a = n;
+                char c = n;
+                int b = g;
+                int d = b + a + c;
+                h();
+                return b + a;
+            }",
+        );
+        ssa::construct(&mut program.functions[0]);
+        propagate_copies(&mut program);
+        let body: Vec<String> = program.functions[0]
+            .body
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "a.1.1 = n.0",
+                "tmp.3.1 = n.0",
+                "c.2.1 = tmp.3.1",
+                "b.4.1 = g",
+                "tmp.6.1 = g + n.0",
+                "tmp.7.1 = tmp.6.1 + tmp.3.1",
+                "d.5.1 = tmp.7.1",
+                "tmp.8.1 = call h()",
+                "tmp.9.1 = b.4.1 + n.0",
+                "return tmp.9.1",
+            ],
+            body
+        );
+    }
+}
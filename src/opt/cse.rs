@@ -0,0 +1,253 @@
+// Common subexpression elimination: an operation whose result a variable
+// already holds becomes a copy of that variable, which is read instead from
+// then on, leaving the copy for dead code elimination. The local pass looks within one block at a time; the
+// global one starts each block from the expressions available on every
+// path to it, a forward dataflow analysis.
+use std::collections::{HashMap, HashSet};
+
+use super::dataflow::{self, Analysis, Direction};
+use crate::ir::cfg::Cfg;
+use crate::ir::{BinaryOperator, Function, Instruction, Program, UnaryOperator, Value};
+
+pub fn eliminate_local(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        eliminate_function(function, false);
+    }
+}
+
+pub fn eliminate_global(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        eliminate_function(function, true);
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum Expression {
+    Unary(UnaryOperator, Value),
+    Binary(BinaryOperator, Value, Value),
+    Address(String),
+}
+impl Expression {
+    fn of(instruction: &Instruction) -> Option<Expression> {
+        match instruction {
+            Instruction::Unary {
+                operator, source, ..
+            } => Some(Expression::Unary(*operator, source.clone())),
+            // Operands of an operator that doesn't care about their order
+            // are sorted, so a + b and b + a are the same expression.
+            Instruction::Binary {
+                operator,
+                left,
+                right,
+                ..
+            } => {
+                let commutes = matches!(
+                    operator,
+                    BinaryOperator::Add
+                        | BinaryOperator::Multiply
+                        | BinaryOperator::Equal
+                        | BinaryOperator::NotEqual
+                );
+                let (left, right) = if commutes && left.to_string() > right.to_string() {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                Some(Expression::Binary(*operator, left.clone(), right.clone()))
+            }
+            Instruction::GetAddress { name, .. } => Some(Expression::Address(name.clone())),
+            _ => None,
+        }
+    }
+
+    // The variables it reads. An address doesn't change, whatever the
+    // variable holds.
+    fn reads(&self) -> Vec<&String> {
+        let values = match self {
+            Expression::Unary(_, source) => vec![source],
+            Expression::Binary(_, left, right) => vec![left, right],
+            Expression::Address(_) => vec![],
+        };
+        values
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Variable(name) => Some(name),
+                Value::Constant(_) => None,
+            })
+            .collect()
+    }
+}
+
+// The expressions some variable holds the value of, with the first such
+// variable.
+struct AvailableExpressions {
+    registers: HashSet<String>,
+}
+impl Analysis for AvailableExpressions {
+    type Fact = HashMap<Expression, String>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> HashMap<Expression, String> {
+        HashMap::new()
+    }
+    fn meet(
+        &self,
+        left: &HashMap<Expression, String>,
+        right: &HashMap<Expression, String>,
+    ) -> HashMap<Expression, String> {
+        left.iter()
+            .filter(|(expression, variable)| right.get(*expression) == Some(variable))
+            .map(|(expression, variable)| (expression.clone(), variable.clone()))
+            .collect()
+    }
+    fn transfer(
+        &self,
+        instruction: &Instruction,
+        available: &HashMap<Expression, String>,
+    ) -> HashMap<Expression, String> {
+        let mut available = available.clone();
+        if super::writes_memory(instruction) {
+            available.retain(|expression, _| {
+                expression
+                    .reads()
+                    .iter()
+                    .all(|name| self.registers.contains(*name))
+            });
+        }
+        let Some(destination) = instruction.destination() else {
+            return available;
+        };
+        available.retain(|expression, variable| {
+            variable != destination && !expression.reads().contains(&destination)
+        });
+        if let Some(expression) = Expression::of(instruction) {
+            if self.registers.contains(destination) && !expression.reads().contains(&destination) {
+                available
+                    .entry(expression)
+                    .or_insert_with(|| destination.clone());
+            }
+        }
+        available
+    }
+}
+
+// Blocks go in reverse postorder, so a variable is renamed before the
+// blocks it dominates read it, except for phis around back edges.
+fn eliminate_function(function: &mut Function, global: bool) {
+    let mut cfg = Cfg::new(function);
+    let analysis = AvailableExpressions {
+        registers: super::registers(function),
+    };
+    let starts = if global {
+        dataflow::solve(&analysis, &cfg).before
+    } else {
+        vec![HashMap::new(); cfg.blocks.len()]
+    };
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    for idx in cfg.reverse_postorder() {
+        let facts = analysis.instruction_facts(&cfg.blocks[idx], &starts[idx]);
+        for (instruction, available) in cfg.blocks[idx].instructions.iter_mut().zip(facts) {
+            if !matches!(instruction, Instruction::Phi { .. }) {
+                for value in instruction.values_mut() {
+                    rename(value, &renamed);
+                }
+            }
+            let (Some(expression), Some(destination)) =
+                (Expression::of(instruction), instruction.destination())
+            else {
+                continue;
+            };
+            let Some(variable) = available.get(&expression) else {
+                continue;
+            };
+            let variable = renamed.get(variable).unwrap_or(variable).clone();
+            // Unless the types match, the copy would convert.
+            let data_type = |name: &str| function.variable(name).map(|v| &v.data_type);
+            if data_type(&variable) == data_type(destination) {
+                renamed.insert(destination.clone(), variable.clone());
+                *instruction = Instruction::Copy {
+                    source: Value::Variable(variable),
+                    destination: destination.clone(),
+                };
+            }
+        }
+    }
+    for block in cfg.blocks.iter_mut() {
+        for instruction in block.instructions.iter_mut() {
+            if let Instruction::Phi { sources, .. } = instruction {
+                for (_, value) in sources.iter_mut() {
+                    rename(value, &renamed);
+                }
+            }
+        }
+    }
+    function.body = cfg.instructions();
+}
+
+fn rename(value: &mut Value, renamed: &HashMap<String, String>) {
+    if let Value::Variable(name) = value {
+        if let Some(variable) = renamed.get(name) {
+            *value = Value::Variable(variable.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+
+    #[test]
+    fn test_common_subexpressions_are_reused() {
+        let source = "int g;
+            int h(void);
+            int f(int a, int b) {
+                int x = a * b + g;
+                int y = b * a + g;
+                h();
+                int z = a * b + g;
+                switch (a) {
+                case 1:
+                    x = a * b;
+                }
+                return x + y + z + a * b;
+            }";
+        let body = |global| {
+            let mut program = lower_source(source);
+            ssa::construct(&mut program.functions[0]);
+            if global {
+                eliminate_global(&mut program);
+            } else {
+                eliminate_local(&mut program);
+            }
+            program.functions[0]
+                .body
+                .iter()
+                .map(|instruction| instruction.to_string())
+                .collect::<Vec<String>>()
+        };
+        // The block before the switch is the same either way, with the
+        // product reused but not the sum read from g across the call.
+        let (local, global) = (body(false), body(true));
+        let expected = vec![
+            "f.block.0:",
+            "tmp.3.1 = a.0 * b.1",
+            "tmp.4.1 = tmp.3.1 + g",
+            "x.2.1 = tmp.4.1",
+            "tmp.6.1 = tmp.3.1",
+            "tmp.7.1 = tmp.4.1",
+            "y.5.1 = tmp.4.1",
+            "tmp.8.1 = call h()",
+            "tmp.10.1 = tmp.3.1",
+            "tmp.11.1 = tmp.3.1 + g",
+            "z.9.1 = tmp.11.1",
+        ];
+        assert_eq!(expected, local[..11]);
+        assert_eq!(expected, global[..11]);
+        assert_eq!("tmp.12.1 = a.0 * b.1", local[13]);
+        assert_eq!("tmp.12.1 = tmp.3.1", global[13]);
+        assert_eq!("tmp.16.1 = tmp.14.1 + tmp.15.1", local[20]);
+        assert_eq!("tmp.16.1 = tmp.14.1 + tmp.3.1", global[20]);
+    }
+}
@@ -0,0 +1,238 @@
+// Dataflow analysis over a function's CFG. An analysis picks the lattice its
+// facts live in, which way they flow, how facts from different paths
+// combine and how an instruction changes them; the solver iterates until the
+// facts at every block stop changing.
+use std::collections::HashSet;
+
+use crate::ir::cfg::{Block, Cfg};
+use crate::ir::{Instruction, Value};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+pub trait Analysis {
+    type Fact: Clone + PartialEq;
+    const DIRECTION: Direction;
+
+    // What holds at the entry going forward, or at the exits going backward.
+    fn boundary(&self) -> Self::Fact;
+    // Combines the facts of paths that meet. For the solver to finish, meet
+    // must be commutative, associative and idempotent, and transfer
+    // monotone.
+    fn meet(&self, left: &Self::Fact, right: &Self::Fact) -> Self::Fact;
+    // What holds on the far side of the instruction from `fact`: after it
+    // going forward, before it going backward.
+    fn transfer(&self, instruction: &Instruction, fact: &Self::Fact) -> Self::Fact;
+
+    // The facts on the near side of each instruction of `block`, in
+    // program order, given the fact at the block's near end.
+    fn instruction_facts(&self, block: &Block, fact: &Self::Fact) -> Vec<Self::Fact> {
+        let mut facts = vec![];
+        let mut fact = fact.clone();
+        let instructions: Vec<&Instruction> = match Self::DIRECTION {
+            Direction::Forward => block.instructions.iter().collect(),
+            Direction::Backward => block.instructions.iter().rev().collect(),
+        };
+        for instruction in instructions {
+            facts.push(fact.clone());
+            fact = self.transfer(instruction, &fact);
+        }
+        if Self::DIRECTION == Direction::Backward {
+            facts.reverse();
+        }
+        facts
+    }
+}
+
+// The facts at the start and at the end of every block, in program order
+// whichever way the analysis runs.
+pub struct Solution<F> {
+    pub before: Vec<F>,
+    pub after: Vec<F>,
+}
+
+// Blocks are visited in reverse postorder going forward and postorder going
+// backward, so most facts are final after one round. A block meets only
+// the neighbours that have a fact yet, which leaves out back edges on the
+// first round as if they held the top of the lattice.
+pub fn solve<A: Analysis>(analysis: &A, cfg: &Cfg) -> Solution<A::Fact> {
+    let mut order = cfg.reverse_postorder();
+    if A::DIRECTION == Direction::Backward {
+        order.reverse();
+    }
+    let mut near: Vec<Option<A::Fact>> = vec![None; cfg.blocks.len()];
+    let mut far: Vec<Option<A::Fact>> = vec![None; cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in order.iter() {
+            let neighbours = match A::DIRECTION {
+                Direction::Forward => &cfg.blocks[*block].predecessors,
+                Direction::Backward => &cfg.blocks[*block].successors,
+            };
+            let input = neighbours
+                .iter()
+                .filter_map(|neighbour| far[*neighbour].as_ref())
+                .fold(None, |fact: Option<A::Fact>, other| match fact {
+                    None => Some(other.clone()),
+                    Some(fact) => Some(analysis.meet(&fact, other)),
+                })
+                .unwrap_or_else(|| analysis.boundary());
+            let instructions: Vec<&Instruction> = match A::DIRECTION {
+                Direction::Forward => cfg.blocks[*block].instructions.iter().collect(),
+                Direction::Backward => cfg.blocks[*block].instructions.iter().rev().collect(),
+            };
+            let output = instructions
+                .into_iter()
+                .fold(input.clone(), |fact, instruction| {
+                    analysis.transfer(instruction, &fact)
+                });
+            if far[*block].as_ref() != Some(&output) {
+                changed = true;
+            }
+            near[*block] = Some(input);
+            far[*block] = Some(output);
+        }
+    }
+    let facts = |facts: Vec<Option<A::Fact>>| -> Vec<A::Fact> {
+        facts
+            .into_iter()
+            .map(|fact| fact.unwrap_or_else(|| analysis.boundary()))
+            .collect()
+    };
+    match A::DIRECTION {
+        Direction::Forward => Solution {
+            before: facts(near),
+            after: facts(far),
+        },
+        Direction::Backward => Solution {
+            before: facts(far),
+            after: facts(near),
+        },
+    }
+}
+
+// The variables among `variables` whose current value may still be read.
+// Phis count as reading their sources at the start of their block, which
+// overstates liveness along the other edges; it's meant for code without
+// them.
+pub struct Liveness<'a> {
+    pub variables: &'a HashSet<String>,
+}
+impl Analysis for Liveness<'_> {
+    type Fact = HashSet<String>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> HashSet<String> {
+        HashSet::new()
+    }
+    fn meet(&self, left: &HashSet<String>, right: &HashSet<String>) -> HashSet<String> {
+        left.union(right).cloned().collect()
+    }
+    fn transfer(&self, instruction: &Instruction, fact: &HashSet<String>) -> HashSet<String> {
+        let mut live = fact.clone();
+        if let Some(destination) = instruction.destination() {
+            live.remove(destination);
+        }
+        for value in instruction.values() {
+            if let Value::Variable(name) = value {
+                if self.variables.contains(name) {
+                    live.insert(name.clone());
+                }
+            }
+        }
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+
+    // The variables written on every path to a point.
+    struct Written;
+    impl Analysis for Written {
+        type Fact = HashSet<String>;
+        const DIRECTION: Direction = Direction::Forward;
+
+        fn boundary(&self) -> HashSet<String> {
+            HashSet::new()
+        }
+        fn meet(&self, left: &HashSet<String>, right: &HashSet<String>) -> HashSet<String> {
+            left.intersection(right).cloned().collect()
+        }
+        fn transfer(&self, instruction: &Instruction, fact: &HashSet<String>) -> HashSet<String> {
+            let mut written = fact.clone();
+            written.extend(instruction.destination().cloned());
+            written
+        }
+    }
+
+    fn sorted(facts: &HashSet<String>) -> Vec<&str> {
+        let mut facts: Vec<&str> = facts.iter().map(|fact| fact.as_str()).collect();
+        facts.sort();
+        facts
+    }
+
+    #[test]
+    fn test_solve_in_both_directions() {
+        let program = lower_source(
+            "int f(int n) {
+                int a = 1;
+                int b;
+                top:
+                switch (n) {
+                case 0:
+                    return a;
+                }
+                b = n;
+                n = n - 1;
+                goto top;
+            }",
+        );
+        let function = &program.functions[0];
+        let mut cfg = Cfg::new(function);
+        cfg.remove_unreachable();
+        let labels: Vec<&str> = cfg
+            .blocks
+            .iter()
+            .map(|block| block.label.as_str())
+            .collect();
+        assert_eq!(vec!["f.block.0", "f.top", "case.1", "switch_end.0"], labels);
+
+        let written = solve(&Written, &cfg);
+        assert_eq!(vec!["a.1"], sorted(&written.before[1]));
+        assert_eq!(
+            vec!["a.1", "b.2", "n.0", "tmp.3"],
+            sorted(&written.after[3])
+        );
+
+        let variables = function
+            .variables
+            .iter()
+            .map(|variable| variable.name.clone())
+            .collect();
+        let liveness = Liveness {
+            variables: &variables,
+        };
+        let live = solve(&liveness, &cfg);
+        assert_eq!(vec!["n.0"], sorted(&live.before[0]));
+        assert_eq!(vec!["a.1", "n.0"], sorted(&live.before[1]));
+        assert_eq!(vec!["a.1", "n.0"], sorted(&live.after[3]));
+        // Going backward, the facts are those after each instruction.
+        let facts = liveness.instruction_facts(&cfg.blocks[3], &live.after[3]);
+        assert_eq!(
+            vec![
+                vec!["a.1", "n.0"],
+                vec!["a.1", "tmp.3"],
+                vec!["a.1", "n.0"],
+                vec!["a.1", "n.0"]
+            ],
+            facts.iter().map(sorted).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// Dead code elimination: drops blocks control can't reach, instructions
+// whose result nothing reads, which covers stores to locals that are never
+// loaded again, and the declarations of variables that are left unused.
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::cfg::Cfg;
+use crate::ir::{BinaryOperator, Function, Instruction, Program, Value};
+
+pub fn eliminate_dead_code(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        let mut cfg = Cfg::new(function);
+        cfg.remove_unreachable();
+        function.body = cfg.instructions();
+        while remove_unused_results(function) {}
+        remove_unused_variables(function);
+    }
+}
+
+// Whether the instruction does anything besides setting its destination.
+// A division may trap, so it stays unless the divisor rules that out.
+pub fn has_side_effects(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Copy { .. }
+        | Instruction::Unary { .. }
+        | Instruction::GetAddress { .. }
+        | Instruction::Phi { .. } => false,
+        Instruction::Binary {
+            operator: BinaryOperator::Divide | BinaryOperator::Remainder,
+            right,
+            ..
+        } => !matches!(right, Value::Constant(divisor) if *divisor != 0 && *divisor != -1),
+        Instruction::Binary { .. } => false,
+        _ => true,
+    }
+}
+
+// Returns whether it removed anything, which may leave more unused.
+fn remove_unused_results(function: &mut Function) -> bool {
+    let mut uses: HashMap<&String, usize> = HashMap::new();
+    for instruction in function.body.iter() {
+        for value in instruction.values() {
+            if let Value::Variable(name) = value {
+                // A phi that only feeds itself around a loop is still unused.
+                if Some(name) != instruction.destination() {
+                    *uses.entry(name).or_default() += 1;
+                }
+            }
+        }
+    }
+    let address_taken = function.address_taken();
+    let unused: HashSet<String> = function
+        .body
+        .iter()
+        .filter_map(|instruction| instruction.destination())
+        .filter(|destination| {
+            !uses.contains_key(destination)
+                && !address_taken.contains(destination.as_str())
+                && function.variable(destination).is_some()
+        })
+        .cloned()
+        .collect();
+    let count = function.body.len();
+    function
+        .body
+        .retain(|instruction| match instruction.destination() {
+            Some(destination) => !unused.contains(destination) || has_side_effects(instruction),
+            None => true,
+        });
+    function.body.len() != count
+}
+
+fn remove_unused_variables(function: &mut Function) {
+    let mut used: HashSet<String> = function.parameters.iter().cloned().collect();
+    for instruction in function.body.iter() {
+        for value in instruction.values() {
+            if let Value::Variable(name) = value {
+                used.insert(name.clone());
+            }
+        }
+        used.extend(instruction.destination().cloned());
+        match instruction {
+            Instruction::GetAddress { name, .. } => used.insert(name.clone()),
+            Instruction::VaStart { list } | Instruction::VaArg { list, .. } => {
+                used.insert(list.clone())
+            }
+            _ => false,
+        };
+    }
+    function
+        .variables
+        .retain(|variable| used.contains(&variable.name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+    use crate::opt::fold::fold_constants;
+
+    #[test]
+    fn test_dead_code_is_removed() {
+        let mut program = lower_source(
+            "int f(int n) {
+                int unused = n * 2;
+                int trap = n / 0;
+                int k = 3;
+                switch (k) {
+                case 3:
+                    return n + k;
+                }
+                n = 4;
+                return n;
+            }",
+        );
+        ssa::construct(&mut program.functions[0]);
+        fold_constants(&mut program);
+        eliminate_dead_code(&mut program);
+        let expected = "\
+global function f(int n.0):
+    int tmp.4.1
+    int tmp.6.1
+    tmp.4.1 = n.0 / 0
+    jump case.1
+  case.1:
+    tmp.6.1 = n.0 + 3
+    return tmp.6.1
+";
+        assert_eq!(expected, program.to_string());
+    }
+}
@@ -0,0 +1,237 @@
+// Constant folding and propagation. Operations on constants are done at
+// compile time, variables known to hold a constant are replaced by it, and
+// jumps that depend on a constant go straight to where they would at run
+// time, dropping the code that can no longer run.
+//
+// Where C leaves the result undefined, dividing by zero or overflowing a
+// signed int, the operation stays for run time, so the program behaves as
+// it does unoptimised.
+use std::collections::HashMap;
+
+use crate::ir::cfg::Cfg;
+use crate::ir::{BinaryOperator, Function, Instruction, Program, UnaryOperator, Value};
+use crate::syntax::DataType;
+
+pub fn fold_constants(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        while fold_function(function) {}
+    }
+}
+
+pub fn evaluate_unary(operator: UnaryOperator, value: i32) -> Option<i32> {
+    match operator {
+        UnaryOperator::Negate => value.checked_neg(),
+        UnaryOperator::Complement => Some(!value),
+        UnaryOperator::Not => Some((value == 0) as i32),
+    }
+}
+
+pub fn evaluate_binary(operator: BinaryOperator, left: i32, right: i32) -> Option<i32> {
+    match operator {
+        BinaryOperator::Add => left.checked_add(right),
+        BinaryOperator::Subtract => left.checked_sub(right),
+        BinaryOperator::Multiply => left.checked_mul(right),
+        // Also None for INT_MIN / -1, which overflows.
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Remainder => left.checked_rem(right),
+        BinaryOperator::Equal => Some((left == right) as i32),
+        BinaryOperator::NotEqual => Some((left != right) as i32),
+        BinaryOperator::LessThan => Some((left < right) as i32),
+        BinaryOperator::LessEqual => Some((left <= right) as i32),
+        BinaryOperator::GreaterThan => Some((left > right) as i32),
+        BinaryOperator::GreaterEqual => Some((left >= right) as i32),
+    }
+}
+
+// The value a variable of `data_type` holds after being assigned `value`,
+// or None for pointers, which constants aren't propagated into.
+fn converted(data_type: &DataType, value: i32) -> Option<i32> {
+    match data_type {
+        DataType::Int => Some(value),
+        DataType::Char => Some(value as i8 as i32),
+        _ => None,
+    }
+}
+
+// One round of folding. Returns whether anything changed, in which case
+// another round may find more.
+fn fold_function(function: &mut Function) -> bool {
+    let address_taken = function.address_taken();
+    let mut constants: HashMap<String, i32> = HashMap::new();
+    for instruction in function.body.iter() {
+        let Some(destination) = instruction.destination() else {
+            continue;
+        };
+        let Some(variable) = function.variable(destination) else {
+            continue;
+        };
+        if address_taken.contains(destination.as_str()) {
+            continue;
+        }
+        let value = match instruction {
+            Instruction::Copy {
+                source: Value::Constant(value),
+                ..
+            } => *value,
+            Instruction::Phi { sources, .. } => match sources.first() {
+                Some((_, Value::Constant(value)))
+                    if sources
+                        .iter()
+                        .all(|(_, source)| *source == Value::Constant(*value)) =>
+                {
+                    *value
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if let Some(value) = converted(&variable.data_type, value) {
+            constants.insert(destination.clone(), value);
+        }
+    }
+
+    let mut changed = false;
+    let mut jumps_changed = false;
+    let mut body = vec![];
+    for mut instruction in function.body.drain(..) {
+        for value in instruction.values_mut() {
+            if let Value::Variable(name) = value {
+                if let Some(constant) = constants.get(name) {
+                    *value = Value::Constant(*constant);
+                    changed = true;
+                }
+            }
+        }
+        let folded = match &instruction {
+            Instruction::Unary {
+                operator,
+                source: Value::Constant(value),
+                destination,
+            } => evaluate_unary(*operator, *value).map(|value| Instruction::Copy {
+                source: Value::Constant(value),
+                destination: destination.clone(),
+            }),
+            Instruction::Binary {
+                operator,
+                left: Value::Constant(left),
+                right: Value::Constant(right),
+                destination,
+            } => evaluate_binary(*operator, *left, *right).map(|value| Instruction::Copy {
+                source: Value::Constant(value),
+                destination: destination.clone(),
+            }),
+            Instruction::JumpIfZero {
+                condition: Value::Constant(value),
+                target,
+            } => {
+                jumps_changed = true;
+                if *value == 0 {
+                    Some(Instruction::Jump(target.clone()))
+                } else {
+                    continue;
+                }
+            }
+            Instruction::JumpIfNotZero {
+                condition: Value::Constant(value),
+                target,
+            } => {
+                jumps_changed = true;
+                if *value != 0 {
+                    Some(Instruction::Jump(target.clone()))
+                } else {
+                    continue;
+                }
+            }
+            Instruction::Switch {
+                value: Value::Constant(value),
+                cases,
+                default,
+            } => {
+                jumps_changed = true;
+                let target = cases
+                    .iter()
+                    .find(|(case, _)| case == value)
+                    .map_or(default, |(_, target)| target);
+                Some(Instruction::Jump(target.clone()))
+            }
+            _ => None,
+        };
+        if let Some(folded) = folded {
+            instruction = folded;
+            changed = true;
+        }
+        body.push(instruction);
+    }
+    function.body = body;
+    if jumps_changed {
+        let mut cfg = Cfg::new(function);
+        cfg.remove_unreachable();
+        function.body = cfg.instructions();
+    }
+    changed || jumps_changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+
+    #[test]
+    fn test_evaluate_leaves_undefined_results() {
+        assert_eq!(Some(3), evaluate_binary(BinaryOperator::Divide, 7, 2));
+        assert_eq!(Some(-1), evaluate_binary(BinaryOperator::Remainder, -7, 2));
+        assert_eq!(None, evaluate_binary(BinaryOperator::Divide, 7, 0));
+        assert_eq!(None, evaluate_binary(BinaryOperator::Remainder, 7, 0));
+        assert_eq!(None, evaluate_binary(BinaryOperator::Divide, i32::MIN, -1));
+        assert_eq!(
+            None,
+            evaluate_binary(BinaryOperator::Remainder, i32::MIN, -1)
+        );
+        assert_eq!(None, evaluate_binary(BinaryOperator::Add, i32::MAX, 1));
+        assert_eq!(
+            None,
+            evaluate_binary(BinaryOperator::Multiply, 65536, 65536)
+        );
+        assert_eq!(Some(1), evaluate_binary(BinaryOperator::LessThan, -1, 0));
+        assert_eq!(None, evaluate_unary(UnaryOperator::Negate, i32::MIN));
+        assert_eq!(Some(-1), evaluate_unary(UnaryOperator::Complement, 0));
+        assert_eq!(Some(0), evaluate_unary(UnaryOperator::Not, 5));
+    }
+
+    #[test]
+    fn test_fold_propagates_into_jumps() {
+        let mut program = lower_source(
+            "int f(int n) {
+                char c = 200;
+                int k = c + 56;
+                switch (k) {
+                case 0:
+                    return n / 0;
+                default:
+                    return n;
+                }
+            }",
+        );
+        ssa::construct(&mut program.functions[0]);
+        fold_constants(&mut program);
+        let body: Vec<String> = program.functions[0]
+            .body
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "tmp.2.1 = 200",
+                "c.1.1 = -56",
+                "tmp.4.1 = 0",
+                "k.3.1 = 0",
+                "jump case.1",
+                "case.1:",
+                "tmp.5.1 = n.0 / 0",
+                "return tmp.5.1",
+            ],
+            body
+        );
+    }
+}
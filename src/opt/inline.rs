@@ -0,0 +1,477 @@
+// Function inlining: a call to a function defined in the same file becomes a
+// copy of the callee's body, with its variables and labels renamed apart
+// from the caller's, its parameters copied from the arguments and its
+// returns jumping to the rest of the caller's block, where a phi picks the
+// result. The passes after it then see the callee's code with the caller's
+// arguments.
+//
+// A function marked always_inline is inlined wherever it's called, even at
+// -O0; otherwise only small static or `inline` functions are, and never one
+// marked noinline. Callees go first, so what they call is already inlined
+// into them. A recursive function is never inlined, and a static one left
+// without callers is dropped.
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::cfg::{Block, Cfg};
+use crate::ir::{Function, Instruction, Program, Value};
+use crate::syntax::statements::Inlining;
+use crate::syntax::DataType;
+
+// The most instructions a function may have to be inlined for its size, and
+// when declared `inline`.
+const SIZE_LIMIT: usize = 16;
+const INLINE_SIZE_LIMIT: usize = 64;
+
+pub fn inline_always(program: &mut Program) {
+    inline_functions(program, false);
+}
+
+pub fn inline_small(program: &mut Program) {
+    inline_functions(program, true);
+}
+
+fn inline_functions(program: &mut Program, by_size: bool) {
+    let calls: HashMap<String, HashSet<String>> = program
+        .functions
+        .iter()
+        .map(|function| (function.name.clone(), called(function)))
+        .collect();
+    let recursive: HashSet<&String> = calls
+        .keys()
+        .filter(|name| reaches(&calls, name, name))
+        .collect();
+    let inlinable: HashSet<String> = program
+        .functions
+        .iter()
+        .filter(|function| {
+            !recursive.contains(&function.name)
+                && !function.variadic
+                && function
+                    .variables
+                    .iter()
+                    .all(|variable| variable.data_type != DataType::VaList)
+                && match function.inlining {
+                    Some(Inlining::Always) => true,
+                    Some(Inlining::Never) => false,
+                    _ if !by_size => false,
+                    Some(Inlining::Hint) => size(function) <= INLINE_SIZE_LIMIT,
+                    None => !function.global && size(function) <= SIZE_LIMIT,
+                }
+        })
+        .map(|function| function.name.clone())
+        .collect();
+
+    let mut inlined = HashSet::new();
+    for name in callees_first(&calls) {
+        let idx = program
+            .functions
+            .iter()
+            .position(|function| function.name == name)
+            .unwrap();
+        let mut caller = std::mem::replace(&mut program.functions[idx], empty(&name));
+        while let Some((block, at, callee)) = next_call(&caller, &inlinable, program) {
+            inline_call(&mut caller, block, at, callee);
+            inlined.insert(callee.name.clone());
+        }
+        program.functions[idx] = caller;
+    }
+    let still_called: HashSet<String> = program.functions.iter().flat_map(called).collect();
+    program.functions.retain(|function| {
+        function.global
+            || !inlined.contains(&function.name)
+            || still_called.contains(&function.name)
+    });
+}
+
+// The functions `function` calls.
+fn called(function: &Function) -> HashSet<String> {
+    function
+        .body
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Call { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn reaches(calls: &HashMap<String, HashSet<String>>, from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<&String> = calls[from].iter().collect();
+    while let Some(name) = pending.pop() {
+        if name == to {
+            return true;
+        }
+        if seen.insert(name) {
+            pending.extend(calls.get(name).into_iter().flatten());
+        }
+    }
+    false
+}
+
+// The defined functions in postorder of the call graph, starting from each
+// in turn so every one is in it.
+fn callees_first(calls: &HashMap<String, HashSet<String>>) -> Vec<String> {
+    let mut names: Vec<&String> = calls.keys().collect();
+    names.sort();
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    for name in names {
+        let mut stack = vec![(name, false)];
+        while let Some((name, done)) = stack.pop() {
+            if done {
+                order.push(name.clone());
+                continue;
+            }
+            if !visited.insert(name) {
+                continue;
+            }
+            stack.push((name, true));
+            let mut callees: Vec<&String> = calls[name]
+                .iter()
+                .filter(|callee| calls.contains_key(*callee))
+                .collect();
+            callees.sort();
+            stack.extend(callees.into_iter().rev().map(|callee| (callee, false)));
+        }
+    }
+    order
+}
+
+fn size(function: &Function) -> usize {
+    function
+        .body
+        .iter()
+        .filter(|instruction| !matches!(instruction, Instruction::Label(_)))
+        .count()
+}
+
+// A stand-in while the function is taken out of the program.
+fn empty(name: &str) -> Function {
+    Function {
+        name: String::from(name),
+        global: false,
+        weak: false,
+        parameters: vec![],
+        variadic: false,
+        inlining: None,
+        variables: vec![],
+        body: vec![],
+    }
+}
+
+// The first call in `caller` to inline, as its block and position in the
+// block, with the callee.
+fn next_call<'a>(
+    caller: &Function,
+    inlinable: &HashSet<String>,
+    program: &'a Program,
+) -> Option<(usize, usize, &'a Function)> {
+    let cfg = Cfg::new(caller);
+    for (idx, block) in cfg.blocks.iter().enumerate() {
+        for (at, instruction) in block.instructions.iter().enumerate() {
+            let Instruction::Call {
+                name, arguments, ..
+            } = instruction
+            else {
+                continue;
+            };
+            if *name == caller.name || !inlinable.contains(name) {
+                continue;
+            }
+            let callee = program
+                .functions
+                .iter()
+                .find(|callee| callee.name == *name)?;
+            if callee.parameters.len() == arguments.len() {
+                return Some((idx, at, callee));
+            }
+        }
+    }
+    None
+}
+
+fn inline_call(caller: &mut Function, block: usize, at: usize, callee: &Function) {
+    let mut cfg = Cfg::new(caller);
+    let labels: HashSet<&String> = cfg.blocks.iter().map(|block| &block.label).collect();
+    let base = (0..)
+        .map(|count| format!("{}.inline.{}", caller.name, count))
+        .find(|base| {
+            let prefix = format!("{}.", base);
+            labels.iter().all(|label| !label.starts_with(&prefix))
+        })
+        .unwrap();
+    let continuation = format!("{}.return", base);
+
+    // The rest of the block moves to the continuation, which takes its place
+    // as the predecessor of its successors.
+    let rest = cfg.blocks[block].instructions.split_off(at + 1);
+    let Some(Instruction::Call {
+        arguments,
+        destination,
+        ..
+    }) = cfg.blocks[block].instructions.pop()
+    else {
+        unreachable!()
+    };
+    let label = cfg.blocks[block].label.clone();
+    for instruction in cfg
+        .blocks
+        .iter_mut()
+        .flat_map(|block| block.instructions.iter_mut())
+    {
+        if let Instruction::Phi { sources, .. } = instruction {
+            for (source, _) in sources.iter_mut() {
+                if *source == label {
+                    *source = continuation.clone();
+                }
+            }
+        }
+    }
+
+    let names: HashMap<String, String> = callee
+        .variables
+        .iter()
+        .map(|variable| {
+            let name = caller.fresh_variable(&variable.name, variable.data_type.clone());
+            (variable.name.clone(), name)
+        })
+        .collect();
+    for (parameter, argument) in callee.parameters.iter().zip(arguments) {
+        cfg.blocks[block].instructions.push(Instruction::Copy {
+            source: argument,
+            destination: names[parameter].clone(),
+        });
+    }
+    // The callee's entry carries on in the caller's block, which nothing
+    // jumps to in the callee.
+    let callee_cfg = Cfg::new(callee);
+    let callee_labels: HashMap<&String, String> = callee_cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(idx, callee_block)| match idx {
+            0 => (&callee_block.label, label.clone()),
+            _ => (
+                &callee_block.label,
+                format!("{}.{}", base, callee_block.label),
+            ),
+        })
+        .collect();
+    // A single return sets the result before it jumps. Otherwise a phi
+    // picks it, from ints, as the call would have converted it.
+    let single = callee
+        .body
+        .iter()
+        .filter(|instruction| matches!(instruction, Instruction::Return(_)))
+        .count()
+        == 1;
+    let mut returns = vec![];
+    let mut blocks = vec![];
+    for callee_block in callee_cfg.blocks.iter() {
+        let label = callee_labels[&callee_block.label].clone();
+        let mut instructions = vec![];
+        for instruction in callee_block.instructions.iter() {
+            let mut instruction = instruction.clone();
+            rename(&mut instruction, &names, &callee_labels);
+            let Instruction::Return(value) = instruction else {
+                instructions.push(instruction);
+                continue;
+            };
+            if single {
+                instructions.push(Instruction::Copy {
+                    source: value,
+                    destination: destination.clone(),
+                });
+            } else {
+                let value = match &value {
+                    Value::Variable(name)
+                        if caller.variable(name).unwrap().data_type != DataType::Int =>
+                    {
+                        let int = caller.fresh_variable(&destination, DataType::Int);
+                        instructions.push(Instruction::Copy {
+                            source: value,
+                            destination: int.clone(),
+                        });
+                        Value::Variable(int)
+                    }
+                    _ => value,
+                };
+                returns.push((label.clone(), value));
+            }
+            instructions.push(Instruction::Jump(continuation.clone()));
+        }
+        blocks.push((label, instructions));
+    }
+    let mut start = vec![];
+    if !returns.is_empty() {
+        if caller.variable(&destination).unwrap().data_type == DataType::Int {
+            start.push(Instruction::Phi {
+                sources: returns,
+                destination,
+            });
+        } else {
+            let int = caller.fresh_variable(&destination, DataType::Int);
+            start.push(Instruction::Phi {
+                sources: returns,
+                destination: int.clone(),
+            });
+            start.push(Instruction::Copy {
+                source: Value::Variable(int),
+                destination,
+            });
+        }
+    }
+    start.extend(rest);
+
+    let mut blocks = blocks.into_iter();
+    let (_, entry) = blocks.next().unwrap();
+    cfg.blocks[block].instructions.extend(entry);
+    let blocks: Vec<Block> = blocks
+        .chain([(continuation, start)])
+        .map(|(label, instructions)| Block {
+            label,
+            instructions,
+            successors: vec![],
+            predecessors: vec![],
+        })
+        .collect();
+    cfg.blocks.splice(block + 1..block + 1, blocks);
+    cfg.remove_unreachable();
+    caller.body = cfg.instructions();
+}
+
+// Moves a callee instruction into the caller's names. Statics and strings
+// keep theirs.
+fn rename(
+    instruction: &mut Instruction,
+    names: &HashMap<String, String>,
+    labels: &HashMap<&String, String>,
+) {
+    let name = |name: &mut String| {
+        if let Some(renamed) = names.get(name) {
+            *name = renamed.clone();
+        }
+    };
+    for value in instruction.values_mut() {
+        if let Value::Variable(variable) = value {
+            name(variable);
+        }
+    }
+    if let Some(destination) = instruction.destination_mut() {
+        name(destination);
+    }
+    match instruction {
+        Instruction::GetAddress { name: target, .. } => name(target),
+        Instruction::VaStart { list } | Instruction::VaArg { list, .. } => name(list),
+        Instruction::Phi { sources, .. } => {
+            for (source, _) in sources.iter_mut() {
+                *source = labels[source].clone();
+            }
+        }
+        _ => {}
+    }
+    for target in instruction.targets_mut() {
+        *target = labels[target].clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+
+    fn inlined(source: &str, by_size: bool) -> Program {
+        let mut program = lower_source(source);
+        for function in program.functions.iter_mut() {
+            ssa::construct(function);
+        }
+        inline_functions(&mut program, by_size);
+        program
+    }
+
+    #[test]
+    fn test_calls_to_small_helpers_disappear() {
+        let source = "static int twice(int x) { return x * 2; }
+            static int sign(int x) {
+                switch (x) {
+                case 0:
+                    return 0;
+                }
+                return 1;
+            }
+            __attribute__((noinline)) static int kept(int x) { return x; }
+            static int fact(int n) {
+                switch (n) {
+                case 0:
+                    return 1;
+                }
+                return n * fact(n - 1);
+            }
+            int main(void) { return twice(3) + sign(4) + kept(5) + fact(6); }";
+        let program = inlined(source, true);
+        let names: Vec<&str> = program
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(vec!["kept", "fact", "main"], names);
+        let expected = "\
+global function main():
+    int tmp.0.1
+    int tmp.1.1
+    int tmp.2.1
+    int tmp.3.1
+    int tmp.4.1
+    int tmp.5.1
+    int tmp.6.1
+    int tmp.7.1
+    int tmp.8.1
+    int tmp.9.1
+    int x.0.1
+    int tmp.1.1.1
+    int x.0.2
+    x.0.1 = 3
+    tmp.1.1.1 = x.0.1 * 2
+    tmp.0.1 = tmp.1.1.1
+    jump main.inline.0.return
+  main.inline.0.return:
+    tmp.1.1 = tmp.0.1
+    x.0.2 = 4
+    switch x.0.2, 0: main.inline.1.case.1, default: main.inline.1.switch_end.0
+  main.inline.1.case.1:
+    jump main.inline.1.return
+  main.inline.1.switch_end.0:
+    jump main.inline.1.return
+  main.inline.1.return:
+    tmp.2.1 = phi [main.inline.1.case.1: 0], [main.inline.1.switch_end.0: 1]
+    tmp.3.1 = tmp.1.1 + tmp.2.1
+    tmp.4.1 = tmp.3.1
+    tmp.5.1 = call kept(5)
+    tmp.6.1 = tmp.4.1 + tmp.5.1
+    tmp.7.1 = tmp.6.1
+    tmp.8.1 = call fact(6)
+    tmp.9.1 = tmp.7.1 + tmp.8.1
+    return tmp.9.1
+";
+        assert_eq!(expected, program.functions[2].to_string());
+    }
+
+    #[test]
+    fn test_always_inline_ignores_size_and_linkage() {
+        let source = "__attribute__((always_inline)) int add(int a, int b) {
+                int c = a + b;
+                c = c + a + b + a + b + a + b + a + b;
+                c = c + a + b + a + b + a + b + a + b;
+                return c;
+            }
+            int big(int a) { return a + 1; }
+            int main(void) { return add(1, 2) + big(3); }";
+        let program = inlined(source, false);
+        // The definition stays for other files to call.
+        assert_eq!(3, program.functions.len());
+        let calls: Vec<String> = called(&program.functions[2]).into_iter().collect();
+        assert_eq!(vec!["big"], calls);
+    }
+}
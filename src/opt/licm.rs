@@ -0,0 +1,352 @@
+// Loop-invariant code motion: an operation in a loop whose operands don't
+// change while the loop runs moves in front of it, so it's done once rather
+// than on every iteration.
+//
+// A loop is found from its back edges, the edges to a block that dominates
+// where they come from. The variables written before the loop are a forward
+// dataflow analysis of what's written on every path, which at the loop's
+// header leaves out everything the loop writes.
+use std::collections::HashSet;
+
+use super::dataflow::{self, Analysis, Direction};
+use super::dead_code::has_side_effects;
+use crate::ir::cfg::{Block, Cfg, Dominators};
+use crate::ir::{Function, Instruction, Program, Value};
+
+pub fn hoist_loop_invariants(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        let mut cfg = Cfg::new(function);
+        let dominators = Dominators::new(&cfg);
+        // Inner loops first, so what leaves one can carry on out of the
+        // loops around it.
+        let headers: Vec<String> = cfg
+            .reverse_postorder()
+            .into_iter()
+            .rev()
+            .filter(|block| {
+                cfg.blocks[*block]
+                    .predecessors
+                    .iter()
+                    .any(|predecessor| dominators.dominates(*block, *predecessor))
+            })
+            .map(|block| cfg.blocks[block].label.clone())
+            .collect();
+        for header in headers {
+            hoist_loop(function, &mut cfg, &header);
+        }
+        function.body = cfg.instructions();
+    }
+}
+
+// The variables written on every path to a point, starting with those no
+// instruction writes, which hold their value from the entry.
+struct Written {
+    registers: HashSet<String>,
+    entry: HashSet<String>,
+}
+impl Analysis for Written {
+    type Fact = HashSet<String>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> HashSet<String> {
+        self.entry.clone()
+    }
+    fn meet(&self, left: &HashSet<String>, right: &HashSet<String>) -> HashSet<String> {
+        left.intersection(right).cloned().collect()
+    }
+    fn transfer(&self, instruction: &Instruction, written: &HashSet<String>) -> HashSet<String> {
+        let mut written = written.clone();
+        if let Some(destination) = instruction.destination() {
+            if self.registers.contains(destination) {
+                written.insert(destination.clone());
+            }
+        }
+        written
+    }
+}
+
+fn hoist_loop(function: &mut Function, cfg: &mut Cfg, header: &str) {
+    let dominators = Dominators::new(cfg);
+    let header = cfg
+        .blocks
+        .iter()
+        .position(|block| block.label == header)
+        .unwrap();
+    // The header and the blocks that get back to it without going through
+    // it.
+    let mut body: HashSet<usize> = HashSet::from([header]);
+    let mut pending: Vec<usize> = cfg.blocks[header]
+        .predecessors
+        .iter()
+        .copied()
+        .filter(|predecessor| dominators.dominates(header, *predecessor))
+        .collect();
+    while let Some(block) = pending.pop() {
+        if body.insert(block) {
+            pending.extend(cfg.blocks[block].predecessors.iter());
+        }
+    }
+    let mut blocks: Vec<usize> = body.iter().copied().collect();
+    blocks.sort();
+
+    let registers = super::registers(function);
+    let mut entry = registers.clone();
+    for block in cfg.blocks.iter() {
+        for destination in block
+            .instructions
+            .iter()
+            .filter_map(|instruction| instruction.destination())
+        {
+            entry.remove(destination);
+        }
+    }
+    let written = Written {
+        registers: registers.clone(),
+        entry,
+    };
+    let mut invariant = dataflow::solve(&written, cfg).before.swap_remove(header);
+    // Memory the loop leaves alone reads the same all the way through it.
+    let instructions = || {
+        blocks
+            .iter()
+            .flat_map(|block| cfg.blocks[*block].instructions.iter())
+    };
+    let memory_written = instructions().any(super::writes_memory);
+    let stores: HashSet<String> = instructions()
+        .filter_map(|instruction| instruction.destination())
+        .filter(|destination| !registers.contains(*destination))
+        .cloned()
+        .collect();
+    let is_invariant = |value: &Value, invariant: &HashSet<String>| match value {
+        Value::Constant(_) => true,
+        Value::Variable(name) if registers.contains(name) => invariant.contains(name),
+        Value::Variable(name) => !memory_written && !stores.contains(name),
+    };
+
+    let mut hoisted = vec![];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in blocks.iter() {
+            let mut kept = vec![];
+            for instruction in cfg.blocks[*block].instructions.drain(..) {
+                let hoistable = match instruction.destination() {
+                    Some(destination) => {
+                        registers.contains(destination)
+                            && !matches!(instruction, Instruction::Phi { .. })
+                            && !has_side_effects(&instruction)
+                            && instruction
+                                .values()
+                                .into_iter()
+                                .all(|value| is_invariant(value, &invariant))
+                    }
+                    None => false,
+                };
+                if hoistable {
+                    invariant.insert(instruction.destination().unwrap().clone());
+                    hoisted.push(instruction);
+                    changed = true;
+                } else {
+                    kept.push(instruction);
+                }
+            }
+            cfg.blocks[*block].instructions = kept;
+        }
+    }
+    if hoisted.is_empty() {
+        return;
+    }
+
+    // A block outside the loop that only leads to the header already runs
+    // right before the loop.
+    let entering: Vec<usize> = cfg.blocks[header]
+        .predecessors
+        .iter()
+        .copied()
+        .filter(|predecessor| !body.contains(predecessor))
+        .collect();
+    if let [predecessor] = entering[..] {
+        if cfg.blocks[predecessor].successors == [header] {
+            let instructions = &mut cfg.blocks[predecessor].instructions;
+            let at = match instructions.last() {
+                Some(last) if last.is_terminator() || !last.targets().is_empty() => {
+                    instructions.len() - 1
+                }
+                _ => instructions.len(),
+            };
+            instructions.splice(at..at, hoisted);
+            return;
+        }
+    }
+
+    // Otherwise a new block goes right in front of the header, and every
+    // way into the loop from outside goes through it.
+    let label = fresh_label(cfg, &format!("{}.preheader", function.name));
+    let header_label = cfg.blocks[header].label.clone();
+    for predecessor in entering.iter() {
+        if let Some(last) = cfg.blocks[*predecessor].instructions.last_mut() {
+            for target in last.targets_mut() {
+                if *target == header_label {
+                    *target = label.clone();
+                }
+            }
+        }
+    }
+    // The values arriving from outside now arrive from the preheader, which
+    // needs phis of its own where they come from more than one block.
+    let entering: Vec<String> = entering
+        .iter()
+        .map(|predecessor| cfg.blocks[*predecessor].label.clone())
+        .collect();
+    let mut instructions = vec![];
+    let mut header_block = std::mem::take(&mut cfg.blocks[header].instructions);
+    for instruction in header_block.iter_mut() {
+        let Instruction::Phi {
+            sources,
+            destination,
+        } = instruction
+        else {
+            continue;
+        };
+        let (outside, inside): (Vec<_>, Vec<_>) = sources
+            .drain(..)
+            .partition(|(source, _)| entering.contains(source));
+        let value = match &outside[..] {
+            [(_, value)] => value.clone(),
+            _ => {
+                let data_type = function.variable(destination).unwrap().data_type.clone();
+                let name = function.fresh_variable(destination, data_type);
+                instructions.push(Instruction::Phi {
+                    sources: outside,
+                    destination: name.clone(),
+                });
+                Value::Variable(name)
+            }
+        };
+        sources.push((label.clone(), value));
+        sources.extend(inside);
+    }
+    cfg.blocks[header].instructions = header_block;
+    instructions.extend(hoisted);
+    cfg.blocks.insert(header, block(label, instructions));
+    // A block in the loop that fell through to the header now jumps there,
+    // past the preheader, from a block of its own in case it ends in a
+    // conditional jump. Phis take that block's values from the new one.
+    if body.contains(&(header - 1))
+        && !cfg.blocks[header - 1]
+            .instructions
+            .last()
+            .is_some_and(|last| last.is_terminator())
+    {
+        let label = fresh_label(cfg, &format!("{}.block", function.name));
+        let previous = cfg.blocks[header - 1].label.clone();
+        for instruction in cfg.blocks[header + 1].instructions.iter_mut() {
+            if let Instruction::Phi { sources, .. } = instruction {
+                for (source, _) in sources.iter_mut() {
+                    if *source == previous {
+                        *source = label.clone();
+                    }
+                }
+            }
+        }
+        cfg.blocks
+            .insert(header, block(label, vec![Instruction::Jump(header_label)]));
+    }
+    cfg.remove_unreachable();
+}
+
+fn block(label: String, instructions: Vec<Instruction>) -> Block {
+    Block {
+        label,
+        instructions,
+        successors: vec![],
+        predecessors: vec![],
+    }
+}
+
+fn fresh_label(cfg: &Cfg, base: &str) -> String {
+    (0..)
+        .map(|count| format!("{}.{}", base, count))
+        .find(|label| cfg.blocks.iter().all(|block| &block.label != label))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::lower_source;
+    use crate::ir::ssa;
+
+    #[test]
+    fn test_invariants_move_out_of_loops() {
+        let mut program = lower_source(
+            "int g;
+            int f(int n, int k) {
+                int total = 0;
+                switch (k) {
+                case 1:
+                    goto top;
+                }
+                total = 5;
+                top:
+                switch (n) {
+                case 0:
+                    return total;
+                }
+                total = total + k * 2 + g;
+                total = total + n / k;
+                n = n - 1;
+                goto top;
+            }",
+        );
+        ssa::construct(&mut program.functions[0]);
+        hoist_loop_invariants(&mut program);
+        let expected = "\
+global int g = 0
+
+global function f(int n.0, int k.1):
+    int total.2.1
+    int total.2.2
+    int n.0.1
+    int total.2.3
+    int tmp.3.1
+    int tmp.4.1
+    int tmp.5.1
+    int total.2.4
+    int tmp.6.1
+    int tmp.7.1
+    int total.2.5
+    int tmp.8.1
+    int n.0.2
+    int n.0.1.1
+    int total.2.3.1
+    total.2.1 = 0
+    switch k.1, 1: case.1, default: switch_end.0
+  case.1:
+    jump f.preheader.0
+  switch_end.0:
+    total.2.2 = 5
+  f.preheader.0:
+    n.0.1.1 = phi [case.1: n.0], [switch_end.0: n.0]
+    total.2.3.1 = phi [case.1: total.2.1], [switch_end.0: total.2.2]
+    tmp.3.1 = k.1 * 2
+  f.top:
+    n.0.1 = phi [f.preheader.0: n.0.1.1], [switch_end.2: n.0.2]
+    total.2.3 = phi [f.preheader.0: total.2.3.1], [switch_end.2: total.2.5]
+    switch n.0.1, 0: case.3, default: switch_end.2
+  case.3:
+    return total.2.3
+  switch_end.2:
+    tmp.4.1 = total.2.3 + tmp.3.1
+    tmp.5.1 = tmp.4.1 + g
+    total.2.4 = tmp.5.1
+    tmp.6.1 = n.0.1 / k.1
+    tmp.7.1 = total.2.4 + tmp.6.1
+    total.2.5 = tmp.7.1
+    tmp.8.1 = n.0.1 - 1
+    n.0.2 = tmp.8.1
+    jump f.top
+";
+        assert_eq!(expected, program.to_string());
+    }
+}
@@ -0,0 +1,86 @@
+// Passes that improve the IR. Each takes the program in SSA form and leaves
+// it in SSA form.
+use std::collections::HashSet;
+
+use crate::ir::{Function, Instruction, Program};
+
+pub mod copy_propagation;
+pub mod cse;
+pub mod dataflow;
+pub mod dead_code;
+pub mod fold;
+pub mod inline;
+pub mod licm;
+
+pub struct Pass {
+    pub name: &'static str,
+    // The lowest -O level that runs the pass.
+    pub level: u8,
+    pub run: fn(&mut Program),
+}
+
+// In the order they run.
+pub const PASSES: [Pass; 8] = [
+    Pass {
+        name: "always-inline",
+        level: 0,
+        run: inline::inline_always,
+    },
+    Pass {
+        name: "inline",
+        level: 1,
+        run: inline::inline_small,
+    },
+    Pass {
+        name: "constant-folding",
+        level: 1,
+        run: fold::fold_constants,
+    },
+    Pass {
+        name: "copy-propagation",
+        level: 2,
+        run: copy_propagation::propagate_copies,
+    },
+    Pass {
+        name: "local-cse",
+        level: 2,
+        run: cse::eliminate_local,
+    },
+    Pass {
+        name: "global-cse",
+        level: 2,
+        run: cse::eliminate_global,
+    },
+    Pass {
+        name: "loop-invariant-code-motion",
+        level: 2,
+        run: licm::hoist_loop_invariants,
+    },
+    Pass {
+        name: "dead-code-elimination",
+        level: 1,
+        run: dead_code::eliminate_dead_code,
+    },
+];
+
+// The variables of the function that only change as the destination of an
+// instruction. Statics and variables whose address is taken live in memory
+// instead.
+pub fn registers(function: &Function) -> HashSet<String> {
+    let address_taken = function.address_taken();
+    function
+        .variables
+        .iter()
+        .map(|variable| variable.name.clone())
+        .filter(|name| !address_taken.contains(name.as_str()))
+        .collect()
+}
+
+// Whether the instruction may change variables in memory other than its
+// destination.
+pub fn writes_memory(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Call { .. } | Instruction::VaStart { .. } | Instruction::VaArg { .. }
+    )
+}
@@ -1,134 +1,637 @@
+use crate::syntax::expressions::{BinaryOperator, PostfixOperator, UnaryOperator};
+use crate::syntax::statements::Inlining;
 use crate::syntax::*;
-use crate::tokenizer::Token;
+use crate::tokenizer::{Span, Token};
 
-/*
-pub struct TokenIteratorContainer<'a> {
-    iterator: &'a std::iter::Peekable<std::slice::Iter<'a, Token<'a>>>,
+// The tokens still to parse, remembering where the last one taken ended so
+// that nodes can be given the span of the tokens they were built from.
+struct TokenIterator<'a> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<(Token<'a>, Span)>>,
+    previous: Span,
 }
-
-impl<'a> TokenIteratorContainer<'a> {
-    pub fn new(token: &'a Vec<Token>) -> TokenIteratorContainer<'a> {
-        let iterator = token.iter().peekable();
-        TokenIteratorContainer { iterator }
+impl<'a> TokenIterator<'a> {
+    fn new(tokens: Vec<(Token<'a>, Span)>) -> TokenIterator<'a> {
+        TokenIterator {
+            tokens: tokens.into_iter().peekable(),
+            previous: Span::default(),
+        }
+    }
+    fn peek(&mut self) -> Option<&Token<'a>> {
+        self.tokens.peek().map(|(token, _)| token)
+    }
+    // Where the next token starts, for the span of the node it begins.
+    fn start(&mut self) -> Span {
+        match self.tokens.peek() {
+            Some((_, span)) => *span,
+            None => self.previous,
+        }
+    }
+    // From `start` up to the end of the last token taken.
+    fn since(&self, start: Span) -> Span {
+        start.to(self.previous)
     }
-    fn get_iter(&mut self) -> &mut std::iter::Peekable<std::slice::Iter<'a, Token<'a>>> {
-        &mut self.iterator
+}
+// Tokens without positions, which give every node a zero span.
+impl<'a> From<Vec<Token<'a>>> for TokenIterator<'a> {
+    fn from(tokens: Vec<Token<'a>>) -> TokenIterator<'a> {
+        TokenIterator::new(
+            tokens
+                .into_iter()
+                .map(|token| (token, Span::default()))
+                .collect(),
+        )
+    }
+}
+impl<'a> Iterator for TokenIterator<'a> {
+    type Item = Token<'a>;
+    fn next(&mut self) -> Option<Token<'a>> {
+        let (token, span) = self.tokens.next()?;
+        self.previous = span;
+        Some(token)
     }
 }
-*/
-type TokenIterator<'a> = std::iter::Peekable<std::vec::IntoIter<Token<'a>>>;
 
-fn string_to_number(s: &str) -> i32 {
-    let mut error_str = "Invalid_syntax: ".to_string();
-    error_str.push_str(s);
-    s.parse::<i32>().expect(&error_str)
+// Decimal, octal after a leading 0, or hexadecimal after 0x, as in #if.
+fn string_to_number(s: &str) -> Result<i32, String> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        i32::from_str_radix(&s[1..], 8)
+    } else {
+        s.parse::<i32>()
+    };
+    parsed.map_err(|_| format!("Integer constant is too large or malformed: {}", s))
 }
 
-fn parse_expression(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
-    // TODO: refactor this to be more idiomatic and rusty
-    while let Some(token) = tokens_iter.next() {
-        match token {
-            Token::Integer(val) => {
-                let int_variable = Type::Integer(string_to_number(&val));
-                let next_token = tokens_iter.next().unwrap();
-                match next_token {
-                    Token::Semicolon => return Ok(expressions::Constant::new(int_variable)),
-                    _ => return Err(String::from("Missing semicolon")),
+fn expect_token(tokens_iter: &mut TokenIterator, expected: Token, msg: &str) -> Result<(), String> {
+    match tokens_iter.next() {
+        Some(token) if token == expected => Ok(()),
+        _ => Err(String::from(msg)),
+    }
+}
+
+fn binary_operator(token: &Token) -> Option<BinaryOperator> {
+    match token {
+        Token::LogicalOr => Some(BinaryOperator::LogicalOr),
+        Token::LogicalAnd => Some(BinaryOperator::LogicalAnd),
+        Token::Equal => Some(BinaryOperator::Equal),
+        Token::NotEqual => Some(BinaryOperator::NotEqual),
+        Token::LessThan => Some(BinaryOperator::LessThan),
+        Token::LessEqual => Some(BinaryOperator::LessEqual),
+        Token::GreaterThan => Some(BinaryOperator::GreaterThan),
+        Token::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+        Token::Addition => Some(BinaryOperator::Addition),
+        Token::Negation => Some(BinaryOperator::Subtraction),
+        Token::Multiplication => Some(BinaryOperator::Multiplication),
+        Token::Division => Some(BinaryOperator::Division),
+        Token::Modulo => Some(BinaryOperator::Modulo),
+        _ => None,
+    }
+}
+const LOWEST_PRECEDENCE: u8 = 1;
+
+fn parse_primary(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let expression = match tokens_iter.next() {
+        Some(Token::Integer(val)) => {
+            let int_variable = Type::Integer(string_to_number(val)?);
+            expressions::Constant::new(int_variable)
+        }
+        Some(Token::StringLiteral(val)) => {
+            let mut value = String::from(val);
+            // Adjacent literals are concatenated.
+            while let Some(Token::StringLiteral(val)) = tokens_iter.peek() {
+                value.push_str(val);
+                tokens_iter.next();
+            }
+            expressions::StringLiteral::new(value)
+        }
+        Some(Token::Identifier(name)) if name.starts_with("__builtin_va_") => {
+            parse_va_builtin(tokens_iter, name)?
+        }
+        Some(Token::Identifier(name)) if tokens_iter.peek() != Some(&Token::OpenBracket) => {
+            expressions::Variable::new(String::from(name))
+        }
+        Some(Token::Identifier(name)) => {
+            tokens_iter.next();
+            let mut arguments = vec![];
+            if tokens_iter.peek() != Some(&Token::CloseBracket) {
+                arguments.push(parse_assignment(tokens_iter)?);
+                while tokens_iter.peek() == Some(&Token::Comma) {
+                    tokens_iter.next();
+                    arguments.push(parse_assignment(tokens_iter)?);
                 }
             }
-            _ => {
-                let mut msg = String::from("Unidentified expression");
-                msg.push_str(format!("{:?}", token).as_str());
-                return Err(msg);
+            expect_token(
+                tokens_iter,
+                Token::CloseBracket,
+                &format!("Missing closing bracket in call to {}", name),
+            )?;
+            expressions::Call::new(String::from(name), arguments)
+        }
+        // A parenthesized expression keeps the span of what is inside.
+        Some(Token::OpenBracket) => {
+            let expression = parse_expression(tokens_iter)?;
+            expect_token(tokens_iter, Token::CloseBracket, "Missing closing bracket")?;
+            return Ok(expression);
+        }
+        Some(token) => {
+            let mut msg = String::from("Unidentified expression");
+            msg.push_str(format!("{:?}", token).as_str());
+            return Err(msg);
+        }
+        None => return Err(String::from("Something went wrong")),
+    };
+    Ok(expression.at(tokens_iter.since(start)))
+}
+
+fn parse_va_builtin(tokens_iter: &mut TokenIterator, name: &str) -> Result<Expression, String> {
+    let invalid = format!("Invalid call to {}", name);
+    expect_token(tokens_iter, Token::OpenBracket, &invalid)?;
+    let list = match tokens_iter.next() {
+        Some(Token::Identifier(list)) => String::from(list),
+        _ => return Err(invalid),
+    };
+    let operation = match name {
+        "__builtin_va_start" => {
+            expect_token(tokens_iter, Token::Comma, &invalid)?;
+            match tokens_iter.next() {
+                Some(Token::Identifier(parameter)) => {
+                    expressions::VaOperation::Start(String::from(parameter))
+                }
+                _ => return Err(invalid),
             }
         }
+        "__builtin_va_arg" => {
+            expect_token(tokens_iter, Token::Comma, &invalid)?;
+            expressions::VaOperation::Arg(parse_data_type(tokens_iter)?)
+        }
+        "__builtin_va_end" => expressions::VaOperation::End,
+        _ => return Err(format!("Unknown builtin {}", name)),
+    };
+    expect_token(tokens_iter, Token::CloseBracket, &invalid)?;
+    Ok(expressions::VaBuiltin::new(operation, list))
+}
+
+fn parse_postfix(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let mut expression = parse_primary(tokens_iter)?;
+    loop {
+        let operator = match tokens_iter.peek() {
+            Some(Token::Increment) => PostfixOperator::Increment,
+            Some(Token::Decrement) => PostfixOperator::Decrement,
+            _ => return Ok(expression),
+        };
+        tokens_iter.next();
+        expression = expressions::Postfix::new(operator, expression).at(tokens_iter.since(start));
     }
-    return Err(String::from("Something went wrong"));
+}
+
+fn parse_unary(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let operator = match tokens_iter.peek() {
+        Some(Token::Negation) => UnaryOperator::Negation,
+        Some(Token::BitwiseComplement) => UnaryOperator::BitwiseComplement,
+        Some(Token::LogicalNegation) => UnaryOperator::LogicalNegation,
+        Some(Token::Increment) => UnaryOperator::PreIncrement,
+        Some(Token::Decrement) => UnaryOperator::PreDecrement,
+        _ => return parse_postfix(tokens_iter),
+    };
+    tokens_iter.next();
+    let operand = parse_unary(tokens_iter)?;
+    Ok(expressions::Unary::new(operator, operand).at(tokens_iter.since(start)))
+}
+
+fn parse_binary(tokens_iter: &mut TokenIterator, min_precedence: u8) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let mut left = parse_unary(tokens_iter)?;
+    while let Some(operator) = tokens_iter.peek().and_then(binary_operator) {
+        let precedence = operator.precedence();
+        if precedence < min_precedence {
+            break;
+        }
+        tokens_iter.next();
+        let right = parse_binary(tokens_iter, precedence + 1)?;
+        left = expressions::Binary::new(operator, left, right).at(tokens_iter.since(start));
+    }
+    Ok(left)
+}
+
+fn parse_assignment(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let left = parse_binary(tokens_iter, LOWEST_PRECEDENCE)?;
+    if tokens_iter.peek() != Some(&Token::Assignment) {
+        return Ok(left);
+    }
+    tokens_iter.next();
+    let value = parse_assignment(tokens_iter)?;
+    match left {
+        Expression::Variable(variable) => {
+            Ok(expressions::Assignment::new(variable.name, value).at(tokens_iter.since(start)))
+        }
+        _ => Err(format!("Expression is not assignable: {}", left)),
+    }
+}
+
+fn parse_expression(tokens_iter: &mut TokenIterator) -> Result<Expression, String> {
+    let start = tokens_iter.start();
+    let mut expression = parse_assignment(tokens_iter)?;
+    while tokens_iter.peek() == Some(&Token::Comma) {
+        tokens_iter.next();
+        expression = expressions::Comma::new(expression, parse_assignment(tokens_iter)?)
+            .at(tokens_iter.since(start));
+    }
+    Ok(expression)
+}
+
+fn parse_block_body(tokens_iter: &mut TokenIterator) -> Result<Vec<Statement>, String> {
+    let mut body: Vec<Statement> = vec![];
+    loop {
+        match tokens_iter.peek() {
+            Some(Token::CloseBrace) => break,
+            Some(_) => body.push(parse_statement(tokens_iter)?),
+            None => return Err(String::from("Missing closing brace")),
+        }
+    }
+    tokens_iter.next();
+    Ok(body)
+}
+
+fn parse_switch(tokens_iter: &mut TokenIterator) -> Result<Statement, String> {
+    expect_token(
+        tokens_iter,
+        Token::OpenBracket,
+        "Missing opening bracket after switch",
+    )?;
+    let expression = parse_expression(tokens_iter)?;
+    expect_token(
+        tokens_iter,
+        Token::CloseBracket,
+        "Missing closing bracket after switch expression",
+    )?;
+    let body = parse_statement(tokens_iter)?;
+    Ok(statements::Switch::new(expression, body))
 }
 
 fn parse_statement(tokens_iter: &mut TokenIterator) -> Result<Statement, String> {
-    // TODO: refactor this to be more idiomatic and rusty
-    while let Some(token) = tokens_iter.next() {
-        match token {
-            Token::Keyword(keyword) => match keyword {
-                "return" => {
-                    let expression = match parse_expression(tokens_iter) {
-                        Ok(expression) => expression,
-                        Err(msg) => return Err(msg),
-                    };
-                    let return_statement = statements::Return::new(expression);
-                    return Ok(return_statement);
-                }
-                "int" => {
-                    let statement_type = Type::Integer(0);
-                    let function_name = tokens_iter.next().unwrap();
-                    let function_name = match function_name {
-                        Token::Identifier(name) => name,
-                        _ => return Err(format!("Invalid syntax {:?}", function_name)),
-                    };
-                    if !matches!(tokens_iter.next().unwrap(), Token::OpenBracket)
-                        || !matches!(tokens_iter.next().unwrap(), Token::CloseBracket)
-                    {
-                        return Err(format!(
-                            "Missing function parameters for function {}",
-                            function_name
-                        ));
-                    }
-                    if !matches!(tokens_iter.next().unwrap(), Token::OpenBrace) {
-                        return Err(format!(
-                            "Missing function body for function {}",
-                            function_name
-                        ));
-                    }
-
-                    let mut body: Vec<Statement> = vec![];
-                    while !matches!(tokens_iter.peek().unwrap(), Token::CloseBrace) {
-                        let statement = match parse_statement(tokens_iter) {
-                            Ok(val) => val,
-                            Err(msg) => return Err(msg),
-                        };
-                        body.push(statement);
-                    }
-                    tokens_iter.next();
-                    let function_statement = statements::Function::new(
-                        statement_type,
-                        String::from(function_name),
-                        body,
-                    );
-                    return Ok(function_statement);
+    let start = tokens_iter.start();
+    let statement = parse_statement_kind(tokens_iter)?;
+    Ok(statement.at(tokens_iter.since(start)))
+}
+
+fn parse_statement_kind(tokens_iter: &mut TokenIterator) -> Result<Statement, String> {
+    if matches!(tokens_iter.peek(), Some(Token::Keyword(keyword)) if TYPE_KEYWORDS.contains(keyword))
+    {
+        return parse_declaration(tokens_iter);
+    }
+    if !matches!(
+        tokens_iter.peek(),
+        Some(Token::Keyword(_)) | Some(Token::OpenBrace) | Some(Token::Semicolon) | None
+    ) {
+        return parse_expression_statement(tokens_iter);
+    }
+    match tokens_iter.next() {
+        Some(Token::Keyword(keyword)) => match keyword {
+            "return" => {
+                let expression = parse_expression(tokens_iter)?;
+                expect_token(tokens_iter, Token::Semicolon, "Missing semicolon")?;
+                Ok(statements::Return::new(expression))
+            }
+            "switch" => parse_switch(tokens_iter),
+            "case" => {
+                let expression = parse_binary(tokens_iter, LOWEST_PRECEDENCE)?;
+                if !expression.is_constant() {
+                    return Err(format!(
+                        "Case label does not reduce to an integer constant: {}",
+                        expression
+                    ));
                 }
-                _ => {
-                    let mut msg = String::from("Invalid syntax: ");
-                    msg.push_str(keyword);
-                    return Err(msg);
+                let value = expression.evaluate();
+                expect_token(tokens_iter, Token::Colon, "Missing colon after case")?;
+                let statement = parse_statement(tokens_iter)?;
+                Ok(statements::Case::new(value, statement))
+            }
+            "default" => {
+                expect_token(tokens_iter, Token::Colon, "Missing colon after default")?;
+                let statement = parse_statement(tokens_iter)?;
+                Ok(statements::Default::new(statement))
+            }
+            "goto" => match tokens_iter.next() {
+                Some(Token::Identifier(label)) => {
+                    expect_token(tokens_iter, Token::Semicolon, "Missing semicolon")?;
+                    Ok(statements::Goto::new(String::from(label)))
                 }
+                _ => Err(String::from("Missing label after goto")),
             },
+            "break" => {
+                expect_token(tokens_iter, Token::Semicolon, "Missing semicolon")?;
+                Ok(statements::Break::new())
+            }
             _ => {
                 let mut msg = String::from("Invalid syntax: ");
-                msg.push_str(&format!("{:?}", token));
-                return Err(msg);
+                msg.push_str(keyword);
+                Err(msg)
             }
+        },
+        Some(Token::OpenBrace) => Ok(statements::Block::new(parse_block_body(tokens_iter)?)),
+        Some(Token::Semicolon) => Ok(statements::Empty::new()),
+        Some(token) => {
+            let mut msg = String::from("Invalid syntax: ");
+            msg.push_str(&format!("{:?}", token));
+            Err(msg)
+        }
+        None => Err(String::from("Something went wrong")),
+    }
+}
+
+// Anything that doesn't start with a keyword, a brace or a semicolon is an
+// expression, or the label in front of another statement.
+fn parse_expression_statement(tokens_iter: &mut TokenIterator) -> Result<Statement, String> {
+    let expression = parse_expression(tokens_iter)?;
+    if let Expression::Variable(variable) = &expression {
+        if tokens_iter.peek() == Some(&Token::Colon) {
+            tokens_iter.next();
+            let statement = parse_statement(tokens_iter)?;
+            return Ok(statements::Label::new(variable.name.clone(), statement));
         }
     }
-    return Err(String::from("Something went wrong"));
+    expect_token(tokens_iter, Token::Semicolon, "Missing semicolon")?;
+    Ok(statements::ExpressionStatement::new(expression))
 }
 
-pub fn parse_program_tokens(tokens: Vec<Token>) -> Result<Program, String> {
-    let mut token_iterator = tokens.into_iter().peekable();
-    let main_function: Statement = match parse_statement(&mut token_iterator) {
-        Ok(main_statement) => match &main_statement {
-            Statement::Function(val) => {
-                if val.name != "main" {
-                    return Err("Missing main function".to_string());
+const TYPE_KEYWORDS: [&str; 7] = [
+    "int",
+    "char",
+    "const",
+    "__builtin_va_list",
+    "static",
+    "inline",
+    "__attribute__",
+];
+
+// `const` may come anywhere among the specifiers, and after each `*` it
+// qualifies the pointer itself.
+fn parse_data_type(tokens_iter: &mut TokenIterator) -> Result<DataType, String> {
+    let mut base_type = None;
+    let mut is_const = false;
+    while let Some(Token::Keyword(keyword)) = tokens_iter.peek() {
+        let specifier = match *keyword {
+            "const" => None,
+            "int" => Some(DataType::Int),
+            "char" => Some(DataType::Char),
+            "__builtin_va_list" => Some(DataType::VaList),
+            _ => break,
+        };
+        tokens_iter.next();
+        match specifier {
+            Some(_) if base_type.is_some() => {
+                return Err(String::from("Multiple type specifiers in declaration"));
+            }
+            Some(specifier) => base_type = Some(specifier),
+            None => is_const = true,
+        }
+    }
+    let mut data_type = base_type.ok_or_else(|| String::from("Missing type specifier"))?;
+    loop {
+        if is_const {
+            data_type = DataType::Const(Box::new(data_type));
+        }
+        if tokens_iter.peek() != Some(&Token::Multiplication) {
+            return Ok(data_type);
+        }
+        tokens_iter.next();
+        data_type = DataType::Pointer(Box::new(data_type));
+        is_const = false;
+        while tokens_iter.peek() == Some(&Token::Keyword("const")) {
+            tokens_iter.next();
+            is_const = true;
+        }
+    }
+}
+
+// `static`, `inline` and attributes, in any order in front of the type.
+fn parse_specifiers(
+    tokens_iter: &mut TokenIterator,
+) -> Result<(bool, bool, Option<Inlining>), String> {
+    let mut is_static = false;
+    let mut is_inline = false;
+    let mut inlining = None;
+    loop {
+        match tokens_iter.peek() {
+            Some(Token::Keyword("static")) => {
+                tokens_iter.next();
+                is_static = true;
+            }
+            Some(Token::Keyword("inline")) => {
+                tokens_iter.next();
+                is_inline = true;
+            }
+            Some(Token::Keyword("__attribute__")) => {
+                tokens_iter.next();
+                for attribute in parse_attributes(tokens_iter)? {
+                    inlining = Inlining::merge(inlining, Some(attribute))?;
                 }
-                main_statement
             }
-            _ => return Err("Missing main function".to_string()),
-        },
-        Err(msg) => return Err(msg),
+            _ => return Ok((is_static, is_inline, inlining)),
+        }
+    }
+}
+
+// The list in `__attribute__((...))`. Only the attributes the inliner takes
+// are known, with or without their surrounding underscores.
+fn parse_attributes(tokens_iter: &mut TokenIterator) -> Result<Vec<Inlining>, String> {
+    let missing = "Missing brackets around attribute list";
+    expect_token(tokens_iter, Token::OpenBracket, missing)?;
+    expect_token(tokens_iter, Token::OpenBracket, missing)?;
+    let mut attributes = vec![];
+    while let Some(Token::Identifier(name)) = tokens_iter.peek() {
+        let name = *name;
+        tokens_iter.next();
+        attributes.push(match name.trim_start_matches("__").trim_end_matches("__") {
+            "always_inline" => Inlining::Always,
+            "noinline" => Inlining::Never,
+            _ => return Err(format!("Unsupported attribute '{}'", name)),
+        });
+        if tokens_iter.peek() != Some(&Token::Comma) {
+            break;
+        }
+        tokens_iter.next();
+    }
+    expect_token(tokens_iter, Token::CloseBracket, missing)?;
+    expect_token(tokens_iter, Token::CloseBracket, missing)?;
+    Ok(attributes)
+}
+
+fn parse_declaration(tokens_iter: &mut TokenIterator) -> Result<Statement, String> {
+    let (is_static, is_inline, mut inlining) = parse_specifiers(tokens_iter)?;
+    let data_type = parse_data_type(tokens_iter)?;
+    // Attributes may also come between the type and the name.
+    while tokens_iter.peek() == Some(&Token::Keyword("__attribute__")) {
+        tokens_iter.next();
+        for attribute in parse_attributes(tokens_iter)? {
+            inlining = Inlining::merge(inlining, Some(attribute))?;
+        }
+    }
+    let name = match tokens_iter.next() {
+        Some(Token::Identifier(name)) => name,
+        token => return Err(format!("Invalid syntax {:?}", token)),
     };
-    let main_program: Program = Program::new(main_function);
-    return Ok(main_program);
+    if tokens_iter.peek() == Some(&Token::OpenBracket) {
+        if data_type != DataType::Int {
+            return Err(format!("Function {} must return int", name));
+        }
+        let mut function = parse_function(tokens_iter, name)?;
+        match &mut function {
+            Statement::Function(function) => {
+                function.is_static = is_static;
+                function.is_inline = is_inline;
+                function.inlining = inlining;
+            }
+            Statement::Prototype(prototype) => {
+                prototype.is_static = is_static;
+                prototype.is_inline = is_inline;
+                prototype.inlining = inlining;
+            }
+            _ => {}
+        }
+        return Ok(function);
+    }
+    if is_inline || inlining.is_some() {
+        return Err(format!(
+            "Variable '{}' declared with an inlining specifier",
+            name
+        ));
+    }
+    let initializer = match tokens_iter.peek() {
+        Some(Token::Assignment) => {
+            tokens_iter.next();
+            Some(parse_assignment(tokens_iter)?)
+        }
+        _ => None,
+    };
+    expect_token(tokens_iter, Token::Semicolon, "Missing semicolon")?;
+    if is_static {
+        return Ok(statements::Declaration::new_static(
+            data_type,
+            String::from(name),
+            initializer,
+        ));
+    }
+    Ok(statements::Declaration::new(
+        data_type,
+        String::from(name),
+        initializer,
+    ))
+}
+
+fn parse_parameters(
+    tokens_iter: &mut TokenIterator,
+    function_name: &str,
+) -> Result<(Vec<statements::Parameter>, bool), String> {
+    let missing_parameters = format!("Missing function parameters for function {}", function_name);
+    expect_token(tokens_iter, Token::OpenBracket, &missing_parameters)?;
+    let mut parameters = vec![];
+    let mut variadic = false;
+    if tokens_iter.peek() == Some(&Token::Keyword("void")) {
+        tokens_iter.next();
+    } else if tokens_iter.peek() != Some(&Token::CloseBracket) {
+        loop {
+            if tokens_iter.peek() == Some(&Token::Ellipsis) {
+                tokens_iter.next();
+                variadic = true;
+                break;
+            }
+            let data_type = parse_data_type(tokens_iter).map_err(|_| missing_parameters.clone())?;
+            let name = match tokens_iter.peek() {
+                Some(Token::Identifier(name)) => String::from(*name),
+                _ => String::new(),
+            };
+            if !name.is_empty() {
+                tokens_iter.next();
+            }
+            parameters.push(statements::Parameter::new(data_type, name));
+            if tokens_iter.peek() != Some(&Token::Comma) {
+                break;
+            }
+            tokens_iter.next();
+        }
+    }
+    expect_token(tokens_iter, Token::CloseBracket, &missing_parameters)?;
+    if variadic && parameters.is_empty() {
+        return Err(format!(
+            "Variadic function {} needs a named parameter before '...'",
+            function_name
+        ));
+    }
+    Ok((parameters, variadic))
+}
+
+fn parse_function(
+    tokens_iter: &mut TokenIterator,
+    function_name: &str,
+) -> Result<Statement, String> {
+    let (parameters, variadic) = parse_parameters(tokens_iter, function_name)?;
+    if tokens_iter.peek() == Some(&Token::Semicolon) {
+        tokens_iter.next();
+        return Ok(statements::Prototype::new(
+            Type::Integer(0),
+            String::from(function_name),
+            parameters,
+            variadic,
+        ));
+    }
+    expect_token(
+        tokens_iter,
+        Token::OpenBrace,
+        &format!("Missing function body for function {}", function_name),
+    )?;
+
+    let body = parse_block_body(tokens_iter)?;
+    Ok(statements::Function::with_parameters(
+        Type::Integer(0),
+        String::from(function_name),
+        parameters,
+        variadic,
+        body,
+    ))
+}
+
+pub fn parse_program_tokens(tokens: Vec<Token>) -> Result<Program, Error> {
+    parse_declarations(TokenIterator::from(tokens)).map(Program::from_declarations)
+}
+
+// Tokens with where they came from, as tokenize_with_spans gives them, so the
+// syntax tree records spans. Nothing is checked beyond the syntax; see
+// sema::analyze.
+pub fn parse_program(tokens: Vec<(Token, Span)>) -> Result<Program, Error> {
+    parse_translation_unit(tokens).map(Program::from_declarations)
+}
+
+// The file scope declarations in source order, which analysis needs to know
+// what each one can see.
+pub fn parse_translation_unit(tokens: Vec<(Token, Span)>) -> Result<Vec<Statement>, Error> {
+    parse_declarations(TokenIterator::new(tokens))
+}
+
+// A syntax error is reported at the last token taken, which is the one that
+// did not fit.
+fn parse_declarations(mut tokens_iter: TokenIterator) -> Result<Vec<Statement>, Error> {
+    let mut declarations = vec![];
+    while tokens_iter.peek().is_some() {
+        let declaration = parse_statement(&mut tokens_iter)
+            .map_err(|message| Error::new(message, tokens_iter.previous))?;
+        if !matches!(
+            declaration,
+            Statement::Function(_) | Statement::Prototype(_) | Statement::Declaration(_)
+        ) {
+            return Err(Error::new(
+                format!("Expected a function definition: {}", declaration),
+                declaration.span(),
+            ));
+        }
+        declarations.push(declaration);
+    }
+    Ok(declarations)
 }
 
 #[cfg(test)]
@@ -138,7 +641,7 @@ mod tests {
     #[test]
     fn test_parse_expression_tokens() {
         let tokens: Vec<Token> = vec![Token::Integer("2"), Token::Semicolon];
-        let mut token_iterator = tokens.into_iter().peekable();
+        let mut token_iterator = TokenIterator::from(tokens);
         let expression: Expression = match parse_expression(&mut token_iterator) {
             Ok(val) => val,
             Err(msg) => panic!("{}", msg),
@@ -156,7 +659,7 @@ mod tests {
             Token::Integer("2"),
             Token::Semicolon,
         ];
-        let mut token_iterator = tokens.into_iter().peekable();
+        let mut token_iterator = TokenIterator::from(tokens);
         let return_statement: Statement = match parse_statement(&mut token_iterator) {
             Ok(val) => val,
             Err(msg) => panic!("{}", msg),
@@ -180,7 +683,7 @@ mod tests {
             Token::Semicolon,
             Token::CloseBrace,
         ];
-        let mut token_iterator = tokens.into_iter().peekable();
+        let mut token_iterator = TokenIterator::from(tokens);
         let function_node: Statement = match parse_statement(&mut token_iterator) {
             Ok(val) => val,
             Err(msg) => panic!("{}", msg),
@@ -215,7 +718,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_parse_tokens_into_program_without_main_function() {
         let tokens: Vec<Token> = vec![
             Token::Keyword("int"),
@@ -232,9 +734,241 @@ mod tests {
             Ok(program) => program,
             Err(msg) => panic!("{}", msg),
         };
+        let expected_format = "\
+PROGRAM_START:
+Function Integer im_not_a_main:
+    body:
+        Return Constant Integer<2>
+";
+        assert_eq!(expected_format, format!("{}", program));
+    }
+
+    fn switch_tokens<'a>(first_case: &'a str, second_case: &'a str) -> Vec<Token<'a>> {
+        vec![
+            Token::Keyword("switch"),
+            Token::OpenBracket,
+            Token::Integer("2"),
+            Token::CloseBracket,
+            Token::OpenBrace,
+            Token::Keyword("case"),
+            Token::Integer(first_case),
+            Token::Colon,
+            Token::Keyword("return"),
+            Token::Integer("2"),
+            Token::Semicolon,
+            Token::Keyword("case"),
+            Token::Integer(second_case),
+            Token::Colon,
+            Token::Keyword("return"),
+            Token::Integer("2"),
+            Token::Semicolon,
+            Token::Keyword("default"),
+            Token::Colon,
+            Token::Keyword("break"),
+            Token::Semicolon,
+            Token::CloseBrace,
+        ]
+    }
+
+    #[test]
+    fn test_parse_switch_statement_tokens() {
+        let mut token_iterator = TokenIterator::from(switch_tokens("1", "2"));
+        let switch_statement: Statement = match parse_statement(&mut token_iterator) {
+            Ok(val) => val,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            format!("{}", test_utils::create_test_switch(&[1, 2], true)),
+            format!("{}", switch_statement)
+        );
+    }
+
+    #[test]
+    fn test_parse_goto_and_label_statements() {
+        let code = "int main() { goto end; return 1; end: return 2; }";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(msg) => panic!("{}", msg),
+        };
         assert_eq!(
-            format!("{}", test_utils::create_test_program()),
+            format!("{}", test_utils::create_test_goto_program()),
             format!("{}", program)
         );
     }
+
+    #[test]
+    fn test_parse_expression_statements() {
+        let code = "int main() { int x = 2; x = f(), x++; x + 1; ; return x; }";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            format!("{}", test_utils::create_test_expression_program()),
+            format!("{}", program)
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_precedence() {
+        let code = "1 + 2 * 3 == 7 || !a && b < c;";
+        let mut token_iterator = TokenIterator::from(crate::tokenizer::tokenize(code));
+        let expression = match parse_expression(&mut token_iterator) {
+            Ok(val) => val,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            "Binary || (Binary == (Binary + (Constant Integer<1>, Binary * (Constant Integer<2>, \
+             Constant Integer<3>)), Constant Integer<7>), Binary && (Unary ! (Variable a), \
+             Binary < (Variable b, Variable c)))",
+            format!("{}", expression)
+        );
+    }
+
+    #[test]
+    fn test_parse_comma_and_assignment_associativity() {
+        let code = "a = b = 1, c";
+        let mut token_iterator = TokenIterator::from(crate::tokenizer::tokenize(code));
+        let expression = match parse_expression(&mut token_iterator) {
+            Ok(val) => val,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            "Comma (Assignment a = (Assignment b = (Constant Integer<1>)), Variable c)",
+            format!("{}", expression)
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment_to_non_lvalue() {
+        let code = "int main() { int x; x + 1 = 2; }";
+        match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(_) => panic!("Assignment to rvalue was accepted"),
+            Err(error) => assert_eq!(
+                "Expression is not assignable: Binary + (Variable x, Constant Integer<1>)",
+                error.message
+            ),
+        }
+    }
+
+    #[test]
+    fn test_parse_multiple_functions_with_parameters() {
+        let code = "int add(int a, int b) { return a + b; } int main() { return add(1, 2); }";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(2, program.functions.len());
+        let names: Vec<&str> = program.functions[0]
+            .parameters
+            .iter()
+            .map(|parameter| parameter.name.as_str())
+            .collect();
+        assert_eq!(vec!["a", "b"], names);
+    }
+
+    #[test]
+    fn test_parse_variadic_prototype_and_builtins() {
+        let code = "int printf(const char *fmt, ...); \
+                    int sum(int count, ...) { __builtin_va_list ap; __builtin_va_start(ap, count); \
+                    int total = __builtin_va_arg(ap, int); __builtin_va_end(ap); return total; } \
+                    int main() { printf(\"%d\\n\", sum(1, 2)); return 0; }";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            "Prototype Integer printf(Pointer<Const<Char>> fmt, ...)",
+            format!("{}", program.prototypes[0])
+        );
+        let printed = format!("{}", program);
+        assert!(printed.contains("Function Integer sum(Integer count, ...):"));
+        assert!(printed.contains("VaStart ap count"));
+        assert!(printed.contains("VaArg ap Integer"));
+        assert!(printed.contains("VaEnd ap"));
+    }
+
+    #[test]
+    fn test_parse_static_declarations() {
+        let code = "static int f(void); int f(void) { static int n = 1; return n++; } \
+                    static int g = 2; int main() { return f() + g; }";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(
+            "Static Declaration Integer g = Constant Integer<2>",
+            format!("{}", program.globals[0])
+        );
+        assert!(
+            format!("{}", program).contains("Static Declaration Integer n = Constant Integer<1>")
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_and_invalid_tokens() {
+        let code = "int main() { return 99999999999; }";
+        match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(_) => panic!("Oversized constant was accepted"),
+            Err(error) => assert_eq!(
+                "Integer constant is too large or malformed: 99999999999",
+                error.message
+            ),
+        }
+        let code = "int main() { return 0 @ 1; }";
+        assert!(parse_program_tokens(crate::tokenizer::tokenize(code)).is_err());
+        for constant in ["09", "0x", "0x1g", "1abc"] {
+            let code = format!("int main() {{ return {}; }}", constant);
+            match parse_program_tokens(crate::tokenizer::tokenize(&code)) {
+                Ok(_) => panic!("Malformed constant {} was accepted", constant),
+                Err(error) => assert_eq!(
+                    format!("Integer constant is too large or malformed: {}", constant),
+                    error.message
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_octal_and_hexadecimal_constants() {
+        let code = "010 + 0x10 + 0XfF + 0";
+        let mut token_iterator = TokenIterator::from(crate::tokenizer::tokenize(code));
+        let expression = match parse_expression(&mut token_iterator) {
+            Ok(val) => val,
+            Err(msg) => panic!("{}", msg),
+        };
+        assert_eq!(Type::Integer(279), expression.evaluate());
+    }
+
+    #[test]
+    fn test_parse_const_qualifiers() {
+        let code = "const int a; int const b; char *const c; const char *const *d;";
+        let program = match parse_program_tokens(crate::tokenizer::tokenize(code)) {
+            Ok(program) => program,
+            Err(error) => panic!("{}", error),
+        };
+        let types: Vec<String> = program
+            .globals
+            .iter()
+            .map(|global| global.variable_type.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "Const<Integer>",
+                "Const<Integer>",
+                "Const<Pointer<Char>>",
+                "Pointer<Const<Pointer<Const<Char>>>>",
+            ],
+            types
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_carry_spans() {
+        let code = "int main() {\n    int b = 1;\n    return b\n}\n";
+        match parse_program(crate::tokenizer::tokenize_with_spans(code)) {
+            Ok(_) => panic!("Missing semicolon was accepted"),
+            Err(error) => assert_eq!("4:1: Missing semicolon", error.to_string()),
+        }
+    }
 }
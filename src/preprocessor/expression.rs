@@ -0,0 +1,163 @@
+use crate::preprocessor::lexer::{PpKind, PpToken};
+use std::iter::Peekable;
+use std::slice::Iter;
+
+type TokenIterator<'a> = Peekable<Iter<'a, PpToken>>;
+
+// Evaluates the controlling expression of #if and #elif once `defined` and
+// macros have been replaced, with any identifier left over counting as 0.
+pub fn evaluate(tokens: &[PpToken]) -> Result<i64, String> {
+    let mut tokens_iter = tokens.iter().peekable();
+    let value = parse_conditional(&mut tokens_iter)?;
+    match tokens_iter.next() {
+        None => Ok(value),
+        Some(token) => Err(format!(
+            "missing binary operator before token \"{}\"",
+            token.text
+        )),
+    }
+}
+
+fn binary_operator(token: &PpToken) -> Option<u8> {
+    if token.kind != PpKind::Punctuator {
+        return None;
+    }
+    let precedence = match token.text.as_str() {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+fn apply(operator: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    let value = match operator {
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err(String::from("division by zero in #if")),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        _ => unreachable!(),
+    };
+    Ok(value)
+}
+
+fn parse_conditional(tokens_iter: &mut TokenIterator) -> Result<i64, String> {
+    let condition = parse_binary(tokens_iter, 1)?;
+    if !tokens_iter.peek().is_some_and(|token| token.is("?")) {
+        return Ok(condition);
+    }
+    tokens_iter.next();
+    let if_true = parse_conditional(tokens_iter)?;
+    match tokens_iter.next() {
+        Some(token) if token.is(":") => {}
+        _ => return Err(String::from("'?' without following ':' in #if")),
+    }
+    let if_false = parse_conditional(tokens_iter)?;
+    Ok(if condition != 0 { if_true } else { if_false })
+}
+
+fn parse_binary(tokens_iter: &mut TokenIterator, min_precedence: u8) -> Result<i64, String> {
+    let mut lhs = parse_unary(tokens_iter)?;
+    while let Some(precedence) = tokens_iter.peek().and_then(|token| binary_operator(token)) {
+        if precedence < min_precedence {
+            break;
+        }
+        let operator = &tokens_iter.next().unwrap().text;
+        let rhs = parse_binary(tokens_iter, precedence + 1)?;
+        lhs = apply(operator, lhs, rhs)?;
+    }
+    Ok(lhs)
+}
+
+fn parse_unary(tokens_iter: &mut TokenIterator) -> Result<i64, String> {
+    let token = tokens_iter
+        .next()
+        .ok_or_else(|| String::from("#if with no expression"))?;
+    match token.kind {
+        PpKind::Number => parse_number(&token.text),
+        PpKind::CharLiteral => parse_char(&token.text),
+        PpKind::Identifier => Ok(0),
+        _ if token.is("(") => {
+            let value = parse_comma(tokens_iter)?;
+            match tokens_iter.next() {
+                Some(token) if token.is(")") => Ok(value),
+                _ => Err(String::from("missing ')' in expression")),
+            }
+        }
+        _ if token.is("-") => Ok(parse_unary(tokens_iter)?.wrapping_neg()),
+        _ if token.is("+") => parse_unary(tokens_iter),
+        _ if token.is("!") => Ok((parse_unary(tokens_iter)? == 0) as i64),
+        _ if token.is("~") => Ok(!parse_unary(tokens_iter)?),
+        _ => Err(format!(
+            "token \"{}\" is not valid in preprocessor expressions",
+            token.text
+        )),
+    }
+}
+
+fn parse_comma(tokens_iter: &mut TokenIterator) -> Result<i64, String> {
+    let mut value = parse_conditional(tokens_iter)?;
+    while tokens_iter.peek().is_some_and(|token| token.is(",")) {
+        tokens_iter.next();
+        value = parse_conditional(tokens_iter)?;
+    }
+    Ok(value)
+}
+
+fn parse_number(text: &str) -> Result<i64, String> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<u64>()
+    };
+    parsed
+        .map(|value| value as i64)
+        .map_err(|_| format!("invalid integer \"{}\" in #if", text))
+}
+
+fn parse_char(text: &str) -> Result<i64, String> {
+    let contents = &text[1..text.len() - 1];
+    let mut chars = contents.chars();
+    let value = match (chars.next(), chars.next()) {
+        (Some('\\'), Some(escaped)) => match escaped {
+            'n' => '\n' as i64,
+            't' => '\t' as i64,
+            'r' => '\r' as i64,
+            '0'..='7' => i64::from_str_radix(&contents[1..], 8)
+                .map_err(|_| format!("invalid character constant {}", text))?,
+            _ => escaped as i64,
+        },
+        (Some(ch), None) => ch as i64,
+        _ => return Err(format!("invalid character constant {}", text)),
+    };
+    Ok(value)
+}
@@ -0,0 +1,21 @@
+// Standard headers built into the compiler, searched after the -I
+// directories. The host's headers rely on extensions the parser doesn't
+// support, so programs never see them.
+const BUILTIN_HEADERS: [(&str, &str); 6] = [
+    ("limits.h", include_str!("include/limits.h")),
+    ("stdarg.h", include_str!("include/stdarg.h")),
+    ("stdbool.h", include_str!("include/stdbool.h")),
+    ("stddef.h", include_str!("include/stddef.h")),
+    ("stdint.h", include_str!("include/stdint.h")),
+    ("stdio.h", include_str!("include/stdio.h")),
+];
+
+// Name builtin headers appear under in diagnostics and line markers.
+pub const BUILTIN_DIRECTORY: &str = "<builtin>";
+
+pub fn lookup(name: &str) -> Option<&'static str> {
+    BUILTIN_HEADERS
+        .iter()
+        .find(|(header, _)| *header == name)
+        .map(|(_, source)| *source)
+}
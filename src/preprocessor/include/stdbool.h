@@ -0,0 +1,9 @@
+#ifndef __STDBOOL_H
+#define __STDBOOL_H
+
+/* bool is _Bool, a one-byte type the compiler doesn't have yet, so only its
+   values are defined. */
+#define true 1
+#define false 0
+
+#endif
@@ -0,0 +1,9 @@
+#ifndef __STDDEF_H
+#define __STDDEF_H
+
+#define NULL 0
+
+/* size_t, ptrdiff_t and wchar_t are left out until there is typedef to
+   declare them with, and a 64-bit integer type for the first two. */
+
+#endif
@@ -0,0 +1,16 @@
+#ifndef __STDINT_H
+#define __STDINT_H
+
+#define int8_t char
+#define int32_t int
+#define int_least8_t char
+#define int_least32_t int
+#define int_fast8_t char
+#define int_fast32_t int
+
+#define INT8_MIN (-128)
+#define INT8_MAX 127
+#define INT32_MIN (-2147483647 - 1)
+#define INT32_MAX 2147483647
+
+#endif
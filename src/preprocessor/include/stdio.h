@@ -0,0 +1,15 @@
+#ifndef __STDIO_H
+#define __STDIO_H
+
+#include <stddef.h>
+
+#define EOF (-1)
+
+int printf(const char *format, ...);
+int sprintf(char *str, const char *format, ...);
+/* snprintf's size is a 64-bit size_t, which there is no type for yet. */
+int puts(const char *s);
+int putchar(int c);
+int getchar(void);
+
+#endif
@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLocation {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum PpKind {
+    Identifier,
+    Number,
+    StringLiteral,
+    CharLiteral,
+    Punctuator,
+    // Anything C has no token for, like a stray quote in a skipped group.
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct PpToken {
+    pub kind: PpKind,
+    pub text: String,
+    pub location: SourceLocation,
+    pub at_line_start: bool,
+    pub leading_space: bool,
+    // Macros this token came out of, which it must not expand again.
+    pub hide_set: BTreeSet<String>,
+}
+impl PpToken {
+    pub fn is(&self, text: &str) -> bool {
+        self.kind != PpKind::StringLiteral && self.kind != PpKind::CharLiteral && self.text == text
+    }
+}
+
+const PUNCTUATORS: [&str; 23] = [
+    "...", "<<=", ">>=", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=",
+    "/=", "%=", "+=", "-=", "&=", "^=", "|=", "##",
+];
+
+// Source characters with their line and column, after backslash-newline
+// splices are removed.
+fn spliced_chars(source: &str) -> Vec<(char, usize, usize)> {
+    let mut chars = vec![];
+    let (mut line, mut column) = (1, 1);
+    let mut source_chars = source.chars().filter(|ch| *ch != '\r').peekable();
+    while let Some(ch) = source_chars.next() {
+        if ch == '\\' && source_chars.peek() == Some(&'\n') {
+            source_chars.next();
+            line += 1;
+            column = 1;
+            continue;
+        }
+        chars.push((ch, line, column));
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    chars
+}
+
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+pub fn lex(file: &Rc<str>, source: &str) -> Result<Vec<PpToken>, String> {
+    let chars = spliced_chars(source);
+    let char_at = |idx: usize| chars.get(idx).map(|(ch, _, _)| *ch);
+    let mut tokens = vec![];
+    let mut at_line_start = true;
+    let mut leading_space = false;
+    let mut idx = 0;
+    while idx < chars.len() {
+        let (ch, line, column) = chars[idx];
+        let location = SourceLocation {
+            file: Rc::clone(file),
+            line,
+            column,
+        };
+        if ch == '\n' {
+            at_line_start = true;
+            leading_space = false;
+            idx += 1;
+            continue;
+        }
+        if ch.is_whitespace() {
+            leading_space = true;
+            idx += 1;
+            continue;
+        }
+        if ch == '/' && char_at(idx + 1) == Some('/') {
+            while idx < chars.len() && chars[idx].0 != '\n' {
+                idx += 1;
+            }
+            continue;
+        }
+        // A comment counts as a single space, even one spanning lines.
+        if ch == '/' && char_at(idx + 1) == Some('*') {
+            idx += 2;
+            while idx < chars.len() && !(chars[idx].0 == '*' && char_at(idx + 1) == Some('/')) {
+                idx += 1;
+            }
+            if idx >= chars.len() {
+                return Err(format!("{}: unterminated comment", location));
+            }
+            idx += 2;
+            leading_space = true;
+            continue;
+        }
+        let start = idx;
+        let kind = if is_identifier_char(ch) && !ch.is_ascii_digit() {
+            while char_at(idx).is_some_and(is_identifier_char) {
+                idx += 1;
+            }
+            PpKind::Identifier
+        } else if ch.is_ascii_digit()
+            || (ch == '.' && char_at(idx + 1).is_some_and(|next| next.is_ascii_digit()))
+        {
+            idx += 1;
+            while let Some(next) = char_at(idx) {
+                let exponent =
+                    matches!(next, '+' | '-') && matches!(chars[idx - 1].0, 'e' | 'E' | 'p' | 'P');
+                if !(is_identifier_char(next) || next == '.' || exponent) {
+                    break;
+                }
+                idx += 1;
+            }
+            PpKind::Number
+        } else if ch == '"' || ch == '\'' {
+            idx += 1;
+            let mut terminated = false;
+            while let Some(next) = char_at(idx) {
+                if next == '\n' {
+                    break;
+                }
+                idx += 1;
+                if next == '\\' && char_at(idx).is_some_and(|escaped| escaped != '\n') {
+                    idx += 1;
+                } else if next == ch {
+                    terminated = true;
+                    break;
+                }
+            }
+            if !terminated {
+                // Left for the caller to reject, since it is fine in a
+                // skipped group.
+                idx = start + 1;
+                PpKind::Other
+            } else if ch == '"' {
+                PpKind::StringLiteral
+            } else {
+                PpKind::CharLiteral
+            }
+        } else if ch.is_ascii_punctuation() {
+            let rest: String = chars[idx..chars.len().min(idx + 3)]
+                .iter()
+                .map(|(ch, _, _)| ch)
+                .collect();
+            let length = PUNCTUATORS
+                .iter()
+                .find(|punctuator| rest.starts_with(*punctuator))
+                .map_or(1, |punctuator| punctuator.len());
+            idx += length;
+            PpKind::Punctuator
+        } else {
+            idx += 1;
+            PpKind::Other
+        };
+        tokens.push(PpToken {
+            kind,
+            text: chars[start..idx].iter().map(|(ch, _, _)| ch).collect(),
+            location,
+            at_line_start,
+            leading_space,
+            hide_set: BTreeSet::new(),
+        });
+        at_line_start = false;
+        leading_space = false;
+    }
+    Ok(tokens)
+}
+
+// Whether writing `lhs` and `rhs` next to each other would lex differently,
+// like `+` followed by `+`, or two identifiers.
+pub fn needs_space(lhs: &PpToken, rhs: &PpToken) -> bool {
+    let word = |token: &PpToken| matches!(token.kind, PpKind::Identifier | PpKind::Number);
+    if word(lhs) && word(rhs) {
+        return true;
+    }
+    if lhs.kind != PpKind::Punctuator || rhs.kind != PpKind::Punctuator {
+        return false;
+    }
+    let joined = format!("{}{}", lhs.text, &rhs.text[..1]);
+    joined == "//"
+        || joined == "/*"
+        || PUNCTUATORS
+            .iter()
+            .any(|punctuator| punctuator.starts_with(&joined))
+}
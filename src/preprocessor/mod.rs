@@ -0,0 +1,1102 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use lexer::{PpKind, PpToken, SourceLocation};
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+const MAX_BLANK_LINES: usize = 8;
+
+struct Macro {
+    // None for object-like macros.
+    parameters: Option<Vec<String>>,
+    variadic: bool,
+    body: Vec<PpToken>,
+}
+impl Macro {
+    fn is_function_like(&self) -> bool {
+        self.parameters.is_some()
+    }
+    // Parameter names in argument order, __VA_ARGS__ last for variadic macros.
+    fn parameter_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .parameters
+            .iter()
+            .flatten()
+            .map(|name| name.as_str())
+            .collect();
+        if self.variadic {
+            names.push("__VA_ARGS__");
+        }
+        names
+    }
+    fn same_definition(&self, other: &Macro) -> bool {
+        self.parameters == other.parameters
+            && self.variadic == other.variadic
+            && self.body.len() == other.body.len()
+            && self
+                .body
+                .iter()
+                .zip(other.body.iter())
+                .all(|(lhs, rhs)| lhs.text == rhs.text && lhs.leading_space == rhs.leading_space)
+    }
+}
+
+struct Conditional {
+    location: SourceLocation,
+    parent_active: bool,
+    active: bool,
+    // Whether any branch so far was taken, so later ones are skipped.
+    taken: bool,
+    seen_else: bool,
+}
+
+// One line of preprocessed output and where it came from.
+pub struct Line {
+    pub location: SourceLocation,
+    pub text: String,
+}
+
+pub struct Preprocessed {
+    pub lines: Vec<Line>,
+    // The file preprocessing started from, then every other file the lines
+    // came from, in the order they first do.
+    pub files: Vec<String>,
+}
+impl Preprocessed {
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| format!("{}\n", line.text))
+            .collect()
+    }
+    // The output of -E: like text(), with `# line "file"` markers wherever
+    // the output stops following the source line by line. Short gaps are
+    // kept as blank lines instead.
+    pub fn line_marked_text(&self) -> String {
+        let mut text = String::new();
+        let mut next: Option<(&str, usize)> = None;
+        for line in self.lines.iter() {
+            let file = &*line.location.file;
+            match next {
+                Some((next_file, next_line))
+                    if next_file == file
+                        && line.location.line >= next_line
+                        && line.location.line - next_line <= MAX_BLANK_LINES =>
+                {
+                    text.push_str(&"\n".repeat(line.location.line - next_line));
+                }
+                _ => text.push_str(&format!("# {} \"{}\"\n", line.location.line, escape(file))),
+            }
+            text.push_str(&" ".repeat(line.location.column - 1));
+            text.push_str(&line.text);
+            text.push('\n');
+            next = Some((file, line.location.line + 1));
+        }
+        text
+    }
+    // Original location of the 1-based line `line` of `text()`.
+    pub fn location(&self, line: usize) -> Option<&SourceLocation> {
+        line.checked_sub(1)
+            .and_then(|idx| self.lines.get(idx))
+            .map(|line| &line.location)
+    }
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Rc<Macro>>,
+    once_files: HashSet<PathBuf>,
+    include_depth: usize,
+    output: Vec<PpToken>,
+    warnings: Vec<String>,
+    trace_macros: bool,
+    trace: Vec<String>,
+}
+
+fn spell(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        if idx > 0 && (token.leading_space || lexer::needs_space(&tokens[idx - 1], token)) {
+            text.push(' ');
+        }
+        text.push_str(&token.text);
+    }
+    text
+}
+
+fn split_lines(tokens: Vec<PpToken>) -> Vec<Vec<PpToken>> {
+    let mut lines: Vec<Vec<PpToken>> = vec![];
+    for token in tokens {
+        match lines.last_mut() {
+            Some(line) if !token.at_line_start => line.push(token),
+            _ => lines.push(vec![token]),
+        }
+    }
+    lines
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn stringize(argument: &[PpToken], hash: &PpToken) -> PpToken {
+    let mut text = String::new();
+    for (idx, token) in argument.iter().enumerate() {
+        if idx > 0 && token.leading_space {
+            text.push(' ');
+        }
+        match token.kind {
+            PpKind::StringLiteral | PpKind::CharLiteral => text.push_str(&escape(&token.text)),
+            _ => text.push_str(&token.text),
+        }
+    }
+    PpToken {
+        kind: PpKind::StringLiteral,
+        text: format!("\"{}\"", text),
+        ..hash.clone()
+    }
+}
+
+fn paste(lhs: &PpToken, rhs: &PpToken) -> Result<PpToken, String> {
+    let text = format!("{}{}", lhs.text, rhs.text);
+    let pasted = lexer::lex(&lhs.location.file, &text).unwrap_or_default();
+    match pasted.as_slice() {
+        [token] if token.text == text => Ok(PpToken {
+            kind: token.kind,
+            text,
+            ..lhs.clone()
+        }),
+        _ => Err(format!(
+            "{}: pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+            lhs.location, lhs.text, rhs.text
+        )),
+    }
+}
+
+fn expect_identifier<'a>(
+    tokens: &'a [PpToken],
+    directive: &PpToken,
+    message: &str,
+) -> Result<&'a PpToken, String> {
+    match tokens.first() {
+        Some(token) if token.kind == PpKind::Identifier => Ok(token),
+        Some(token) => Err(format!("{}: {}", token.location, message)),
+        None => Err(format!("{}: {}", directive.location, message)),
+    }
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<PathBuf>) -> Preprocessor {
+        Preprocessor {
+            include_paths,
+            macros: HashMap::new(),
+            once_files: HashSet::new(),
+            include_depth: 0,
+            output: vec![],
+            warnings: vec![],
+            trace_macros: false,
+            trace: vec![],
+        }
+    }
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+    // Records every macro expansion, nested ones included, with where it was
+    // invoked.
+    pub fn set_trace_macros(&mut self, trace_macros: bool) {
+        self.trace_macros = trace_macros;
+    }
+    pub fn trace(&self) -> &[String] {
+        &self.trace
+    }
+    pub fn preprocess_file(&mut self, path: &Path) -> Result<Preprocessed, String> {
+        let source =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.preprocess_source(&path.to_string_lossy(), &source)
+    }
+    pub fn preprocess_source(&mut self, name: &str, source: &str) -> Result<Preprocessed, String> {
+        self.output.clear();
+        self.process(Rc::from(name), source)?;
+        let mut lines: Vec<Line> = vec![];
+        let mut line_tokens: Vec<PpToken> = vec![];
+        for token in self.output.drain(..) {
+            let same_line = line_tokens.first().is_some_and(|first| {
+                first.location.file == token.location.file
+                    && first.location.line == token.location.line
+            });
+            if !same_line && !line_tokens.is_empty() {
+                lines.push(Line {
+                    location: line_tokens[0].location.clone(),
+                    text: spell(&line_tokens),
+                });
+                line_tokens.clear();
+            }
+            line_tokens.push(token);
+        }
+        if !line_tokens.is_empty() {
+            lines.push(Line {
+                location: line_tokens[0].location.clone(),
+                text: spell(&line_tokens),
+            });
+        }
+        let mut files = vec![String::from(name)];
+        for line in lines.iter() {
+            if !files.iter().any(|file| **file == *line.location.file) {
+                files.push(line.location.file.to_string());
+            }
+        }
+        Ok(Preprocessed { lines, files })
+    }
+
+    fn process(&mut self, file: Rc<str>, source: &str) -> Result<(), String> {
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut text: Vec<PpToken> = vec![];
+        // Set by #line and by the `# 12 "file"` markers -E writes, so its
+        // output keeps pointing at the original source.
+        let mut presumed: Option<(Rc<str>, isize)> = None;
+        for mut line in split_lines(lexer::lex(&file, source)?) {
+            if let Some((presumed_file, line_offset)) = &presumed {
+                for token in line.iter_mut() {
+                    token.location.file = Rc::clone(presumed_file);
+                    token.location.line = (token.location.line as isize + line_offset) as usize;
+                }
+            }
+            let active = conditionals.iter().all(|conditional| conditional.active);
+            if active
+                && line[0].is("#")
+                && line.len() > 1
+                && (line[1].kind == PpKind::Number || line[1].is("line"))
+            {
+                self.flush(&mut text)?;
+                presumed = Some(self.line_directive(&line, &presumed)?);
+                continue;
+            }
+            if !line[0].is("#") {
+                if conditionals.iter().all(|conditional| conditional.active) {
+                    text.extend(line);
+                }
+                continue;
+            }
+            // Text between two directives is expanded as a whole, so a macro
+            // call may span several lines but never a directive.
+            self.flush(&mut text)?;
+            self.directive(&line, &mut conditionals)?;
+        }
+        self.flush(&mut text)?;
+        match conditionals.last() {
+            Some(conditional) => Err(format!(
+                "{}: unterminated conditional directive",
+                conditional.location
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&mut self, text: &mut Vec<PpToken>) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let expanded = self.expand(std::mem::take(text))?;
+        if let Some(token) = expanded
+            .iter()
+            .find(|token| token.kind == PpKind::Other && (token.text == "\"" || token.text == "'"))
+        {
+            return Err(format!(
+                "{}: missing terminating {} character",
+                token.location, token.text
+            ));
+        }
+        self.output.extend(expanded);
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        line: &[PpToken],
+        conditionals: &mut Vec<Conditional>,
+    ) -> Result<(), String> {
+        let hash = &line[0];
+        let name = match line.get(1) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let arguments = &line[2..];
+        let active = conditionals.iter().all(|conditional| conditional.active);
+        match name.text.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                let condition = active
+                    && match name.text.as_str() {
+                        "if" => self.condition(arguments, name)?,
+                        directive => {
+                            let macro_name = expect_identifier(
+                                arguments,
+                                name,
+                                "macro names must be identifiers",
+                            )?;
+                            self.macros.contains_key(&macro_name.text) == (directive == "ifdef")
+                        }
+                    };
+                conditionals.push(Conditional {
+                    location: hash.location.clone(),
+                    parent_active: active,
+                    active: condition,
+                    taken: condition || !active,
+                    seen_else: false,
+                });
+            }
+            "elif" => {
+                let conditional = conditionals
+                    .last()
+                    .ok_or_else(|| format!("{}: #elif without #if", hash.location))?;
+                if conditional.seen_else {
+                    return Err(format!("{}: #elif after #else", hash.location));
+                }
+                let condition = !conditional.taken && self.condition(arguments, name)?;
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = condition;
+                conditional.taken = conditional.taken || condition;
+            }
+            "else" => {
+                let conditional = conditionals
+                    .last_mut()
+                    .ok_or_else(|| format!("{}: #else without #if", hash.location))?;
+                if conditional.seen_else {
+                    return Err(format!("{}: #else after #else", hash.location));
+                }
+                conditional.active = conditional.parent_active && !conditional.taken;
+                conditional.taken = true;
+                conditional.seen_else = true;
+            }
+            "endif" => {
+                conditionals
+                    .pop()
+                    .ok_or_else(|| format!("{}: #endif without #if", hash.location))?;
+            }
+            _ if !active => {}
+            "define" => self.define(arguments, name)?,
+            "undef" => {
+                let macro_name =
+                    expect_identifier(arguments, name, "macro names must be identifiers")?;
+                self.macros.remove(&macro_name.text);
+            }
+            "include" => self.include(arguments, name)?,
+            "error" => return Err(format!("{}: #error {}", hash.location, spell(arguments))),
+            "warning" => {
+                self.warnings
+                    .push(format!("{}: #warning {}", hash.location, spell(arguments)))
+            }
+            "pragma" => {
+                if arguments.first().is_some_and(|token| token.is("once")) {
+                    if let Ok(path) = fs::canonicalize(&*hash.location.file) {
+                        self.once_files.insert(path);
+                    }
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "{}: invalid preprocessing directive #{}",
+                    name.location, name.text
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the presumed file name and how far presumed line numbers are
+    // from physical ones from the next line on.
+    fn line_directive(
+        &mut self,
+        line: &[PpToken],
+        presumed: &Option<(Rc<str>, isize)>,
+    ) -> Result<(Rc<str>, isize), String> {
+        let arguments = if line[1].is("line") {
+            self.expand(line[2..].to_vec())?
+        } else {
+            line[1..].to_vec()
+        };
+        let number = arguments
+            .first()
+            .filter(|token| token.kind == PpKind::Number)
+            .and_then(|token| token.text.parse::<usize>().ok())
+            .ok_or_else(|| {
+                format!(
+                    "{}: invalid line number in #line directive",
+                    line[0].location
+                )
+            })?;
+        let file = match arguments.get(1) {
+            Some(token) if token.kind == PpKind::StringLiteral => Rc::from(
+                token.text[1..token.text.len() - 1]
+                    .replace("\\\"", "\"")
+                    .replace("\\\\", "\\"),
+            ),
+            Some(token) if line[1].is("line") => {
+                return Err(format!(
+                    "{}: invalid filename \"{}\"",
+                    token.location, token.text
+                ));
+            }
+            _ => presumed.as_ref().map_or_else(
+                || Rc::clone(&line[0].location.file),
+                |(file, _)| Rc::clone(file),
+            ),
+        };
+        let physical_line = line[0].location.line as isize
+            - presumed.as_ref().map_or(0, |(_, line_offset)| *line_offset);
+        Ok((file, number as isize - physical_line - 1))
+    }
+
+    fn condition(&mut self, arguments: &[PpToken], directive: &PpToken) -> Result<bool, String> {
+        // `defined` has to be resolved before macros are expanded.
+        let mut tokens = vec![];
+        let mut arguments_iter = arguments.iter();
+        while let Some(token) = arguments_iter.next() {
+            if !token.is("defined") {
+                tokens.push(token.clone());
+                continue;
+            }
+            let mut operand = arguments_iter.next();
+            let parenthesised = operand.is_some_and(|token| token.is("("));
+            if parenthesised {
+                operand = arguments_iter.next();
+            }
+            let macro_name = match operand {
+                Some(operand) if operand.kind == PpKind::Identifier => operand,
+                _ => {
+                    return Err(format!(
+                        "{}: operator \"defined\" requires an identifier",
+                        token.location
+                    ))
+                }
+            };
+            if parenthesised && !arguments_iter.next().is_some_and(|token| token.is(")")) {
+                return Err(format!("{}: missing ')' after \"defined\"", token.location));
+            }
+            let value = self.macros.contains_key(&macro_name.text) as i32;
+            tokens.push(PpToken {
+                kind: PpKind::Number,
+                text: value.to_string(),
+                ..token.clone()
+            });
+        }
+        let expanded = self.expand(tokens)?;
+        expression::evaluate(&expanded)
+            .map(|value| value != 0)
+            .map_err(|msg| format!("{}: {}", directive.location, msg))
+    }
+
+    fn define(&mut self, arguments: &[PpToken], directive: &PpToken) -> Result<(), String> {
+        let name = expect_identifier(arguments, directive, "macro names must be identifiers")?;
+        if name.text == "defined" {
+            return Err(format!(
+                "{}: \"defined\" cannot be used as a macro name",
+                name.location
+            ));
+        }
+        let mut rest = &arguments[1..];
+        let mut parameters = None;
+        let mut variadic = false;
+        // Only a parenthesis right after the name makes a function-like macro.
+        if rest
+            .first()
+            .is_some_and(|token| token.is("(") && !token.leading_space)
+        {
+            let mut names: Vec<String> = vec![];
+            let mut idx = 1;
+            loop {
+                let token = rest.get(idx).ok_or_else(|| {
+                    format!("{}: missing ')' in macro parameter list", name.location)
+                })?;
+                idx += 1;
+                if token.is(")") && names.is_empty() && !variadic {
+                    break;
+                }
+                if token.is("...") {
+                    variadic = true;
+                } else if token.kind == PpKind::Identifier && !names.contains(&token.text) {
+                    names.push(token.text.clone());
+                } else {
+                    return Err(format!(
+                        "{}: invalid macro parameter \"{}\"",
+                        token.location, token.text
+                    ));
+                }
+                match rest.get(idx) {
+                    Some(next) if next.is(")") => {
+                        idx += 1;
+                        break;
+                    }
+                    Some(next) if next.is(",") && !variadic => idx += 1,
+                    _ => {
+                        return Err(format!(
+                            "{}: missing ')' in macro parameter list",
+                            name.location
+                        ))
+                    }
+                }
+            }
+            rest = &rest[idx..];
+            parameters = Some(names);
+        }
+        let mut body = rest.to_vec();
+        if let Some(first) = body.first_mut() {
+            first.leading_space = false;
+        }
+        if body.first().is_some_and(|token| token.is("##"))
+            || body.last().is_some_and(|token| token.is("##"))
+        {
+            return Err(format!(
+                "{}: '##' cannot appear at either end of a macro expansion",
+                name.location
+            ));
+        }
+        let definition = Macro {
+            parameters,
+            variadic,
+            body,
+        };
+        if definition.is_function_like() {
+            let names = definition.parameter_names();
+            for (idx, token) in definition.body.iter().enumerate() {
+                let stringized = definition.body.get(idx + 1);
+                if token.is("#")
+                    && !stringized.is_some_and(|next| names.contains(&next.text.as_str()))
+                {
+                    return Err(format!(
+                        "{}: '#' is not followed by a macro parameter",
+                        token.location
+                    ));
+                }
+            }
+        }
+        if let Some(previous) = self.macros.get(&name.text) {
+            if !previous.same_definition(&definition) {
+                self.warnings
+                    .push(format!("{}: \"{}\" redefined", name.location, name.text));
+            }
+        }
+        self.macros.insert(name.text.clone(), Rc::new(definition));
+        Ok(())
+    }
+
+    fn include(&mut self, arguments: &[PpToken], directive: &PpToken) -> Result<(), String> {
+        let header = match arguments.first() {
+            Some(token) if token.kind == PpKind::StringLiteral => {
+                Some((token.text[1..token.text.len() - 1].to_string(), true))
+            }
+            Some(token) if token.is("<") => {
+                let end = arguments.iter().position(|token| token.is(">"));
+                end.map(|end| (spell(&arguments[1..end]), false))
+            }
+            _ => None,
+        };
+        let (name, quoted) = match header {
+            Some(header) => header,
+            None if arguments
+                .first()
+                .is_some_and(|token| token.kind == PpKind::Identifier) =>
+            {
+                // #include MACRO, where the macro expands to a header name.
+                let expanded = self.expand(arguments.to_vec())?;
+                if expanded
+                    .first()
+                    .is_some_and(|token| token.kind == PpKind::Identifier)
+                {
+                    return Err(format!(
+                        "{}: #include expects \"FILENAME\" or <FILENAME>",
+                        directive.location
+                    ));
+                }
+                return self.include(&expanded, directive);
+            }
+            None => {
+                return Err(format!(
+                    "{}: #include expects \"FILENAME\" or <FILENAME>",
+                    directive.location
+                ))
+            }
+        };
+        if self.include_depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "{}: #include nested too deeply",
+                directive.location
+            ));
+        }
+        let (file, source) = match self.resolve(&name, quoted, &directive.location.file) {
+            Some(path) => {
+                if fs::canonicalize(&path).is_ok_and(|path| self.once_files.contains(&path)) {
+                    return Ok(());
+                }
+                let source = fs::read_to_string(&path).map_err(|err| {
+                    format!("{}: {}: {}", directive.location, path.display(), err)
+                })?;
+                (path.to_string_lossy().to_string(), source)
+            }
+            None => {
+                let source = headers::lookup(&name)
+                    .ok_or_else(|| format!("{}: '{}' file not found", directive.location, name))?;
+                (
+                    format!("{}/{}", headers::BUILTIN_DIRECTORY, name),
+                    String::from(source),
+                )
+            }
+        };
+        self.include_depth += 1;
+        let result = self.process(Rc::from(file), &source);
+        self.include_depth -= 1;
+        result
+    }
+
+    // Quoted headers are looked up next to the including file first, then
+    // both forms search the -I directories in order, and finally the
+    // builtin headers.
+    fn resolve(&self, name: &str, quoted: bool, including_file: &str) -> Option<PathBuf> {
+        let current_dir = Path::new(including_file)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut candidates = vec![];
+        if quoted {
+            candidates.push(current_dir.join(name));
+        }
+        candidates.extend(self.include_paths.iter().map(|dir| dir.join(name)));
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+
+    fn builtin(&self, token: &PpToken) -> Option<PpToken> {
+        let (kind, text) = match token.text.as_str() {
+            "__FILE__" => (
+                PpKind::StringLiteral,
+                format!("\"{}\"", escape(&token.location.file)),
+            ),
+            "__LINE__" => (PpKind::Number, token.location.line.to_string()),
+            _ => return None,
+        };
+        Some(PpToken {
+            kind,
+            text,
+            ..token.clone()
+        })
+    }
+
+    // Macro expansion with hide sets: every token remembers the macros it was
+    // produced by and never expands them again, which is what stops recursion.
+    fn expand(&mut self, tokens: Vec<PpToken>) -> Result<Vec<PpToken>, String> {
+        let mut input: VecDeque<PpToken> = tokens.into();
+        let mut output = vec![];
+        while let Some(token) = input.pop_front() {
+            if token.kind != PpKind::Identifier || token.hide_set.contains(&token.text) {
+                output.push(token);
+                continue;
+            }
+            if let Some(builtin) = self.builtin(&token) {
+                output.push(builtin);
+                continue;
+            }
+            let definition = match self.macros.get(&token.text) {
+                Some(definition) => Rc::clone(definition),
+                None => {
+                    output.push(token);
+                    continue;
+                }
+            };
+            let mut hide_set = token.hide_set.clone();
+            let arguments = if definition.is_function_like() {
+                if !input.front().is_some_and(|next| next.is("(")) {
+                    output.push(token);
+                    continue;
+                }
+                input.pop_front();
+                let (arguments, close) = self.collect_arguments(&mut input, &definition, &token)?;
+                hide_set = hide_set.intersection(&close.hide_set).cloned().collect();
+                arguments
+            } else {
+                vec![]
+            };
+            hide_set.insert(token.text.clone());
+            // Expansions inside the arguments are logged after this one.
+            let trace_index = self.trace.len();
+            let mut expansion = self.substitute(&definition, &arguments)?;
+            if self.trace_macros {
+                let invocation = if definition.is_function_like() {
+                    let spelled: Vec<String> =
+                        arguments.iter().map(|argument| spell(argument)).collect();
+                    format!("{}({})", token.text, spelled.join(", "))
+                } else {
+                    token.text.clone()
+                };
+                self.trace.insert(
+                    trace_index,
+                    format!(
+                        "{}: {} -> {}",
+                        token.location,
+                        invocation,
+                        spell(&expansion)
+                    ),
+                );
+            }
+            for (idx, expanded) in expansion.iter_mut().enumerate() {
+                expanded.location = token.location.clone();
+                expanded.at_line_start = false;
+                if idx == 0 {
+                    expanded.leading_space = token.leading_space;
+                }
+                expanded.hide_set.extend(hide_set.iter().cloned());
+            }
+            for expanded in expansion.into_iter().rev() {
+                input.push_front(expanded);
+            }
+        }
+        Ok(output)
+    }
+
+    fn collect_arguments(
+        &self,
+        input: &mut VecDeque<PpToken>,
+        definition: &Macro,
+        name: &PpToken,
+    ) -> Result<(Vec<Vec<PpToken>>, PpToken), String> {
+        let named = definition
+            .parameters
+            .as_ref()
+            .map_or(0, |names| names.len());
+        let mut arguments: Vec<Vec<PpToken>> = vec![vec![]];
+        let mut depth = 0;
+        let close = loop {
+            let token = input.pop_front().ok_or_else(|| {
+                format!(
+                    "{}: unterminated argument list invoking macro \"{}\"",
+                    name.location, name.text
+                )
+            })?;
+            if token.is(")") && depth == 0 {
+                break token;
+            }
+            if token.is("(") {
+                depth += 1;
+            } else if token.is(")") {
+                depth -= 1;
+            }
+            // Commas in the variable part belong to __VA_ARGS__.
+            if token.is(",") && depth == 0 && !(definition.variadic && arguments.len() > named) {
+                arguments.push(vec![]);
+                continue;
+            }
+            arguments.last_mut().unwrap().push(token);
+        };
+        if named == 0 && arguments.len() == 1 && arguments[0].is_empty() && !definition.variadic {
+            arguments.clear();
+        }
+        if definition.variadic && arguments.len() == named {
+            arguments.push(vec![]);
+        }
+        let expected = definition.parameter_names().len();
+        if arguments.len() < expected {
+            return Err(format!(
+                "{}: macro \"{}\" requires {} arguments, but only {} given",
+                name.location,
+                name.text,
+                expected,
+                arguments.len()
+            ));
+        }
+        if arguments.len() > expected {
+            return Err(format!(
+                "{}: macro \"{}\" passed {} arguments, but takes just {}",
+                name.location,
+                name.text,
+                arguments.len(),
+                expected
+            ));
+        }
+        for argument in arguments.iter_mut() {
+            if let Some(first) = argument.first_mut() {
+                first.leading_space = false;
+            }
+        }
+        Ok((arguments, close))
+    }
+
+    // Replaces parameters in the macro body. Operands of # and ## use the
+    // argument as written, everywhere else it is fully expanded first.
+    fn substitute(
+        &mut self,
+        definition: &Macro,
+        arguments: &[Vec<PpToken>],
+    ) -> Result<Vec<PpToken>, String> {
+        let names = definition.parameter_names();
+        let parameter = |token: &PpToken| {
+            if token.kind == PpKind::Identifier {
+                names.iter().position(|name| *name == token.text)
+            } else {
+                None
+            }
+        };
+        let body = &definition.body;
+        let mut result: Vec<PpToken> = vec![];
+        // Set when the last operand was an empty argument, which ## pastes as
+        // nothing.
+        let mut placemarker = false;
+        let mut idx = 0;
+        while idx < body.len() {
+            let token = &body[idx];
+            if definition.is_function_like() && token.is("#") {
+                let argument = parameter(&body[idx + 1]).unwrap();
+                result.push(stringize(&arguments[argument], token));
+                placemarker = false;
+                idx += 2;
+                continue;
+            }
+            if token.is("##") {
+                let rhs = &body[idx + 1];
+                let mut rhs_tokens = match parameter(rhs) {
+                    Some(argument) => arguments[argument].clone(),
+                    None => vec![rhs.clone()],
+                }
+                .into_iter();
+                idx += 2;
+                if let Some(first) = rhs_tokens.next() {
+                    if placemarker {
+                        result.push(first);
+                    } else {
+                        let lhs = result.pop().unwrap();
+                        result.push(paste(&lhs, &first)?);
+                    }
+                    result.extend(rhs_tokens);
+                    placemarker = false;
+                }
+                continue;
+            }
+            idx += 1;
+            let argument = match parameter(token) {
+                Some(argument) => argument,
+                None => {
+                    result.push(token.clone());
+                    placemarker = false;
+                    continue;
+                }
+            };
+            let mut replacement = if body.get(idx).is_some_and(|next| next.is("##")) {
+                arguments[argument].clone()
+            } else {
+                self.expand(arguments[argument].clone())?
+            };
+            placemarker = replacement.is_empty();
+            if let Some(first) = replacement.first_mut() {
+                first.leading_space = token.leading_space;
+            }
+            result.extend(replacement);
+        }
+        Ok(result)
+    }
+}
+
+pub mod expression;
+pub mod headers;
+pub mod lexer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(source: &str) -> Result<String, String> {
+        Preprocessor::new(vec![])
+            .preprocess_source("test.c", source)
+            .map(|preprocessed| preprocessed.text())
+    }
+
+    #[test]
+    fn test_object_and_function_like_macros() {
+        let source =
+            "#define N 4\n#define MAX(a, b) ((a) > (b) ? (a) : (b))\nint x = MAX(N, 2 + 1);\n";
+        assert_eq!(
+            "int x = ((4) > (2 + 1) ? (4) : (2 + 1));\n",
+            preprocess(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stringize_and_paste() {
+        let source = "#define STR(x) #x\n#define CAT(a, b) a ## b\n#define EMPTY\n\
+                      CAT(foo, 1) STR( a  \"b\\n\" ) CAT(, x) CAT(EMPTY, y)\n";
+        assert_eq!(
+            "foo1 \"a \\\"b\\\\n\\\"\" x EMPTYy\n",
+            preprocess(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recursive_macros_stop() {
+        // From the C standard's examples of rescanning.
+        let source = "#define x 3\n#define f(a) f(x * (a))\n#undef x\n#define x 2\n\
+                      #define g f\n#define z z[0]\n#define h g(~\n#define m(a) a(w)\n#define w 0,1\n\
+                      f(y+1) + f(f(z)) % t(t(g)(0) + t)(1);\ng(x+(3,4)-w) | h 5) & m\n(f)^m(m);\n";
+        assert_eq!(
+            "f(2 * (y+1)) + f(2 * (f(2 * (z[0])))) % t(t(f)(0) + t)(1);\n\
+             f(2 * (2+(3,4)-0,1)) | f(2 * (~ 5)) & f(2 * (0,1))\n^m(0,1);\n",
+            preprocess(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_variadic_macro() {
+        let source = "#define LOG(fmt, ...) printf(fmt, __VA_ARGS__)\nLOG(\"%d %d\", 1, 2);\n";
+        assert_eq!("printf(\"%d %d\", 1, 2);\n", preprocess(source).unwrap());
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let source = "#define A 2\n#if A * 2 == 4 && defined(A)\nyes\n#elif 1\nno\n#else\nno\n#endif\n\
+                      #ifndef A\nno ' unterminated\n#elif A >> 1\nshifted\n#endif\n#if B\nno\n#endif\n";
+        assert_eq!("yes\nshifted\n", preprocess(source).unwrap());
+    }
+
+    #[test]
+    fn test_file_and_line() {
+        let source = "\n#define HERE __LINE__\nint line = HERE; char *file = __FILE__;\n";
+        let preprocessed = Preprocessor::new(vec![])
+            .preprocess_source("dir/test.c", source)
+            .unwrap();
+        assert_eq!(
+            "int line = 3; char *file = \"dir/test.c\";\n",
+            preprocessed.text()
+        );
+        assert_eq!(3, preprocessed.location(1).unwrap().line);
+    }
+
+    #[test]
+    fn test_include_search_paths_and_pragma_once() {
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+        let source = "#include <answer.h>\n#include \"include/answer.h\"\nint x = ANSWER;\n";
+        let preprocessed = Preprocessor::new(vec![samples.join("include")])
+            .preprocess_source(&samples.join("main.c").to_string_lossy(), source)
+            .unwrap();
+        assert_eq!("int answer(void);\nint x = 42;\n", preprocessed.text());
+        assert!(preprocessed.lines[0].location.file.ends_with("answer.h"));
+    }
+
+    #[test]
+    fn test_diagnostics_carry_locations() {
+        let errors = [
+            ("#if 1\n", "test.c:1:1: unterminated conditional directive"),
+            ("\n  #error bad  thing\n", "test.c:2:3: #error bad thing"),
+            (
+                "#include \"missing.h\"\n",
+                "test.c:1:2: 'missing.h' file not found",
+            ),
+            (
+                "#define F(a, b) a\nF(1)\n",
+                "test.c:2:1: macro \"F\" requires 2 arguments, but only 1 given",
+            ),
+            (
+                "#define P(a) a ## +\nP(x)\n",
+                "test.c:2:3: pasting \"x\" and \"+\" does not give a valid preprocessing token",
+            ),
+            ("#endif\n", "test.c:1:1: #endif without #if"),
+            ("#foo\n", "test.c:1:2: invalid preprocessing directive #foo"),
+            ("#if 1 / 0\n#endif\n", "test.c:1:2: division by zero in #if"),
+        ];
+        for (source, expected) in errors.iter() {
+            assert_eq!(Err(String::from(*expected)), preprocess(source));
+        }
+    }
+
+    #[test]
+    fn test_line_markers() {
+        let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+        let source =
+            "#include \"include/answer.h\"\n\nint a;\n\nint b;\n\n\n\n\n\n\n\n\n\nint c;\n";
+        let main_file = samples.join("main.c").to_string_lossy().to_string();
+        let preprocessed = Preprocessor::new(vec![])
+            .preprocess_source(&main_file, source)
+            .unwrap();
+        let header = samples
+            .join("include/answer.h")
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(
+            format!(
+                "# 5 \"{}\"\nint answer(void);\n# 3 \"{}\"\nint a;\n\nint b;\n# 15 \"{}\"\nint c;\n",
+                header, main_file, main_file
+            ),
+            preprocessed.line_marked_text()
+        );
+    }
+
+    #[test]
+    fn test_line_markers_are_read_back() {
+        let source = "# 40 \"orig.c\"\nint x = __LINE__;\n#line 7\n#error stop\n";
+        assert_eq!(
+            Err(String::from("orig.c:7:1: #error stop")),
+            preprocess(source)
+        );
+    }
+
+    #[test]
+    fn test_trace_macros() {
+        let mut preprocessor = Preprocessor::new(vec![]);
+        preprocessor.set_trace_macros(true);
+        let source = "#define TWICE(x) ((x) + (x))\n#define N 3\nint y =\n  TWICE(N);\n";
+        preprocessor.preprocess_source("test.c", source).unwrap();
+        assert_eq!(
+            vec![
+                "test.c:4:3: TWICE(N) -> ((3) + (3))",
+                "test.c:4:9: N -> 3",
+                "test.c:4:9: N -> 3"
+            ],
+            preprocessor.trace()
+        );
+    }
+
+    #[test]
+    fn test_builtin_headers() {
+        let source = "#include <stdarg.h>\n#include <stdio.h>\n#include <limits.h>\n\
+                      int f(int n, ...) { va_list ap; va_start(ap, n); return va_arg(ap, int) + INT_MAX; }\n";
+        let preprocessed = Preprocessor::new(vec![])
+            .preprocess_source("test.c", source)
+            .unwrap();
+        assert_eq!(
+            "int f(int n, ...) { __builtin_va_list ap; __builtin_va_start(ap, n); \
+             return __builtin_va_arg(ap, int) + 2147483647; }",
+            preprocessed.lines.last().unwrap().text
+        );
+        assert!(preprocessed
+            .lines
+            .iter()
+            .any(|line| &*line.location.file == "<builtin>/stdio.h"
+                && line.text.starts_with("int printf(")));
+        assert!(!preprocessed
+            .lines
+            .iter()
+            .any(|line| line.text.contains("snprintf")));
+        // Every builtin header has to get through the compiler's own parser.
+        let all_headers = "#include <stddef.h>\n#include <stdint.h>\n#include <stdbool.h>\n\
+                           #include <stdarg.h>\n#include <limits.h>\n#include <stdio.h>\n\
+                           int main() { int ok = true; int32_t max = INT32_MAX; char *p = NULL; \
+                           return ok + max + !p + CHAR_MIN + EOF; }\n";
+        let code = Preprocessor::new(vec![])
+            .preprocess_source("test.c", all_headers)
+            .unwrap()
+            .text();
+        crate::parser::parse_program_tokens(crate::tokenizer::tokenize(&code)).unwrap();
+        // Types the compiler can't declare at their real width are left out.
+        let source = "#include <stddef.h>\n#include <stdbool.h>\nsize_t ptrdiff_t wchar_t bool\n";
+        let preprocessed = Preprocessor::new(vec![])
+            .preprocess_source("test.c", source)
+            .unwrap();
+        assert_eq!(
+            "size_t ptrdiff_t wchar_t bool",
+            preprocessed.lines.last().unwrap().text
+        );
+    }
+
+    #[test]
+    fn test_redefinition_warning() {
+        let mut preprocessor = Preprocessor::new(vec![]);
+        let source = "#define A 1\n#define A 1\n#define A 2\n";
+        preprocessor.preprocess_source("test.c", source).unwrap();
+        assert_eq!(vec!["test.c:3:9: \"A\" redefined"], preprocessor.warnings());
+    }
+}
@@ -0,0 +1,1017 @@
+use std::collections::HashMap;
+
+use crate::syntax::expressions::{self, UnaryOperator, VaOperation};
+use crate::syntax::signatures::Signature;
+use crate::syntax::statements;
+use crate::syntax::visit::{
+    fold_declaration, fold_expression, fold_statement, walk_expression, walk_statement, Fold,
+    Visitor,
+};
+use crate::syntax::{DataType, Error, Expression, ExpressionTrait, Program, Statement, Type};
+
+// Semantic analysis, between parsing and code generation. File scope
+// declarations are checked against each other in source order, then every
+// function body has its names resolved and its expressions typed, with
+// operands, lvalues and calls checked. Implicit conversions are then made
+// explicit over the whole program. Code generation only ever sees a program
+// that has been through here. Errors are placed at the innermost node they
+// concern.
+pub fn analyze(declarations: Vec<Statement>) -> Result<Program, Error> {
+    if declarations.is_empty() {
+        return Err(Error::from(String::from(
+            "Translation unit contains no declarations",
+        )));
+    }
+    let no_signatures = HashMap::new();
+    let mut declared: Vec<Statement> = vec![];
+    let mut global_scope = vec![];
+    // The globals each function definition can see, in definition order.
+    let mut visible_globals = vec![];
+    for mut statement in declarations {
+        match &mut statement {
+            Statement::Declaration(declaration) => {
+                check_global(declaration, &declared)
+                    .map_err(|message| Error::new(message, declaration.span))?;
+                let mut context = Context::new(vec![global_scope.clone()], &no_signatures);
+                context
+                    .analyze_initializer(declaration)
+                    .map_err(|error| error.within(declaration.span))?;
+                global_scope.push((declaration.name.clone(), declaration.variable_type.clone()));
+            }
+            _ => {
+                let span = statement.span();
+                check_function(&mut statement, &declared)
+                    .map_err(|message| Error::new(message, span))?;
+                if let Statement::Function(_) = statement {
+                    visible_globals.push(global_scope.clone());
+                }
+            }
+        }
+        declared.push(statement);
+    }
+    let mut program = Program::from_declarations(declared);
+    let names = program
+        .prototypes
+        .iter()
+        .map(|prototype| &prototype.name)
+        .chain(program.functions.iter().map(|function| &function.name));
+    let signatures: HashMap<String, Signature> = names
+        .filter_map(|name| Some((name.clone(), program.signature(name)?)))
+        .collect();
+    for (function, globals) in program.functions.iter_mut().zip(visible_globals) {
+        analyze_function(function, globals, &signatures)?;
+    }
+    let mut conversions = Conversions {
+        signatures: &signatures,
+    };
+    Ok(conversions.fold_program(program))
+}
+
+fn analyze_function(
+    function: &mut statements::Function,
+    globals: Vec<(String, DataType)>,
+    signatures: &HashMap<String, Signature>,
+) -> Result<(), Error> {
+    let at_function = |message| Error::new(message, function.span);
+    check_parameters(function).map_err(at_function)?;
+    for statement in function.body.iter() {
+        check_statement_context(statement, false)?;
+    }
+    check_goto_labels(function).map_err(at_function)?;
+    check_va_builtins(function).map_err(at_function)?;
+    let mut context = Context::new(vec![globals, vec![]], signatures);
+    for parameter in function.parameters.iter() {
+        context
+            .declare(&parameter.name, &parameter.data_type)
+            .map_err(at_function)?;
+    }
+    for statement in function.body.iter_mut() {
+        context.analyze_statement(statement)?;
+    }
+    Ok(())
+}
+
+fn check_parameters(function: &statements::Function) -> Result<(), String> {
+    for parameter in function.parameters.iter() {
+        if parameter.name.is_empty() {
+            return Err(format!(
+                "Missing parameter name in definition of function {}",
+                function.name
+            ));
+        }
+        if *parameter.data_type.unqualified() == DataType::VaList {
+            return Err(format!(
+                "va_list parameters are not supported in definition of function {}",
+                function.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Static storage is initialised before the program runs, so there is nothing
+// to evaluate a non-constant initializer with.
+// Static storage is filled in before the program runs, from an integer
+// constant, or for a pointer a null pointer constant or a string literal.
+fn check_static_initializer(declaration: &statements::Declaration) -> Result<(), String> {
+    if let Some(initializer) = &declaration.initializer {
+        let constant = match declaration.variable_type.unqualified() {
+            DataType::Pointer(_) => {
+                initializer.is_constant() || matches!(initializer, Expression::StringLiteral(_))
+            }
+            data_type => is_integer(data_type) && initializer.is_constant(),
+        };
+        if !constant {
+            return Err(format!(
+                "Initializer of '{}' is not a constant",
+                declaration.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// case, default and break are only meaningful inside a switch body.
+fn check_statement_context(statement: &Statement, in_switch: bool) -> Result<(), Error> {
+    let message = match statement {
+        Statement::Block(block) => {
+            for statement in block.body.iter() {
+                check_statement_context(statement, in_switch)?;
+            }
+            return Ok(());
+        }
+        Statement::Switch(switch) => return check_statement_context(&switch.body, true),
+        Statement::Case(case) if in_switch => {
+            return check_statement_context(&case.statement, in_switch)
+        }
+        Statement::Label(label) => return check_statement_context(&label.statement, in_switch),
+        Statement::Default(default) if in_switch => {
+            return check_statement_context(&default.statement, in_switch)
+        }
+        Statement::Case(_) => "case label not within a switch statement",
+        Statement::Default(_) => "default label not within a switch statement",
+        Statement::Break(_) if !in_switch => "break statement not within a switch statement",
+        _ => return Ok(()),
+    };
+    Err(Error::new(String::from(message), statement.span()))
+}
+
+fn check_switch_labels(switch: &statements::Switch) -> Result<(), String> {
+    let labels = switch.labels();
+    for (idx, label) in labels.iter().enumerate() {
+        if labels[..idx].contains(label) {
+            return Err(match label {
+                Some(value) => format!("Duplicate case value {} in switch", value),
+                None => String::from("Multiple default labels in one switch"),
+            });
+        }
+    }
+    Ok(())
+}
+
+// va_start only makes sense in a variadic function, naming its last
+// named parameter.
+fn check_va_builtins(function: &statements::Function) -> Result<(), String> {
+    #[derive(Default)]
+    struct VaStarts<'ast> {
+        parameters: Vec<&'ast String>,
+    }
+    impl<'ast> Visitor<'ast> for VaStarts<'ast> {
+        fn visit_expression(&mut self, expression: &'ast Expression) {
+            if let Expression::VaBuiltin(builtin) = expression {
+                if let VaOperation::Start(parameter) = &builtin.operation {
+                    self.parameters.push(parameter);
+                }
+            }
+            walk_expression(self, expression);
+        }
+    }
+    let mut starts = VaStarts::default();
+    starts.visit_function(function);
+    for parameter in starts.parameters {
+        if !function.variadic {
+            return Err(format!(
+                "va_start used in function {} with fixed arguments",
+                function.name
+            ));
+        }
+        if function.parameters.last().map(|last| &last.name) != Some(parameter) {
+            return Err(format!(
+                "Second argument to va_start is not the last named parameter of {}",
+                function.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Labels defined and labels jumped to share one namespace per function.
+fn check_goto_labels(function: &statements::Function) -> Result<(), String> {
+    #[derive(Default)]
+    struct GotoLabels<'ast> {
+        defined: Vec<&'ast str>,
+        used: Vec<&'ast str>,
+    }
+    impl<'ast> Visitor<'ast> for GotoLabels<'ast> {
+        fn visit_statement(&mut self, statement: &'ast Statement) {
+            match statement {
+                Statement::Label(val) => self.defined.push(&val.name),
+                Statement::Goto(val) => self.used.push(&val.label),
+                _ => {}
+            }
+            walk_statement(self, statement);
+        }
+        fn visit_expression(&mut self, _expression: &'ast Expression) {}
+    }
+    let mut labels = GotoLabels::default();
+    labels.visit_function(function);
+    let GotoLabels { defined, used } = labels;
+    for (idx, label) in defined.iter().enumerate() {
+        if defined[..idx].contains(label) {
+            return Err(format!(
+                "Duplicate label '{}' in function {}",
+                label, function.name
+            ));
+        }
+    }
+    match used.iter().find(|label| !defined.contains(label)) {
+        Some(label) => Err(format!(
+            "Use of undeclared label '{}' in function {}",
+            label, function.name
+        )),
+        None => Ok(()),
+    }
+}
+
+fn function_name(statement: &Statement) -> &str {
+    match statement {
+        Statement::Function(function) => &function.name,
+        Statement::Prototype(prototype) => &prototype.name,
+        _ => "",
+    }
+}
+
+fn is_static(statement: &Statement) -> bool {
+    match statement {
+        Statement::Function(function) => function.is_static,
+        Statement::Prototype(prototype) => prototype.is_static,
+        Statement::Declaration(declaration) => declaration.is_static,
+        _ => false,
+    }
+}
+
+fn is_inline(statement: &Statement) -> bool {
+    match statement {
+        Statement::Function(function) => function.is_inline,
+        Statement::Prototype(prototype) => prototype.is_inline,
+        _ => false,
+    }
+}
+
+fn inlining(statement: &Statement) -> Option<statements::Inlining> {
+    match statement {
+        Statement::Function(function) => function.inlining,
+        Statement::Prototype(prototype) => prototype.inlining,
+        _ => None,
+    }
+}
+
+fn check_global(global: &statements::Declaration, declared: &[Statement]) -> Result<(), String> {
+    let name = &global.name;
+    for statement in declared.iter() {
+        match statement {
+            Statement::Declaration(declaration) if &declaration.name == name => {
+                return Err(format!("Redefinition of '{}'", name));
+            }
+            Statement::Function(_) | Statement::Prototype(_)
+                if function_name(statement) == name =>
+            {
+                return Err(format!("'{}' redeclared as different kind of symbol", name));
+            }
+            _ => {}
+        }
+    }
+    check_static_initializer(global)
+}
+
+fn check_function(function: &mut Statement, declared: &[Statement]) -> Result<(), String> {
+    let signature = match &*function {
+        Statement::Function(function) => {
+            let redefined = declared.iter().any(
+                |defined| matches!(defined, Statement::Function(val) if val.name == function.name),
+            );
+            if redefined {
+                return Err(format!("Redefinition of function '{}'", function.name));
+            }
+            function.signature()
+        }
+        Statement::Prototype(prototype) => prototype.signature(),
+        _ => return Err(format!("Expected a function definition: {}", function)),
+    };
+    let name = function_name(function);
+    let mut earlier = declared
+        .iter()
+        .filter(|statement| function_name(statement) == name)
+        .peekable();
+    if earlier.peek().is_none() {
+        return Ok(());
+    }
+    let mut internal = false;
+    let mut inline = is_inline(function);
+    let mut merged = inlining(function);
+    for statement in earlier {
+        let conflicting = match statement {
+            Statement::Function(function) => function.signature() != signature,
+            Statement::Prototype(prototype) => prototype.signature() != signature,
+            _ => return Err(format!("'{}' redeclared as different kind of symbol", name)),
+        };
+        if conflicting {
+            return Err(format!("Conflicting types for '{}'", name));
+        }
+        internal = internal || is_static(statement);
+        inline = inline || is_inline(statement);
+        merged = statements::Inlining::merge(merged, inlining(statement))?;
+    }
+    if is_static(function) && !internal {
+        return Err(format!(
+            "Static declaration of '{}' follows non-static declaration",
+            name
+        ));
+    }
+    // A function keeps the linkage of its first declaration, and what any
+    // declaration asks of the inliner.
+    match function {
+        Statement::Function(function) => {
+            function.is_static = internal;
+            function.is_inline = inline;
+            function.inlining = merged;
+        }
+        Statement::Prototype(prototype) => {
+            prototype.is_static = internal;
+            prototype.is_inline = inline;
+            prototype.inlining = merged;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Char | DataType::Int)
+}
+
+// Whether `expression` converts from `from` to `to` as assignment does:
+// between integer types, from a null pointer constant to a pointer, or to a
+// pointer that adds const to what is pointed to.
+fn check_conversion(expression: &Expression, from: &DataType, to: &DataType) -> Result<(), String> {
+    let (from, to) = (from.unqualified(), to.unqualified());
+    if from == to {
+        return Ok(());
+    }
+    let allowed = match (from, to) {
+        (_, DataType::Char | DataType::Int) => is_integer(from),
+        (DataType::Pointer(pointee), DataType::Pointer(target)) => {
+            pointee.unqualified() == target.unqualified()
+                && (target.is_const() || !pointee.is_const())
+        }
+        (_, DataType::Pointer(_)) => {
+            is_integer(from)
+                && expression.is_constant()
+                && expression.evaluate() == Type::Integer(0)
+        }
+        _ => false,
+    };
+    if !allowed {
+        return Err(format!("Cannot convert {} to {}", from, to));
+    }
+    Ok(())
+}
+
+fn converted(expression: Expression, to: &DataType) -> Expression {
+    let to = to.unqualified();
+    match (expression.data_type(), to) {
+        (Some(from), _) if from == *to => expression,
+        // Adding const to what is pointed to leaves the value as it was.
+        (Some(DataType::Pointer(_)), DataType::Pointer(_)) => expression,
+        _ => expressions::Conversion::new(to.clone(), expression),
+    }
+}
+
+// Wraps every value analysis has checked in the conversion it gets: operands
+// are promoted to int, and values take the type of the variable, return or
+// parameter they go to.
+struct Conversions<'a> {
+    signatures: &'a HashMap<String, Signature>,
+}
+impl Fold for Conversions<'_> {
+    fn fold_declaration(
+        &mut self,
+        declaration: statements::Declaration,
+    ) -> statements::Declaration {
+        let mut declaration = fold_declaration(self, declaration);
+        let variable_type = &declaration.variable_type;
+        declaration.initializer = declaration
+            .initializer
+            .take()
+            .map(|initializer| converted(initializer, variable_type));
+        declaration
+    }
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        match fold_statement(self, statement) {
+            Statement::Return(mut val) => {
+                val.expression = converted(val.expression, &DataType::Int);
+                Statement::Return(val)
+            }
+            Statement::Switch(mut val) => {
+                val.expression = converted(val.expression, &DataType::Int);
+                Statement::Switch(val)
+            }
+            statement => statement,
+        }
+    }
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        match fold_expression(self, expression) {
+            Expression::Unary(mut val)
+                if !matches!(
+                    val.operator,
+                    UnaryOperator::PreIncrement | UnaryOperator::PreDecrement
+                ) =>
+            {
+                val.operand = Box::new(converted(*val.operand, &DataType::Int));
+                Expression::Unary(val)
+            }
+            Expression::Binary(mut val) => {
+                val.left = Box::new(converted(*val.left, &DataType::Int));
+                val.right = Box::new(converted(*val.right, &DataType::Int));
+                Expression::Binary(val)
+            }
+            Expression::Assignment(mut val) => {
+                let target = val.data_type.clone().unwrap();
+                val.value = Box::new(converted(*val.value, &target));
+                Expression::Assignment(val)
+            }
+            Expression::Call(mut val) => {
+                let parameters = self
+                    .signatures
+                    .get(&val.name)
+                    .map(|signature| signature.parameters.as_slice())
+                    .unwrap_or_default();
+                val.arguments = val
+                    .arguments
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, argument)| {
+                        let parameter = parameters
+                            .get(idx)
+                            .cloned()
+                            .unwrap_or_else(|| argument.data_type().unwrap().promoted());
+                        converted(argument, &parameter)
+                    })
+                    .collect();
+                Expression::Call(val)
+            }
+            expression => expression,
+        }
+    }
+}
+
+// Names in scope, innermost last, and the signatures of every function in the
+// translation unit.
+struct Context<'a> {
+    scopes: Vec<Vec<(String, DataType)>>,
+    signatures: &'a HashMap<String, Signature>,
+}
+impl<'a> Context<'a> {
+    fn new(
+        scopes: Vec<Vec<(String, DataType)>>,
+        signatures: &'a HashMap<String, Signature>,
+    ) -> Self {
+        Context { scopes, signatures }
+    }
+
+    // Every name must be declared before it is used, and only once per scope.
+    fn declare(&mut self, name: &str, data_type: &DataType) -> Result<(), String> {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.iter().any(|(declared, _)| declared == name) {
+            return Err(format!("Redefinition of '{}'", name));
+        }
+        scope.push((String::from(name), data_type.clone()));
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<DataType, String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| {
+                scope
+                    .iter()
+                    .rev()
+                    .find(|(declared, _)| declared == name)
+                    .map(|(_, data_type)| data_type.clone())
+            })
+            .ok_or_else(|| format!("Use of undeclared identifier '{}'", name))
+    }
+
+    // The type of a variable about to be written to, which must not be const.
+    fn writable(&self, name: &str) -> Result<DataType, String> {
+        let data_type = self.lookup(name)?;
+        if data_type.is_const() {
+            return Err(format!("Cannot modify const '{}'", name));
+        }
+        Ok(data_type)
+    }
+
+    fn analyze_statement(&mut self, statement: &mut Statement) -> Result<(), Error> {
+        let span = statement.span();
+        self.analyze_statement_kind(statement)
+            .map_err(|error| error.within(span))
+    }
+
+    fn analyze_statement_kind(&mut self, statement: &mut Statement) -> Result<(), Error> {
+        match statement {
+            Statement::Function(function) => {
+                self.scopes.push(vec![]);
+                for parameter in function.parameters.iter() {
+                    self.declare(&parameter.name, &parameter.data_type)?;
+                }
+                for statement in function.body.iter_mut() {
+                    self.analyze_statement(statement)?;
+                }
+                self.scopes.pop();
+            }
+            Statement::Block(block) => {
+                self.scopes.push(vec![]);
+                for statement in block.body.iter_mut() {
+                    self.analyze_statement(statement)?;
+                }
+                self.scopes.pop();
+            }
+            Statement::Return(val) => {
+                let data_type = self.value(&mut val.expression)?;
+                check_conversion(&val.expression, &data_type, &DataType::Int)?;
+            }
+            Statement::Switch(val) => {
+                self.promote(&mut val.expression, "switch")?;
+                check_switch_labels(val)?;
+                self.analyze_statement(&mut val.body)?;
+            }
+            Statement::Case(val) => self.analyze_statement(&mut val.statement)?,
+            Statement::Default(val) => self.analyze_statement(&mut val.statement)?,
+            Statement::Label(val) => self.analyze_statement(&mut val.statement)?,
+            // A name is in scope from its declarator on, initializer included.
+            Statement::Declaration(val) => {
+                if val.is_static {
+                    check_static_initializer(val)?;
+                }
+                self.declare(&val.name, &val.variable_type)?;
+                self.analyze_initializer(val)?;
+            }
+            Statement::Expression(val) => {
+                self.analyze_expression(&mut val.expression)?;
+            }
+            Statement::Break(_)
+            | Statement::Goto(_)
+            | Statement::Empty(_)
+            | Statement::Prototype(_) => {}
+        }
+        Ok(())
+    }
+
+    fn analyze_initializer(
+        &mut self,
+        declaration: &mut statements::Declaration,
+    ) -> Result<(), Error> {
+        if let Some(initializer) = &mut declaration.initializer {
+            let data_type = self.value(initializer)?;
+            check_conversion(initializer, &data_type, &declaration.variable_type)?;
+        }
+        Ok(())
+    }
+
+    // Resolves the names in `expression` and gives its type, or None if it
+    // has no value.
+    fn analyze_expression(
+        &mut self,
+        expression: &mut Expression,
+    ) -> Result<Option<DataType>, Error> {
+        let span = expression.span();
+        self.analyze_expression_kind(expression)
+            .map_err(|error| error.within(span))
+    }
+
+    fn analyze_expression_kind(
+        &mut self,
+        expression: &mut Expression,
+    ) -> Result<Option<DataType>, Error> {
+        match expression {
+            Expression::Constant(_) | Expression::StringLiteral(_) => {}
+            Expression::Variable(val) => {
+                val.data_type = Some(self.lookup(&val.name)?.unqualified().clone())
+            }
+            Expression::Unary(val) => match val.operator {
+                UnaryOperator::PreIncrement | UnaryOperator::PreDecrement => {
+                    self.increment(&mut val.operand, val.operator.symbol())?
+                }
+                _ => self.promote(&mut val.operand, val.operator.symbol())?,
+            },
+            Expression::Postfix(val) => self.increment(&mut val.operand, val.operator.symbol())?,
+            Expression::Binary(val) => {
+                self.promote(&mut val.left, val.operator.symbol())?;
+                self.promote(&mut val.right, val.operator.symbol())?;
+            }
+            Expression::Assignment(val) => {
+                let target = self.writable(&val.name)?;
+                if target == DataType::VaList {
+                    return Err(format!("'{}' is not assignable", val.name).into());
+                }
+                let data_type = self.value(&mut val.value)?;
+                check_conversion(&val.value, &data_type, &target)?;
+                val.data_type = Some(target);
+            }
+            Expression::Comma(val) => {
+                self.analyze_expression(&mut val.left)?;
+                self.analyze_expression(&mut val.right)?;
+            }
+            Expression::Call(val) => self.analyze_call(val)?,
+            Expression::VaBuiltin(val) => {
+                if *self.lookup(&val.list)?.unqualified() != DataType::VaList {
+                    return Err(format!("'{}' is not a va_list", val.list).into());
+                }
+                if let VaOperation::Arg(data_type) = &val.operation {
+                    if data_type.promoted() != *data_type.unqualified() {
+                        return Err(format!(
+                            "{} is promoted to {} when passed through '...'",
+                            data_type,
+                            data_type.promoted()
+                        )
+                        .into());
+                    }
+                }
+            }
+            Expression::Conversion(val) => {
+                self.analyze_expression(&mut val.operand)?;
+            }
+        }
+        Ok(expression.data_type())
+    }
+
+    fn value(&mut self, expression: &mut Expression) -> Result<DataType, Error> {
+        self.analyze_expression(expression)?.ok_or_else(|| {
+            Error::new(
+                format!("Value of {} is used, but it has none", expression),
+                expression.span(),
+            )
+        })
+    }
+
+    // Arithmetic operands are promoted to int, and must be integers.
+    fn promote(&mut self, operand: &mut Expression, operator: &str) -> Result<(), Error> {
+        let data_type = self.value(operand)?;
+        if !is_integer(&data_type) {
+            return Err(format!("Invalid operand of type {} to {}", data_type, operator).into());
+        }
+        Ok(check_conversion(operand, &data_type, &DataType::Int)?)
+    }
+
+    fn increment(&mut self, operand: &mut Expression, operator: &str) -> Result<(), Error> {
+        let Expression::Variable(variable) = &*operand else {
+            return Err(format!("Operand of {} is not assignable", operator).into());
+        };
+        self.writable(&variable.name)?;
+        let data_type = self.value(operand)?;
+        if !is_integer(&data_type) {
+            return Err(format!("Invalid operand of type {} to {}", data_type, operator).into());
+        }
+        Ok(())
+    }
+
+    // Arguments must convert to their parameter's type. Anything passed
+    // through `...` or to an undeclared function gets the default promotions.
+    fn analyze_call(&mut self, call: &mut expressions::Call) -> Result<(), Error> {
+        if self.lookup(&call.name).is_ok() {
+            return Err(format!("Called object '{}' is not a function", call.name).into());
+        }
+        let signature = self.signatures.get(&call.name);
+        if let Some(signature) = signature {
+            if call.arguments.len() < signature.parameters.len() {
+                return Err(format!("Too few arguments in call to {}", call.name).into());
+            }
+            if call.arguments.len() > signature.parameters.len() && !signature.variadic {
+                return Err(format!("Too many arguments in call to {}", call.name).into());
+            }
+        }
+        for (idx, argument) in call.arguments.iter_mut().enumerate() {
+            let data_type = self.value(argument)?;
+            let parameter = signature
+                .and_then(|signature| signature.parameters.get(idx))
+                .cloned()
+                .unwrap_or_else(|| data_type.promoted());
+            check_conversion(argument, &data_type, &parameter)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, tokenizer};
+
+    fn analyze_code(code: &str) -> Result<Program, String> {
+        parser::parse_translation_unit(tokenizer::tokenize_with_spans(code))
+            .and_then(analyze)
+            .map_err(|error| error.message)
+    }
+
+    #[test]
+    fn test_analyze_errors_carry_spans() {
+        let errors = [
+            (
+                "int main() {\n    int a = 1;\n    return a + b;\n}\n",
+                "3:16: Use of undeclared identifier 'b'",
+            ),
+            (
+                "int main() {\n    switch (1) { default: break; }\n    break;\n}\n",
+                "3:5: break statement not within a switch statement",
+            ),
+            (
+                "int f(void);\nint f;\n",
+                "2:1: 'f' redeclared as different kind of symbol",
+            ),
+        ];
+        for (code, expected) in errors.iter() {
+            let declarations =
+                parser::parse_translation_unit(tokenizer::tokenize_with_spans(code)).unwrap();
+            match analyze(declarations) {
+                Ok(_) => panic!("{} was accepted", code),
+                Err(error) => assert_eq!(*expected, error.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_analyze_switch_with_duplicate_case() {
+        let code = "int main() { switch (1) { case 1: return 1; case 1: break; } return 0; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Duplicate case was accepted"),
+            Err(msg) => assert_eq!("Duplicate case value Integer<1> in switch", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_case_outside_switch() {
+        let code = "int main() { case 1: return 2; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Case outside switch was accepted"),
+            Err(msg) => assert_eq!("case label not within a switch statement", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_break_outside_switch() {
+        let code = "int main() { break; return 2; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Break outside switch was accepted"),
+            Err(msg) => assert_eq!("break statement not within a switch statement", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_goto_undeclared_label() {
+        let code = "int main() { goto missing; return 2; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Undeclared label was accepted"),
+            Err(msg) => assert_eq!("Use of undeclared label 'missing' in function main", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_duplicate_label() {
+        let code = "int main() { again: { again: return 2; } }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Duplicate label was accepted"),
+            Err(msg) => assert_eq!("Duplicate label 'again' in function main", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_undeclared_identifier() {
+        let code = "int main() { { int x; } return x; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Undeclared identifier was accepted"),
+            Err(msg) => assert_eq!("Use of undeclared identifier 'x'", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_va_arg_promoted_type() {
+        let code = "int f(int n, ...) { __builtin_va_list ap; __builtin_va_start(ap, n); \
+                    return __builtin_va_arg(ap, char); } int main() { return f(1, 2); }";
+        match analyze_code(code) {
+            Ok(_) => panic!("va_arg of a promoted type was accepted"),
+            Err(msg) => assert_eq!("Char is promoted to Integer when passed through '...'", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_va_start_in_fixed_function() {
+        let code = "int f(int n) { __builtin_va_list ap; __builtin_va_start(ap, n); return n; } \
+                    int main() { return f(1); }";
+        match analyze_code(code) {
+            Ok(_) => panic!("va_start in a fixed function was accepted"),
+            Err(msg) => assert_eq!("va_start used in function f with fixed arguments", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_call_arity() {
+        let code = "int f(int a, ...); int main() { return f(); }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Call with too few arguments was accepted"),
+            Err(msg) => assert_eq!("Too few arguments in call to f", msg),
+        }
+        let code = "int f(int a); int main() { return f(1, 2); }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Call with too many arguments was accepted"),
+            Err(msg) => assert_eq!("Too many arguments in call to f", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_conflicting_prototype() {
+        let code = "int f(int a); int f(char a, ...); int main() { return 0; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Conflicting prototypes were accepted"),
+            Err(msg) => assert_eq!("Conflicting types for 'f'", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_static_initializer_not_constant() {
+        let code = "int main() { int x = 1; static int y = x; return y; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Non-constant static initializer was accepted"),
+            Err(msg) => assert_eq!("Initializer of 'y' is not a constant", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_pointer_static_initializers() {
+        let code = "const char *msg = \"hi\"; char *const p = 0;
+            int main() { static const char *s = \"bye\"; return 0; }";
+        assert!(analyze_code(code).is_ok());
+        let code = "char *p = 1;";
+        match analyze_code(code) {
+            Ok(_) => panic!("Non-null integer pointer initializer was accepted"),
+            Err(msg) => assert_eq!("Cannot convert Integer to Pointer<Char>", msg),
+        }
+        let code = "const char *msg = \"hi\"; const char *copy = msg;";
+        match analyze_code(code) {
+            Ok(_) => panic!("Non-constant pointer initializer was accepted"),
+            Err(msg) => assert_eq!("Initializer of 'copy' is not a constant", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_static_follows_non_static() {
+        let code = "int f(void); static int f(void) { return 1; } int main() { return f(); }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Static after non-static declaration was accepted"),
+            Err(msg) => assert_eq!(
+                "Static declaration of 'f' follows non-static declaration",
+                msg
+            ),
+        }
+    }
+
+    #[test]
+    fn test_analyze_static_linkage() {
+        let code = "static int f(void); int f(void) { return 1; } int main() { return f(); }";
+        let program = analyze_code(code).unwrap();
+        // The definition keeps the internal linkage of the prototype.
+        assert!(program.functions[0].is_static);
+    }
+
+    #[test]
+    fn test_analyze_inlining() {
+        let code = "static inline int f(void); __attribute__((noinline)) int f(void) { return 1; }
+            int main() { return f(); }";
+        let program = analyze_code(code).unwrap();
+        // Every declaration counts, and the attribute outweighs the keyword.
+        assert_eq!(
+            Some(statements::Inlining::Never),
+            program.functions[0].inlining
+        );
+        assert!(program.functions[0].is_inline);
+        let code = "__attribute__((always_inline)) int f(void);
+            __attribute__((noinline)) int f(void) { return 1; }";
+        match analyze_code(code) {
+            Ok(_) => panic!("Conflicting attributes were accepted"),
+            Err(msg) => assert_eq!(
+                "Attributes 'always_inline' and 'noinline' are incompatible",
+                msg
+            ),
+        }
+    }
+
+    #[test]
+    fn test_analyze_global_declared_later() {
+        let code = "int main() { return g; } int g = 1;";
+        match analyze_code(code) {
+            Ok(_) => panic!("Use of a later global was accepted"),
+            Err(msg) => assert_eq!("Use of undeclared identifier 'g'", msg),
+        }
+    }
+
+    #[test]
+    fn test_analyze_types_and_conversions() {
+        let code = "int f(char c); int main() { char c = 300; int n = c + 1; c = n; return f(n); }";
+        let program = analyze_code(code).unwrap();
+        let body: Vec<String> = program.functions[0]
+            .body
+            .iter()
+            .map(|statement| format!("{}", statement))
+            .collect();
+        assert_eq!(
+            vec![
+                "Declaration Char c = Conversion<Char> (Constant Integer<300>)",
+                "Declaration Integer n = Binary + (Conversion<Integer> (Variable c), Constant Integer<1>)",
+                "Expression Assignment c = (Conversion<Char> (Variable n))",
+                "Return Call f (Conversion<Char> (Variable n))",
+            ],
+            body
+        );
+        let Statement::Return(statement) = &program.functions[0].body[3] else {
+            panic!("Expected a return");
+        };
+        assert_eq!(Some(DataType::Int), statement.expression.data_type());
+    }
+
+    #[test]
+    fn test_analyze_type_errors() {
+        let errors = [
+            (
+                "int main() { char *s = \"a\"; return s; }",
+                "Cannot convert Pointer<Char> to Integer",
+            ),
+            (
+                "int main() { char *s = 1; return 0; }",
+                "Cannot convert Integer to Pointer<Char>",
+            ),
+            (
+                "int main() { char *s = \"a\"; return s + 1; }",
+                "Invalid operand of type Pointer<Char> to +",
+            ),
+            (
+                "int main() { return -\"a\"; }",
+                "Invalid operand of type Pointer<Char> to -",
+            ),
+            (
+                "int main() { int x; return (x + 1)++; }",
+                "Operand of ++ is not assignable",
+            ),
+            (
+                "int main() { int f = 1; return f(); }",
+                "Called object 'f' is not a function",
+            ),
+            (
+                "int f(int n, ...) { __builtin_va_list ap; return __builtin_va_start(ap, n) + 1; }",
+                "Value of VaStart ap n is used, but it has none",
+            ),
+            (
+                "const int limit = 3; int main() { limit = 4; return limit; }",
+                "Cannot modify const 'limit'",
+            ),
+            (
+                "int main() { const char c = 1; return c++; }",
+                "Cannot modify const 'c'",
+            ),
+            (
+                "int f(const int n) { return --n; }",
+                "Cannot modify const 'n'",
+            ),
+            (
+                "int main() { char *const s = \"a\"; s = 0; return 0; }",
+                "Cannot modify const 's'",
+            ),
+            (
+                "int main() { const char *s = \"a\"; char *t = s; return 0; }",
+                "Cannot convert Pointer<Const<Char>> to Pointer<Char>",
+            ),
+        ];
+        for (code, expected) in errors.iter() {
+            assert_eq!(
+                Err(String::from(*expected)),
+                analyze_code(code).map(|_| ()),
+                "{}",
+                code
+            );
+        }
+        assert!(analyze_code("int main() { char *s = 0; return 0; }").is_ok());
+        // Initializing a const is fine, and s points to const but can itself
+        // be changed.
+        let code = "int main() { const int n = 1; const char *s = \"a\"; s = 0; return n; }";
+        assert!(analyze_code(code).is_ok());
+    }
+}
@@ -0,0 +1,341 @@
+use serde_json::{Map, Value};
+
+use crate::syntax::Program;
+
+// Structural views of a syntax tree for tools and golden tests. All of them
+// are rendered from the tree's serde serialization, where a node is an enum
+// variant wrapping a struct that has a span.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Format {
+    Json,
+    Sexpr,
+    Tree,
+}
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Self::Json),
+            "sexpr" => Some(Self::Sexpr),
+            "tree" => Some(Self::Tree),
+            _ => None,
+        }
+    }
+}
+
+// Program keeps its declarations in typed lists; tag them like the statements
+// they were parsed as, so that every node names its kind.
+fn tagged_program(program: &Program) -> Value {
+    let mut value = serde_json::to_value(program).expect("syntax trees always serialize");
+    let lists = [
+        ("globals", "Declaration"),
+        ("prototypes", "Prototype"),
+        ("functions", "Function"),
+    ];
+    for (list, tag) in lists.iter() {
+        if let Some(Value::Array(items)) = value.get_mut(*list) {
+            for item in items.iter_mut() {
+                let mut node = Map::new();
+                node.insert(String::from(*tag), item.take());
+                *item = Value::Object(node);
+            }
+        }
+    }
+    value
+}
+
+pub fn dump(program: &Program, format: Format) -> String {
+    let value = tagged_program(program);
+    let fields = value.as_object().expect("a program serializes to a map");
+    let mut out = String::new();
+    match format {
+        Format::Json => out.push_str(&serde_json::to_string_pretty(&value).unwrap()),
+        Format::Sexpr => write_sexpr_node("Program", fields, 0, &mut out),
+        Format::Tree => write_tree_node("Program", fields, 0, "", &mut out),
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+// The tree without its spans or file table, for comparing syntax regardless
+// of where it was parsed from.
+pub fn structure(program: &Program) -> Value {
+    let mut value = tagged_program(program);
+    strip_locations(&mut value);
+    value
+}
+
+// The same for a program already dumped as JSON.
+pub fn strip_locations(program: &mut Value) {
+    if let Some(fields) = program.as_object_mut() {
+        fields.remove("files");
+    }
+    strip_spans(program);
+}
+
+fn strip_spans(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.remove("span");
+            fields.values_mut().for_each(strip_spans);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_spans),
+        _ => {}
+    }
+}
+
+// The variant name and fields of a serialized node.
+fn as_node(value: &Value) -> Option<(&str, &Map<String, Value>)> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    let (tag, fields) = object.iter().next()?;
+    let fields = fields.as_object()?;
+    if !fields.contains_key("span") {
+        return None;
+    }
+    Some((tag, fields))
+}
+
+fn contains_nodes(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => items.iter().any(|item| as_node(item).is_some()),
+        _ => as_node(value).is_some(),
+    }
+}
+
+fn span_text(span: &Value) -> String {
+    let position = |key: &str| {
+        format!(
+            "{}:{}",
+            span[key]["line"].as_u64().unwrap_or(0),
+            span[key]["column"].as_u64().unwrap_or(0)
+        )
+    };
+    format!("{}-{}", position("start"), position("end"))
+}
+
+// Strings that read as symbols are written bare, anything else quoted.
+fn string_text(text: &str) -> String {
+    let symbol = text
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && text
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if symbol {
+        String::from(text)
+    } else {
+        serde_json::to_string(text).unwrap()
+    }
+}
+
+fn sexpr_atom(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("nil"),
+        Value::Bool(val) => val.to_string(),
+        Value::Number(val) => val.to_string(),
+        Value::String(val) => string_text(val),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(sexpr_atom).collect();
+            format!("({})", items.join(" "))
+        }
+        Value::Object(fields) if fields.len() == 1 => {
+            let (tag, val) = fields.iter().next().unwrap();
+            format!("({} {})", tag, sexpr_atom(val))
+        }
+        Value::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, val)| format!("({} {})", name, sexpr_atom(val)))
+                .collect();
+            format!("({})", fields.join(" "))
+        }
+    }
+}
+
+fn write_sexpr(value: &Value, indent: usize, out: &mut String) {
+    match as_node(value) {
+        Some((tag, fields)) => write_sexpr_node(tag, fields, indent, out),
+        None => out.push_str(&sexpr_atom(value)),
+    }
+}
+
+// (Tag start-end
+//   (field value)
+//   (field
+//     (Child ...)))
+fn write_sexpr_node(tag: &str, fields: &Map<String, Value>, indent: usize, out: &mut String) {
+    out.push('(');
+    out.push_str(tag);
+    if let Some(span) = fields.get("span") {
+        out.push(' ');
+        out.push_str(&span_text(span));
+    }
+    let pad = " ".repeat(indent + 2);
+    for (name, value) in fields.iter().filter(|(name, _)| *name != "span") {
+        out.push('\n');
+        out.push_str(&pad);
+        out.push('(');
+        out.push_str(name);
+        // Lists are spliced into the field, nodes one per line.
+        let items = match value {
+            Value::Array(items) => items.iter().collect(),
+            _ => vec![value],
+        };
+        for item in items {
+            if as_node(item).is_some() {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent + 4));
+                write_sexpr(item, indent + 4, out);
+            } else {
+                out.push(' ');
+                out.push_str(&sexpr_atom(item));
+            }
+        }
+        out.push(')');
+    }
+    out.push(')');
+}
+
+fn tree_atom(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("none"),
+        Value::Bool(val) => val.to_string(),
+        Value::Number(val) => val.to_string(),
+        Value::String(val) => string_text(val),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(tree_atom).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(fields) if fields.len() == 1 => {
+            let (tag, val) = fields.iter().next().unwrap();
+            format!("{}({})", tag, tree_atom(val))
+        }
+        Value::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, val)| format!("{}={}", name, tree_atom(val)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+// One line per node with its span and plain fields, then the nodes it holds
+// indented below, labelled with the field they are in.
+fn write_tree_node(
+    tag: &str,
+    fields: &Map<String, Value>,
+    indent: usize,
+    label: &str,
+    out: &mut String,
+) {
+    out.push_str(&" ".repeat(indent));
+    out.push_str(label);
+    out.push_str(tag);
+    if let Some(span) = fields.get("span") {
+        out.push_str(&format!(" <{}>", span_text(span)));
+    }
+    let (children, plain): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .filter(|(name, _)| *name != "span")
+        .partition(|(_, value)| contains_nodes(value));
+    for (name, value) in plain {
+        out.push_str(&format!(" {}={}", name, tree_atom(value)));
+    }
+    out.push('\n');
+    for (name, value) in children {
+        if let Some((tag, fields)) = as_node(value) {
+            write_tree_node(tag, fields, indent + 2, &format!("{}: ", name), out);
+            continue;
+        }
+        out.push_str(&format!("{}{}:\n", " ".repeat(indent + 2), name));
+        for item in value.as_array().into_iter().flatten() {
+            match as_node(item) {
+                Some((tag, fields)) => write_tree_node(tag, fields, indent + 4, "", out),
+                None => out.push_str(&format!("{}{}\n", " ".repeat(indent + 4), tree_atom(item))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, tokenizer};
+
+    fn parse(code: &str) -> Program {
+        parser::parse_program(tokenizer::tokenize_with_spans(code)).unwrap()
+    }
+
+    #[test]
+    fn test_dump_json_has_spans() {
+        let program = parse("int main() {\n    return 2;\n}\n");
+        let value: Value = serde_json::from_str(&dump(&program, Format::Json)).unwrap();
+        let function = &value["functions"][0]["Function"];
+        assert_eq!("main", function["name"]);
+        assert_eq!(1, function["span"]["start"]["line"]);
+        assert_eq!(2, function["span"]["end"]["column"]);
+        let constant = &function["body"][0]["Return"]["expression"]["Constant"];
+        assert_eq!(2, constant["value"]["Integer"]);
+        assert_eq!(
+            serde_json::json!({"start": {"line": 2, "column": 12}, "end": {"line": 2, "column": 13}}),
+            constant["span"]
+        );
+    }
+
+    #[test]
+    fn test_dump_sexpr() {
+        let program = parse("int f(int x) {\n    return -x;\n}");
+        let expected = "\
+(Program
+  (globals)
+  (prototypes)
+  (functions
+    (Function 1:1-3:2
+      (return_type (Integer 0))
+      (name f)
+      (parameters ((data_type Int) (name x)))
+      (variadic false)
+      (body
+        (Return 2:5-2:15
+          (expression
+            (Unary 2:12-2:14
+              (operator Negation)
+              (operand
+                (Variable 2:13-2:14
+                  (name x)))))))
+      (is_static false)
+      (is_inline false))))
+";
+        assert_eq!(expected, dump(&program, Format::Sexpr));
+    }
+
+    #[test]
+    fn test_dump_tree() {
+        let program =
+            parse("int g = 1;\nint f(char *s);\nint main() {\n    return f(\"a b\") + g;\n}");
+        let expected = "\
+Program
+  globals:
+    Declaration <1:1-1:11> variable_type=Int name=g is_static=false
+      initializer: Constant <1:9-1:10> value=Integer(1)
+  prototypes:
+    Prototype <2:1-2:16> return_type=Integer(0) name=f parameters=[{data_type=Pointer(Char), name=s}] variadic=false is_static=false is_inline=false
+  functions:
+    Function <3:1-5:2> return_type=Integer(0) name=main parameters=[] variadic=false is_static=false is_inline=false
+      body:
+        Return <4:5-4:25>
+          expression: Binary <4:12-4:24> operator=Addition
+            left: Call <4:12-4:20> name=f
+              arguments:
+                StringLiteral <4:14-4:19> value=\"a b\"
+            right: Variable <4:23-4:24> name=g
+";
+        assert_eq!(expected, dump(&program, Format::Tree));
+    }
+}
@@ -1,25 +1,32 @@
 use crate::syntax::*;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Constant {
-    value: Type,
+    pub value: Type,
+    pub span: Span,
 }
 impl Constant {
     pub fn new(value: Type) -> Expression {
-        Expression::Constant(Constant { value })
+        Expression::Constant(Constant {
+            value,
+            span: Span::default(),
+        })
     }
 }
 impl Node for Constant {
     fn type_of(&self) -> &'static str {
         "Constant"
     }
-    fn to_asm(&self) -> String {
-        return self.value.to_asm();
-    }
 }
 impl ExpressionTrait for Constant {
     fn evaluate(&self) -> Type {
-        return self.value;
+        self.value
+    }
+    fn is_constant(&self) -> bool {
+        true
+    }
+    fn has_side_effects(&self) -> bool {
+        false
     }
 }
 impl fmt::Display for Constant {
@@ -28,4 +35,562 @@ impl fmt::Display for Constant {
         let formatted_string = format!("{} {}", type_string, self.value);
         write!(f, "{}", formatted_string)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize)]
+pub struct Variable {
+    pub name: String,
+    // The type of the declaration the name resolves to, once analysed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataType>,
+    pub span: Span,
+}
+impl Variable {
+    pub fn new(name: String) -> Expression {
+        Expression::Variable(Variable {
+            name,
+            data_type: None,
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Variable {
+    fn type_of(&self) -> &'static str {
+        "Variable"
+    }
+}
+impl ExpressionTrait for Variable {
+    fn evaluate(&self) -> Type {
+        panic!("Variable {} is not a compile-time constant", self.name)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        false
+    }
+}
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.type_of(), self.name)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize)]
+pub enum UnaryOperator {
+    Negation,
+    BitwiseComplement,
+    LogicalNegation,
+    PreIncrement,
+    PreDecrement,
+}
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Negation => "-",
+            Self::BitwiseComplement => "~",
+            Self::LogicalNegation => "!",
+            Self::PreIncrement => "++",
+            Self::PreDecrement => "--",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Unary {
+    pub operator: UnaryOperator,
+    pub operand: Box<Expression>,
+    pub span: Span,
+}
+impl Unary {
+    pub fn new(operator: UnaryOperator, operand: Expression) -> Expression {
+        Expression::Unary(Unary {
+            operator,
+            operand: Box::new(operand),
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Unary {
+    fn type_of(&self) -> &'static str {
+        "Unary"
+    }
+}
+impl ExpressionTrait for Unary {
+    fn evaluate(&self) -> Type {
+        let Type::Integer(val) = self.operand.evaluate();
+        match self.operator {
+            UnaryOperator::Negation => Type::Integer(val.wrapping_neg()),
+            UnaryOperator::BitwiseComplement => Type::Integer(!val),
+            UnaryOperator::LogicalNegation => Type::Integer((val == 0) as i32),
+            _ => panic!("{} is not a compile-time constant", self),
+        }
+    }
+    fn is_constant(&self) -> bool {
+        !self.has_side_effects() && self.operand.is_constant()
+    }
+    fn has_side_effects(&self) -> bool {
+        matches!(
+            self.operator,
+            UnaryOperator::PreIncrement | UnaryOperator::PreDecrement
+        ) || self.operand.has_side_effects()
+    }
+}
+impl fmt::Display for Unary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({})",
+            self.type_of(),
+            self.operator.symbol(),
+            self.operand
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize)]
+pub enum PostfixOperator {
+    Increment,
+    Decrement,
+}
+impl PostfixOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Increment => "++",
+            Self::Decrement => "--",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Postfix {
+    pub operator: PostfixOperator,
+    pub operand: Box<Expression>,
+    pub span: Span,
+}
+impl Postfix {
+    pub fn new(operator: PostfixOperator, operand: Expression) -> Expression {
+        Expression::Postfix(Postfix {
+            operator,
+            operand: Box::new(operand),
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Postfix {
+    fn type_of(&self) -> &'static str {
+        "Postfix"
+    }
+}
+impl ExpressionTrait for Postfix {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+}
+impl fmt::Display for Postfix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({})",
+            self.type_of(),
+            self.operator.symbol(),
+            self.operand
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize)]
+pub enum BinaryOperator {
+    Addition,
+    Subtraction,
+    Multiplication,
+    Division,
+    Modulo,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+}
+impl BinaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Addition => "+",
+            Self::Subtraction => "-",
+            Self::Multiplication => "*",
+            Self::Division => "/",
+            Self::Modulo => "%",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::LessThan => "<",
+            Self::LessEqual => "<=",
+            Self::GreaterThan => ">",
+            Self::GreaterEqual => ">=",
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+        }
+    }
+    // Higher binds tighter.
+    pub const fn precedence(&self) -> u8 {
+        match self {
+            Self::LogicalOr => 1,
+            Self::LogicalAnd => 2,
+            Self::Equal | Self::NotEqual => 3,
+            Self::LessThan | Self::LessEqual | Self::GreaterThan | Self::GreaterEqual => 4,
+            Self::Addition | Self::Subtraction => 5,
+            Self::Multiplication | Self::Division | Self::Modulo => 6,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Binary {
+    pub operator: BinaryOperator,
+    pub left: Box<Expression>,
+    pub right: Box<Expression>,
+    pub span: Span,
+}
+impl Binary {
+    pub fn new(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+        Expression::Binary(Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Binary {
+    fn type_of(&self) -> &'static str {
+        "Binary"
+    }
+}
+impl ExpressionTrait for Binary {
+    fn evaluate(&self) -> Type {
+        let Type::Integer(left) = self.left.evaluate();
+        let Type::Integer(right) = self.right.evaluate();
+        let value = match self.operator {
+            BinaryOperator::Addition => left.wrapping_add(right),
+            BinaryOperator::Subtraction => left.wrapping_sub(right),
+            BinaryOperator::Multiplication => left.wrapping_mul(right),
+            BinaryOperator::Division => left
+                .checked_div(right)
+                .unwrap_or_else(|| panic!("{} is not a compile-time constant", self)),
+            BinaryOperator::Modulo => left
+                .checked_rem(right)
+                .unwrap_or_else(|| panic!("{} is not a compile-time constant", self)),
+            BinaryOperator::Equal => (left == right) as i32,
+            BinaryOperator::NotEqual => (left != right) as i32,
+            BinaryOperator::LessThan => (left < right) as i32,
+            BinaryOperator::LessEqual => (left <= right) as i32,
+            BinaryOperator::GreaterThan => (left > right) as i32,
+            BinaryOperator::GreaterEqual => (left >= right) as i32,
+            BinaryOperator::LogicalAnd => (left != 0 && right != 0) as i32,
+            BinaryOperator::LogicalOr => (left != 0 || right != 0) as i32,
+        };
+        Type::Integer(value)
+    }
+    fn is_constant(&self) -> bool {
+        if !self.left.is_constant() || !self.right.is_constant() {
+            return false;
+        }
+        match self.operator {
+            BinaryOperator::Division | BinaryOperator::Modulo => {
+                let Type::Integer(left) = self.left.evaluate();
+                let Type::Integer(right) = self.right.evaluate();
+                left.checked_rem(right).is_some()
+            }
+            _ => true,
+        }
+    }
+    fn has_side_effects(&self) -> bool {
+        self.left.has_side_effects() || self.right.has_side_effects()
+    }
+}
+impl fmt::Display for Binary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}, {})",
+            self.type_of(),
+            self.operator.symbol(),
+            self.left,
+            self.right
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Box<Expression>,
+    // The type of the variable assigned to, once analysed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataType>,
+    pub span: Span,
+}
+impl Assignment {
+    pub fn new(name: String, value: Expression) -> Expression {
+        Expression::Assignment(Assignment {
+            name,
+            value: Box::new(value),
+            data_type: None,
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Assignment {
+    fn type_of(&self) -> &'static str {
+        "Assignment"
+    }
+}
+impl ExpressionTrait for Assignment {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+}
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} = ({})", self.type_of(), self.name, self.value)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Comma {
+    pub left: Box<Expression>,
+    pub right: Box<Expression>,
+    pub span: Span,
+}
+impl Comma {
+    pub fn new(left: Expression, right: Expression) -> Expression {
+        Expression::Comma(Comma {
+            left: Box::new(left),
+            right: Box::new(right),
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Comma {
+    fn type_of(&self) -> &'static str {
+        "Comma"
+    }
+}
+impl ExpressionTrait for Comma {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    // A comma expression is never a constant expression in C, even if both
+    // operands are.
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        self.left.has_side_effects() || self.right.has_side_effects()
+    }
+}
+impl fmt::Display for Comma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}, {})", self.type_of(), self.left, self.right)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Call {
+    pub name: String,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
+impl Call {
+    pub fn new(name: String, arguments: Vec<Expression>) -> Expression {
+        Expression::Call(Call {
+            name,
+            arguments,
+            span: Span::default(),
+        })
+    }
+}
+impl Node for Call {
+    fn type_of(&self) -> &'static str {
+        "Call"
+    }
+}
+impl ExpressionTrait for Call {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+}
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let arguments: Vec<String> = self
+            .arguments
+            .iter()
+            .map(|argument| format!("{}", argument))
+            .collect();
+        write!(
+            f,
+            "{} {} ({})",
+            self.type_of(),
+            self.name,
+            arguments.join(", ")
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub struct StringLiteral {
+    // The literal as written between the quotes, escapes included.
+    pub value: String,
+    pub span: Span,
+}
+impl StringLiteral {
+    pub fn new(value: String) -> Expression {
+        Expression::StringLiteral(StringLiteral {
+            value,
+            span: Span::default(),
+        })
+    }
+}
+impl Node for StringLiteral {
+    fn type_of(&self) -> &'static str {
+        "StringLiteral"
+    }
+}
+impl ExpressionTrait for StringLiteral {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        false
+    }
+}
+impl fmt::Display for StringLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} \"{}\"", self.type_of(), self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum VaOperation {
+    // Names the last named parameter, as va_start(list, last) does.
+    Start(String),
+    Arg(DataType),
+    End,
+}
+
+// __builtin_va_start, __builtin_va_arg and __builtin_va_end, operating on a
+// va_list laid out as in the SysV ABI.
+#[derive(Serialize)]
+pub struct VaBuiltin {
+    pub operation: VaOperation,
+    pub list: String,
+    pub span: Span,
+}
+impl VaBuiltin {
+    pub fn new(operation: VaOperation, list: String) -> Expression {
+        Expression::VaBuiltin(VaBuiltin {
+            operation,
+            list,
+            span: Span::default(),
+        })
+    }
+}
+impl Node for VaBuiltin {
+    fn type_of(&self) -> &'static str {
+        match self.operation {
+            VaOperation::Start(_) => "VaStart",
+            VaOperation::Arg(_) => "VaArg",
+            VaOperation::End => "VaEnd",
+        }
+    }
+}
+impl ExpressionTrait for VaBuiltin {
+    fn evaluate(&self) -> Type {
+        panic!("{} is not a compile-time constant", self)
+    }
+    fn is_constant(&self) -> bool {
+        false
+    }
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+}
+impl fmt::Display for VaBuiltin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.operation {
+            VaOperation::Start(parameter) => {
+                write!(f, "{} {} {}", self.type_of(), self.list, parameter)
+            }
+            VaOperation::Arg(data_type) => {
+                write!(f, "{} {} {}", self.type_of(), self.list, data_type)
+            }
+            VaOperation::End => write!(f, "{} {}", self.type_of(), self.list),
+        }
+    }
+}
+
+// A conversion C makes implicitly, such as narrowing an int assigned to a
+// char. Only analysis inserts these; the parser never does.
+#[derive(Serialize)]
+pub struct Conversion {
+    pub target: DataType,
+    pub operand: Box<Expression>,
+    pub span: Span,
+}
+impl Conversion {
+    pub fn new(target: DataType, operand: Expression) -> Expression {
+        let span = operand.span();
+        Expression::Conversion(Conversion {
+            target,
+            operand: Box::new(operand),
+            span,
+        })
+    }
+}
+impl Node for Conversion {
+    fn type_of(&self) -> &'static str {
+        "Conversion"
+    }
+}
+impl ExpressionTrait for Conversion {
+    fn evaluate(&self) -> Type {
+        match (self.operand.evaluate(), &self.target) {
+            (Type::Integer(val), DataType::Char) => Type::Integer(val as i8 as i32),
+            (value, _) => value,
+        }
+    }
+    fn is_constant(&self) -> bool {
+        self.operand.is_constant()
+    }
+    fn has_side_effects(&self) -> bool {
+        self.operand.has_side_effects()
+    }
+}
+impl fmt::Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}<{}> ({})", self.type_of(), self.target, self.operand)
+    }
+}
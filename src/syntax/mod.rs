@@ -1,22 +1,25 @@
+#![allow(clippy::new_ret_no_self)]
 use std::fmt;
 
-use indoc::formatdoc;
+use serde::Serialize;
 use textwrap::indent;
 
+use crate::tokenizer::Span;
+use visit::{walk_statement, Visitor};
+
 pub trait Node: fmt::Display {
     fn type_of(&self) -> &'static str;
-    fn to_asm(&self) -> String;
 }
 
 // TODO: Create a variable struct that has type Type Enum
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize)]
 pub enum Type {
     Integer(i32),
 }
 impl Type {
     fn value_to_string(&self) -> String {
         match self {
-            Self::Integer(val) => return format!("{}", val),
+            Self::Integer(val) => format!("{}", val),
         }
     }
 }
@@ -26,11 +29,6 @@ impl Node for Type {
             Self::Integer(_) => "Integer",
         }
     }
-    fn to_asm(&self) -> String {
-        match self {
-            Self::Integer(val) => return format!("${}", val),
-        }
-    }
 }
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -40,16 +38,185 @@ impl fmt::Display for Type {
     }
 }
 
+// The type a variable or parameter is declared with. Type above only ever
+// describes a constant value.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum DataType {
+    Char,
+    Int,
+    Pointer(Box<DataType>),
+    VaList,
+    // A const qualified type: `const char` is Const<Char>, and `char *const`
+    // is Const<Pointer<Char>>.
+    Const(Box<DataType>),
+}
+impl DataType {
+    pub fn size(&self) -> i32 {
+        match self {
+            Self::Char => 1,
+            Self::Int => 4,
+            Self::Pointer(_) => 8,
+            // gp_offset, fp_offset, overflow_arg_area and reg_save_area
+            Self::VaList => 24,
+            Self::Const(val) => val.size(),
+        }
+    }
+    pub fn is_const(&self) -> bool {
+        matches!(self, Self::Const(_))
+    }
+    // The type without its own qualifiers, which is the type of its value.
+    // Anything pointed to keeps its qualifiers.
+    pub fn unqualified(&self) -> &DataType {
+        match self {
+            Self::Const(val) => val.unqualified(),
+            _ => self,
+        }
+    }
+    pub fn alignment(&self) -> i32 {
+        match self.unqualified() {
+            Self::VaList => 8,
+            _ => self.size(),
+        }
+    }
+    // Default argument promotions, applied to arguments passed through `...`.
+    pub fn promoted(&self) -> DataType {
+        match self.unqualified() {
+            Self::Char => Self::Int,
+            unqualified => unqualified.clone(),
+        }
+    }
+}
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Char => write!(f, "Char"),
+            Self::Int => write!(f, "Integer"),
+            Self::Pointer(val) => write!(f, "Pointer<{}>", val),
+            Self::VaList => write!(f, "VaList"),
+            Self::Const(val) => write!(f, "Const<{}>", val),
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub enum Expression {
     Constant(expressions::Constant),
+    Variable(expressions::Variable),
+    Unary(expressions::Unary),
+    Postfix(expressions::Postfix),
+    Binary(expressions::Binary),
+    Assignment(expressions::Assignment),
+    Comma(expressions::Comma),
+    Call(expressions::Call),
+    StringLiteral(expressions::StringLiteral),
+    VaBuiltin(expressions::VaBuiltin),
+    Conversion(expressions::Conversion),
 }
 pub trait ExpressionTrait: Node {
     fn evaluate(&self) -> Type;
+    fn is_constant(&self) -> bool;
+    fn has_side_effects(&self) -> bool;
+}
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Constant(val) => val.span,
+            Self::Variable(val) => val.span,
+            Self::Unary(val) => val.span,
+            Self::Postfix(val) => val.span,
+            Self::Binary(val) => val.span,
+            Self::Assignment(val) => val.span,
+            Self::Comma(val) => val.span,
+            Self::Call(val) => val.span,
+            Self::StringLiteral(val) => val.span,
+            Self::VaBuiltin(val) => val.span,
+            Self::Conversion(val) => val.span,
+        }
+    }
+    // The same expression, recorded as coming from `span`.
+    pub fn at(mut self, span: Span) -> Expression {
+        match &mut self {
+            Self::Constant(val) => val.span = span,
+            Self::Variable(val) => val.span = span,
+            Self::Unary(val) => val.span = span,
+            Self::Postfix(val) => val.span = span,
+            Self::Binary(val) => val.span = span,
+            Self::Assignment(val) => val.span = span,
+            Self::Comma(val) => val.span = span,
+            Self::Call(val) => val.span = span,
+            Self::StringLiteral(val) => val.span = span,
+            Self::VaBuiltin(val) => val.span = span,
+            Self::Conversion(val) => val.span = span,
+        }
+        self
+    }
+    // The type of the expression's value, or None for one without a value.
+    // Names only have a type once analysis has resolved them.
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Self::Constant(_) | Self::Binary(_) | Self::Call(_) => Some(DataType::Int),
+            Self::Variable(val) => val.data_type.clone(),
+            Self::Unary(val) => match val.operator {
+                expressions::UnaryOperator::PreIncrement
+                | expressions::UnaryOperator::PreDecrement => val.operand.data_type(),
+                _ => Some(DataType::Int),
+            },
+            Self::Postfix(val) => val.operand.data_type(),
+            Self::Assignment(val) => val.data_type.clone(),
+            Self::Comma(val) => val.right.data_type(),
+            Self::StringLiteral(_) => Some(DataType::Pointer(Box::new(DataType::Char))),
+            Self::VaBuiltin(val) => match &val.operation {
+                expressions::VaOperation::Arg(data_type) => Some(data_type.unqualified().clone()),
+                _ => None,
+            },
+            Self::Conversion(val) => Some(val.target.clone()),
+        }
+    }
 }
 impl ExpressionTrait for Expression {
     fn evaluate(&self) -> Type {
         match self {
             Self::Constant(val) => val.evaluate(),
+            Self::Variable(val) => val.evaluate(),
+            Self::Unary(val) => val.evaluate(),
+            Self::Postfix(val) => val.evaluate(),
+            Self::Binary(val) => val.evaluate(),
+            Self::Assignment(val) => val.evaluate(),
+            Self::Comma(val) => val.evaluate(),
+            Self::Call(val) => val.evaluate(),
+            Self::StringLiteral(val) => val.evaluate(),
+            Self::VaBuiltin(val) => val.evaluate(),
+            Self::Conversion(val) => val.evaluate(),
+        }
+    }
+    fn is_constant(&self) -> bool {
+        match self {
+            Self::Constant(val) => val.is_constant(),
+            Self::Variable(val) => val.is_constant(),
+            Self::Unary(val) => val.is_constant(),
+            Self::Postfix(val) => val.is_constant(),
+            Self::Binary(val) => val.is_constant(),
+            Self::Assignment(val) => val.is_constant(),
+            Self::Comma(val) => val.is_constant(),
+            Self::Call(val) => val.is_constant(),
+            Self::StringLiteral(val) => val.is_constant(),
+            Self::VaBuiltin(val) => val.is_constant(),
+            Self::Conversion(val) => val.is_constant(),
+        }
+    }
+    fn has_side_effects(&self) -> bool {
+        match self {
+            Self::Constant(val) => val.has_side_effects(),
+            Self::Variable(val) => val.has_side_effects(),
+            Self::Unary(val) => val.has_side_effects(),
+            Self::Postfix(val) => val.has_side_effects(),
+            Self::Binary(val) => val.has_side_effects(),
+            Self::Assignment(val) => val.has_side_effects(),
+            Self::Comma(val) => val.has_side_effects(),
+            Self::Call(val) => val.has_side_effects(),
+            Self::StringLiteral(val) => val.has_side_effects(),
+            Self::VaBuiltin(val) => val.has_side_effects(),
+            Self::Conversion(val) => val.has_side_effects(),
         }
     }
 }
@@ -57,11 +224,16 @@ impl Node for Expression {
     fn type_of(&self) -> &'static str {
         match self {
             Self::Constant(val) => val.type_of(),
-        }
-    }
-    fn to_asm(&self) -> String {
-        match self {
-            Self::Constant(val) => val.to_asm(),
+            Self::Variable(val) => val.type_of(),
+            Self::Unary(val) => val.type_of(),
+            Self::Postfix(val) => val.type_of(),
+            Self::Binary(val) => val.type_of(),
+            Self::Assignment(val) => val.type_of(),
+            Self::Comma(val) => val.type_of(),
+            Self::Call(val) => val.type_of(),
+            Self::StringLiteral(val) => val.type_of(),
+            Self::VaBuiltin(val) => val.type_of(),
+            Self::Conversion(val) => val.type_of(),
         }
     }
 }
@@ -69,27 +241,92 @@ impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Constant(val) => write!(f, "{}", val),
+            Self::Variable(val) => write!(f, "{}", val),
+            Self::Unary(val) => write!(f, "{}", val),
+            Self::Postfix(val) => write!(f, "{}", val),
+            Self::Binary(val) => write!(f, "{}", val),
+            Self::Assignment(val) => write!(f, "{}", val),
+            Self::Comma(val) => write!(f, "{}", val),
+            Self::Call(val) => write!(f, "{}", val),
+            Self::StringLiteral(val) => write!(f, "{}", val),
+            Self::VaBuiltin(val) => write!(f, "{}", val),
+            Self::Conversion(val) => write!(f, "{}", val),
         }
     }
 }
 
+#[derive(Serialize)]
 pub enum Statement {
     Function(statements::Function),
     Return(statements::Return),
+    Block(statements::Block),
+    Switch(statements::Switch),
+    Case(statements::Case),
+    Default(statements::Default),
+    Break(statements::Break),
+    Label(statements::Label),
+    Goto(statements::Goto),
+    Declaration(statements::Declaration),
+    Expression(statements::ExpressionStatement),
+    Empty(statements::Empty),
+    Prototype(statements::Prototype),
 }
-trait StatementTrait: Node {}
+pub trait StatementTrait: Node {}
 impl StatementTrait for Statement {}
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Function(val) => val.span,
+            Self::Return(val) => val.span,
+            Self::Block(val) => val.span,
+            Self::Switch(val) => val.span,
+            Self::Case(val) => val.span,
+            Self::Default(val) => val.span,
+            Self::Break(val) => val.span,
+            Self::Label(val) => val.span,
+            Self::Goto(val) => val.span,
+            Self::Declaration(val) => val.span,
+            Self::Expression(val) => val.span,
+            Self::Empty(val) => val.span,
+            Self::Prototype(val) => val.span,
+        }
+    }
+    // The same statement, recorded as coming from `span`.
+    pub fn at(mut self, span: Span) -> Statement {
+        match &mut self {
+            Self::Function(val) => val.span = span,
+            Self::Return(val) => val.span = span,
+            Self::Block(val) => val.span = span,
+            Self::Switch(val) => val.span = span,
+            Self::Case(val) => val.span = span,
+            Self::Default(val) => val.span = span,
+            Self::Break(val) => val.span = span,
+            Self::Label(val) => val.span = span,
+            Self::Goto(val) => val.span = span,
+            Self::Declaration(val) => val.span = span,
+            Self::Expression(val) => val.span = span,
+            Self::Empty(val) => val.span = span,
+            Self::Prototype(val) => val.span = span,
+        }
+        self
+    }
+}
 impl Node for Statement {
     fn type_of(&self) -> &'static str {
         match self {
             Self::Function(val) => val.type_of(),
             Self::Return(val) => val.type_of(),
-        }
-    }
-    fn to_asm(&self) -> String {
-        match self {
-            Self::Function(val) => val.to_asm(),
-            Self::Return(val) => val.to_asm(),
+            Self::Block(val) => val.type_of(),
+            Self::Switch(val) => val.type_of(),
+            Self::Case(val) => val.type_of(),
+            Self::Default(val) => val.type_of(),
+            Self::Break(val) => val.type_of(),
+            Self::Label(val) => val.type_of(),
+            Self::Goto(val) => val.type_of(),
+            Self::Declaration(val) => val.type_of(),
+            Self::Expression(val) => val.type_of(),
+            Self::Empty(val) => val.type_of(),
+            Self::Prototype(val) => val.type_of(),
         }
     }
 }
@@ -98,35 +335,261 @@ impl fmt::Display for Statement {
         match self {
             Self::Function(val) => write!(f, "{}", val),
             Self::Return(val) => write!(f, "{}", val),
+            Self::Block(val) => write!(f, "{}", val),
+            Self::Switch(val) => write!(f, "{}", val),
+            Self::Case(val) => write!(f, "{}", val),
+            Self::Default(val) => write!(f, "{}", val),
+            Self::Break(val) => write!(f, "{}", val),
+            Self::Label(val) => write!(f, "{}", val),
+            Self::Goto(val) => write!(f, "{}", val),
+            Self::Declaration(val) => write!(f, "{}", val),
+            Self::Expression(val) => write!(f, "{}", val),
+            Self::Empty(val) => write!(f, "{}", val),
+            Self::Prototype(val) => write!(f, "{}", val),
         }
     }
 }
 
+// A parse or analysis error and where in the source it was found. The span
+// is zero until the node the error concerns is known.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub message: String,
+    pub span: Span,
+}
+impl Error {
+    pub fn new(message: String, span: Span) -> Error {
+        Error { message, span }
+    }
+    // Places an error from inside a node at that node, unless something more
+    // precise already did.
+    pub fn within(self, span: Span) -> Error {
+        if self.span == Span::default() {
+            Error { span, ..self }
+        } else {
+            self
+        }
+    }
+}
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::new(message, Span::default())
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.span == Span::default() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.span.location(&[]), self.message)
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct Program {
-    pub root: statements::Function,
+    // The files the program was read from, in source order. Spans refer to
+    // them by index.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    pub globals: Vec<statements::Declaration>,
+    pub prototypes: Vec<statements::Prototype>,
+    pub functions: Vec<statements::Function>,
 }
 impl Program {
     pub fn new(main_statement: Statement) -> Program {
-        match main_statement {
-            Statement::Function(func) => Program { root: func },
-            _ => panic!("Missing main function!"),
+        let mut program = Program {
+            files: vec![],
+            globals: vec![],
+            prototypes: vec![],
+            functions: vec![],
+        };
+        program.push_function(main_statement);
+        program
+    }
+    pub fn from_declarations(declarations: Vec<Statement>) -> Program {
+        let mut program = Program {
+            files: vec![],
+            globals: vec![],
+            prototypes: vec![],
+            functions: vec![],
+        };
+        declarations
+            .into_iter()
+            .for_each(|declaration| program.push_function(declaration));
+        program
+    }
+    // Takes a function definition, a prototype or a file scope variable. The
+    // parser rejects anything else at file scope.
+    pub fn push_function(&mut self, function: Statement) {
+        match function {
+            Statement::Function(func) => self.functions.push(func),
+            Statement::Prototype(prototype) => self.prototypes.push(prototype),
+            Statement::Declaration(global) => self.globals.push(global),
+            statement => unreachable!("{} at file scope", statement.type_of()),
         }
     }
+    pub fn signature(&self, name: &str) -> Option<signatures::Signature> {
+        self.functions
+            .iter()
+            .find(|function| function.name == name)
+            .map(|function| function.signature())
+            .or_else(|| {
+                self.prototypes
+                    .iter()
+                    .find(|prototype| prototype.name == name)
+                    .map(|prototype| prototype.signature())
+            })
+    }
+    // Where each expression statement starts whose value is computed only to
+    // be thrown away.
+    pub fn unused_results(&self) -> Vec<Span> {
+        let mut unused = UnusedResults::default();
+        for function in self.functions.iter() {
+            unused.visit_function(function);
+        }
+        unused.spans
+    }
+    // Where each run of statements control can never reach starts, by
+    // function: after a return, goto or break up to the next label, and in a
+    // switch before its first case.
+    pub fn unreachable_code(&self) -> Vec<(&str, Span)> {
+        let mut spans = vec![];
+        for function in self.functions.iter() {
+            let mut unreachable = UnreachableCode {
+                reachable: true,
+                reported: false,
+                switches: vec![],
+                spans: vec![],
+            };
+            for statement in function.body.iter() {
+                unreachable.visit_statement(statement);
+            }
+            spans.extend(
+                unreachable
+                    .spans
+                    .into_iter()
+                    .map(|span| (function.name.as_str(), span)),
+            );
+        }
+        spans
+    }
+}
+struct UnreachableCode {
+    reachable: bool,
+    // Whether the unreachable run being walked has been reported already.
+    reported: bool,
+    // The switches around the statement, innermost last.
+    switches: Vec<SwitchReach>,
+    spans: Vec<Span>,
+}
+struct SwitchReach {
+    // Whether the switch can be reached, and with it its cases.
+    entered: bool,
+    // Whether a reachable break leaves it.
+    broken: bool,
+}
+impl UnreachableCode {
+    fn revive(&mut self) {
+        self.reachable = true;
+        self.reported = false;
+    }
+}
+impl<'ast> Visitor<'ast> for UnreachableCode {
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        match statement {
+            Statement::Label(_) => self.revive(),
+            Statement::Case(_) | Statement::Default(_) => {
+                if self.switches.last().is_some_and(|switch| switch.entered) {
+                    self.revive();
+                }
+            }
+            // Blocks are reported by their first statement. A break after a
+            // return is a common habit, and empty statements and bare
+            // declarations don't run anything.
+            Statement::Block(_) | Statement::Break(_) | Statement::Empty(_) => {}
+            Statement::Declaration(val) if val.initializer.is_none() || val.is_static => {}
+            _ => {
+                if !self.reachable && !self.reported {
+                    self.spans.push(statement.span());
+                    self.reported = true;
+                }
+            }
+        }
+        match statement {
+            Statement::Switch(val) => {
+                let entered = self.reachable;
+                self.switches.push(SwitchReach {
+                    entered,
+                    broken: false,
+                });
+                self.reachable = false;
+                self.reported = !entered;
+                self.visit_statement(&val.body);
+                let switch = self.switches.pop().unwrap();
+                // Without a default, a value no case matches skips the body.
+                let skipped = entered && !val.labels().contains(&None);
+                if self.reachable || switch.broken || skipped {
+                    self.revive();
+                }
+            }
+            Statement::Break(_) => {
+                if self.reachable {
+                    if let Some(switch) = self.switches.last_mut() {
+                        switch.broken = true;
+                    }
+                }
+                self.reachable = false;
+            }
+            Statement::Return(_) | Statement::Goto(_) => {
+                self.reachable = false;
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+    fn visit_expression(&mut self, _expression: &'ast Expression) {}
+}
+// Expression statements whose value is computed only to be thrown away.
+#[derive(Default)]
+struct UnusedResults {
+    spans: Vec<Span>,
+}
+impl<'ast> Visitor<'ast> for UnusedResults {
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        if let Statement::Expression(val) = statement {
+            if !val.expression.has_side_effects() {
+                self.spans.push(val.expression.span());
+            }
+        }
+        walk_statement(self, statement);
+    }
+    fn visit_expression(&mut self, _expression: &'ast Expression) {}
 }
 impl Node for Program {
     fn type_of(&self) -> &'static str {
         "Program"
     }
-    fn to_asm(&self) -> String {
-        self.root.to_asm()
-    }
 }
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "PROGRAM_START:\n{}", self.root)
+        writeln!(f, "PROGRAM_START:")?;
+        for global in self.globals.iter() {
+            writeln!(f, "{}", global)?;
+        }
+        for prototype in self.prototypes.iter() {
+            writeln!(f, "{}", prototype)?;
+        }
+        for function in self.functions.iter() {
+            write!(f, "{}", function)?;
+        }
+        Ok(())
     }
 }
 
+pub mod dump;
 pub mod expressions;
+pub mod signatures;
 pub mod statements;
-pub mod tests;
\ No newline at end of file
+pub mod tests;
+pub mod unparse;
+pub mod visit;
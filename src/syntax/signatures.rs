@@ -0,0 +1,9 @@
+use crate::syntax::DataType;
+
+// The parameter list of a function, as declared or defined. A parameter's own
+// qualifiers are left out, since they don't change how it is called.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    pub parameters: Vec<DataType>,
+    pub variadic: bool,
+}
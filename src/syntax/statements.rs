@@ -1,11 +1,17 @@
+use crate::syntax::visit::{walk_statement, Visitor};
 use crate::syntax::*;
 
+#[derive(Serialize)]
 pub struct Return {
     pub expression: Expression,
+    pub span: Span,
 }
 impl Return {
     pub fn new(expression: Expression) -> Statement {
-        Statement::Return(Return { expression })
+        Statement::Return(Return {
+            expression,
+            span: Span::default(),
+        })
     }
 }
 impl StatementTrait for Return {}
@@ -13,16 +19,6 @@ impl Node for Return {
     fn type_of(&self) -> &'static str {
         "Return"
     }
-    fn to_asm(&self) -> String {
-        let return_expression_asm = self.expression.to_asm();
-        let return_asm = formatdoc! {"
-            movl {}, %eax
-            ret
-            ",
-            return_expression_asm
-        };
-        return return_asm;
-    }
 }
 
 impl fmt::Display for Return {
@@ -32,39 +28,118 @@ impl fmt::Display for Return {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Parameter {
+    pub data_type: DataType,
+    // Prototypes may leave parameters unnamed.
+    pub name: String,
+}
+impl Parameter {
+    pub fn new(data_type: DataType, name: String) -> Parameter {
+        Parameter { data_type, name }
+    }
+}
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.data_type)
+        } else {
+            write!(f, "{} {}", self.data_type, self.name)
+        }
+    }
+}
+
+fn format_parameters(parameters: &[Parameter], variadic: bool) -> String {
+    let mut formatted_parameters: Vec<String> = parameters
+        .iter()
+        .map(|parameter| format!("{}", parameter))
+        .collect();
+    if variadic {
+        formatted_parameters.push(String::from("..."));
+    }
+    format!("({})", formatted_parameters.join(", "))
+}
+
+// What a function asks of the inliner: the `inline` keyword hints at it, and
+// the always_inline and noinline attributes settle it. Declarations keep the
+// keyword apart in `is_inline` and only carry the attributes here.
+#[derive(Serialize, Debug, PartialEq, Copy, Clone)]
+pub enum Inlining {
+    Hint,
+    Always,
+    Never,
+}
+impl Inlining {
+    // An attribute outweighs the keyword, but the two attributes can't be
+    // combined.
+    pub fn merge(
+        left: Option<Inlining>,
+        right: Option<Inlining>,
+    ) -> Result<Option<Inlining>, String> {
+        match (left, right) {
+            (Some(Inlining::Always), Some(Inlining::Never))
+            | (Some(Inlining::Never), Some(Inlining::Always)) => Err(String::from(
+                "Attributes 'always_inline' and 'noinline' are incompatible",
+            )),
+            (Some(Inlining::Always), _) | (_, Some(Inlining::Always)) => Ok(Some(Inlining::Always)),
+            (Some(Inlining::Never), _) | (_, Some(Inlining::Never)) => Ok(Some(Inlining::Never)),
+            _ => Ok(left.or(right)),
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct Function {
     pub return_type: Type,
     pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub variadic: bool,
     pub body: Vec<Statement>,
+    pub is_static: bool,
+    pub is_inline: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlining: Option<Inlining>,
+    pub span: Span,
 }
 impl Function {
     pub fn new(return_type: Type, name: String, body: Vec<Statement>) -> Statement {
+        Function::with_parameters(return_type, name, vec![], false, body)
+    }
+    pub fn with_parameters(
+        return_type: Type,
+        name: String,
+        parameters: Vec<Parameter>,
+        variadic: bool,
+        body: Vec<Statement>,
+    ) -> Statement {
         Statement::Function(Function {
             return_type,
-            name: name,
+            name,
+            parameters,
+            variadic,
             body,
+            is_static: false,
+            is_inline: false,
+            inlining: None,
+            span: Span::default(),
         })
     }
+    pub fn signature(&self) -> signatures::Signature {
+        signatures::Signature {
+            parameters: self
+                .parameters
+                .iter()
+                .map(|parameter| parameter.data_type.unqualified().clone())
+                .collect(),
+            variadic: self.variadic,
+        }
+    }
 }
 impl StatementTrait for Function {}
 impl Node for Function {
     fn type_of(&self) -> &'static str {
         "Function"
     }
-    fn to_asm(&self) -> String {
-        let asm_body: Vec<String> = self
-            .body
-            .iter()
-            .map(|statement| statement.to_asm())
-            .rev()
-            .collect();
-        let mut function_asm = formatdoc! {"
-            .globl {name}
-            {name}:
-        ", name=self.name};
-        asm_body.iter().for_each(|asm| function_asm.push_str(&asm));
-        return function_asm;
-    }
 }
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -74,12 +149,399 @@ impl fmt::Display for Function {
             formatted_statements_body.push_str(&formatted_statement);
         }
         formatted_statements_body = indent(&formatted_statements_body, "        ");
+        let formatted_parameters = if self.parameters.is_empty() && !self.variadic {
+            String::new()
+        } else {
+            format_parameters(&self.parameters, self.variadic)
+        };
         let formatted_function = format!(
-            "Function {} {}:\n    body:\n{}",
+            "{}Function {} {}{}:\n    body:\n{}",
+            if self.is_static { "Static " } else { "" },
             self.return_type.type_of(),
             self.name,
+            formatted_parameters,
             formatted_statements_body
         );
         write!(f, "{}", formatted_function)
     }
 }
+
+#[derive(Serialize)]
+pub struct Block {
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+impl Block {
+    pub fn new(body: Vec<Statement>) -> Statement {
+        Statement::Block(Block {
+            body,
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Block {}
+impl Node for Block {
+    fn type_of(&self) -> &'static str {
+        "Block"
+    }
+}
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut formatted_block = String::from(self.type_of());
+        formatted_block.push(':');
+        for statement in self.body.iter() {
+            formatted_block.push('\n');
+            formatted_block.push_str(&indent(&format!("{}", statement), "    "));
+        }
+        write!(f, "{}", formatted_block)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Switch {
+    pub expression: Expression,
+    pub body: Box<Statement>,
+    pub span: Span,
+}
+impl Switch {
+    pub fn new(expression: Expression, body: Statement) -> Statement {
+        Statement::Switch(Switch {
+            expression,
+            body: Box::new(body),
+            span: Span::default(),
+        })
+    }
+    // The case values (None for default) that belong to this switch, leaving
+    // out those of nested switches.
+    pub fn labels(&self) -> Vec<Option<Type>> {
+        let mut labels = SwitchLabels::default();
+        labels.visit_statement(&self.body);
+        labels.labels
+    }
+}
+impl StatementTrait for Switch {}
+impl Node for Switch {
+    fn type_of(&self) -> &'static str {
+        "Switch"
+    }
+}
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted_body = indent(&format!("{}", self.body), "        ");
+        write!(
+            f,
+            "Switch {}:\n    body:\n{}",
+            self.expression, formatted_body
+        )
+    }
+}
+
+#[derive(Default)]
+struct SwitchLabels {
+    labels: Vec<Option<Type>>,
+}
+impl<'ast> Visitor<'ast> for SwitchLabels {
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        match statement {
+            Statement::Switch(_) => return,
+            Statement::Case(val) => self.labels.push(Some(val.value)),
+            Statement::Default(_) => self.labels.push(None),
+            _ => {}
+        }
+        walk_statement(self, statement);
+    }
+    fn visit_expression(&mut self, _expression: &'ast Expression) {}
+}
+
+#[derive(Serialize)]
+pub struct Case {
+    pub value: Type,
+    pub statement: Box<Statement>,
+    pub span: Span,
+}
+impl Case {
+    pub fn new(value: Type, statement: Statement) -> Statement {
+        Statement::Case(Case {
+            value,
+            statement: Box::new(statement),
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Case {}
+impl Node for Case {
+    fn type_of(&self) -> &'static str {
+        "Case"
+    }
+}
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted_statement = indent(&format!("{}", self.statement), "    ");
+        write!(f, "Case {}:\n{}", self.value, formatted_statement)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Default {
+    pub statement: Box<Statement>,
+    pub span: Span,
+}
+impl Default {
+    pub fn new(statement: Statement) -> Statement {
+        Statement::Default(Default {
+            statement: Box::new(statement),
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Default {}
+impl Node for Default {
+    fn type_of(&self) -> &'static str {
+        "Default"
+    }
+}
+impl fmt::Display for Default {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted_statement = indent(&format!("{}", self.statement), "    ");
+        write!(f, "Default:\n{}", formatted_statement)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Break {
+    pub span: Span,
+}
+impl Break {
+    pub fn new() -> Statement {
+        Statement::Break(Break {
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Break {}
+impl Node for Break {
+    fn type_of(&self) -> &'static str {
+        "Break"
+    }
+}
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Break")
+    }
+}
+
+#[derive(Serialize)]
+pub struct Label {
+    pub name: String,
+    pub statement: Box<Statement>,
+    pub span: Span,
+}
+impl Label {
+    pub fn new(name: String, statement: Statement) -> Statement {
+        Statement::Label(Label {
+            name,
+            statement: Box::new(statement),
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Label {}
+impl Node for Label {
+    fn type_of(&self) -> &'static str {
+        "Label"
+    }
+}
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted_statement = indent(&format!("{}", self.statement), "    ");
+        write!(f, "Label {}:\n{}", self.name, formatted_statement)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Goto {
+    pub label: String,
+    pub span: Span,
+}
+impl Goto {
+    pub fn new(label: String) -> Statement {
+        Statement::Goto(Goto {
+            label,
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Goto {}
+impl Node for Goto {
+    fn type_of(&self) -> &'static str {
+        "Goto"
+    }
+}
+impl fmt::Display for Goto {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Goto {}", self.label)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Declaration {
+    pub variable_type: DataType,
+    pub name: String,
+    pub initializer: Option<Expression>,
+    pub is_static: bool,
+    pub span: Span,
+}
+impl Declaration {
+    pub fn new(
+        variable_type: DataType,
+        name: String,
+        initializer: Option<Expression>,
+    ) -> Statement {
+        Statement::Declaration(Declaration {
+            variable_type,
+            name,
+            initializer,
+            is_static: false,
+            span: Span::default(),
+        })
+    }
+    pub fn new_static(
+        variable_type: DataType,
+        name: String,
+        initializer: Option<Expression>,
+    ) -> Statement {
+        Statement::Declaration(Declaration {
+            variable_type,
+            name,
+            initializer,
+            is_static: true,
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Declaration {}
+impl Node for Declaration {
+    fn type_of(&self) -> &'static str {
+        "Declaration"
+    }
+}
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_static {
+            write!(f, "Static ")?;
+        }
+        write!(f, "Declaration {} {}", self.variable_type, self.name)?;
+        match &self.initializer {
+            Some(initializer) => write!(f, " = {}", initializer),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExpressionStatement {
+    pub expression: Expression,
+    pub span: Span,
+}
+impl ExpressionStatement {
+    pub fn new(expression: Expression) -> Statement {
+        Statement::Expression(ExpressionStatement {
+            expression,
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for ExpressionStatement {}
+impl Node for ExpressionStatement {
+    fn type_of(&self) -> &'static str {
+        "Expression"
+    }
+}
+impl fmt::Display for ExpressionStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expression {}", self.expression)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Empty {
+    pub span: Span,
+}
+impl Empty {
+    pub fn new() -> Statement {
+        Statement::Empty(Empty {
+            span: Span::default(),
+        })
+    }
+}
+impl StatementTrait for Empty {}
+impl Node for Empty {
+    fn type_of(&self) -> &'static str {
+        "Empty"
+    }
+}
+impl fmt::Display for Empty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Empty")
+    }
+}
+
+#[derive(Serialize)]
+pub struct Prototype {
+    pub return_type: Type,
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub variadic: bool,
+    pub is_static: bool,
+    pub is_inline: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlining: Option<Inlining>,
+    pub span: Span,
+}
+impl Prototype {
+    pub fn new(
+        return_type: Type,
+        name: String,
+        parameters: Vec<Parameter>,
+        variadic: bool,
+    ) -> Statement {
+        Statement::Prototype(Prototype {
+            return_type,
+            name,
+            parameters,
+            variadic,
+            is_static: false,
+            is_inline: false,
+            inlining: None,
+            span: Span::default(),
+        })
+    }
+    pub fn signature(&self) -> signatures::Signature {
+        signatures::Signature {
+            parameters: self
+                .parameters
+                .iter()
+                .map(|parameter| parameter.data_type.unqualified().clone())
+                .collect(),
+            variadic: self.variadic,
+        }
+    }
+}
+impl StatementTrait for Prototype {}
+impl Node for Prototype {
+    fn type_of(&self) -> &'static str {
+        "Prototype"
+    }
+}
+impl fmt::Display for Prototype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}Prototype {} {}{}",
+            if self.is_static { "Static " } else { "" },
+            self.return_type.type_of(),
+            self.name,
+            format_parameters(&self.parameters, self.variadic)
+        )
+    }
+}
@@ -24,9 +24,76 @@ pub mod test_utils {
   pub fn create_test_program() -> Program {
       Program::new(create_test_function())
   }
+
+  pub fn create_test_goto_program() -> Program {
+      let body: Vec<Statement> = vec![
+          statements::Goto::new(String::from("end")),
+          statements::Return::new(expressions::Constant::new(Type::Integer(1))),
+          statements::Label::new(String::from("end"), create_test_return_statement()),
+      ];
+      Program::new(statements::Function::new(
+          Type::Integer(0),
+          String::from("main"),
+          body,
+      ))
+  }
+
+  pub fn create_test_variable(name: &str) -> Expression {
+      expressions::Variable::new(String::from(name))
+  }
+
+  // int main() { int x = 2; x = f(), x++; x + 1; ; return x; }
+  pub fn create_test_expression_program() -> Program {
+      let call_and_increment = expressions::Comma::new(
+          expressions::Assignment::new(
+              String::from("x"),
+              expressions::Call::new(String::from("f"), vec![]),
+          ),
+          expressions::Postfix::new(
+              expressions::PostfixOperator::Increment,
+              create_test_variable("x"),
+          ),
+      );
+      let unused_sum = expressions::Binary::new(
+          expressions::BinaryOperator::Addition,
+          create_test_variable("x"),
+          expressions::Constant::new(Type::Integer(1)),
+      );
+      let body: Vec<Statement> = vec![
+          statements::Declaration::new(
+              DataType::Int,
+              String::from("x"),
+              Some(create_test_constant_expression()),
+          ),
+          statements::ExpressionStatement::new(call_and_increment),
+          statements::ExpressionStatement::new(unused_sum),
+          statements::Empty::new(),
+          statements::Return::new(create_test_variable("x")),
+      ];
+      Program::new(statements::Function::new(
+          Type::Integer(0),
+          String::from("main"),
+          body,
+      ))
+  }
+
+  pub fn create_test_switch(case_values: &[i32], with_default: bool) -> Statement {
+      let mut body: Vec<Statement> = case_values
+          .iter()
+          .map(|val| statements::Case::new(Type::Integer(*val), create_test_return_statement()))
+          .collect();
+      if with_default {
+          body.push(statements::Default::new(statements::Break::new()));
+      }
+      statements::Switch::new(
+          create_test_constant_expression(),
+          statements::Block::new(body),
+      )
+  }
 }
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
   use super::test_utils::*;
   use crate::syntax::*;
@@ -38,7 +105,6 @@ mod tests {
       let integer_formatted: String = format!("{}", integer);
       assert_eq!("Integer", integer.type_of());
       assert_eq!("Integer<2>", &integer_formatted);
-      assert_eq!("$2", integer.to_asm());
   }
 
   #[test]
@@ -49,7 +115,6 @@ mod tests {
       assert_eq!(integer, constant.evaluate());
       assert_eq!("Constant", constant.type_of());
       assert_eq!("Constant Integer<2>", constant_formatted);
-      assert_eq!("$2", constant.to_asm());
   }
 
   #[test]
@@ -58,11 +123,6 @@ mod tests {
       let return_formatted: String = format!("{}", return_statement);
       assert_eq!("Return", return_statement.type_of());
       assert_eq!("Return Constant Integer<2>", return_formatted);
-      let expected_asm = indoc! {"
-        movl $2, %eax
-        ret
-    "};
-      assert_eq!(expected_asm, return_statement.to_asm());
   }
 
   #[test]
@@ -73,15 +133,8 @@ mod tests {
         Function Integer main:
             body:
                 Return Constant Integer<2>
-    "};
-      let expected_asm = indoc! {"
-        .globl main
-        main:
-        movl $2, %eax
-        ret
     "};
       assert_eq!(expected_format, function_formatted);
-      assert_eq!(expected_asm, function.to_asm());
   }
 
   #[test]
@@ -95,13 +148,180 @@ mod tests {
     "};
       let program_formatted: String = format!("{}", main_program);
       assert_eq!(expected_format, program_formatted);
-      let expected_asm = indoc! {"
-        .globl main
-        main:
-        movl $2, %eax
-        ret
+  }
+
+  #[test]
+  fn test_switch() {
+      let switch = create_test_switch(&[1, 2], true);
+      let expected_format = indoc! {"
+        Switch Constant Integer<2>:
+            body:
+                Block:
+                    Case Integer<1>:
+                        Return Constant Integer<2>
+                    Case Integer<2>:
+                        Return Constant Integer<2>
+                    Default:
+                        Break"};
+      assert_eq!("Switch", switch.type_of());
+      assert_eq!(expected_format, format!("{}", switch));
+  }
+
+  #[test]
+  fn test_goto_and_label() {
+      let program = create_test_goto_program();
+      let expected_format = indoc! {"
+        PROGRAM_START:
+        Function Integer main:
+            body:
+                Goto end
+                Return Constant Integer<1>
+                Label end:
+                    Return Constant Integer<2>
     "};
-      assert_eq!(expected_asm, main_program.to_asm());
+      assert_eq!(expected_format, format!("{}", program));
+  }
+
+  #[test]
+  fn test_binary_expression() {
+      let binary = expressions::Binary::new(
+          expressions::BinaryOperator::LessThan,
+          create_test_constant_expression(),
+          expressions::Unary::new(
+              expressions::UnaryOperator::Negation,
+              create_test_constant_expression(),
+          ),
+      );
+      assert_eq!("Binary < (Constant Integer<2>, Unary - (Constant Integer<2>))", format!("{}", binary));
+      assert!(binary.is_constant());
+      assert_eq!(Type::Integer(0), binary.evaluate());
+  }
+
+  #[test]
+  fn test_division_by_zero_is_not_constant() {
+      let division = expressions::Binary::new(
+          expressions::BinaryOperator::Division,
+          create_test_constant_expression(),
+          expressions::Constant::new(Type::Integer(0)),
+      );
+      assert!(!division.is_constant());
+  }
+
+  #[test]
+  fn test_expression_statements() {
+      let program = create_test_expression_program();
+      let expected_format = indoc! {"
+        PROGRAM_START:
+        Function Integer main:
+            body:
+                Declaration Integer x = Constant Integer<2>
+                Expression Comma (Assignment x = (Call f ()), Postfix ++ (Variable x))
+                Expression Binary + (Variable x, Constant Integer<1>)
+                Empty
+                Return Variable x
+    "};
+      assert_eq!(expected_format, format!("{}", program));
+  }
+
+  #[test]
+  fn test_unused_expression_results() {
+      let code = indoc! {"
+        int main() {
+            int x = 2;
+            x = 3, x++;
+            x + 1;
+            {
+                x;
+            }
+            return x;
+        }
+      "};
+      let tokens = crate::tokenizer::tokenize_with_spans(code);
+      let program = crate::parser::parse_program(tokens).unwrap();
+      let starts: Vec<String> = program
+          .unused_results()
+          .into_iter()
+          .map(|span| span.start.to_string())
+          .collect();
+      assert_eq!(vec!["4:5", "6:9"], starts);
+  }
+
+  #[test]
+  fn test_unreachable_code() {
+      let code = indoc! {"
+        int f(int n) {
+            switch (n) {
+                n = 3;
+            case 1:
+                return 1;
+                break;
+            default:
+                goto out;
+                n = 5;
+                n = 6;
+            }
+            return 5;
+        out:
+            return n;
+            int unused;
+            n = 1;
+        }
+      "};
+      let tokens = crate::tokenizer::tokenize_with_spans(code);
+      let program = crate::parser::parse_program(tokens).unwrap();
+      let starts: Vec<(&str, String)> = program
+          .unreachable_code()
+          .into_iter()
+          .map(|(function, span)| (function, span.start.to_string()))
+          .collect();
+      assert_eq!(
+          vec![
+              ("f", String::from("3:9")),
+              ("f", String::from("9:9")),
+              ("f", String::from("16:5")),
+          ],
+          starts
+      );
+  }
+
+  #[test]
+  fn test_unreachable_code_after_switch() {
+      let code = indoc! {"
+        int f(int x) {
+            switch (x) {
+            case 1:
+                return 1;
+            default:
+                return 2;
+            }
+            return 3;
+        }
+        int g(int x) {
+            switch (x) {
+            case 1:
+                return 1;
+            }
+            switch (x) {
+            case 1:
+                break;
+            default:
+                return 2;
+            }
+            switch (x) {
+            default:
+                x = 2;
+            }
+            return x;
+        }
+      "};
+      let tokens = crate::tokenizer::tokenize_with_spans(code);
+      let program = crate::parser::parse_program(tokens).unwrap();
+      let starts: Vec<(&str, String)> = program
+          .unreachable_code()
+          .into_iter()
+          .map(|(function, span)| (function, span.start.to_string()))
+          .collect();
+      assert_eq!(vec![("f", String::from("8:5"))], starts);
   }
 
   #[test]
@@ -0,0 +1,480 @@
+use std::mem;
+
+use crate::syntax::expressions::{BinaryOperator, VaOperation};
+use crate::syntax::statements::{Declaration, Inlining, Parameter, Prototype};
+use crate::syntax::*;
+
+// Prints a syntax tree back as C in one canonical layout: four space indents,
+// one statement per line, case and goto labels outdented by a level, and only
+// the parentheses that precedence needs. Parsing the output gives the same
+// tree, up to spans and the order of globals, prototypes and functions.
+pub fn unparse(program: &Program) -> String {
+    let mut sections = vec![];
+    let globals: String = program
+        .globals
+        .iter()
+        .map(|global| format!("{}\n", declaration(global)))
+        .collect();
+    sections.push(globals);
+    let prototypes: String = program.prototypes.iter().map(prototype).collect();
+    sections.push(prototypes);
+    for function in program.functions.iter() {
+        let mut out = String::new();
+        write_function(function, 0, &mut out);
+        sections.push(out);
+    }
+    sections.retain(|section| !section.is_empty());
+    sections.join("\n")
+}
+
+// File scope declarations as C in the order given, laid out as unparse lays
+// out a program. Printing them in source order keeps redeclarations valid.
+pub fn unparse_declarations(declarations: &[Statement]) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&Statement> = None;
+    for declaration in declarations.iter() {
+        // Functions stand apart, and so does each run of variables or
+        // prototypes.
+        let apart = previous.is_some_and(|previous| {
+            matches!(previous, Statement::Function(_))
+                || matches!(declaration, Statement::Function(_))
+                || mem::discriminant(previous) != mem::discriminant(declaration)
+        });
+        if apart {
+            out.push('\n');
+        }
+        write_statement(declaration, 0, &mut out);
+        previous = Some(declaration);
+    }
+    out
+}
+
+const INDENT: &str = "    ";
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Char => String::from("char"),
+        DataType::Int => String::from("int"),
+        DataType::Pointer(pointee) => format!("{} *", type_name(pointee)),
+        DataType::VaList => String::from("__builtin_va_list"),
+        DataType::Const(val) => match &**val {
+            DataType::Pointer(pointee) => format!("{} *const", type_name(pointee)),
+            _ => format!("const {}", type_name(val)),
+        },
+    }
+}
+
+// `char *s`, `int n`, or just the type when there is no name.
+pub fn declarator(data_type: &DataType, name: &str) -> String {
+    let base = type_name(data_type);
+    if name.is_empty() || base.ends_with('*') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{} {}", base, name)
+    }
+}
+
+fn return_type(value: &Type) -> &'static str {
+    match value {
+        Type::Integer(_) => "int",
+    }
+}
+
+fn signature(
+    is_static: bool,
+    is_inline: bool,
+    inlining: Option<Inlining>,
+    value: &Type,
+    name: &str,
+    parameters: &[Parameter],
+    variadic: bool,
+) -> String {
+    let mut list: Vec<String> = parameters
+        .iter()
+        .map(|parameter| declarator(&parameter.data_type, &parameter.name))
+        .collect();
+    if variadic {
+        list.push(String::from("..."));
+    }
+    if list.is_empty() {
+        list.push(String::from("void"));
+    }
+    let inlining = match inlining {
+        None | Some(Inlining::Hint) => "",
+        Some(Inlining::Always) => "__attribute__((always_inline)) ",
+        Some(Inlining::Never) => "__attribute__((noinline)) ",
+    };
+    format!(
+        "{}{}{}{} {}({})",
+        if is_static { "static " } else { "" },
+        if is_inline { "inline " } else { "" },
+        inlining,
+        return_type(value),
+        name,
+        list.join(", ")
+    )
+}
+
+fn prototype(prototype: &Prototype) -> String {
+    format!(
+        "{};\n",
+        signature(
+            prototype.is_static,
+            prototype.is_inline,
+            prototype.inlining,
+            &prototype.return_type,
+            &prototype.name,
+            &prototype.parameters,
+            prototype.variadic
+        )
+    )
+}
+
+fn declaration(declaration: &Declaration) -> String {
+    let mut line = String::new();
+    if declaration.is_static {
+        line.push_str("static ");
+    }
+    line.push_str(&declarator(&declaration.variable_type, &declaration.name));
+    if let Some(initializer) = &declaration.initializer {
+        line.push_str(" = ");
+        line.push_str(&operand(initializer, ASSIGNMENT));
+    }
+    line.push(';');
+    line
+}
+
+fn write_function(function: &statements::Function, indent: usize, out: &mut String) {
+    out.push_str(&pad(indent));
+    out.push_str(&signature(
+        function.is_static,
+        function.is_inline,
+        function.inlining,
+        &function.return_type,
+        &function.name,
+        &function.parameters,
+        function.variadic,
+    ));
+    out.push_str(" {\n");
+    for statement in function.body.iter() {
+        write_statement(statement, indent + 1, out);
+    }
+    out.push_str(&pad(indent));
+    out.push_str("}\n");
+}
+
+fn write_block(body: &[Statement], indent: usize, out: &mut String) {
+    out.push_str("{\n");
+    for statement in body.iter() {
+        write_statement(statement, indent + 1, out);
+    }
+    out.push_str(&pad(indent));
+    out.push_str("}\n");
+}
+
+// A label goes a level out from the statement it marks, which follows on the
+// next line.
+fn write_labelled(label: &str, statement: &Statement, indent: usize, out: &mut String) {
+    out.push_str(&pad(indent.saturating_sub(1)));
+    out.push_str(label);
+    out.push_str(":\n");
+    write_statement(statement, indent, out);
+}
+
+fn write_statement(statement: &Statement, indent: usize, out: &mut String) {
+    match statement {
+        Statement::Function(function) => return write_function(function, indent, out),
+        Statement::Block(block) => {
+            out.push_str(&pad(indent));
+            return write_block(&block.body, indent, out);
+        }
+        Statement::Switch(switch) => {
+            out.push_str(&pad(indent));
+            out.push_str(&format!("switch ({}) ", expression(&switch.expression)));
+            match &*switch.body {
+                Statement::Block(block) => write_block(&block.body, indent, out),
+                body => {
+                    out.push('\n');
+                    write_statement(body, indent + 1, out);
+                }
+            }
+            return;
+        }
+        Statement::Case(case) => {
+            let label = format!("case {}", constant(&case.value));
+            return write_labelled(&label, &case.statement, indent, out);
+        }
+        Statement::Default(default) => {
+            return write_labelled("default", &default.statement, indent, out)
+        }
+        Statement::Label(label) => {
+            return write_labelled(&label.name, &label.statement, indent, out)
+        }
+        _ => {}
+    }
+    let line = match statement {
+        Statement::Return(val) => format!("return {};", expression(&val.expression)),
+        Statement::Break(_) => String::from("break;"),
+        Statement::Goto(val) => format!("goto {};", val.label),
+        Statement::Declaration(val) => declaration(val),
+        Statement::Expression(val) => format!("{};", expression(&val.expression)),
+        Statement::Empty(_) => String::from(";"),
+        Statement::Prototype(val) => String::from(prototype(val).trim_end()),
+        _ => unreachable!("compound statements are written above"),
+    };
+    out.push_str(&pad(indent));
+    out.push_str(&line);
+    out.push('\n');
+}
+
+// Binding strength of each kind of expression, around the binary operator
+// precedences, which sit between assignment and unary.
+const COMMA: u8 = 0;
+const ASSIGNMENT: u8 = COMMA + 1;
+const UNARY: u8 = BinaryOperator::Multiplication.precedence() + ASSIGNMENT + 1;
+const POSTFIX: u8 = UNARY + 1;
+const PRIMARY: u8 = POSTFIX + 1;
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Comma(_) => COMMA,
+        Expression::Assignment(_) => ASSIGNMENT,
+        Expression::Binary(binary) => binary.operator.precedence() + ASSIGNMENT,
+        Expression::Unary(_) => UNARY,
+        Expression::Postfix(_) => POSTFIX,
+        Expression::Conversion(val) => precedence(&val.operand),
+        // Only folded trees hold negative constants; they print as negations.
+        Expression::Constant(val) => match val.value {
+            Type::Integer(i32::MIN) => BinaryOperator::Subtraction.precedence() + ASSIGNMENT,
+            Type::Integer(val) if val < 0 => UNARY,
+            Type::Integer(_) => PRIMARY,
+        },
+        _ => PRIMARY,
+    }
+}
+
+// `expression`, parenthesized if it binds looser than `minimum`.
+fn operand(expression: &Expression, minimum: u8) -> String {
+    let text = self::expression(expression);
+    if precedence(expression) < minimum {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn constant(value: &Type) -> String {
+    match value {
+        // 2147483648 is not an int, so the smallest int has no literal.
+        Type::Integer(i32::MIN) => format!("-{} - 1", i32::MAX),
+        Type::Integer(val) => val.to_string(),
+    }
+}
+
+pub fn expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Constant(val) => constant(&val.value),
+        Expression::Variable(val) => val.name.clone(),
+        Expression::Unary(val) => {
+            let symbol = val.operator.symbol();
+            let operand = operand(&val.operand, UNARY);
+            // Keep `- -x` from reading as `--x`.
+            if symbol.ends_with('-') && operand.starts_with('-') {
+                format!("{} {}", symbol, operand)
+            } else {
+                format!("{}{}", symbol, operand)
+            }
+        }
+        Expression::Postfix(val) => {
+            format!(
+                "{}{}",
+                operand(&val.operand, POSTFIX),
+                val.operator.symbol()
+            )
+        }
+        // Binary operators all associate to the left.
+        Expression::Binary(val) => {
+            let precedence = val.operator.precedence() + ASSIGNMENT;
+            format!(
+                "{} {} {}",
+                operand(&val.left, precedence),
+                val.operator.symbol(),
+                operand(&val.right, precedence + 1)
+            )
+        }
+        Expression::Assignment(val) => {
+            format!("{} = {}", val.name, operand(&val.value, ASSIGNMENT))
+        }
+        Expression::Comma(val) => format!(
+            "{}, {}",
+            operand(&val.left, COMMA),
+            operand(&val.right, ASSIGNMENT)
+        ),
+        Expression::Call(val) => {
+            let arguments: Vec<String> = val
+                .arguments
+                .iter()
+                .map(|argument| operand(argument, ASSIGNMENT))
+                .collect();
+            format!("{}({})", val.name, arguments.join(", "))
+        }
+        Expression::StringLiteral(val) => format!("\"{}\"", val.value),
+        Expression::VaBuiltin(val) => match &val.operation {
+            VaOperation::Start(last) => format!("__builtin_va_start({}, {})", val.list, last),
+            VaOperation::Arg(data_type) => {
+                format!("__builtin_va_arg({}, {})", val.list, type_name(data_type))
+            }
+            VaOperation::End => format!("__builtin_va_end({})", val.list),
+        },
+        // Conversions are implicit in C, and analysis inserts them again.
+        Expression::Conversion(val) => self::expression(&val.operand),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::dump;
+    use crate::syntax::expressions::UnaryOperator;
+    use crate::{parser, tokenizer};
+
+    fn parse(code: &str) -> Program {
+        parser::parse_program(tokenizer::tokenize_with_spans(code)).unwrap()
+    }
+
+    // Reformats `code`, checking that the result parses back to the same
+    // tree and is already in canonical form.
+    fn reformat(code: &str) -> String {
+        let program = parse(code);
+        let printed = unparse(&program);
+        let reparsed = parse(&printed);
+        assert_eq!(dump::structure(&program), dump::structure(&reparsed));
+        assert_eq!(printed, unparse(&reparsed));
+        let declarations = parser::parse_translation_unit(tokenizer::tokenize_with_spans(&printed));
+        assert_eq!(printed, unparse_declarations(&declarations.unwrap()));
+        printed
+    }
+
+    #[test]
+    fn test_unparse_layout() {
+        let code = "static int f(int);int g=1;int printf(const char*fmt,...);\
+                    static int f(int n){switch(n){case 1:n++;break;default:;}done:return n;}\
+                    int main(){static char c;{int x=-1;}goto end;end:return f(\"a\" \"b\");}";
+        let expected = "\
+int g = 1;
+
+static int f(int);
+int printf(const char *fmt, ...);
+
+static int f(int n) {
+    switch (n) {
+    case 1:
+        n++;
+        break;
+    default:
+        ;
+    }
+done:
+    return n;
+}
+
+int main(void) {
+    static char c;
+    {
+        int x = -1;
+    }
+    goto end;
+end:
+    return f(\"ab\");
+}
+";
+        assert_eq!(expected, reformat(code));
+    }
+
+    #[test]
+    fn test_unparse_parentheses() {
+        let code = "int f(int a, int b, int c) { \
+                    return (a - (b - c)) * -(a + b) / (c, - -a) % f(a = b, (a, b), c) || a && !(b == c); }";
+        let expected = "int f(int a, int b, int c) {\n    \
+                        return (a - (b - c)) * -(a + b) / (c, - -a) % f(a = b, (a, b), c) || a && !(b == c);\n\
+                        }\n";
+        assert_eq!(expected, reformat(code));
+        assert_eq!(
+            "int f(int a) {\n    return a = (a, 1), (a + 1) * 2 - 3 - -a--;\n}\n",
+            reformat("int f(int a) { return ((a = (a, 1)), ((((a + 1)) * 2) - 3) - (-(a--))); }")
+        );
+    }
+
+    #[test]
+    fn test_unparse_qualifiers() {
+        let code = "int puts(const char *s); int const limit = 3; char *const name = 0; \
+                    int f(const char *const *names, ...) { __builtin_va_list ap; \
+                    __builtin_va_start(ap, names); const int n = __builtin_va_arg(ap, const int); return n; }";
+        let expected = "\
+const int limit = 3;
+char *const name = 0;
+
+int puts(const char *s);
+
+int f(const char *const *names, ...) {
+    __builtin_va_list ap;
+    __builtin_va_start(ap, names);
+    const int n = __builtin_va_arg(ap, const int);
+    return n;
+}
+";
+        assert_eq!(expected, reformat(code));
+    }
+
+    #[test]
+    fn test_unparse_inline_with_attributes() {
+        let code = "static inline __attribute__((noinline)) int f(void); \
+                    __attribute__((always_inline)) inline int g(void) { return 1; }";
+        let expected = "\
+static inline __attribute__((noinline)) int f(void);
+
+inline __attribute__((always_inline)) int g(void) {
+    return 1;
+}
+";
+        assert_eq!(expected, reformat(code));
+    }
+
+    #[test]
+    fn test_unparse_va_builtins() {
+        let code = "int sum(int n, ...) { __builtin_va_list ap; __builtin_va_start(ap, n); \
+                    int total = __builtin_va_arg(ap, int); __builtin_va_end(ap); return total; }";
+        let expected = "\
+int sum(int n, ...) {
+    __builtin_va_list ap;
+    __builtin_va_start(ap, n);
+    int total = __builtin_va_arg(ap, int);
+    __builtin_va_end(ap);
+    return total;
+}
+";
+        assert_eq!(expected, reformat(code));
+    }
+
+    #[test]
+    fn test_unparse_negative_constants() {
+        let folded = |value| {
+            Expression::Constant(expressions::Constant {
+                value: Type::Integer(value),
+                span: Span::default(),
+            })
+        };
+        let negation = expressions::Unary::new(UnaryOperator::Negation, folded(-2));
+        assert_eq!("- -2", expression(&negation));
+        let sum = expressions::Binary::new(BinaryOperator::Addition, folded(1), folded(i32::MIN));
+        assert_eq!("1 + (-2147483647 - 1)", expression(&sum));
+        assert_eq!(
+            "int f(int n) {\n    switch (n) {\n    case -3:\n        return 1;\n    }\n    return 0;\n}\n",
+            reformat("int f(int n) { switch (n) { case -(1 + 2): return 1; } return 0; }")
+        );
+    }
+}
@@ -0,0 +1,461 @@
+// Traversals over the syntax tree. A pass overrides the methods for the nodes
+// it cares about and calls the matching walk function (or, for Fold, the
+// free fold function) to carry on into the children; everything else is
+// walked by the defaults.
+use crate::syntax::statements::{Declaration, Function, Prototype};
+use crate::syntax::{Expression, Program, Statement};
+
+pub trait Visitor<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program);
+    }
+    fn visit_function(&mut self, function: &'ast Function) {
+        walk_function(self, function);
+    }
+    fn visit_prototype(&mut self, _prototype: &'ast Prototype) {}
+    fn visit_declaration(&mut self, declaration: &'ast Declaration) {
+        walk_declaration(self, declaration);
+    }
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        walk_statement(self, statement);
+    }
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        walk_expression(self, expression);
+    }
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, program: &'ast Program) {
+    program
+        .globals
+        .iter()
+        .for_each(|global| visitor.visit_declaration(global));
+    program
+        .prototypes
+        .iter()
+        .for_each(|prototype| visitor.visit_prototype(prototype));
+    program
+        .functions
+        .iter()
+        .for_each(|function| visitor.visit_function(function));
+}
+
+pub fn walk_function<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, function: &'ast Function) {
+    function
+        .body
+        .iter()
+        .for_each(|statement| visitor.visit_statement(statement));
+}
+
+pub fn walk_declaration<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    declaration: &'ast Declaration,
+) {
+    if let Some(initializer) = &declaration.initializer {
+        visitor.visit_expression(initializer);
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    statement: &'ast Statement,
+) {
+    match statement {
+        Statement::Function(val) => visitor.visit_function(val),
+        Statement::Prototype(val) => visitor.visit_prototype(val),
+        Statement::Declaration(val) => visitor.visit_declaration(val),
+        Statement::Return(val) => visitor.visit_expression(&val.expression),
+        Statement::Expression(val) => visitor.visit_expression(&val.expression),
+        Statement::Block(val) => val
+            .body
+            .iter()
+            .for_each(|statement| visitor.visit_statement(statement)),
+        Statement::Switch(val) => {
+            visitor.visit_expression(&val.expression);
+            visitor.visit_statement(&val.body);
+        }
+        Statement::Case(val) => visitor.visit_statement(&val.statement),
+        Statement::Default(val) => visitor.visit_statement(&val.statement),
+        Statement::Label(val) => visitor.visit_statement(&val.statement),
+        Statement::Break(_) | Statement::Goto(_) | Statement::Empty(_) => {}
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    expression: &'ast Expression,
+) {
+    match expression {
+        Expression::Unary(val) => visitor.visit_expression(&val.operand),
+        Expression::Postfix(val) => visitor.visit_expression(&val.operand),
+        Expression::Conversion(val) => visitor.visit_expression(&val.operand),
+        Expression::Assignment(val) => visitor.visit_expression(&val.value),
+        Expression::Binary(val) => {
+            visitor.visit_expression(&val.left);
+            visitor.visit_expression(&val.right);
+        }
+        Expression::Comma(val) => {
+            visitor.visit_expression(&val.left);
+            visitor.visit_expression(&val.right);
+        }
+        Expression::Call(val) => val
+            .arguments
+            .iter()
+            .for_each(|argument| visitor.visit_expression(argument)),
+        Expression::Constant(_)
+        | Expression::Variable(_)
+        | Expression::StringLiteral(_)
+        | Expression::VaBuiltin(_) => {}
+    }
+}
+
+// Visitor, for passes that rewrite nodes in place.
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        walk_function_mut(self, function);
+    }
+    fn visit_prototype_mut(&mut self, _prototype: &mut Prototype) {}
+    fn visit_declaration_mut(&mut self, declaration: &mut Declaration) {
+        walk_declaration_mut(self, declaration);
+    }
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    program
+        .globals
+        .iter_mut()
+        .for_each(|global| visitor.visit_declaration_mut(global));
+    program
+        .prototypes
+        .iter_mut()
+        .for_each(|prototype| visitor.visit_prototype_mut(prototype));
+    program
+        .functions
+        .iter_mut()
+        .for_each(|function| visitor.visit_function_mut(function));
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut Function) {
+    function
+        .body
+        .iter_mut()
+        .for_each(|statement| visitor.visit_statement_mut(statement));
+}
+
+pub fn walk_declaration_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    declaration: &mut Declaration,
+) {
+    if let Some(initializer) = &mut declaration.initializer {
+        visitor.visit_expression_mut(initializer);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match statement {
+        Statement::Function(val) => visitor.visit_function_mut(val),
+        Statement::Prototype(val) => visitor.visit_prototype_mut(val),
+        Statement::Declaration(val) => visitor.visit_declaration_mut(val),
+        Statement::Return(val) => visitor.visit_expression_mut(&mut val.expression),
+        Statement::Expression(val) => visitor.visit_expression_mut(&mut val.expression),
+        Statement::Block(val) => val
+            .body
+            .iter_mut()
+            .for_each(|statement| visitor.visit_statement_mut(statement)),
+        Statement::Switch(val) => {
+            visitor.visit_expression_mut(&mut val.expression);
+            visitor.visit_statement_mut(&mut val.body);
+        }
+        Statement::Case(val) => visitor.visit_statement_mut(&mut val.statement),
+        Statement::Default(val) => visitor.visit_statement_mut(&mut val.statement),
+        Statement::Label(val) => visitor.visit_statement_mut(&mut val.statement),
+        Statement::Break(_) | Statement::Goto(_) | Statement::Empty(_) => {}
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression {
+        Expression::Unary(val) => visitor.visit_expression_mut(&mut val.operand),
+        Expression::Postfix(val) => visitor.visit_expression_mut(&mut val.operand),
+        Expression::Conversion(val) => visitor.visit_expression_mut(&mut val.operand),
+        Expression::Assignment(val) => visitor.visit_expression_mut(&mut val.value),
+        Expression::Binary(val) => {
+            visitor.visit_expression_mut(&mut val.left);
+            visitor.visit_expression_mut(&mut val.right);
+        }
+        Expression::Comma(val) => {
+            visitor.visit_expression_mut(&mut val.left);
+            visitor.visit_expression_mut(&mut val.right);
+        }
+        Expression::Call(val) => val
+            .arguments
+            .iter_mut()
+            .for_each(|argument| visitor.visit_expression_mut(argument)),
+        Expression::Constant(_)
+        | Expression::Variable(_)
+        | Expression::StringLiteral(_)
+        | Expression::VaBuiltin(_) => {}
+    }
+}
+
+// Takes the tree by value and builds a new one, for passes that replace a
+// node with one of a different kind.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+    fn fold_function(&mut self, function: Function) -> Function {
+        fold_function(self, function)
+    }
+    fn fold_prototype(&mut self, prototype: Prototype) -> Prototype {
+        prototype
+    }
+    fn fold_declaration(&mut self, declaration: Declaration) -> Declaration {
+        fold_declaration(self, declaration)
+    }
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        fold_statement(self, statement)
+    }
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        fold_expression(self, expression)
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        files: program.files,
+        globals: program
+            .globals
+            .into_iter()
+            .map(|global| folder.fold_declaration(global))
+            .collect(),
+        prototypes: program
+            .prototypes
+            .into_iter()
+            .map(|prototype| folder.fold_prototype(prototype))
+            .collect(),
+        functions: program
+            .functions
+            .into_iter()
+            .map(|function| folder.fold_function(function))
+            .collect(),
+    }
+}
+
+pub fn fold_function<F: Fold + ?Sized>(folder: &mut F, mut function: Function) -> Function {
+    function.body = fold_statements(folder, function.body);
+    function
+}
+
+pub fn fold_declaration<F: Fold + ?Sized>(
+    folder: &mut F,
+    mut declaration: Declaration,
+) -> Declaration {
+    declaration.initializer = declaration
+        .initializer
+        .map(|initializer| folder.fold_expression(initializer));
+    declaration
+}
+
+fn fold_statements<F: Fold + ?Sized>(folder: &mut F, statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .map(|statement| folder.fold_statement(statement))
+        .collect()
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(folder: &mut F, statement: Statement) -> Statement {
+    match statement {
+        Statement::Function(val) => Statement::Function(folder.fold_function(val)),
+        Statement::Prototype(val) => Statement::Prototype(folder.fold_prototype(val)),
+        Statement::Declaration(val) => Statement::Declaration(folder.fold_declaration(val)),
+        Statement::Return(mut val) => {
+            val.expression = folder.fold_expression(val.expression);
+            Statement::Return(val)
+        }
+        Statement::Expression(mut val) => {
+            val.expression = folder.fold_expression(val.expression);
+            Statement::Expression(val)
+        }
+        Statement::Block(mut val) => {
+            val.body = fold_statements(folder, val.body);
+            Statement::Block(val)
+        }
+        Statement::Switch(mut val) => {
+            val.expression = folder.fold_expression(val.expression);
+            val.body = Box::new(folder.fold_statement(*val.body));
+            Statement::Switch(val)
+        }
+        Statement::Case(mut val) => {
+            val.statement = Box::new(folder.fold_statement(*val.statement));
+            Statement::Case(val)
+        }
+        Statement::Default(mut val) => {
+            val.statement = Box::new(folder.fold_statement(*val.statement));
+            Statement::Default(val)
+        }
+        Statement::Label(mut val) => {
+            val.statement = Box::new(folder.fold_statement(*val.statement));
+            Statement::Label(val)
+        }
+        Statement::Break(_) | Statement::Goto(_) | Statement::Empty(_) => statement,
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(folder: &mut F, expression: Expression) -> Expression {
+    match expression {
+        Expression::Unary(mut val) => {
+            val.operand = Box::new(folder.fold_expression(*val.operand));
+            Expression::Unary(val)
+        }
+        Expression::Postfix(mut val) => {
+            val.operand = Box::new(folder.fold_expression(*val.operand));
+            Expression::Postfix(val)
+        }
+        Expression::Conversion(mut val) => {
+            val.operand = Box::new(folder.fold_expression(*val.operand));
+            Expression::Conversion(val)
+        }
+        Expression::Assignment(mut val) => {
+            val.value = Box::new(folder.fold_expression(*val.value));
+            Expression::Assignment(val)
+        }
+        Expression::Binary(mut val) => {
+            val.left = Box::new(folder.fold_expression(*val.left));
+            val.right = Box::new(folder.fold_expression(*val.right));
+            Expression::Binary(val)
+        }
+        Expression::Comma(mut val) => {
+            val.left = Box::new(folder.fold_expression(*val.left));
+            val.right = Box::new(folder.fold_expression(*val.right));
+            Expression::Comma(val)
+        }
+        Expression::Call(mut val) => {
+            val.arguments = val
+                .arguments
+                .into_iter()
+                .map(|argument| folder.fold_expression(argument))
+                .collect();
+            Expression::Call(val)
+        }
+        Expression::Constant(_)
+        | Expression::Variable(_)
+        | Expression::StringLiteral(_)
+        | Expression::VaBuiltin(_) => expression,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::syntax::expressions::Constant;
+    use crate::syntax::Type;
+    use crate::tokenizer;
+
+    fn program(code: &str) -> Program {
+        let tokens = tokenizer::tokenize_with_spans(code);
+        Program::from_declarations(parser::parse_translation_unit(tokens).unwrap())
+    }
+
+    #[derive(Default)]
+    struct Names<'ast> {
+        names: Vec<&'ast str>,
+    }
+    impl<'ast> Visitor<'ast> for Names<'ast> {
+        fn visit_function(&mut self, function: &'ast Function) {
+            self.names.push(&function.name);
+            walk_function(self, function);
+        }
+        fn visit_declaration(&mut self, declaration: &'ast Declaration) {
+            self.names.push(&declaration.name);
+            walk_declaration(self, declaration);
+        }
+        fn visit_expression(&mut self, expression: &'ast Expression) {
+            match expression {
+                Expression::Variable(val) => self.names.push(&val.name),
+                Expression::Call(val) => self.names.push(&val.name),
+                Expression::Assignment(val) => self.names.push(&val.name),
+                _ => {}
+            }
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn test_visitor_reaches_every_node() {
+        let program = program(
+            "int g = 1;
+            int f(int a);
+            int main(void) {
+                int x = f(g);
+                switch (x) {
+                case 1:
+                    { l: x = -x++ + (x, g); }
+                default:
+                    return f(x);
+                }
+            }",
+        );
+        let mut names = Names::default();
+        names.visit_program(&program);
+        assert_eq!(
+            names.names,
+            vec!["g", "main", "x", "f", "g", "x", "x", "x", "x", "g", "f", "x"]
+        );
+    }
+
+    struct Rename;
+    impl VisitorMut for Rename {
+        fn visit_expression_mut(&mut self, expression: &mut Expression) {
+            if let Expression::Variable(val) = expression {
+                val.name = val.name.to_uppercase();
+            }
+            walk_expression_mut(self, expression);
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut_rewrites_in_place() {
+        let mut program = program("int main(void) { int a = 1; return a + f(a, -a); }");
+        Rename.visit_program_mut(&mut program);
+        assert_eq!(
+            format!("{}", program.functions[0].body[1]),
+            format!(
+                "{}",
+                self::program("int main(void) { return A + f(A, -A); }").functions[0].body[0]
+            )
+        );
+    }
+
+    // Replaces every name with the constant 0, which needs Fold since the
+    // node changes kind.
+    struct Zero;
+    impl Fold for Zero {
+        fn fold_expression(&mut self, expression: Expression) -> Expression {
+            match expression {
+                Expression::Variable(_) => Constant::new(Type::Integer(0)),
+                _ => fold_expression(self, expression),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_replaces_nodes() {
+        let program = Zero.fold_program(program(
+            "int main(void) { int a = 2; switch (a) { case 1: return a * (a, 3); } }",
+        ));
+        let expected = self::program(
+            "int main(void) { int a = 2; switch (0) { case 1: return 0 * (0, 3); } }",
+        );
+        assert_eq!(format!("{}", program), format!("{}", expected));
+    }
+}
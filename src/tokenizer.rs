@@ -1,73 +1,354 @@
+use serde::Serialize;
+use std::fmt;
+
 // TODO: Use Number token instead of Integer to support decimals
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token<'a> {
     Keyword(&'a str),
     Identifier(&'a str),
     Integer(&'a str),
+    // Contents between the quotes, with escape sequences left as written.
+    StringLiteral(&'a str),
     OpenBrace,
     CloseBrace,
     OpenBracket,
     CloseBracket,
     Semicolon,
+    Colon,
+    Comma,
     Negation,
     LogicalNegation,
     BitwiseComplement,
+    Addition,
+    Multiplication,
+    Division,
+    Modulo,
+    Assignment,
+    Increment,
+    Decrement,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+    Ellipsis,
+    // Text that starts no C token, left for the parser to report.
+    Invalid(&'a str),
+}
+
+impl<'a> Token<'a> {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Keyword(_) => "Keyword",
+            Self::Identifier(_) => "Identifier",
+            Self::Integer(_) => "Integer",
+            Self::StringLiteral(_) => "StringLiteral",
+            Self::OpenBrace => "OpenBrace",
+            Self::CloseBrace => "CloseBrace",
+            Self::OpenBracket => "OpenBracket",
+            Self::CloseBracket => "CloseBracket",
+            Self::Semicolon => "Semicolon",
+            Self::Colon => "Colon",
+            Self::Comma => "Comma",
+            Self::Negation => "Negation",
+            Self::LogicalNegation => "LogicalNegation",
+            Self::BitwiseComplement => "BitwiseComplement",
+            Self::Addition => "Addition",
+            Self::Multiplication => "Multiplication",
+            Self::Division => "Division",
+            Self::Modulo => "Modulo",
+            Self::Assignment => "Assignment",
+            Self::Increment => "Increment",
+            Self::Decrement => "Decrement",
+            Self::Equal => "Equal",
+            Self::NotEqual => "NotEqual",
+            Self::LessThan => "LessThan",
+            Self::LessEqual => "LessEqual",
+            Self::GreaterThan => "GreaterThan",
+            Self::GreaterEqual => "GreaterEqual",
+            Self::LogicalAnd => "LogicalAnd",
+            Self::LogicalOr => "LogicalOr",
+            Self::Ellipsis => "Ellipsis",
+            Self::Invalid(_) => "Invalid",
+        }
+    }
+    // The token as it is written in source.
+    pub fn lexeme(&self) -> String {
+        let text = match self {
+            Self::Keyword(text)
+            | Self::Identifier(text)
+            | Self::Integer(text)
+            | Self::Invalid(text) => text,
+            Self::StringLiteral(text) => return format!("\"{}\"", text),
+            Self::OpenBrace => "{",
+            Self::CloseBrace => "}",
+            Self::OpenBracket => "(",
+            Self::CloseBracket => ")",
+            Self::Semicolon => ";",
+            Self::Colon => ":",
+            Self::Comma => ",",
+            Self::Negation => "-",
+            Self::LogicalNegation => "!",
+            Self::BitwiseComplement => "~",
+            Self::Addition => "+",
+            Self::Multiplication => "*",
+            Self::Division => "/",
+            Self::Modulo => "%",
+            Self::Assignment => "=",
+            Self::Increment => "++",
+            Self::Decrement => "--",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::LessThan => "<",
+            Self::LessEqual => "<=",
+            Self::GreaterThan => ">",
+            Self::GreaterEqual => ">=",
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+            Self::Ellipsis => "...",
+        };
+        String::from(text)
+    }
 }
 
+// Operators made of several punctuation characters, longest first so they
+// are matched before their prefixes.
+const MULTI_CHAR_OPERATORS: [&str; 9] = ["...", "++", "--", "==", "!=", "<=", ">=", "&&", "||"];
+
+const KEYWORDS: [&str; 14] = [
+    "int",
+    "char",
+    "const",
+    "void",
+    "__builtin_va_list",
+    "return",
+    "switch",
+    "case",
+    "default",
+    "break",
+    "goto",
+    "static",
+    "inline",
+    "__attribute__",
+];
+
 pub struct TokenFactory {}
 impl TokenFactory {
-    fn create(token: &str) -> Token {
+    fn create(token: &str) -> Token<'_> {
         if is_string_number(token) {
-            return Token::Integer(token);
+            Token::Integer(token)
+        } else if MULTI_CHAR_OPERATORS.contains(&token) {
+            match token {
+                "..." => Token::Ellipsis,
+                "++" => Token::Increment,
+                "--" => Token::Decrement,
+                "==" => Token::Equal,
+                "!=" => Token::NotEqual,
+                "<=" => Token::LessEqual,
+                ">=" => Token::GreaterEqual,
+                "&&" => Token::LogicalAnd,
+                _ => Token::LogicalOr,
+            }
         } else if token.len() == 1 {
-            let token_ch = token.chars().nth(0).unwrap();
+            let token_ch = token.chars().next().unwrap();
             match token_ch {
-                '{' => return Token::OpenBrace,
-                '}' => return Token::CloseBrace,
-                '(' => return Token::OpenBracket,
-                ')' => return Token::CloseBracket,
-                ';' => return Token::Semicolon,
-                '~' => return Token::BitwiseComplement,
-                '-' => return Token::Negation,
-                '!' => return Token::LogicalNegation,
-                _ => panic!("Invalid symbol"),
+                '{' => Token::OpenBrace,
+                '}' => Token::CloseBrace,
+                '(' => Token::OpenBracket,
+                ')' => Token::CloseBracket,
+                ';' => Token::Semicolon,
+                ':' => Token::Colon,
+                ',' => Token::Comma,
+                '~' => Token::BitwiseComplement,
+                '-' => Token::Negation,
+                '!' => Token::LogicalNegation,
+                '+' => Token::Addition,
+                '*' => Token::Multiplication,
+                '/' => Token::Division,
+                '%' => Token::Modulo,
+                '=' => Token::Assignment,
+                '<' => Token::LessThan,
+                '>' => Token::GreaterThan,
+                _ if token_ch.is_alphabetic() || token_ch == '_' => Token::Identifier(token),
+                _ => Token::Invalid(token),
             }
         } else {
             // match to keyword
-            match token {
-                "int" => return Token::Keyword(token),
-                "return" => return Token::Keyword(token),
-                _ => {}
+            if KEYWORDS.contains(&token) {
+                return Token::Keyword(token);
             }
             // all other strings are identifiers
-            return Token::Identifier(token);
+            Token::Identifier(token)
         }
     }
 }
 
+// Anything that starts with a digit is a number, which the parser reads in
+// its radix or rejects.
 fn is_string_number(s: &str) -> bool {
-    s.chars().all(char::is_numeric)
+    s.starts_with(|ch: char| ch.is_ascii_digit())
+}
+
+// A 1-based line and column. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Copy, Clone, Default, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// The source text a token or syntax node came from, ending just past its last
+// character, and the file it was written in, by its index in the program's
+// file table, once the preprocessor has told us. Nodes built without source,
+// as in tests, have a zero span.
+#[derive(Debug, PartialEq, Copy, Clone, Default, Serialize)]
+pub struct Span {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<usize>,
+    pub start: Position,
+    pub end: Position,
+}
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            start: self.start,
+            end: other.end,
+        }
+    }
+    // Where the span starts, as `file:line:column` for diagnostics, naming
+    // the file from the table its index points into.
+    pub fn location(&self, files: &[String]) -> String {
+        match self.file.and_then(|file| files.get(file)) {
+            Some(file) => format!("{}:{}", file, self.start),
+            None => self.start.to_string(),
+        }
+    }
+}
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+// Turns byte offsets into positions, walking forward through the source as
+// the offsets grow.
+struct PositionCursor<'a> {
+    code: &'a str,
+    offset: usize,
+    position: Position,
+}
+impl<'a> PositionCursor<'a> {
+    fn new(code: &'a str) -> PositionCursor<'a> {
+        PositionCursor {
+            code,
+            offset: 0,
+            position: Position { line: 1, column: 1 },
+        }
+    }
+    fn advance(&mut self, offset: usize) -> Position {
+        for ch in self.code[self.offset..offset].chars() {
+            if ch == '\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+        self.offset = offset;
+        self.position
+    }
+}
+
+pub fn tokenize(code: &str) -> Vec<Token<'_>> {
+    tokenize_with_spans(code)
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect()
+}
+
+// One token per line as `line:column kind lexeme`, in columns, for --dump-tokens
+// and golden tests of the lexer.
+pub fn dump(tokens: &[(Token, Span)]) -> String {
+    tokens
+        .iter()
+        .map(|(token, span)| {
+            format!(
+                "{:<8}{:<18}{}\n",
+                span.start.to_string(),
+                token.kind(),
+                token.lexeme()
+            )
+        })
+        .collect()
 }
 
 // TODO: cleanup tokenize function
-pub fn tokenize(code: &str) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
+pub fn tokenize_with_spans(code: &str) -> Vec<(Token<'_>, Span)> {
+    // Tokens with the byte range they were read from.
+    let mut tokens: Vec<(Token, usize, usize)> = Vec::new();
 
     let mut found_token: bool = false;
     let mut token_start: usize = 0;
-    for (idx, ch) in code.chars().enumerate() {
+    let mut skip: usize = 0;
+    let mut string_start: Option<usize> = None;
+    let mut escaped: bool = false;
+    for (idx, ch) in code.char_indices() {
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        if let Some(start) = string_start {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                tokens.push((Token::StringLiteral(&code[start + 1..idx]), start, idx + 1));
+                string_start = None;
+            }
+            continue;
+        }
         if ch.is_whitespace() {
             if found_token {
-                tokens.push(TokenFactory::create(&code[token_start..idx]));
+                tokens.push((
+                    TokenFactory::create(&code[token_start..idx]),
+                    token_start,
+                    idx,
+                ));
                 found_token = false;
             }
             continue;
-        } else if ch.is_ascii_punctuation() {
+        } else if ch.is_ascii_punctuation() && ch != '_' {
             if found_token {
-                tokens.push(TokenFactory::create(&code[token_start..idx]));
+                tokens.push((
+                    TokenFactory::create(&code[token_start..idx]),
+                    token_start,
+                    idx,
+                ));
                 found_token = false;
             }
-            tokens.push(TokenFactory::create(&code[idx..idx + 1]));
+            if ch == '"' {
+                string_start = Some(idx);
+                continue;
+            }
+            let operator = MULTI_CHAR_OPERATORS
+                .iter()
+                .find(|operator| code[idx..].starts_with(*operator));
+            match operator {
+                Some(operator) => {
+                    tokens.push((TokenFactory::create(operator), idx, idx + operator.len()));
+                    skip = operator.len() - 1;
+                }
+                None => tokens.push((TokenFactory::create(&code[idx..idx + 1]), idx, idx + 1)),
+            }
             continue;
         }
 
@@ -76,7 +357,25 @@ pub fn tokenize(code: &str) -> Vec<Token> {
             found_token = true;
         }
     }
-    return tokens;
+    if found_token {
+        tokens.push((
+            TokenFactory::create(&code[token_start..]),
+            token_start,
+            code.len(),
+        ));
+    }
+    let mut cursor = PositionCursor::new(code);
+    tokens
+        .into_iter()
+        .map(|(token, start, end)| {
+            let span = Span {
+                file: None,
+                start: cursor.advance(start),
+                end: cursor.advance(end),
+            };
+            (token, span)
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -86,7 +385,7 @@ mod tests {
     #[test]
     fn test_token_factory_builds_punctuation() {
         let tokens = ["{", "}", "(", ")", ";"];
-        let enums = vec![
+        let enums = [
             Token::OpenBrace,
             Token::CloseBrace,
             Token::OpenBracket,
@@ -101,7 +400,7 @@ mod tests {
     #[test]
     fn test_token_factory_builds_unary_operations() {
         let tokens = ["-", "~", "!"];
-        let enums = vec![
+        let enums = [
             Token::Negation,
             Token::BitwiseComplement,
             Token::LogicalNegation,
@@ -114,7 +413,7 @@ mod tests {
     #[test]
     fn test_token_factory_builds_integer_constants() {
         let tokens = ["2", "23"];
-        let enums = vec![Token::Integer("2"), Token::Integer("23")];
+        let enums = [Token::Integer("2"), Token::Integer("23")];
         for (idx, token_str) in tokens.iter().enumerate() {
             assert_eq!(&enums[idx], &TokenFactory::create(token_str));
         }
@@ -122,7 +421,7 @@ mod tests {
     #[test]
     fn test_token_factory_builds_identifiers() {
         let identifier_tokens = ["main", "some_name"];
-        let identifier_enums = vec![Token::Identifier("main"), Token::Identifier("some_name")];
+        let identifier_enums = [Token::Identifier("main"), Token::Identifier("some_name")];
         for (idx, token_str) in identifier_tokens.iter().enumerate() {
             assert_eq!(&identifier_enums[idx], &TokenFactory::create(token_str));
         }
@@ -130,7 +429,7 @@ mod tests {
     #[test]
     fn test_token_factory_builds_keywords() {
         let keyword_tokens = ["int", "return"];
-        let keyword_enums = vec![Token::Keyword("int"), Token::Keyword("return")];
+        let keyword_enums = [Token::Keyword("int"), Token::Keyword("return")];
         for (idx, token_str) in keyword_tokens.iter().enumerate() {
             assert_eq!(&keyword_enums[idx], &TokenFactory::create(token_str));
         }
@@ -185,4 +484,139 @@ mod tests {
             assert_eq!(&correct_tokens[idx], token);
         }
     }
+
+    #[test]
+    fn test_token_factory_builds_binary_operations() {
+        let tokens = ["+", "*", "/", "%", "=", ",", "<", ">"];
+        let enums = [
+            Token::Addition,
+            Token::Multiplication,
+            Token::Division,
+            Token::Modulo,
+            Token::Assignment,
+            Token::Comma,
+            Token::LessThan,
+            Token::GreaterThan,
+        ];
+        for (idx, token_str) in tokens.iter().enumerate() {
+            assert_eq!(&enums[idx], &TokenFactory::create(token_str));
+        }
+    }
+
+    #[test]
+    fn test_tokenize_two_char_operators() {
+        let code = "x++ + --y==z!=a<=b>=c&&d||e";
+        let correct_tokens = vec![
+            Token::Identifier("x"),
+            Token::Increment,
+            Token::Addition,
+            Token::Decrement,
+            Token::Identifier("y"),
+            Token::Equal,
+            Token::Identifier("z"),
+            Token::NotEqual,
+            Token::Identifier("a"),
+            Token::LessEqual,
+            Token::Identifier("b"),
+            Token::GreaterEqual,
+            Token::Identifier("c"),
+            Token::LogicalAnd,
+            Token::Identifier("d"),
+            Token::LogicalOr,
+            Token::Identifier("e"),
+        ];
+        assert_eq!(correct_tokens, tokenize(code));
+    }
+
+    #[test]
+    fn test_tokenize_identifiers_with_underscores() {
+        let code = "some_name=_x;";
+        let correct_tokens = vec![
+            Token::Identifier("some_name"),
+            Token::Assignment,
+            Token::Identifier("_x"),
+            Token::Semicolon,
+        ];
+        assert_eq!(correct_tokens, tokenize(code));
+    }
+
+    #[test]
+    fn test_tokenize_string_literals_and_ellipsis() {
+        let code = r#"int printf(const char *, ...); printf("a \"b\"\n", x);"#;
+        let correct_tokens = vec![
+            Token::Keyword("int"),
+            Token::Identifier("printf"),
+            Token::OpenBracket,
+            Token::Keyword("const"),
+            Token::Keyword("char"),
+            Token::Multiplication,
+            Token::Comma,
+            Token::Ellipsis,
+            Token::CloseBracket,
+            Token::Semicolon,
+            Token::Identifier("printf"),
+            Token::OpenBracket,
+            Token::StringLiteral(r#"a \"b\"\n"#),
+            Token::Comma,
+            Token::Identifier("x"),
+            Token::CloseBracket,
+            Token::Semicolon,
+        ];
+        assert_eq!(correct_tokens, tokenize(code));
+    }
+
+    #[test]
+    fn test_tokenize_invalid_text() {
+        let code = "a @ 'b";
+        let correct_tokens = vec![
+            Token::Identifier("a"),
+            Token::Invalid("@"),
+            Token::Invalid("'"),
+            Token::Identifier("b"),
+        ];
+        assert_eq!(correct_tokens, tokenize(code));
+    }
+
+    #[test]
+    fn test_tokenize_with_spans() {
+        let code = "int x;\n  f(\"a b\") >= 10;";
+        let position = |line, column| Position { line, column };
+        let spans: Vec<(Token, Position, Position)> = tokenize_with_spans(code)
+            .into_iter()
+            .map(|(token, span)| (token, span.start, span.end))
+            .collect();
+        let expected = vec![
+            (Token::Keyword("int"), position(1, 1), position(1, 4)),
+            (Token::Identifier("x"), position(1, 5), position(1, 6)),
+            (Token::Semicolon, position(1, 6), position(1, 7)),
+            (Token::Identifier("f"), position(2, 3), position(2, 4)),
+            (Token::OpenBracket, position(2, 4), position(2, 5)),
+            (Token::StringLiteral("a b"), position(2, 5), position(2, 10)),
+            (Token::CloseBracket, position(2, 10), position(2, 11)),
+            (Token::GreaterEqual, position(2, 12), position(2, 14)),
+            (Token::Integer("10"), position(2, 15), position(2, 17)),
+            (Token::Semicolon, position(2, 17), position(2, 18)),
+        ];
+        assert_eq!(expected, spans);
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let code = "int main(void) {\n  return x >= \"s\";\n}";
+        let expected = "\
+1:1     Keyword           int
+1:5     Identifier        main
+1:9     OpenBracket       (
+1:10    Keyword           void
+1:14    CloseBracket      )
+1:16    OpenBrace         {
+2:3     Keyword           return
+2:10    Identifier        x
+2:12    GreaterEqual      >=
+2:15    StringLiteral     \"s\"
+2:18    Semicolon         ;
+3:1     CloseBrace        }
+";
+        assert_eq!(expected, dump(&tokenize_with_spans(code)));
+    }
 }
@@ -0,0 +1,3 @@
+int main() {
+    RETURN 0;
+}
@@ -0,0 +1,10 @@
+int
+main
+(
+)
+{
+return
+0
+;
+}
+// exit: 0
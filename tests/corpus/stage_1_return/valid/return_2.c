@@ -0,0 +1,4 @@
+int main() {
+    return 2;
+}
+// exit: 2
@@ -0,0 +1,4 @@
+int main() {
+    return !5;
+}
+// exit: 0
@@ -0,0 +1,4 @@
+int main() {
+    return 1 + 2;
+}
+// exit: 3
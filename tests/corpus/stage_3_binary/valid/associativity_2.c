@@ -0,0 +1,4 @@
+int main() {
+    return 6 / 3 / 2;
+}
+// exit: 1
@@ -0,0 +1,4 @@
+int main() {
+    return 4 / 2;
+}
+// exit: 2
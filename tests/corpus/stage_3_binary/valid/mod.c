@@ -0,0 +1,4 @@
+int main() {
+    return 17 % 5;
+}
+// exit: 2
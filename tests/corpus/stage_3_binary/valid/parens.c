@@ -0,0 +1,4 @@
+int main() {
+    return 2 * (3 + 4);
+}
+// exit: 14
@@ -0,0 +1,4 @@
+int main() {
+    return ~2 + 3;
+}
+// exit: 0
@@ -0,0 +1,4 @@
+int main() {
+    return 0 <= 2;
+}
+// exit: 1
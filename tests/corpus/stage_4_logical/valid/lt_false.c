@@ -0,0 +1,4 @@
+int main() {
+    return 2 < 1;
+}
+// exit: 0
@@ -0,0 +1,4 @@
+int main() {
+    return (1 || 0) && 0;
+}
+// exit: 0
@@ -0,0 +1,4 @@
+int main() {
+    return 2 == 2 > 0;
+}
+// exit: 0
@@ -0,0 +1,6 @@
+int main() {
+    int a = 0;
+    0 && (a = 5);
+    return a;
+}
+// exit: 0
@@ -0,0 +1,4 @@
+int main() {
+    ints a = 1;
+    return a;
+}
@@ -0,0 +1,4 @@
+int main() {
+    int foo bar = 3;
+    return bar;
+}
@@ -0,0 +1,5 @@
+int main() {
+    int a = 2;
+    a + 3 = 4;
+    return a;
+}
@@ -0,0 +1,5 @@
+int main() {
+    a = 1 + 2;
+    int a;
+    return a;
+}
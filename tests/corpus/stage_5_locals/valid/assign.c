@@ -0,0 +1,6 @@
+int main() {
+    int a;
+    a = 2;
+    return a;
+}
+// exit: 2
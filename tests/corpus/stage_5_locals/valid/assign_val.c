@@ -0,0 +1,6 @@
+int main() {
+    int a;
+    int b = a = 0;
+    return b;
+}
+// exit: 0
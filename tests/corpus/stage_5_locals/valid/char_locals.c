@@ -0,0 +1,6 @@
+int main() {
+    char c = 100;
+    char d = c + c;
+    return d + 100;
+}
+// exit: 44
@@ -0,0 +1,7 @@
+int main() {
+    int a;
+    int b;
+    a = b = 4;
+    return a - b;
+}
+// exit: 0
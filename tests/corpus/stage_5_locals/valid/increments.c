@@ -0,0 +1,7 @@
+int main() {
+    int a = 1;
+    int b = a++;
+    int c = a--;
+    return a * 100 + b * 10 + c;
+}
+// exit: 112
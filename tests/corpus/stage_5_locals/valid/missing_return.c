@@ -0,0 +1,4 @@
+int main() {
+
+}
+// exit: 0
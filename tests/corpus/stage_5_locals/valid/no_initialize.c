@@ -0,0 +1,5 @@
+int main() {
+    int a;
+    return 0;
+}
+// exit: 0
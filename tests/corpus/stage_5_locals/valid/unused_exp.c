@@ -0,0 +1,5 @@
+int main() {
+    2 + 2;
+    return 0;
+}
+// exit: 0
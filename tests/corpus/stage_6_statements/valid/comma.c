@@ -0,0 +1,6 @@
+int main() {
+    int a = 1;
+    int b = (a = a + 1, a * 3);
+    return b;
+}
+// exit: 6
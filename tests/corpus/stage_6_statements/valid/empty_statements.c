@@ -0,0 +1,6 @@
+int main() {
+    ;;
+    {}
+    return 4;
+}
+// exit: 4
@@ -0,0 +1,12 @@
+int main() {
+    int i = 0;
+    int total = 0;
+loop:
+    total = total + i;
+    i++;
+    switch (i < 10) {
+    case 1: goto loop;
+    }
+    return total;
+}
+// exit: 45
@@ -0,0 +1,7 @@
+int main() {
+    switch (7) {
+    case 1: return 1;
+    default: return 9;
+    }
+}
+// exit: 9
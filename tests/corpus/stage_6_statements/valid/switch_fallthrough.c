@@ -0,0 +1,17 @@
+int main() {
+    int a = 2;
+    int b = 0;
+    switch (a) {
+    case 1:
+        b = b + 1;
+    case 2:
+        b = b + 10;
+    case 3:
+        b = b + 100;
+        break;
+    default:
+        b = 0;
+    }
+    return b;
+}
+// exit: 110
@@ -0,0 +1,12 @@
+int main() {
+    int a = 5;
+    switch (a) {
+    case 1: return 10;
+    case 2: return 20;
+    case 3: return 30;
+    case 4: return 40;
+    case 5: return 50;
+    default: return 0;
+    }
+}
+// exit: 50
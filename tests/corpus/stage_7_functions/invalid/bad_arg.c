@@ -0,0 +1,7 @@
+int foo(int a) {
+    return a;
+}
+
+int main() {
+    return foo();
+}
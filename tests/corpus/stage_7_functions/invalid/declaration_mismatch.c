@@ -0,0 +1,9 @@
+int foo(int a);
+
+int foo(int a, int b) {
+    return a + b;
+}
+
+int main() {
+    return foo(1, 2);
+}
@@ -0,0 +1,11 @@
+int foo() {
+    return 1;
+}
+
+int foo() {
+    return 2;
+}
+
+int main() {
+    return foo();
+}
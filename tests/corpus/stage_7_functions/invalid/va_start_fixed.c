@@ -0,0 +1,9 @@
+int foo(int a) {
+    __builtin_va_list ap;
+    __builtin_va_start(ap, a);
+    return a;
+}
+
+int main() {
+    return foo(1);
+}
@@ -0,0 +1,8 @@
+int add(char a, char b) {
+    return a + b;
+}
+
+int main() {
+    return add(100, 27);
+}
+// exit: 127
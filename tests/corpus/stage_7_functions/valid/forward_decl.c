@@ -0,0 +1,10 @@
+int foo(void);
+
+int main() {
+    return foo();
+}
+
+int foo(void) {
+    return 3;
+}
+// exit: 3
@@ -0,0 +1,12 @@
+int putchar(int c);
+
+int main() {
+    putchar(72);
+    putchar(101);
+    putchar(108);
+    putchar(108);
+    putchar(111);
+    putchar(10);
+    return 0;
+}
+// exit: 0
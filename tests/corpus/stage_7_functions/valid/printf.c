@@ -0,0 +1,7 @@
+int printf(const char *format, ...);
+
+int main() {
+    printf("%d + %d = %d\n", 2, 3, 2 + 3);
+    return 0;
+}
+// exit: 0
@@ -0,0 +1,13 @@
+int total(int count, ...) {
+    __builtin_va_list ap;
+    __builtin_va_start(ap, count);
+    int first = __builtin_va_arg(ap, int);
+    int second = __builtin_va_arg(ap, int);
+    __builtin_va_end(ap);
+    return count + first * second;
+}
+
+int main() {
+    return total(2, 5, 7);
+}
+// exit: 37
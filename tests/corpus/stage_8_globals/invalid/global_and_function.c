@@ -0,0 +1,9 @@
+int foo = 1;
+
+int foo() {
+    return 2;
+}
+
+int main() {
+    return 0;
+}
@@ -0,0 +1,6 @@
+int a = 1;
+int a = 2;
+
+int main() {
+    return a;
+}
@@ -0,0 +1,14 @@
+const int limit = 3;
+
+int count(const char *s, int const n) {
+    const int step = 1;
+    return n + step;
+}
+
+int main() {
+    char *const greeting = "hi";
+    const char *s = greeting;
+    s = 0;
+    return count(s, limit);
+}
+// exit: 4
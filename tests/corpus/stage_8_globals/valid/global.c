@@ -0,0 +1,12 @@
+int counter = 3;
+
+int bump(void) {
+    counter = counter + 1;
+    return counter;
+}
+
+int main() {
+    bump();
+    return bump();
+}
+// exit: 5
@@ -0,0 +1,20 @@
+int puts(const char *s);
+
+const char *greeting = "hi";
+char *const nothing = 0;
+static const char *farewell = "bye";
+
+int show(const char *s) {
+    static const char *prefix = "> ";
+    puts(prefix);
+    return puts(s);
+}
+
+int main() {
+    const char *s = nothing;
+    show(greeting);
+    show(farewell);
+    s = greeting;
+    return 7;
+}
+// exit: 7
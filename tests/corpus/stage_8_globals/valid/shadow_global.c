@@ -0,0 +1,7 @@
+int a = 5;
+
+int main() {
+    int a = 2;
+    return a;
+}
+// exit: 2
@@ -0,0 +1,8 @@
+static int helper(int a) {
+    return a * 3;
+}
+
+int main() {
+    return helper(5);
+}
+// exit: 15
@@ -0,0 +1,12 @@
+int next(void) {
+    static int value;
+    value++;
+    return value;
+}
+
+int main() {
+    next();
+    next();
+    return next();
+}
+// exit: 3
@@ -0,0 +1,4 @@
+#error this should fail
+int main() {
+    return 0;
+}
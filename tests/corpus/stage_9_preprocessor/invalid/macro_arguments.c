@@ -0,0 +1,4 @@
+#define F(a, b) a + b
+int main() {
+    return F(1);
+}
@@ -0,0 +1,4 @@
+#include "no_such_header.h"
+int main() {
+    return 0;
+}
@@ -0,0 +1,10 @@
+#define LEVEL 2
+
+int main() {
+#if LEVEL > 1
+    return 1;
+#else
+    return 2;
+#endif
+}
+// exit: 1
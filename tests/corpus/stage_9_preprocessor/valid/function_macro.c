@@ -0,0 +1,6 @@
+#define SQUARE(x) ((x) * (x))
+
+int main() {
+    return SQUARE(1 + 2);
+}
+// exit: 9
@@ -0,0 +1,4 @@
+int main() {
+    return __LINE__;
+}
+// exit: 2
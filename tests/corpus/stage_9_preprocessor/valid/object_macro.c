@@ -0,0 +1,6 @@
+#define ANSWER 42
+
+int main() {
+    return ANSWER;
+}
+// exit: 42
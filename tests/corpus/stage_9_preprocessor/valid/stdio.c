@@ -0,0 +1,7 @@
+#include <stdio.h>
+
+int main() {
+    puts("from stdio");
+    return 0;
+}
+// exit: 0
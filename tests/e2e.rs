@@ -0,0 +1,494 @@
+// End-to-end checks that run the compiler binary over c_samples and the
+// staged corpus under tests/corpus. Valid programs are built and run, and
+// their exit code and output compared against an `// exit: N` annotation and,
+// when one is installed, the host C compiler. Invalid programs must be
+// rejected with a diagnostic rather than a crash.
+//
+// Programs under tests/golden are dumped with the flag each sibling
+// expectation file names, and must match it exactly. UPDATE_GOLDEN=1 rewrites
+// the expectations instead.
+//
+// Every program the compiler accepts is also printed back with --unparse,
+// which must parse to the same tree and print the same again.
+//
+// Valid programs, and those under tests/optimize, must also behave the same
+// at every optimization level as at -O0.
+
+use rust_cc::syntax::dump;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+const RUST_CC: &str = env!("CARGO_BIN_EXE_rust_cc");
+
+struct Scratch(PathBuf);
+impl Scratch {
+    fn new(name: &str) -> Scratch {
+        let path = env::temp_dir().join(format!("rust_cc_e2e_{}_{}", process::id(), name));
+        fs::create_dir_all(&path).unwrap();
+        Scratch(path)
+    }
+}
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Run {
+    exit_code: Option<i32>,
+    stdout: String,
+}
+
+fn sources(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {}", dir.display(), err))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn corpus_stages() -> Vec<PathBuf> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut stages: Vec<PathBuf> = fs::read_dir(corpus)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    stages.sort();
+    stages
+}
+
+fn expected_exit_code(source: &Path) -> Option<i32> {
+    fs::read_to_string(source)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("// exit:"))
+        .map(|code| code.trim().parse().expect("malformed exit annotation"))
+}
+
+fn host_cc_available() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn build(compiler: &str, flags: &[&str], source: &Path, executable: &Path) -> Output {
+    Command::new(compiler)
+        .args(flags)
+        .arg("-o")
+        .arg(executable)
+        .arg(source)
+        .output()
+        .unwrap_or_else(|err| panic!("cannot run {}: {}", compiler, err))
+}
+
+fn execute(executable: &Path) -> Run {
+    let output = Command::new(executable).output().unwrap();
+    Run {
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+    }
+}
+
+// Problems with a rejected program, or None if it was diagnosed properly.
+fn check_rejected(source: &Path, output: &Output) -> Option<String> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("panicked") {
+        Some(format!(
+            "{}: compiler panicked\n{}",
+            source.display(),
+            stderr
+        ))
+    } else if output.status.code() != Some(1) || !stderr.contains("error:") {
+        Some(format!(
+            "{}: expected a diagnostic and exit code 1, got {:?}\n{}",
+            source.display(),
+            output.status.code(),
+            stderr
+        ))
+    } else {
+        None
+    }
+}
+
+// Builds and runs a program the host accepts, comparing it with the
+// annotation and the host build. Returns a description of any mismatch. Our
+// build also checks the IR after every pass.
+fn check_valid(source: &Path, flags: &[&str], scratch: &Path, host_cc: bool) -> Option<String> {
+    let executable = scratch.join("ours");
+    let mut our_flags = vec!["--verify-ir"];
+    our_flags.extend(flags);
+    let output = build(RUST_CC, &our_flags, source, &executable);
+    if !output.status.success() {
+        return Some(format!(
+            "{}: rejected a valid program\n{}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let ours = execute(&executable);
+    if let Some(code) = expected_exit_code(source) {
+        if ours.exit_code != Some(code) {
+            return Some(format!(
+                "{}: expected exit code {}, got {:?}",
+                source.display(),
+                code,
+                ours.exit_code
+            ));
+        }
+    }
+    if host_cc {
+        let reference = scratch.join("reference");
+        let mut host_flags = vec!["-w"];
+        host_flags.extend(flags);
+        if !build("cc", &host_flags, source, &reference)
+            .status
+            .success()
+        {
+            return Some(format!("{}: host cc rejected it", source.display()));
+        }
+        let expected = execute(&reference);
+        if ours != expected {
+            return Some(format!(
+                "{}: expected {:?}, got {:?}",
+                source.display(),
+                expected,
+                ours
+            ));
+        }
+    }
+    None
+}
+
+fn report(failures: Vec<String>) {
+    assert!(
+        failures.is_empty(),
+        "{} failure(s):\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn test_c_samples() {
+    let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+    let include = samples.join("include");
+    let flags = ["-I", include.to_str().unwrap()];
+    let scratch = Scratch::new("samples");
+    if !host_cc_available() {
+        eprintln!("skipping c_samples: no host cc to compare with");
+        return;
+    }
+    let mut failures = vec![];
+    for source in sources(&samples) {
+        let reference = scratch.0.join("reference");
+        let mut host_flags = vec!["-w"];
+        host_flags.extend(&flags);
+        let accepted = build("cc", &host_flags, &source, &reference)
+            .status
+            .success();
+        let failure = if accepted {
+            check_valid(&source, &flags, &scratch.0, true)
+        } else {
+            let output = build(RUST_CC, &flags, &source, &scratch.0.join("ours"));
+            check_rejected(&source, &output)
+        };
+        failures.extend(failure);
+    }
+    report(failures);
+}
+
+#[test]
+fn test_valid_corpus() {
+    let scratch = Scratch::new("valid");
+    let host_cc = host_cc_available();
+    let mut failures = vec![];
+    for stage in corpus_stages() {
+        for source in sources(&stage.join("valid")) {
+            failures.extend(check_valid(&source, &[], &scratch.0, host_cc));
+        }
+    }
+    report(failures);
+}
+
+#[test]
+fn test_invalid_corpus() {
+    let scratch = Scratch::new("invalid");
+    let mut failures = vec![];
+    for stage in corpus_stages() {
+        for source in sources(&stage.join("invalid")) {
+            let output = build(RUST_CC, &[], &source, &scratch.0.join("ours"));
+            failures.extend(check_rejected(&source, &output));
+        }
+    }
+    report(failures);
+}
+
+// Levels whose programs must behave as they do at -O0.
+const OPTIMIZATION_LEVELS: [&str; 2] = ["-O1", "-O2"];
+
+// Builds a program at -O0 and at each optimization level and compares the
+// runs. Returns a description of each mismatch.
+fn check_optimized(source: &Path, flags: &[&str], scratch: &Path) -> Vec<String> {
+    let mut failures = vec![];
+    let mut runs = vec![];
+    for level in ["-O0"].iter().chain(OPTIMIZATION_LEVELS.iter()) {
+        let executable = scratch.join("ours");
+        let mut our_flags = vec!["--verify-ir", level];
+        our_flags.extend(flags);
+        let output = build(RUST_CC, &our_flags, source, &executable);
+        if !output.status.success() {
+            failures.push(format!(
+                "{} at {}: rejected a valid program\n{}",
+                source.display(),
+                level,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+            return failures;
+        }
+        runs.push((level, execute(&executable)));
+    }
+    let (_, unoptimized) = &runs[0];
+    if let Some(code) = expected_exit_code(source) {
+        if unoptimized.exit_code != Some(code) {
+            failures.push(format!(
+                "{}: expected exit code {}, got {:?}",
+                source.display(),
+                code,
+                unoptimized.exit_code
+            ));
+        }
+    }
+    for (level, run) in runs.iter().skip(1) {
+        if run != unoptimized {
+            failures.push(format!(
+                "{} at {}: expected {:?} as at -O0, got {:?}",
+                source.display(),
+                level,
+                unoptimized,
+                run
+            ));
+        }
+    }
+    failures
+}
+
+// The valid corpus and the programs in tests/optimize, which exercise the
+// optimizer's edge cases, including run-time traps it must keep.
+#[test]
+fn test_optimization_levels() {
+    let scratch = Scratch::new("optimize");
+    let mut programs: Vec<PathBuf> = corpus_stages()
+        .iter()
+        .flat_map(|stage| sources(&stage.join("valid")))
+        .collect();
+    programs.extend(sources(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/optimize"),
+    ));
+    let mut failures = vec![];
+    for source in programs {
+        failures.extend(check_optimized(&source, &[], &scratch.0));
+    }
+    report(failures);
+}
+
+// An inline function defined in a header is compiled into every file that
+// includes it, and the copies must still link together.
+#[test]
+fn test_inline_definition_in_two_files() {
+    let scratch = Scratch::new("inline");
+    let dir = &scratch.0;
+    fs::write(
+        dir.join("square.h"),
+        "inline int square(int x) {\n    return x * x;\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("main.c"),
+        "#include \"square.h\"\nint cube(int x);\nint main() {\n    return square(2) + cube(2);\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("cube.c"),
+        "#include \"square.h\"\nint cube(int x) {\n    return square(x) * x;\n}\n",
+    )
+    .unwrap();
+    let executable = dir.join("inline");
+    let mut failures = vec![];
+    for level in ["-O0", "-O2"] {
+        let output = Command::new(RUST_CC)
+            .arg(level)
+            .arg("-o")
+            .arg(&executable)
+            .arg(dir.join("main.c"))
+            .arg(dir.join("cube.c"))
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            failures.push(format!(
+                "{}: failed to build\n{}",
+                level,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        } else if execute(&executable).exit_code != Some(12) {
+            failures.push(format!("{}: wrong exit code", level));
+        }
+    }
+    report(failures);
+}
+
+// Expectation file extensions and the flag whose output they hold.
+const GOLDEN_DUMPS: [(&str, &str); 4] = [
+    ("tokens", "--dump-tokens"),
+    ("tree", "--dump-ast=tree"),
+    ("ir", "--emit=ir"),
+    ("ssa", "--emit=ssa"),
+];
+
+#[test]
+fn test_error_prefix() {
+    let scratch = Scratch::new("errors");
+    let source = scratch.0.join("bad.c");
+    fs::write(&source, "int main() {\n    return x;\n}\n").unwrap();
+    let mut failures = vec![];
+    for flags in [&["-O3"][..], &[]] {
+        let output = Command::new(RUST_CC)
+            .args(flags)
+            .arg("-S")
+            .arg(&source)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.code() != Some(1) || !stderr.starts_with("rust_cc: error: ") {
+            failures.push(format!("{:?}: unexpected diagnostic\n{}", flags, stderr));
+        }
+    }
+    report(failures);
+}
+
+#[test]
+fn test_golden_dumps() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var("UPDATE_GOLDEN").is_ok_and(|value| value == "1");
+    let mut failures = vec![];
+    for source in sources(&golden) {
+        for (extension, flag) in GOLDEN_DUMPS.iter() {
+            let expected_path = source.with_extension(extension);
+            if !expected_path.exists() {
+                continue;
+            }
+            let output = Command::new(RUST_CC)
+                .arg(flag)
+                .arg(source.file_name().unwrap())
+                .current_dir(&golden)
+                .output()
+                .unwrap();
+            if !output.status.success() {
+                failures.push(format!(
+                    "{} {}: {}",
+                    flag,
+                    source.display(),
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+                continue;
+            }
+            let actual = String::from_utf8_lossy(&output.stdout);
+            if update {
+                fs::write(&expected_path, actual.as_bytes()).unwrap();
+            } else if actual != fs::read_to_string(&expected_path).unwrap() {
+                failures.push(format!(
+                    "{} differs from {}:\n{}",
+                    flag,
+                    expected_path.display(),
+                    actual
+                ));
+            }
+        }
+    }
+    report(failures);
+}
+
+fn run(flags: &[&str], source: &Path) -> Result<String, String> {
+    let output = Command::new(RUST_CC)
+        .args(flags)
+        .arg(source)
+        .output()
+        .unwrap();
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+fn structure(flags: &[&str], source: &Path) -> serde_json::Value {
+    let mut flags = flags.to_vec();
+    flags.push("--dump-ast=json");
+    let mut value: serde_json::Value = serde_json::from_str(&run(&flags, source).unwrap()).unwrap();
+    dump::strip_locations(&mut value);
+    value
+}
+
+// Problems printing a program back as C, or None if it round-trips.
+fn check_unparse(source: &Path, flags: &[&str], scratch: &Path) -> Option<String> {
+    let mut unparse_flags = flags.to_vec();
+    unparse_flags.push("--unparse");
+    let printed = match run(&unparse_flags, source) {
+        Ok(printed) => printed,
+        // Rejected programs are covered by the other tests.
+        Err(_) => return None,
+    };
+    let printed_path = scratch.join("printed.c");
+    fs::write(&printed_path, &printed).unwrap();
+    match run(&["--unparse"], &printed_path) {
+        Ok(reprinted) if reprinted == printed => {}
+        Ok(reprinted) => {
+            return Some(format!(
+                "{}: printing is not stable:\n{}\nthen\n{}",
+                source.display(),
+                printed,
+                reprinted
+            ))
+        }
+        Err(err) => {
+            return Some(format!(
+                "{}: printed program was rejected: {}\n{}",
+                source.display(),
+                err,
+                printed
+            ))
+        }
+    }
+    if structure(flags, source) != structure(&[], &printed_path) {
+        return Some(format!(
+            "{}: printed program parses differently:\n{}",
+            source.display(),
+            printed
+        ));
+    }
+    None
+}
+
+#[test]
+fn test_unparse_round_trips() {
+    let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("c_samples");
+    let include = samples.join("include");
+    let sample_flags = ["-I", include.to_str().unwrap()];
+    let scratch = Scratch::new("unparse");
+    let mut failures = vec![];
+    for source in sources(&samples) {
+        failures.extend(check_unparse(&source, &sample_flags, &scratch.0));
+    }
+    for stage in corpus_stages() {
+        for source in sources(&stage.join("valid")) {
+            failures.extend(check_unparse(&source, &[], &scratch.0));
+        }
+    }
+    report(failures);
+}
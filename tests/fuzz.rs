@@ -0,0 +1,551 @@
+// Property tests that throw random input at the front end and random,
+// well-defined programs at the whole compiler. Generated programs stay inside
+// the supported subset and avoid undefined behaviour the way Csmith does,
+// routing arithmetic through helpers that refuse to overflow or divide by
+// zero, so any difference from the host C compiler, at -O0, -O1 or -O2, is
+// a bug in ours.
+// Whatever the parser accepts must also survive printing back as C.
+//
+// PROPTEST_CASES raises the number of programs tried from the default.
+
+use proptest::prelude::*;
+use rust_cc::syntax::{dump, unparse};
+use rust_cc::{parser, sema, tokenizer};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const RUST_CC: &str = env!("CARGO_BIN_EXE_rust_cc");
+
+const SAFE_MATH: &str = "\
+int printf(const char *format, ...);
+
+int safe_add(int a, int b) {
+    switch ((b > 0 && a > 2147483647 - b) || (b < 0 && a < -2147483647 - 1 - b)) {
+    case 1:
+        return a;
+    }
+    return a + b;
+}
+
+int safe_sub(int a, int b) {
+    switch ((b < 0 && a > 2147483647 + b) || (b > 0 && a < -2147483647 - 1 + b)) {
+    case 1:
+        return a;
+    }
+    return a - b;
+}
+
+int safe_mul(int a, int b) {
+    switch ((a > 0 && b > 0 && a > 2147483647 / b) || (a > 0 && b <= 0 && b < (-2147483647 - 1) / a)
+            || (a <= 0 && b > 0 && a < (-2147483647 - 1) / b)
+            || (a < 0 && b <= 0 && b < 2147483647 / a)) {
+    case 1:
+        return a;
+    }
+    return a * b;
+}
+
+int safe_div(int a, int b) {
+    switch (b == 0 || (a == -2147483647 - 1 && b == -1)) {
+    case 1:
+        return a;
+    }
+    return a / b;
+}
+
+int safe_mod(int a, int b) {
+    switch (b == 0 || (a == -2147483647 - 1 && b == -1)) {
+    case 1:
+        return a;
+    }
+    return a % b;
+}
+
+int safe_neg(int a) {
+    switch (a == -2147483647 - 1) {
+    case 1:
+        return a;
+    }
+    return -a;
+}
+";
+
+const UNARY_OPERATORS: [&str; 3] = ["-", "~", "!"];
+
+const BINARY_OPERATORS: [&str; 13] = [
+    "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||",
+];
+
+// Fragments for building token soup that gets further into the parser than
+// random bytes do.
+const FRAGMENTS: [&str; 40] = [
+    "int",
+    "char",
+    "const",
+    "void",
+    "static",
+    "return",
+    "switch",
+    "case",
+    "default",
+    "break",
+    "goto",
+    "__builtin_va_list",
+    "__builtin_va_start",
+    "__builtin_va_arg",
+    "__builtin_va_end",
+    "main",
+    "x",
+    "f",
+    "0",
+    "1",
+    "2147483647",
+    "2147483648",
+    "\"s\\n\"",
+    "(",
+    ")",
+    "{",
+    "}",
+    ";",
+    ":",
+    ",",
+    "...",
+    "*",
+    "+",
+    "-",
+    "/",
+    "%",
+    "=",
+    "++",
+    "==",
+    "&&",
+];
+
+// Variables and functions are picked by index, modulo what is in scope when
+// the program is printed, so every generated tree prints as a valid program.
+#[derive(Debug, Clone)]
+enum Expr {
+    Constant(i32),
+    Variable(u8),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(u8, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Assign(u8, Expr),
+    Print(Expr),
+    Block(Vec<Stmt>),
+    Switch(Expr, Vec<(i8, Vec<Stmt>, bool)>, Option<Vec<Stmt>>),
+    // A forward goto over the inner statements.
+    Skip(Vec<Stmt>),
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    // Whether each local is a char, and its initializer.
+    locals: Vec<(bool, Expr)>,
+    body: Vec<Stmt>,
+    result: Expr,
+}
+
+#[derive(Debug, Clone)]
+struct Program {
+    globals: Vec<i32>,
+    functions: Vec<Function>,
+    main: Function,
+}
+
+fn constant() -> impl Strategy<Value = i32> {
+    prop_oneof![
+        -10..10,
+        Just(i32::MAX),
+        Just(i32::MIN),
+        Just(-1),
+        any::<i32>(),
+    ]
+}
+
+fn expression() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![
+        constant().prop_map(Expr::Constant),
+        any::<u8>().prop_map(Expr::Variable),
+    ];
+    leaf.prop_recursive(4, 24, 2, |inner| {
+        prop_oneof![
+            (prop::sample::select(&UNARY_OPERATORS[..]), inner.clone())
+                .prop_map(|(operator, operand)| Expr::Unary(operator, Box::new(operand))),
+            (
+                prop::sample::select(&BINARY_OPERATORS[..]),
+                inner.clone(),
+                inner.clone()
+            )
+                .prop_map(|(operator, lhs, rhs)| Expr::Binary(
+                    operator,
+                    Box::new(lhs),
+                    Box::new(rhs)
+                )),
+            (any::<u8>(), inner.clone(), inner).prop_map(|(callee, a, b)| Expr::Call(
+                callee,
+                Box::new(a),
+                Box::new(b)
+            )),
+        ]
+    })
+}
+
+fn statement() -> impl Strategy<Value = Stmt> {
+    let leaf = prop_oneof![
+        (any::<u8>(), expression()).prop_map(|(target, value)| Stmt::Assign(target, value)),
+        expression().prop_map(Stmt::Print),
+    ];
+    leaf.prop_recursive(3, 16, 3, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..3).prop_map(Stmt::Block),
+            (
+                expression(),
+                prop::collection::vec(
+                    (
+                        -2i8..4,
+                        prop::collection::vec(inner.clone(), 0..3),
+                        any::<bool>()
+                    ),
+                    0..4
+                ),
+                prop::option::of(prop::collection::vec(inner.clone(), 0..2))
+            )
+                .prop_map(|(scrutinee, cases, default)| Stmt::Switch(scrutinee, cases, default)),
+            prop::collection::vec(inner, 1..3).prop_map(Stmt::Skip),
+        ]
+    })
+}
+
+fn function(min_locals: usize) -> impl Strategy<Value = Function> {
+    (
+        prop::collection::vec((any::<bool>(), expression()), min_locals..4),
+        prop::collection::vec(statement(), 0..4),
+        expression(),
+    )
+        .prop_map(|(locals, body, result)| Function {
+            locals,
+            body,
+            result,
+        })
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    (
+        prop::collection::vec(0..=i32::MAX, 1..3),
+        prop::collection::vec(function(0), 0..4),
+        function(1),
+    )
+        .prop_map(|(globals, functions, main)| Program {
+            globals,
+            functions,
+            main,
+        })
+}
+
+struct Scope {
+    readable: Vec<String>,
+    writable: Vec<String>,
+    callable: usize,
+    in_main: bool,
+    labels: usize,
+}
+
+fn print_constant(value: i32) -> String {
+    match value {
+        i32::MIN => String::from("(-2147483647 - 1)"),
+        _ if value < 0 => format!("(-{})", -(value as i64)),
+        _ => value.to_string(),
+    }
+}
+
+fn print_expression(expr: &Expr, scope: &Scope) -> String {
+    match expr {
+        Expr::Constant(value) => print_constant(*value),
+        Expr::Variable(idx) => scope.readable[*idx as usize % scope.readable.len()].clone(),
+        Expr::Unary("-", operand) => format!("safe_neg({})", print_expression(operand, scope)),
+        Expr::Unary(operator, operand) => {
+            format!("{}({})", operator, print_expression(operand, scope))
+        }
+        Expr::Binary(operator, lhs, rhs) => {
+            let (lhs, rhs) = (print_expression(lhs, scope), print_expression(rhs, scope));
+            let helper = match *operator {
+                "+" => "safe_add",
+                "-" => "safe_sub",
+                "*" => "safe_mul",
+                "/" => "safe_div",
+                "%" => "safe_mod",
+                _ => return format!("({} {} {})", lhs, operator, rhs),
+            };
+            format!("{}({}, {})", helper, lhs, rhs)
+        }
+        Expr::Call(_, a, _) if scope.callable == 0 => print_expression(a, scope),
+        Expr::Call(callee, a, b) => format!(
+            "f{}({}, {})",
+            *callee as usize % scope.callable,
+            print_expression(a, scope),
+            print_expression(b, scope)
+        ),
+    }
+}
+
+fn print_statement(stmt: &Stmt, scope: &mut Scope, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent * 4);
+    match stmt {
+        Stmt::Assign(target, value) => {
+            let target = &scope.writable[*target as usize % scope.writable.len()];
+            let value = print_expression(value, scope);
+            writeln!(out, "{}{} = {};", pad, target, value).unwrap();
+        }
+        // Functions other than main stay silent so that the order in which
+        // calls in one expression are evaluated cannot show in the output.
+        Stmt::Print(value) if scope.in_main => {
+            let value = print_expression(value, scope);
+            writeln!(out, "{}printf(\"%d\\n\", {});", pad, value).unwrap();
+        }
+        Stmt::Print(value) => {
+            writeln!(out, "{}{};", pad, print_expression(value, scope)).unwrap();
+        }
+        Stmt::Block(body) => {
+            writeln!(out, "{}{{", pad).unwrap();
+            for stmt in body {
+                print_statement(stmt, scope, indent + 1, out);
+            }
+            writeln!(out, "{}}}", pad).unwrap();
+        }
+        Stmt::Switch(scrutinee, cases, default) => {
+            let scrutinee = print_expression(scrutinee, scope);
+            writeln!(out, "{}switch ({}) {{", pad, scrutinee).unwrap();
+            let mut seen = vec![];
+            for (value, body, ends_with_break) in cases {
+                if seen.contains(value) {
+                    continue;
+                }
+                seen.push(*value);
+                writeln!(out, "{}case {}:", pad, print_constant(*value as i32)).unwrap();
+                for stmt in body {
+                    print_statement(stmt, scope, indent + 1, out);
+                }
+                if *ends_with_break {
+                    writeln!(out, "{}    break;", pad).unwrap();
+                } else if body.is_empty() {
+                    writeln!(out, "{}    ;", pad).unwrap();
+                }
+            }
+            if let Some(body) = default {
+                writeln!(out, "{}default:", pad).unwrap();
+                if body.is_empty() {
+                    writeln!(out, "{}    ;", pad).unwrap();
+                }
+                for stmt in body {
+                    print_statement(stmt, scope, indent + 1, out);
+                }
+            }
+            writeln!(out, "{}}}", pad).unwrap();
+        }
+        Stmt::Skip(body) => {
+            let label = format!("skip_{}", scope.labels);
+            scope.labels += 1;
+            writeln!(out, "{}goto {};", pad, label).unwrap();
+            for stmt in body {
+                print_statement(stmt, scope, indent, out);
+            }
+            writeln!(out, "{}:\n{};", label, pad).unwrap();
+        }
+    }
+}
+
+fn print_function(name: &str, function: &Function, globals: &[String], callable: usize) -> String {
+    let mut out = String::new();
+    let in_main = name == "main";
+    let mut scope = Scope {
+        readable: globals.to_vec(),
+        writable: vec![],
+        callable,
+        in_main,
+        labels: 0,
+    };
+    if in_main {
+        // Only main writes globals, so no call in an expression can change a
+        // global that the same expression reads.
+        scope.writable = globals.to_vec();
+        writeln!(out, "int main(void) {{").unwrap();
+    } else {
+        scope
+            .readable
+            .extend([String::from("p0"), String::from("p1")]);
+        scope
+            .writable
+            .extend([String::from("p0"), String::from("p1")]);
+        writeln!(out, "int {}(int p0, int p1) {{", name).unwrap();
+    }
+    for (idx, (is_char, initializer)) in function.locals.iter().enumerate() {
+        let data_type = if *is_char { "char" } else { "int" };
+        let initializer = print_expression(initializer, &scope);
+        let local = format!("l{}", idx);
+        writeln!(out, "    {} {} = {};", data_type, local, initializer).unwrap();
+        scope.readable.push(local.clone());
+        scope.writable.push(local);
+    }
+    for stmt in &function.body {
+        print_statement(stmt, &mut scope, 1, &mut out);
+    }
+    let result = print_expression(&function.result, &scope);
+    writeln!(out, "    return {};\n}}\n", result).unwrap();
+    out
+}
+
+fn print_program(program: &Program) -> String {
+    let mut out = String::from(SAFE_MATH);
+    let globals: Vec<String> = (0..program.globals.len())
+        .map(|idx| format!("g{}", idx))
+        .collect();
+    out.push('\n');
+    for (name, value) in globals.iter().zip(&program.globals) {
+        writeln!(out, "int {} = {};", name, value).unwrap();
+    }
+    out.push('\n');
+    for (idx, function) in program.functions.iter().enumerate() {
+        out.push_str(&print_function(
+            &format!("f{}", idx),
+            function,
+            &globals,
+            idx,
+        ));
+    }
+    out.push_str(&print_function(
+        "main",
+        &program.main,
+        &globals,
+        program.functions.len(),
+    ));
+    out
+}
+
+fn cases() -> u32 {
+    env::var("PROPTEST_CASES")
+        .ok()
+        .and_then(|cases| cases.parse().ok())
+        .unwrap_or(24)
+}
+
+fn host_cc_available() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+struct Scratch(PathBuf);
+impl Scratch {
+    fn new() -> Scratch {
+        let path = env::temp_dir().join(format!(
+            "rust_cc_fuzz_{}_{}",
+            process::id(),
+            PROGRAMS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Scratch(path)
+    }
+}
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// The exit code and output of `source` built by `compiler`, or its
+// diagnostics if it did not build.
+fn build_and_run(
+    compiler: &str,
+    flags: &[&str],
+    source: &Path,
+    executable: &Path,
+) -> Result<(Option<i32>, String), String> {
+    let output = Command::new(compiler)
+        .args(flags)
+        .arg("-o")
+        .arg(executable)
+        .arg(source)
+        .output()
+        .unwrap_or_else(|err| panic!("cannot run {}: {}", compiler, err));
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    let output = Command::new(executable).output().unwrap();
+    Ok((
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    ))
+}
+
+// Prints a parsed program back as C, which must parse to the same tree and
+// print the same again.
+fn check_round_trip(code: &str) -> Result<(), TestCaseError> {
+    let program = match parser::parse_program(tokenizer::tokenize_with_spans(code)) {
+        Ok(program) => program,
+        Err(_) => return Ok(()),
+    };
+    let printed = unparse::unparse(&program);
+    let reparsed = parser::parse_program(tokenizer::tokenize_with_spans(&printed))
+        .map_err(|err| TestCaseError::fail(format!("{} in printed program:\n{}", err, printed)))?;
+    prop_assert_eq!(
+        dump::structure(&program),
+        dump::structure(&reparsed),
+        "for program:\n{}",
+        printed
+    );
+    prop_assert_eq!(&printed, &unparse::unparse(&reparsed));
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(cases()))]
+
+    #[test]
+    fn test_front_end_survives_arbitrary_bytes(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+        let code = String::from_utf8_lossy(&bytes);
+        let _ = parser::parse_translation_unit(tokenizer::tokenize_with_spans(&code)).and_then(sema::analyze);
+    }
+
+    #[test]
+    fn test_front_end_survives_token_soup(
+        fragments in prop::collection::vec(prop::sample::select(&FRAGMENTS[..]), 0..64)
+    ) {
+        let code = fragments.join(" ");
+        let _ = parser::parse_translation_unit(tokenizer::tokenize_with_spans(&code)).and_then(sema::analyze);
+        check_round_trip(&code)?;
+    }
+
+    #[test]
+    fn test_unparse_round_trips(program in program()) {
+        check_round_trip(&print_program(&program))?;
+    }
+
+    #[test]
+    fn test_matches_host_cc(program in program()) {
+        if !host_cc_available() {
+            return Ok(());
+        }
+        let scratch = Scratch::new();
+        let source = scratch.0.join("program.c");
+        let code = print_program(&program);
+        fs::write(&source, &code).unwrap();
+        let expected = build_and_run("cc", &["-w"], &source, &scratch.0.join("reference"))
+            .unwrap_or_else(|err| panic!("host cc rejected a generated program:\n{}\n{}", err, code));
+        for level in ["-O0", "-O1", "-O2"] {
+            let actual = build_and_run(RUST_CC, &["--verify-ir", level], &source, &scratch.0.join("ours"));
+            prop_assert_eq!(Ok(expected.clone()), actual, "at {} for program:\n{}", level, code);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+#define LIMIT 10
+
+int printf(const char *format, ...);
+
+static int count(int n) {
+    int total = 0;
+    total = total + n * 2 - n / 3 % 4;
+    switch (n) {
+    case 1:
+        total++;
+        break;
+    default:
+        --total;
+    }
+    return !(total >= LIMIT) || ~total != -1 && total <= 3;
+}
+
+int main(void) {
+    printf("%d\n", count(2), "adjacent" "strings");
+    goto done;
+done:
+    return count(5) == 1 || 2 < 3 || 4 > 5;
+}
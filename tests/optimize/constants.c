@@ -0,0 +1,45 @@
+// Folding must leave what C doesn't define for run time, and stores to char
+// must wrap.
+int printf(char *fmt, ...);
+
+int never = 0;
+
+int fold(void) {
+    int a = 6;
+    int b = a * 7;
+    char c = 300;
+    char d = -129;
+    int e = c + d;
+    switch (never) {
+    case 1:
+        b = 1 / 0;
+        e = -2147483647 - 1;
+        e = e / -1;
+        e = e % -1;
+        break;
+    }
+    return b * 100 + e;
+}
+
+int logic(void) {
+    int zero = 0;
+    int one = !zero;
+    return (one && zero) * 4 + (zero || one) * 2 + (one - 1 || -one);
+}
+
+int big(void) {
+    int max = 2147483647;
+    int sum = max + 1;
+    return sum < 0;
+}
+
+int main(void) {
+    printf("%d %d %d\n", fold(), logic(), big());
+    switch (3 * 4 - 12) {
+    case 0:
+        return 10;
+    default:
+        return 20;
+    }
+}
+// exit: 10
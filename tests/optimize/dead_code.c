@@ -0,0 +1,31 @@
+// Dead stores and unreachable code go, but calls and divisions that may
+// trap stay even when their results are unused.
+int printf(char *fmt, ...);
+
+int calls = 0;
+int one = 1;
+
+int count(void) {
+    calls++;
+    return calls;
+}
+
+int f(int n) {
+    int unused = n * 2;
+    int ignored = count();
+    int kept = n / 1;
+    n = 5;
+    n = 6;
+    return n + kept;
+    n = 7;
+    count();
+}
+
+int main(void) {
+    int quotient;
+    printf("%d\n", f(3));
+    printf("%d\n", calls);
+    quotient = 9 / one;
+    return 1;
+}
+// exit: 1
@@ -0,0 +1,6 @@
+// Dividing by a constant zero must still trap at run time.
+int main(void) {
+    int zero = 0;
+    int n = 7;
+    return n / zero;
+}
@@ -0,0 +1,62 @@
+// Helpers small enough to inline, with several returns, a char result and
+// calls of their own, next to ones that must stay calls.
+int printf(char *fmt, ...);
+
+int counter;
+
+static int twice(int x) { return x * 2; }
+
+static int clamp(int x) {
+    switch (x > 100) {
+    case 1:
+        return 100;
+    }
+    switch (x < 0) {
+    case 1:
+        return 0;
+    }
+    return x;
+}
+
+static int low_byte(int x) {
+    char c = x;
+    return c;
+}
+
+static int bump(void) {
+    counter = counter + 1;
+    return counter;
+}
+
+static inline int combined(int x) { return clamp(twice(x)) + bump(); }
+
+__attribute__((noinline)) static int kept(int x) { return x - 1; }
+
+static int fib(int n) {
+    switch (n < 2) {
+    case 1:
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+static __attribute__((always_inline)) int spin(int n) {
+    int total = 0;
+top:
+    switch (n) {
+    case 0:
+        return total;
+    }
+    total = total + n;
+    n = n - 1;
+    goto top;
+}
+
+int main(void) {
+    char c = low_byte(300);
+    int a = combined(30) + combined(70) + combined(-5);
+    int b = kept(c) + fib(10) + spin(10);
+    printf("%d %d %d %d\n", c, a, b, counter);
+    return (a + b + counter) % 256;
+}
+// exit: 66
@@ -0,0 +1,82 @@
+// Loops whose invariants can't all move out, and repeated expressions that
+// a call or store in between makes different.
+int printf(char *fmt, ...);
+
+int counter;
+
+int bump(void) {
+    counter = counter + 1;
+    return counter;
+}
+
+// The division is the same every time round, but only runs when d isn't
+// zero, so it can't move in front of the loop.
+int guarded(int n, int d) {
+    int total = 0;
+again:
+    switch (n) {
+    case 0:
+        return total;
+    }
+    switch (d) {
+    case 0:
+        break;
+    default:
+        total = total + 100 / d;
+    }
+    n = n - 1;
+    goto again;
+}
+
+// counter changes inside the loop, so reading it doesn't move out of it.
+int reads(int n) {
+    int total = 0;
+again:
+    switch (n) {
+    case 0:
+        return total;
+    }
+    total = total + counter * 2;
+    bump();
+    n = n - 1;
+    goto again;
+}
+
+// The loop is entered from two places and falls through into its header.
+int entered_twice(int n, int k) {
+    int total = 0;
+    switch (k) {
+    case 1:
+        goto top;
+    }
+    total = 1;
+    goto top;
+body:
+    total = total + k * 3;
+    n = n - 1;
+top:
+    switch (n) {
+    case 0:
+        return total;
+    }
+    goto body;
+}
+
+int repeated(int a) {
+    int first = counter + a;
+    bump();
+    int second = counter + a;
+    counter = 7;
+    int third = counter + a;
+    return first * 100 + second * 10 + third - (counter + a) + a * a - a * a;
+}
+
+int main(void) {
+    printf("%d\n", guarded(3, 0));
+    printf("%d\n", guarded(3, 5));
+    printf("%d\n", reads(3));
+    printf("%d\n", repeated(1));
+    printf("%d %d\n", entered_twice(4, 2), entered_twice(3, 1));
+    return guarded(2, 50) + reads(2);
+}
+// exit: 34
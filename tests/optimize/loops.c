@@ -0,0 +1,34 @@
+// Constants carried around loops through phis.
+int printf(char *fmt, ...);
+
+int sum(int n) {
+    int total = 0;
+    int step = 2;
+top:
+    switch (n) {
+    case 0:
+        return total;
+    }
+    total = total + step;
+    n--;
+    goto top;
+}
+
+int unchanged(void) {
+    int k = 5;
+    int i = 3;
+again:
+    k = 5;
+    i = i - 1;
+    switch (i) {
+    case 0:
+        return k;
+    }
+    goto again;
+}
+
+int main(void) {
+    printf("%d %d\n", sum(10), unchanged());
+    return sum(3);
+}
+// exit: 6